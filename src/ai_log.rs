@@ -0,0 +1,36 @@
+#[cfg(debug_assertions)]
+use std::collections::VecDeque;
+
+/// Entries kept before the oldest are dropped
+#[cfg(debug_assertions)]
+const AI_LOG_CAPACITY: usize = 200;
+
+/// What each monster decided to do, turn by turn, for working out why one did or didn't act.
+/// Only kept in debug builds and read from the debug console. Release builds carry an empty
+/// struct and never build the entries.
+#[derive(Default)]
+pub struct AiLog {
+    #[cfg(debug_assertions)]
+    entries: VecDeque<String>,
+}
+
+impl AiLog {
+    /// Records an entry, dropping the oldest once full. The text is only built in debug builds.
+    pub fn record(&mut self, _entry: impl FnOnce() -> String) {
+        #[cfg(debug_assertions)]
+        {
+            if self.entries.len() == AI_LOG_CAPACITY {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(_entry());
+        }
+    }
+
+    /// Up to `count` of the most recent entries, oldest first
+    #[cfg(debug_assertions)]
+    pub fn latest(&self, count: usize) -> impl Iterator<Item = &String> {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+    }
+}
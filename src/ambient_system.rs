@@ -0,0 +1,209 @@
+use super::{
+    gamelog::Gamelog, map_themes::MapThemeKind, random_table::RandomTable, settings::GameSettings,
+    Equipped, InBackpack, LightSource, Map,
+};
+use rltk::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+/// One in this many rounds something happens on the level
+const AMBIENT_EVENT_CHANCE: i32 = 150;
+/// Rounds the glimpse of the level from a tremor lasts
+const TREMOR_REVEAL_TURNS: i32 = 3;
+const COLD_SNAP_TURNS: i32 = 20;
+
+/// Level-wide events still under way. Saved with the game and cleared on arriving at a level.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct AmbientState {
+    /// Rounds left of the cold snap
+    cold_snap: i32,
+    /// Rounds left of the tremor's glimpse
+    tremor: i32,
+    /// Tiles only the tremor has revealed, which go back to unexplored when it ends
+    tremor_tiles: Vec<usize>,
+}
+
+impl AmbientState {
+    /// In a cold snap, monsters and pets only act every other round
+    pub fn sluggish(&self, round: i32) -> bool {
+        self.cold_snap > 0 && round % 2 == 1
+    }
+
+    /// The whole level has been revealed some other way, so nothing goes back to unexplored when
+    /// the tremor ends
+    pub fn keep_revealed(&mut self) {
+        self.tremor_tiles.clear();
+    }
+}
+
+/// What can happen on a level. Deeper dungeons shake more, and the forest is colder.
+fn event_table(map: &Map) -> RandomTable {
+    let tremor = if map.depth > 0 { 1 + map.depth / 2 } else { 0 };
+    match map.theme {
+        MapThemeKind::Dungeon => RandomTable::new()
+            .add("Drip", 6)
+            .add("Echo", 3)
+            .add("Tremor", tremor)
+            .add("Cold Snap", 1),
+        MapThemeKind::Forest => RandomTable::new()
+            .add("Wind", 6)
+            .add("Birdsong", 3)
+            .add("Tremor", tremor)
+            .add("Cold Snap", 3),
+    }
+}
+
+/// Winds down events under way, then rarely rolls a new one. Most are only a line in the log;
+/// a tremor briefly shows the layout of the level and a cold snap slows everyone for a while.
+/// Neither starts again while it's still going. Runs once a round.
+pub fn run_ambient_events(ecs: &mut World) {
+    wind_down(ecs);
+    if !ecs.fetch::<GameSettings>().ambient_events {
+        return;
+    }
+
+    let event = {
+        let map = ecs.fetch::<Map>();
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        if rng.roll_dice(1, AMBIENT_EVENT_CHANCE) != 1 {
+            return;
+        }
+        event_table(&map).roll(&mut rng)
+    };
+    let flavor = match event.as_str() {
+        "Drip" => "Water drips somewhere in the dark.",
+        "Echo" => "A distant echo rolls through the halls.",
+        "Wind" => "The wind stirs the leaves overhead.",
+        "Birdsong" => "A bird calls somewhere out of sight.",
+        "Tremor" => return tremor(ecs),
+        "Cold Snap" => return cold_snap(ecs),
+        _ => return,
+    };
+    ecs.write_resource::<Gamelog>()
+        .entries
+        .push(flavor.to_string());
+}
+
+fn tremor(ecs: &mut World) {
+    let mut ambient = ecs.write_resource::<AmbientState>();
+    if ambient.tremor > 0 {
+        return;
+    }
+    let mut map = ecs.write_resource::<Map>();
+    ambient.tremor_tiles = (0..map.revealed_tiles.len())
+        .filter(|idx| !map.revealed_tiles[*idx])
+        .collect();
+    for idx in ambient.tremor_tiles.iter() {
+        map.revealed_tiles[*idx] = true;
+    }
+    ambient.tremor = TREMOR_REVEAL_TURNS;
+    ecs.write_resource::<Gamelog>()
+        .entries
+        .push("The ground shakes! For a moment you sense the shape of the level.".to_string());
+}
+
+fn cold_snap(ecs: &mut World) {
+    let mut ambient = ecs.write_resource::<AmbientState>();
+    if ambient.cold_snap > 0 {
+        return;
+    }
+    ambient.cold_snap = COLD_SNAP_TURNS;
+    ecs.write_resource::<Gamelog>().entries.push(
+        "A bitter cold settles over the level. Everything slows, but a light keeps you warm."
+            .to_string(),
+    );
+}
+
+fn wind_down(ecs: &mut World) {
+    let mut ambient = ecs.write_resource::<AmbientState>();
+    let mut log = ecs.write_resource::<Gamelog>();
+
+    if ambient.tremor > 0 {
+        // Anything seen while the tremor lasts has been seen properly, so it stays explored
+        let mut map = ecs.write_resource::<Map>();
+        ambient.tremor_tiles.retain(|idx| !map.visible_tiles[*idx]);
+        ambient.tremor -= 1;
+        if ambient.tremor == 0 {
+            for idx in std::mem::take(&mut ambient.tremor_tiles) {
+                map.revealed_tiles[idx] = false;
+            }
+            log.entries
+                .push("The tremor passes, and with it your sense of the level.".to_string());
+        }
+    }
+
+    if ambient.cold_snap > 0 {
+        ambient.cold_snap -= 1;
+        if ambient.cold_snap == 0 {
+            log.entries.push("The cold lifts.".to_string());
+        }
+    }
+}
+
+/// In a cold snap the player is slowed along with everything else, unless they carry a light to
+/// keep warm
+pub fn player_is_chilled(ecs: &World) -> bool {
+    if ecs.fetch::<AmbientState>().cold_snap < 1 {
+        return false;
+    }
+    let player_entity = *ecs.fetch::<Entity>();
+    let lights = ecs.read_storage::<LightSource>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let has_light = lights.get(player_entity).is_some()
+        || (&backpack, &lights)
+            .join()
+            .any(|(pack, _light)| pack.owner == player_entity)
+        || (&equipped, &lights)
+            .join()
+            .any(|(equipped, _light)| equipped.owner == player_entity);
+    !has_light
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_after_tremor() -> World {
+        let mut ecs = World::new();
+        let mut map = Map::new(1);
+        map.revealed_tiles[0] = true;
+        ecs.insert(map);
+        ecs.insert(AmbientState::default());
+        ecs.insert(Gamelog { entries: vec![] });
+        tremor(&mut ecs);
+        ecs
+    }
+
+    #[test]
+    fn tremor_forgets_only_tiles_never_seen() {
+        let mut ecs = world_after_tremor();
+        // Seen during the first round of the tremor, then out of view again
+        ecs.write_resource::<Map>().visible_tiles[1] = true;
+        wind_down(&mut ecs);
+        ecs.write_resource::<Map>().visible_tiles[1] = false;
+        for _ in 1..TREMOR_REVEAL_TURNS {
+            wind_down(&mut ecs);
+        }
+
+        let map = ecs.fetch::<Map>();
+        assert!(map.revealed_tiles[0]);
+        assert!(map.revealed_tiles[1]);
+        assert!(!map.revealed_tiles[2]);
+    }
+
+    #[test]
+    fn magic_mapping_keeps_tremor_tiles() {
+        let mut ecs = world_after_tremor();
+        ecs.write_resource::<AmbientState>().keep_revealed();
+        for _ in 0..TREMOR_REVEAL_TURNS {
+            wind_down(&mut ecs);
+        }
+
+        assert!(ecs
+            .fetch::<Map>()
+            .revealed_tiles
+            .iter()
+            .all(|revealed| *revealed));
+    }
+}
@@ -0,0 +1,120 @@
+use super::{
+    classes::{self, ClassStats},
+    gamelog::Gamelog,
+    settings::Difficulty,
+    Attributes, CharacterClass, CombatStats, Experience,
+};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+const XP_PER_LEVEL: i32 = 50;
+const HP_PER_FITNESS: i32 = 5;
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Attribute {
+    /// Hits harder
+    Might,
+    /// More hit points
+    Fitness,
+    /// Harder to hit
+    Quickness,
+}
+
+/// Experience needed to go from `level` to the next one
+pub fn xp_to_next_level(level: i32) -> i32 {
+    level * XP_PER_LEVEL
+}
+
+/// Adds `xp`, granting an attribute point for every level gained. Returns the levels gained.
+pub fn gain_xp(experience: &mut Experience, xp: i32) -> i32 {
+    experience.xp += xp;
+    let mut levels = 0;
+    while experience.xp >= xp_to_next_level(experience.level) {
+        experience.xp -= xp_to_next_level(experience.level);
+        experience.level += 1;
+        experience.unspent_points += 1;
+        levels += 1;
+    }
+    levels
+}
+
+/// Does the player have a level up waiting to be allocated?
+pub fn player_has_unspent_points(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    ecs.read_storage::<Experience>()
+        .get(*player_entity)
+        .is_some_and(|experience| experience.unspent_points > 0)
+}
+
+/// Spends one of the player's unspent points on `attribute` and recomputes their stats
+pub fn spend_point(ecs: &mut World, attribute: Attribute) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut experiences = ecs.write_storage::<Experience>();
+    let mut attributes = ecs.write_storage::<Attributes>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let classes = ecs.read_storage::<CharacterClass>();
+
+    let (Some(experience), Some(attrs), Some(stats), Some(class)) = (
+        experiences.get_mut(player_entity),
+        attributes.get_mut(player_entity),
+        combat_stats.get_mut(player_entity),
+        classes.get(player_entity),
+    ) else {
+        return;
+    };
+    if experience.unspent_points < 1 {
+        return;
+    }
+    experience.unspent_points -= 1;
+
+    let name = match attribute {
+        Attribute::Might => {
+            attrs.might += 1;
+            "stronger"
+        }
+        Attribute::Fitness => {
+            attrs.fitness += 1;
+            "tougher"
+        }
+        Attribute::Quickness => {
+            attrs.quickness += 1;
+            "quicker"
+        }
+    };
+    apply_attributes(stats, &classes::class_definition(class.class).stats, attrs);
+    ecs.fetch_mut::<Gamelog>()
+        .entries
+        .push(format!("You feel {name}."));
+}
+
+/// Shifts each of the player's attributes by the difficulty's bonus. Only called as a run starts,
+/// so later changes to the setting don't touch a run in progress.
+pub fn apply_difficulty(ecs: &mut World, difficulty: Difficulty) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut attributes = ecs.write_storage::<Attributes>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let classes = ecs.read_storage::<CharacterClass>();
+
+    let (Some(attrs), Some(stats), Some(class)) = (
+        attributes.get_mut(player_entity),
+        combat_stats.get_mut(player_entity),
+        classes.get(player_entity),
+    ) else {
+        return;
+    };
+    let bonus = difficulty.attribute_bonus();
+    attrs.might += bonus;
+    attrs.fitness += bonus;
+    attrs.quickness += bonus;
+    apply_attributes(stats, &classes::class_definition(class.class).stats, attrs);
+}
+
+/// Recomputes stats from the class's base stats plus attributes. Gaining maximum hit points
+/// also heals by the same amount.
+fn apply_attributes(stats: &mut CombatStats, base: &ClassStats, attributes: &Attributes) {
+    let max_hp = base.max_hp + attributes.fitness * HP_PER_FITNESS;
+    stats.hp += max_hp - stats.max_hp;
+    stats.max_hp = max_hp;
+    stats.power = base.power + attributes.might;
+    stats.defense = base.defense + attributes.quickness;
+}
@@ -0,0 +1,47 @@
+use super::{spawner, CombatStats};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What the player has learned about one kind of monster
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BestiaryEntry {
+    /// Stats of the first one the player saw
+    pub max_hp: i32,
+    pub power: i32,
+    pub defense: i32,
+    pub first_seen_depth: i32,
+    pub kills: i32,
+}
+
+/// Every kind of monster the player has come across this game. Variants such as Fierce Goblins
+/// count as their plain kind. Saved with the game and emptied for a new one.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Bestiary {
+    entries: HashMap<String, BestiaryEntry>,
+}
+
+impl Bestiary {
+    /// Notes a monster the player can see. Only the first sighting of each kind is kept.
+    pub fn record_sighting(&mut self, name: &str, stats: &CombatStats, depth: i32) {
+        self.entries
+            .entry(spawner::species_name(name).to_string())
+            .or_insert(BestiaryEntry {
+                max_hp: stats.max_hp,
+                power: stats.power,
+                defense: stats.defense,
+                first_seen_depth: depth,
+                kills: 0,
+            });
+    }
+
+    /// Counts a kill. Kinds the player has never seen aren't listed, so their kills aren't either.
+    pub fn record_kill(&mut self, name: &str) {
+        if let Some(entry) = self.entries.get_mut(spawner::species_name(name)) {
+            entry.kills += 1;
+        }
+    }
+
+    pub fn entry(&self, species: &str) -> Option<&BestiaryEntry> {
+        self.entries.get(species)
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// The starting loadouts, embedded so the game doesn't depend on the working directory
+const CLASSES_RON: &str = include_str!("../raws/classes.ron");
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum PlayerClass {
+    Fighter,
+    Rogue,
+    Mage,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ClassStats {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+/// One entry in `raws/classes.ron`
+#[derive(Deserialize, Clone)]
+pub struct ClassDefinition {
+    pub class: PlayerClass,
+    pub name: String,
+    pub description: String,
+    pub stats: ClassStats,
+    /// Spawned into the backpack and then equipped, so their bonuses apply straight away
+    pub equipped: Vec<String>,
+    pub backpack: Vec<String>,
+}
+
+/// Every class, in the order they're offered on New Game
+pub fn class_definitions() -> Vec<ClassDefinition> {
+    ron::from_str(CLASSES_RON).expect("Unable to parse classes.ron")
+}
+
+pub fn class_definition(class: PlayerClass) -> ClassDefinition {
+    class_definitions()
+        .into_iter()
+        .find(|definition| definition.class == class)
+        .expect("Class missing from classes.ron")
+}
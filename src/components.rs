@@ -34,9 +34,50 @@ pub struct Viewshed {
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Monster {}
 
+/// A companion on the player's side, doing as its orders say. Whatever they are, it fights back
+/// against hostiles right next to it.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Pet {
+    #[serde(default)]
+    pub orders: PetOrders,
+}
+
+/// What the player has told a pet to do
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum PetOrders {
+    /// Keep to the player's side and see off anything threatening them
+    #[default]
+    Follow,
+    /// Go after any hostile in sight
+    Attack,
+    /// Hold the current tile
+    Stay,
+}
+
+impl PetOrders {
+    /// The orders after these, when the player cycles through them
+    pub fn next(self) -> PetOrders {
+        match self {
+            PetOrders::Follow => PetOrders::Attack,
+            PetOrders::Attack => PetOrders::Stay,
+            PetOrders::Stay => PetOrders::Follow,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PetOrders::Follow => "Follow",
+            PetOrders::Attack => "Attack",
+            PetOrders::Stay => "Stay",
+        }
+    }
+}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Name {
     pub name: String,
+    /// Proper nouns, like a unique monster's name, never take an article
+    pub proper: bool,
 }
 
 #[derive(Component, Serialize, Deserialize, Clone)]
@@ -55,18 +96,46 @@ pub struct WantsToMelee {
     pub target: Entity,
 }
 
-#[derive(Component, Debug, ConvertSaveload, Clone)]
+/// Damage waiting to be applied. Cleared every turn, so it's never saved.
+#[derive(Component, Debug, Clone)]
 pub struct SufferDamage {
-    pub amount: Vec<i32>,
+    /// Each hit and what it's made of, before any resistances
+    pub amount: Vec<(i32, DamageType)>,
+    /// Name of whoever dealt the most recent hit, if anyone did
+    pub source: Option<String>,
+    /// The entity behind the most recent hit, if it was a creature or item rather than hunger,
+    /// drowning and the like
+    pub attacker: Option<Entity>,
+    /// Set if any of the hits came from the player, who earns experience for the kill
+    pub from_player: bool,
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        damage_type: DamageType,
+        source: Option<&str>,
+        attacker: Option<Entity>,
+        from_player: bool,
+    ) {
+        let source = source.map(|name| name.to_string());
         if let Some(suffering) = store.get_mut(victim) {
-            suffering.amount.push(amount);
+            suffering.amount.push((amount, damage_type));
+            if source.is_some() {
+                suffering.source = source;
+            }
+            if attacker.is_some() {
+                suffering.attacker = attacker;
+            }
+            suffering.from_player |= from_player;
         } else {
             let dmg = SufferDamage {
-                amount: vec![amount],
+                amount: vec![(amount, damage_type)],
+                source,
+                attacker,
+                from_player,
             };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
@@ -103,48 +172,282 @@ pub struct WantsToUseItem {
     pub target: Option<Point>,
 }
 
+/// Sits on the item itself, so several items can be dropped in the same turn
 #[derive(Component, Debug, ConvertSaveload)]
 pub struct WantsToDropItem {
+    pub dropped_by: Entity,
+}
+
+/// Throwing an item from the backpack, or one in hand, towards `target`
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToThrowItem {
+    pub item: Entity,
+    pub target: Point,
+}
+
+/// The item an identify scroll being used alongside it should reveal
+#[derive(Component, Debug, ConvertSaveload)]
+pub struct WantsToIdentifyItem {
     pub item: Entity,
 }
 
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Consumable {}
 
+/// `count` identical items held as one. An item without it is a single one.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Stack {
+    pub count: i32,
+}
+
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Ranged {
     pub range: i32,
 }
 
-#[derive(Component, Debug, ConvertSaveload, Clone)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct InflictsDamage {
-    pub damage: i32,
+    pub damage: super::dice::DiceExpr,
+    #[serde(default)]
+    pub damage_type: DamageType,
 }
 
-#[derive(Component, Debug, ConvertSaveload, Clone)]
+/// What a hit is made of, for anything with `Resistances`
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Cold,
+    Poison,
+    Lightning,
+}
+
+impl DamageType {
+    pub fn name(self) -> &'static str {
+        match self {
+            DamageType::Physical => "physical",
+            DamageType::Fire => "fire",
+            DamageType::Cold => "cold",
+            DamageType::Poison => "poison",
+            DamageType::Lightning => "lightning",
+        }
+    }
+}
+
+/// Damage types a creature shrugs off entirely, and ones that hurt it twice as much
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Resistances {
+    pub resist: Vec<DamageType>,
+    pub vulnerable: Vec<DamageType>,
+}
+
+impl Resistances {
+    /// How much of a hit of this type gets through, as a percentage. A type listed as both
+    /// resisted and a vulnerability cancels out to a normal hit.
+    pub fn percent(&self, damage_type: DamageType) -> i32 {
+        match (
+            self.resist.contains(&damage_type),
+            self.vulnerable.contains(&damage_type),
+        ) {
+            (true, false) => 0,
+            (false, true) => 200,
+            _ => 100,
+        }
+    }
+}
+
+/// The shape an area effect covers
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum AoeShape {
+    /// A blast spreading out from where it lands
+    #[default]
+    Circle,
+    /// A beam from the user through the target
+    Line,
+    /// A fan opening from the user towards the target
+    Cone,
+    /// Straight lines out from where it lands in the four cardinal directions
+    Cross,
+}
+
+/// `radius` is how far the shape reaches: the blast radius, the length of a beam or cone, or the
+/// length of each arm of a cross
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct AreaOfEffect {
     pub radius: i32,
+    #[serde(default)]
+    pub shape: AoeShape,
 }
 
+/// On an item, confuses whatever it's used on for `turns`
 #[derive(Component, Debug, ConvertSaveload, Clone)]
 pub struct Confusion {
     pub turns: i32,
 }
 
+/// On an item, saps `amount` melee power from whatever it's used on for `turns`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Weakness {
+    pub amount: i32,
+    pub turns: i32,
+}
+
+/// On an item, sends whatever it's used on into a rage for `turns`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Rage {
+    pub turns: i32,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum StatusEffectKind {
+    /// Loses its turns, or for the player, stumbles about at random
+    Confused,
+    /// Strikes with `magnitude` less power
+    Weakened,
+    /// Hits harder and shrugs off more, but won't run or stop to use items
+    Raging,
+}
+
+impl StatusEffectKind {
+    /// How the status line shows it
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            StatusEffectKind::Confused => "Conf",
+            StatusEffectKind::Weakened => "Weak",
+            StatusEffectKind::Raging => "Rage",
+        }
+    }
+
+    /// Describes getting over it, for the log. `subject` comes with its verb, as in "You are".
+    pub fn expiry_message(self, subject: &str) -> String {
+        match self {
+            StatusEffectKind::Confused => format!("{subject} no longer confused."),
+            StatusEffectKind::Weakened => format!("{subject} no longer weakened."),
+            StatusEffectKind::Raging => format!("{subject} no longer raging."),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ActiveEffect {
+    pub kind: StatusEffectKind,
+    /// How strong it is, for kinds that vary. Confusion is all or nothing.
+    pub magnitude: i32,
+    pub turns: i32,
+}
+
+/// Everything currently affecting a creature, ticked down once a round by the status system
+#[derive(Component, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<ActiveEffect>,
+}
+
+impl StatusEffects {
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// The strength of the active effect of this kind, or 0 if there isn't one
+    pub fn magnitude(&self, kind: StatusEffectKind) -> i32 {
+        self.effects
+            .iter()
+            .find(|effect| effect.kind == kind)
+            .map_or(0, |effect| effect.magnitude)
+    }
+
+    /// Reapplying a kind that's already active refreshes it rather than stacking: it lasts for
+    /// whichever is longer of what's left and the new duration, at the stronger of the two
+    /// magnitudes. Chaining the same effect can never hold something down for longer than one
+    /// fresh dose.
+    pub fn apply(&mut self, effect: ActiveEffect) {
+        match self
+            .effects
+            .iter_mut()
+            .find(|active| active.kind == effect.kind)
+        {
+            Some(active) => {
+                active.turns = i32::max(active.turns, effect.turns);
+                active.magnitude = i32::max(active.magnitude, effect.magnitude);
+            }
+            None => self.effects.push(effect),
+        }
+    }
+}
+
 pub struct IsSerialized;
 
 // Special component that exists to help serialize the game data
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct SerializationHelper {
     pub map: super::map::Map,
+    pub turns: i32,
+    // Fields added since saves were first written default when an older save lacks them
+    #[serde(default)]
+    pub level_start: i32,
+    #[serde(default)]
+    pub time_pressure: bool,
+    pub dungeon_map: super::master_dungeon_map::MasterDungeonMap,
+    #[serde(default)]
+    pub bestiary: super::bestiary::Bestiary,
+    #[serde(default)]
+    pub ambient: super::ambient_system::AmbientState,
+    #[serde(default)]
+    pub level_stats: super::level_stats::LevelStats,
+    #[serde(default)]
+    pub run_seed: super::scores::RunSeed,
 }
 
 #[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum EquipmentSlot {
     Melee,
-    Shield,
+    /// Holds a shield, or a second one-handed weapon for an extra, weaker attack
+    #[serde(alias = "Shield")]
+    OffHand,
+    /// A ring or charm, worn alongside whatever is in hand
+    Trinket,
 }
 
+/// Breaks where it lands when thrown, working on whatever is there as if it had been used
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Fragile {}
+
+/// Drifts through walls. Never blocks the tile it's on, and can't be seen while inside solid rock.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Phasing {}
+
+/// Dozing where it stands. Asleep monsters don't act until they notice the player or get hurt, and
+/// the first blow against one lands as a sneak attack.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Asleep {}
+
+/// Brings a monster of the named kind through onto a free tile beside it every `cooldown`
+/// rounds, until it's broken
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Portal {
+    pub spawns: String,
+    pub cooldown: i32,
+    /// Rounds left until the next monster comes through
+    pub timer: i32,
+}
+
+/// Lets whoever has it equipped see this many tiles further
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct SightBonus {
+    pub range: i32,
+}
+
+/// Tiles the gear a viewer has on currently adds to its `Viewshed` range, so the bonus can be
+/// taken back off when that gear changes
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ExtendedSight {
+    pub bonus: i32,
+}
+
+/// A weapon that needs both hands, so nothing can be held in the off hand alongside it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct TwoHanded {}
+
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Equippable {
     pub slot: EquipmentSlot,
@@ -162,9 +465,12 @@ impl Owned for Equipped {
     }
 }
 
-#[derive(Component, ConvertSaveload, Clone)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct MeleePowerBonus {
     pub amount: i32,
+    /// What a blow with the weapon deals. Saves from before damage types hit physically.
+    #[serde(default)]
+    pub damage_type: DamageType,
 }
 
 #[derive(Component, ConvertSaveload, Clone)]
@@ -184,6 +490,12 @@ pub trait Owned {
 #[derive(Component, Serialize, Deserialize, Clone)]
 pub struct ParticleLifetime {
     pub lifetime_ms: f32,
+    /// Time left before the particle appears. Its lifetime only starts counting down after.
+    pub delay_ms: f32,
+    /// How fast the particle drifts up the screen, in tiles per second
+    pub rise_per_sec: f32,
+    /// Drift not yet large enough to move a whole tile
+    pub risen: f32,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, PartialEq)]
@@ -206,6 +518,17 @@ pub struct ProvidesFood {}
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct MagicMapper {}
 
+/// On an item, reaches every hostile the user can see instead of a chosen tile or area. One that
+/// `fizzles` is used up even with nothing in sight, rather than being kept for later.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct MassEffect {
+    pub fizzles: bool,
+}
+
+/// Reveals the true name of an item the player picks from their backpack
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Identifier {}
+
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Hidden {}
 
@@ -217,3 +540,175 @@ pub struct EntityMoved {}
 
 #[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct SingleActivation {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Reach {
+    pub tiles: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct StartsFire {
+    pub turns: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Weight {
+    pub pounds: f32,
+}
+
+/// Carrying more than its capacity. Monsters get an extra turn and the carrier doesn't regenerate.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Encumbered {}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Corpse {}
+
+/// Wounded monsters with this will eat adjacent corpses to heal
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Scavenger {}
+
+/// Follows the player's scent trail when it can't see them
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Tracker {}
+
+/// Nerve. Drops when allies die nearby or the monster is badly hurt, and it routs when low.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Morale {
+    pub value: i32,
+}
+
+/// Never routs, however bad things get
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Fearless {}
+
+/// Flies into a rage for `turns` the first time it's badly wounded, even if it was running
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Berserker {
+    pub turns: i32,
+}
+
+/// Routed: runs from the player until it rallies
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Fleeing {
+    pub turns: i32,
+}
+
+/// Name of whatever last hurt this entity, used as the cause of death
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct LastHitBy {
+    pub name: String,
+}
+
+/// The creature or item that last hurt this entity, reported as the killer when it dies.
+/// Entity references don't survive a reload, so this isn't saved.
+#[derive(Component, Debug, Clone)]
+pub struct LastAttacker {
+    pub attacker: Entity,
+}
+
+/// The class the player picked on New Game, kept so it survives a save and load
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct CharacterClass {
+    pub class: super::classes::PlayerClass,
+}
+
+/// Points spent on level up. Each one feeds a stat derived from the player's class.
+#[derive(Component, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Attributes {
+    pub might: i32,
+    pub fitness: i32,
+    pub quickness: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Experience {
+    pub level: i32,
+    pub xp: i32,
+    /// Attribute points earned by levelling up but not yet allocated
+    pub unspent_points: i32,
+}
+
+/// Wears down with use. Weapons lose a point for every blow they strike and armour for every
+/// blow its wearer takes, and the item breaks at zero.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Durability {
+    pub current: i32,
+    pub max: i32,
+}
+
+/// A tool that digs through walls when its wielder walks into them, losing durability for each
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Digger {}
+
+/// Trades with the player. Both lists hold real item names, so disguised items are still
+/// recognised.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Vendor {
+    pub buys: Vec<String>,
+    pub sells: Vec<String>,
+}
+
+/// What an item costs to buy. Vendors buy it back for half.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Value {
+    pub gold: i32,
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Gold {
+    pub amount: i32,
+}
+
+/// A pile of gold. Picking it up adds to the collector's `Gold` rather than filling a backpack
+/// slot.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Coins {
+    pub amount: i32,
+}
+
+/// A weapon whose damaging blows drive the target up to `distance` tiles straight away from the
+/// attacker
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Knockback {
+    pub distance: i32,
+}
+
+/// Where a monster was spawned. Once it has lost the player for long enough it walks back here
+/// and patrols nearby.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Home {
+    pub spawn_pos: Point,
+    /// Turns since the monster last saw or smelled the player
+    pub turns_lost: i32,
+}
+
+/// Lets whoever carries it see `range` tiles around them in the dark
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct LightSource {
+    pub range: i32,
+}
+
+/// Can cross deep water without drowning
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct CanSwim {}
+
+/// Lives in deep water and never leaves it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Aquatic {}
+
+/// The way a monster is looking, one step in any of the eight directions. Monsters without one
+/// are aware all around them.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Facing {
+    pub direction: Point,
+}
+
+/// How far off a monster notices the player, apart from how far its viewshed reaches. Monsters
+/// without one notice the player whenever their viewshed takes them in.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Senses {
+    /// Range it notices the player in plain view. Nothing is seen at 0.
+    pub sight: i32,
+    /// Range it hears the player, walls or no walls
+    pub hearing: i32,
+}
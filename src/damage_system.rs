@@ -1,5 +1,16 @@
-use super::{gamelog::Gamelog, CombatStats, Map, Name, Player, Position, RunState, SufferDamage};
-use specs::prelude::*;
+use super::{
+    attributes,
+    bestiary::Bestiary,
+    gamelog::Gamelog,
+    grammar::{capitalize, is_are, with_the},
+    level_stats::LevelStats,
+    morale_system,
+    particle_system::ParticleBuilder,
+    saveload_system, scores, spawner, Asleep, CombatStats, Experience, LastAttacker, LastHitBy,
+    Map, Monster, Morale, Name, Player, Portal, Position, Resistances, RunState, SufferDamage,
+};
+use rltk::{Point, RGB};
+use specs::{prelude::*, shrev::EventChannel};
 
 pub struct DamageSystem;
 
@@ -10,56 +21,275 @@ impl<'a> System<'a> for DamageSystem {
         ReadStorage<'a, Position>,
         WriteExpect<'a, Map>,
         Entities<'a>,
+        WriteStorage<'a, LastHitBy>,
+        ReadExpect<'a, Entity>,
+        WriteStorage<'a, Experience>,
+        WriteExpect<'a, Gamelog>,
+        WriteStorage<'a, Morale>,
+        WriteStorage<'a, LastAttacker>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, Resistances>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Asleep>,
+        WriteExpect<'a, LevelStats>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut damage, positions, mut map, entities) = data;
+        let (
+            mut stats,
+            mut damage,
+            positions,
+            mut map,
+            entities,
+            mut last_hit_by,
+            player_entity,
+            mut experiences,
+            mut gamelog,
+            mut morale,
+            mut last_attackers,
+            mut particle_builder,
+            resistances,
+            names,
+            mut asleep,
+            mut level_stats,
+        ) = data;
+        let mut xp_gained = 0;
 
         for (entity, mut stats, damage) in (&entities, &mut stats, &damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
+            let hp_before = stats.hp;
+            let was_alive = stats.hp > 0;
+            let percents: Vec<i32> = damage
+                .amount
+                .iter()
+                .map(|(_amount, damage_type)| {
+                    resistances
+                        .get(entity)
+                        .map_or(100, |resistances| resistances.percent(*damage_type))
+                })
+                .collect();
+            let hits: Vec<i32> = damage
+                .amount
+                .iter()
+                .zip(percents.iter())
+                .map(|((amount, _damage_type), percent)| amount * percent / 100)
+                .collect();
+            stats.hp -= hits.iter().sum::<i32>();
+            if entity == *player_entity {
+                level_stats.damage_taken += hits.iter().sum::<i32>();
+            }
+            // Nothing sleeps through being hurt
+            asleep.remove(entity);
+
+            let seen = positions
+                .get(entity)
+                .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+            if let (true, Some(name)) = (seen, names.get(entity)) {
+                let subject = capitalize(&with_the(name));
+                if percents.contains(&0) {
+                    gamelog.entries.push(format!("{subject} resists!"));
+                }
+                if percents.iter().any(|percent| *percent > 100) {
+                    gamelog
+                        .entries
+                        .push(format!("{subject} {} vulnerable!", is_are(name)));
+                }
+            }
+
+            // Being knocked below a third of their health shakes a monster, once
+            if stats.hp > 0
+                && morale_system::is_badly_wounded(stats.hp, stats.max_hp)
+                && !morale_system::is_badly_wounded(hp_before, stats.max_hp)
+            {
+                if let Some(morale) = morale.get_mut(entity) {
+                    morale.value -= morale_system::WOUND_PENALTY;
+                }
+            }
+
+            // Tougher foes are worth more
+            if was_alive && stats.hp < 1 && damage.from_player && entity != *player_entity {
+                xp_gained += stats.max_hp;
+            }
+
+            let name = damage.source.clone().unwrap_or("misadventure".to_string());
+            last_hit_by
+                .insert(entity, LastHitBy { name })
+                .expect("Unable to insert last hit");
+            match damage.attacker {
+                Some(attacker) => {
+                    last_attackers
+                        .insert(entity, LastAttacker { attacker })
+                        .expect("Unable to insert last attacker");
+                }
+                None => {
+                    last_attackers.remove(entity);
+                }
+            }
 
-            // Inserting bloodstains
             if let Some(pos) = positions.get(entity) {
+                // Inserting bloodstains
                 let idx = map.xy_idx(pos.x, pos.y);
                 map.bloodstains.insert(idx);
+
+                // Each hit gets its own number, stacked so they don't overlap
+                for (stack, amount) in hits.iter().filter(|&&a| a > 0).enumerate() {
+                    particle_builder.request_text(
+                        pos,
+                        &amount.to_string(),
+                        RGB::named(rltk::RED),
+                        stack as i32,
+                    );
+                }
             }
         }
 
         damage.clear();
+
+        if xp_gained > 0 {
+            if let Some(experience) = experiences.get_mut(*player_entity) {
+                if attributes::gain_xp(experience, xp_gained) > 0 {
+                    gamelog.entries.push(format!(
+                        "Welcome to level {}! Choose an attribute to improve.",
+                        experience.level
+                    ));
+                }
+            }
+        }
     }
 }
 
+/// Published by `delete_the_dead` for everything that dies, before it's removed from the world
+pub struct DeathEvent {
+    pub victim: Entity,
+    /// The creature or item that landed the last blow, if there was one
+    pub killer: Option<Entity>,
+    pub position: Point,
+    /// Whether the victim was a monster. Readers usually see the event after the victim has been
+    /// deleted, so they can't look this up themselves.
+    pub hostile: bool,
+}
+
+/// Publishes a `DeathEvent` for everything at zero hit points, then deletes it and leaves a
+/// corpse. The player isn't deleted: their death ends the game instead.
 pub fn delete_the_dead(ecs: &mut World) {
     let mut dead: Vec<Entity> = Vec::new();
+    let mut corpses: Vec<(Position, String)> = Vec::new();
+    let mut loot_drops: Vec<Position> = Vec::new();
+    let mut events: Vec<DeathEvent> = Vec::new();
+    let mut player_died = false;
     // Using a scope to make the borrow checker happy
     {
         let combat_stats = ecs.read_storage::<CombatStats>();
         let players = ecs.read_storage::<Player>();
         let entities = ecs.entities();
         let names = ecs.read_storage::<Name>();
+        let positions = ecs.read_storage::<Position>();
+        let last_attackers = ecs.read_storage::<LastAttacker>();
+        let monsters = ecs.read_storage::<Monster>();
+        let portals = ecs.read_storage::<Portal>();
+        let runstate = ecs.fetch::<RunState>();
+        let player_entity = ecs.fetch::<Entity>();
         let mut log = ecs.write_resource::<Gamelog>();
+        let mut bestiary = ecs.write_resource::<Bestiary>();
+        let mut level_stats = ecs.write_resource::<LevelStats>();
+        let map = ecs.fetch::<Map>();
 
         for (entity, stats) in (&entities, &combat_stats).join() {
-            if stats.hp < 1 {
-                // Check if dead entity is player
-                match players.get(entity) {
-                    None => {
-                        if let Some(victim_name) = names.get(entity) {
-                            log.entries
-                                .push(format!("{name} is dead", name = &victim_name.name))
-                        }
-                        dead.push(entity)
+            if stats.hp >= 1 {
+                continue;
+            }
+            // The player lies where they fell until the game over screen, so only report it once
+            let is_player = players.get(entity).is_some();
+            if is_player && *runstate == RunState::GameOver {
+                continue;
+            }
+
+            if let Some(pos) = positions.get(entity) {
+                events.push(DeathEvent {
+                    victim: entity,
+                    killer: last_attackers.get(entity).map(|last| last.attacker),
+                    position: Point::new(pos.x, pos.y),
+                    hostile: monsters.get(entity).is_some(),
+                });
+            }
+
+            if is_player {
+                player_died = true;
+                continue;
+            }
+            if let Some(victim_name) = names.get(entity) {
+                // Deaths the player can't see, such as monsters fighting elsewhere, go unreported
+                let seen = positions
+                    .get(entity)
+                    .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+                if seen {
+                    let fate = if portals.get(entity).is_some() {
+                        "collapses"
+                    } else {
+                        "is dead"
+                    };
+                    log.entries.push(format!("{} {fate}", &victim_name.name));
+                }
+                let killed_by_player = last_attackers
+                    .get(entity)
+                    .is_some_and(|last| last.attacker == *player_entity);
+                if killed_by_player && monsters.get(entity).is_some() {
+                    bestiary.record_kill(&victim_name.name);
+                    level_stats.kills += 1;
+                }
+                if let Some(pos) = positions.get(entity) {
+                    // A broken portal just winks out
+                    if portals.get(entity).is_none() {
+                        corpses.push((pos.clone(), victim_name.name.clone()));
                     }
-                    Some(_) => {
-                        let mut runstate = ecs.write_resource::<RunState>();
-                        *runstate = RunState::GameOver;
+                    if monsters.get(entity).is_some() {
+                        loot_drops.push(pos.clone());
                     }
                 }
             }
+            dead.push(entity)
         }
     }
 
+    // Subscribers hear about deaths while the victims still exist
+    ecs.write_resource::<EventChannel<DeathEvent>>()
+        .drain_vec_write(&mut events);
+
     for victim in dead {
         ecs.delete_entity(victim).expect("Unable to delete");
     }
+    for (pos, name) in corpses {
+        spawner::corpse(ecs, pos, &name);
+    }
+    for pos in loot_drops {
+        spawner::drop_loot(ecs, pos);
+    }
+    if player_died {
+        *ecs.write_resource::<RunState>() = RunState::GameOver;
+        scores::record_death(ecs);
+        // Death is final, so an autosave mustn't bring the run back
+        saveload_system::delete_save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::RandomNumberGenerator;
+
+    #[test]
+    fn player_death_deletes_the_save() {
+        let (mut ecs, _) = crate::new_world(RandomNumberGenerator::seeded(1));
+        saveload_system::save_game(&mut ecs).unwrap();
+        assert!(saveload_system::save_exists());
+
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<CombatStats>()
+            .get_mut(player)
+            .unwrap()
+            .hp = 0;
+        delete_the_dead(&mut ecs);
+
+        assert!(*ecs.fetch::<RunState>() == RunState::GameOver);
+        assert!(!saveload_system::save_exists());
+    }
 }
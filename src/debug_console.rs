@@ -0,0 +1,223 @@
+use super::{
+    ai_log::AiLog, dice, inventory_system, spawner, CombatStats, Item, Map, Position, State,
+    TileType, Viewshed,
+};
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode, RGB};
+use specs::prelude::*;
+
+/// Most AI log entries the `ai` command shows at once, as many as fit over the map
+const MAX_AI_LOG_LINES: usize = 40;
+
+/// Text typed into the debug console and the result of the last command. The output can run to
+/// several lines.
+#[derive(Default)]
+pub struct DebugConsole {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ConsoleResult {
+    Open,
+    Close,
+}
+
+/// Draws the console over the top of the map and handles typing into it
+pub fn debug_console(gs: &mut State, ctx: &mut Rltk) -> ConsoleResult {
+    let result = match ctx.key {
+        None => ConsoleResult::Open,
+        Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::Grave) => ConsoleResult::Close,
+        Some(VirtualKeyCode::Return) => {
+            let input = std::mem::take(&mut gs.ecs.fetch_mut::<DebugConsole>().input);
+            let output = match run_command(&mut gs.ecs, &input) {
+                Ok(output) => output,
+                Err(error) => format!("Error: {error}"),
+            };
+            gs.ecs.fetch_mut::<DebugConsole>().output = output;
+            ConsoleResult::Open
+        }
+        Some(VirtualKeyCode::Back) => {
+            gs.ecs.fetch_mut::<DebugConsole>().input.pop();
+            ConsoleResult::Open
+        }
+        Some(key) => {
+            if let Some(c) = key_to_char(key, ctx.shift) {
+                gs.ecs.fetch_mut::<DebugConsole>().input.push(c);
+            }
+            ConsoleResult::Open
+        }
+    };
+
+    let console = gs.ecs.fetch::<DebugConsole>();
+    let output_lines = console.output.lines().count().max(1) as i32;
+    ctx.draw_box(
+        0,
+        0,
+        79,
+        2 + output_lines,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        2,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Debug Console",
+    );
+    ctx.print(2, 1, format!("> {}_", console.input));
+    for (i, line) in console.output.lines().enumerate() {
+        ctx.print_color(
+            2,
+            2 + i as i32,
+            RGB::named(rltk::GREY),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+
+    result
+}
+
+fn key_to_char(key: VirtualKeyCode, shift: bool) -> Option<char> {
+    use VirtualKeyCode::*;
+    let letter = rltk::letter_to_option(key);
+    if letter > -1 {
+        return char::from_u32(97 + letter as u32);
+    }
+    let c = match key {
+        Key0 | Numpad0 => '0',
+        Key1 | Numpad1 => '1',
+        Key2 | Numpad2 => '2',
+        Key3 | Numpad3 => '3',
+        Key4 | Numpad4 => '4',
+        Key5 | Numpad5 => '5',
+        Key6 | Numpad6 => '6',
+        Key7 | Numpad7 => '7',
+        Key8 | Numpad8 => '8',
+        Key9 | Numpad9 => '9',
+        Space => ' ',
+        Minus if shift => '_',
+        Minus | NumpadSubtract => '-',
+        Equals if shift => '+',
+        NumpadAdd => '+',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Parses and runs one console command. Returns the line to show in the console.
+fn run_command(ecs: &mut World, input: &str) -> Result<String, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    match words.as_slice() {
+        [] => Ok(String::new()),
+        ["spawn", name @ ..] => {
+            let name = resolve_name(name)?;
+            let pos = free_tile_near_player(ecs).ok_or("No free tile next to the player")?;
+            spawner::spawn_named(ecs, name, pos);
+            Ok(format!("Spawned {name}"))
+        }
+        ["give", name @ ..] => {
+            let name = resolve_name(name)?;
+            let player_pos = *ecs.fetch::<Point>();
+            let pos = Position {
+                x: player_pos.x,
+                y: player_pos.y,
+            };
+            let entity = spawner::spawn_named(ecs, name, pos).ok_or("Unable to spawn")?;
+            if ecs.read_storage::<Item>().get(entity).is_none() {
+                ecs.delete_entity(entity).expect("Unable to delete entity");
+                return Err(format!("{name} is not an item"));
+            }
+            let player_entity = *ecs.fetch::<Entity>();
+            inventory_system::stow(ecs, entity, player_entity);
+            Ok(format!("Added {name} to your backpack"))
+        }
+        ["tp", x, y] => {
+            let x: i32 = x.parse().map_err(|_| format!("'{x}' is not a number"))?;
+            let y: i32 = y.parse().map_err(|_| format!("'{y}' is not a number"))?;
+            teleport_player(ecs, x, y)?;
+            Ok(format!("Teleported to {x}, {y}"))
+        }
+        ["heal"] => {
+            let player_entity = *ecs.fetch::<Entity>();
+            if let Some(stats) = ecs.write_storage::<CombatStats>().get_mut(player_entity) {
+                stats.hp = stats.max_hp;
+            }
+            Ok("Healed to full".to_string())
+        }
+        ["roll", expr @ ..] => {
+            let expr = expr.join("");
+            let result = dice::roll_str(&mut ecs.fetch_mut::<RandomNumberGenerator>(), &expr)?;
+            Ok(format!("{expr} rolled {result}"))
+        }
+        ["reveal"] => {
+            let mut map = ecs.fetch_mut::<Map>();
+            for revealed in map.revealed_tiles.iter_mut() {
+                *revealed = true;
+            }
+            Ok("Map revealed".to_string())
+        }
+        ["ai", count @ ..] => {
+            let count = match count {
+                [] => MAX_AI_LOG_LINES,
+                [count] => count
+                    .parse::<usize>()
+                    .map_err(|_| format!("'{count}' is not a number"))?
+                    .min(MAX_AI_LOG_LINES),
+                _ => return Err("Usage: ai [count]".to_string()),
+            };
+            let log = ecs.fetch::<AiLog>();
+            let lines: Vec<&str> = log.latest(count).map(String::as_str).collect();
+            if lines.is_empty() {
+                Ok("No monster has acted yet".to_string())
+            } else {
+                Ok(lines.join("\n"))
+            }
+        }
+        [command, ..] => Err(format!(
+            "Unknown command '{command}'. Try spawn, give, tp, heal, roll, reveal or ai"
+        )),
+    }
+}
+
+/// Matches typed words like `health_potion` against the names the spawner knows
+fn resolve_name(words: &[&str]) -> Result<&'static str, String> {
+    let wanted = words.join(" ").replace('_', " ");
+    spawner::spawnable_names()
+        .find(|name| name.eq_ignore_ascii_case(&wanted))
+        .ok_or(format!("Nothing called '{wanted}' can be spawned"))
+}
+
+fn free_tile_near_player(ecs: &World) -> Option<Position> {
+    let player_pos = *ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    (-1..=1)
+        .flat_map(|dy| (-1..=1).map(move |dx| (player_pos.x + dx, player_pos.y + dy)))
+        .filter(|&(x, y)| x > 0 && x < map.width - 1 && y > 0 && y < map.height - 1)
+        .find(|&(x, y)| !map.blocked[map.xy_idx(x, y)] && map.entities_at(x, y).is_empty())
+        .map(|(x, y)| Position { x, y })
+}
+
+fn teleport_player(ecs: &mut World, x: i32, y: i32) -> Result<(), String> {
+    {
+        let map = ecs.fetch::<Map>();
+        if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+            return Err(format!("{x}, {y} is outside the map"));
+        }
+        if map.tiles[map.xy_idx(x, y)] == TileType::Wall {
+            return Err(format!("{x}, {y} is a wall"));
+        }
+    }
+
+    let player_entity = *ecs.fetch::<Entity>();
+    *ecs.write_resource::<Point>() = Point::new(x, y);
+    if let Some(pos) = ecs.write_storage::<Position>().get_mut(player_entity) {
+        pos.x = x;
+        pos.y = y;
+    }
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+        viewshed.dirty = true;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use rltk::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// A dice expression like `3d6-1`: roll `count` dice with `sides` sides each and add `bonus`.
+/// The count and the modifier are optional, so `d20` and `1d8` are fine. A bare number like
+/// `5` is a fixed amount. Item and trap damage is rolled from these. Melee damage isn't, as it
+/// comes from power and defense.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct DiceExpr {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl DiceExpr {
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> i32 {
+        if self.count > 0 {
+            rng.roll_dice(self.count, self.sides) + self.bonus
+        } else {
+            self.bonus
+        }
+    }
+}
+
+impl FromStr for DiceExpr {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let expr = expr.trim();
+        let invalid = || format!("'{expr}' is not a dice expression like 2d6+1");
+        let number = |text: &str| text.trim().parse::<i32>().map_err(|_| invalid());
+
+        let Some((count, rest)) = expr.split_once(['d', 'D']) else {
+            return Ok(DiceExpr {
+                count: 0,
+                sides: 0,
+                bonus: number(expr)?,
+            });
+        };
+        let count = if count.trim().is_empty() {
+            1
+        } else {
+            number(count)?
+        };
+        let (sides, bonus) = match rest.find(['+', '-']) {
+            None => (number(rest)?, 0),
+            Some(sign) => {
+                let bonus = number(&rest[sign + 1..])?;
+                let bonus = if rest[sign..].starts_with('-') {
+                    -bonus
+                } else {
+                    bonus
+                };
+                (number(&rest[..sign])?, bonus)
+            }
+        };
+
+        if count < 1 || sides < 1 {
+            return Err(invalid());
+        }
+        Ok(DiceExpr {
+            count,
+            sides,
+            bonus,
+        })
+    }
+}
+
+impl fmt::Display for DiceExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "{}", self.bonus);
+        }
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.bonus {
+            0 => Ok(()),
+            bonus if bonus > 0 => write!(f, "+{bonus}"),
+            bonus => write!(f, "{bonus}"),
+        }
+    }
+}
+
+/// Parses and rolls `expr` in one go, e.g. `roll_str(rng, "2d6+2")`
+pub fn roll_str(rng: &mut RandomNumberGenerator, expr: &str) -> Result<i32, String> {
+    Ok(expr.parse::<DiceExpr>()?.roll(rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiceExpr;
+
+    fn parse(expr: &str) -> Result<DiceExpr, String> {
+        expr.parse()
+    }
+
+    fn dice(count: i32, sides: i32, bonus: i32) -> DiceExpr {
+        DiceExpr {
+            count,
+            sides,
+            bonus,
+        }
+    }
+
+    #[test]
+    fn parses_dice_expressions() {
+        assert_eq!(parse("1d8"), Ok(dice(1, 8, 0)));
+        assert_eq!(parse("3d6-1"), Ok(dice(3, 6, -1)));
+        assert_eq!(parse("d20"), Ok(dice(1, 20, 0)));
+        assert_eq!(parse("2D6+2"), Ok(dice(2, 6, 2)));
+        assert_eq!(parse(" 5 "), Ok(dice(0, 0, 5)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "", "d", "abc", "2d", "0d6", "2d0", "-1d6", "2d6+", "2x6", "1d8+x",
+        ] {
+            assert!(parse(expr).is_err(), "{expr:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for expr in ["1d8", "3d6-1", "2d6+2", "5"] {
+            assert_eq!(
+                parse(expr).map(|dice| dice.to_string()),
+                Ok(expr.to_string())
+            );
+        }
+    }
+}
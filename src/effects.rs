@@ -0,0 +1,272 @@
+use super::{
+    particle_system::{ParticleBuilder, PROJECTILE_STEP_MS},
+    settings::GameSettings,
+    ActiveEffect, Burning, CombatStats, DamageType, HungerClock, HungerState, Map, Name, Position,
+    StatusEffects, SufferDamage,
+};
+use rltk::{FontCharType, LineAlg, Point, RGB};
+use specs::prelude::*;
+use std::collections::VecDeque;
+
+/// Something that happens to an entity or a tile. Systems queue these instead of applying
+/// them inline, so items, traps and abilities share one implementation of each effect.
+pub enum EffectType {
+    Damage {
+        amount: i32,
+        damage_type: DamageType,
+    },
+    Healing {
+        amount: i32,
+    },
+    /// Adds to, or refreshes, the target's status effects
+    Status {
+        effect: ActiveEffect,
+    },
+    WellFed,
+    Fire {
+        turns: i32,
+    },
+    Particle {
+        glyph: FontCharType,
+        fg: RGB,
+        bg: RGB,
+        lifetime: f32,
+    },
+    /// Flies tile by tile from the creator to the target tile. Purely cosmetic.
+    Projectile {
+        glyph: FontCharType,
+        fg: RGB,
+    },
+}
+
+impl EffectType {
+    /// Tile effects change the map itself rather than whoever is standing on it
+    fn affects_tile(&self) -> bool {
+        matches!(
+            self,
+            EffectType::Fire { .. } | EffectType::Particle { .. } | EffectType::Projectile { .. }
+        )
+    }
+}
+
+pub enum Targets {
+    Single { target: Entity },
+    TargetList { targets: Vec<Entity> },
+    Tile { tile_idx: usize },
+    Tiles { tiles: Vec<usize> },
+}
+
+pub struct EffectRequest {
+    pub creator: Option<Entity>,
+    pub effect_type: EffectType,
+    pub targets: Targets,
+}
+
+#[derive(Default)]
+pub struct EffectQueue {
+    requests: VecDeque<EffectRequest>,
+}
+
+impl EffectQueue {
+    pub fn add_effect(
+        &mut self,
+        creator: Option<Entity>,
+        effect_type: EffectType,
+        targets: Targets,
+    ) {
+        self.requests.push_back(EffectRequest {
+            creator,
+            effect_type,
+            targets,
+        });
+    }
+}
+
+/// Applies every queued effect, in the order they were queued
+pub fn run_effects_queue(ecs: &mut World) {
+    loop {
+        let request = ecs.write_resource::<EffectQueue>().requests.pop_front();
+        match request {
+            None => break,
+            Some(request) => apply_effect(ecs, &request),
+        }
+    }
+}
+
+fn apply_effect(ecs: &mut World, request: &EffectRequest) {
+    match &request.targets {
+        Targets::Single { target } => affect_entity(ecs, request, *target),
+        Targets::TargetList { targets } => {
+            for target in targets.iter() {
+                affect_entity(ecs, request, *target);
+            }
+        }
+        Targets::Tile { tile_idx } => affect_tile(ecs, request, *tile_idx),
+        Targets::Tiles { tiles } => {
+            for tile_idx in tiles.iter() {
+                affect_tile(ecs, request, *tile_idx);
+            }
+        }
+    }
+}
+
+/// Applies a tile effect to the tile, or an entity effect to everyone standing on it
+fn affect_tile(ecs: &mut World, request: &EffectRequest, tile_idx: usize) {
+    if request.effect_type.affects_tile() {
+        apply_to_tile(ecs, request, tile_idx);
+        return;
+    }
+
+    let content = ecs.fetch::<Map>().tile_content[tile_idx].clone();
+    for entity in content {
+        apply_to_entity(ecs, request, entity);
+    }
+}
+
+/// Applies an entity effect to the entity, or a tile effect to the tile it stands on
+fn affect_entity(ecs: &mut World, request: &EffectRequest, target: Entity) {
+    if !request.effect_type.affects_tile() {
+        apply_to_entity(ecs, request, target);
+        return;
+    }
+
+    let tile_idx = {
+        let positions = ecs.read_storage::<Position>();
+        let map = ecs.fetch::<Map>();
+        positions.get(target).map(|pos| map.xy_idx(pos.x, pos.y))
+    };
+    if let Some(tile_idx) = tile_idx {
+        apply_to_tile(ecs, request, tile_idx);
+    }
+}
+
+fn apply_to_entity(ecs: &mut World, request: &EffectRequest, target: Entity) {
+    match &request.effect_type {
+        EffectType::Damage {
+            amount,
+            damage_type,
+        } => {
+            if ecs.read_storage::<CombatStats>().get(target).is_some() {
+                let names = ecs.read_storage::<Name>();
+                let source = request
+                    .creator
+                    .and_then(|creator| names.get(creator))
+                    .map(|name| name.name.as_str());
+                let from_player = request.creator == Some(*ecs.fetch::<Entity>());
+                let mut suffer_damage = ecs.write_storage::<SufferDamage>();
+                SufferDamage::new_damage(
+                    &mut suffer_damage,
+                    target,
+                    *amount,
+                    *damage_type,
+                    source,
+                    request.creator,
+                    from_player,
+                );
+            }
+        }
+        EffectType::Healing { amount } => {
+            let healed = match ecs.write_storage::<CombatStats>().get_mut(target) {
+                Some(stats) => {
+                    let hp_before = stats.hp;
+                    stats.hp = i32::min(stats.max_hp, stats.hp + amount);
+                    stats.hp - hp_before
+                }
+                None => 0,
+            };
+            if let Some(pos) = ecs
+                .read_storage::<Position>()
+                .get(target)
+                .filter(|_| healed > 0)
+            {
+                ecs.fetch_mut::<ParticleBuilder>().request_text(
+                    pos,
+                    &format!("+{healed}"),
+                    RGB::named(rltk::GREEN),
+                    0,
+                );
+            }
+        }
+        EffectType::Status { effect } => {
+            let mut statuses = ecs.write_storage::<StatusEffects>();
+            match statuses.get_mut(target) {
+                Some(status) => status.apply(effect.clone()),
+                None => {
+                    statuses
+                        .insert(
+                            target,
+                            StatusEffects {
+                                effects: vec![effect.clone()],
+                            },
+                        )
+                        .expect("Unable to insert status");
+                }
+            }
+        }
+        EffectType::WellFed => {
+            if let Some(hc) = ecs.write_storage::<HungerClock>().get_mut(target) {
+                hc.state = HungerState::WellFed;
+                hc.duration = 20;
+            }
+        }
+        EffectType::Fire { .. } | EffectType::Particle { .. } | EffectType::Projectile { .. } => {}
+    }
+}
+
+fn apply_to_tile(ecs: &mut World, request: &EffectRequest, tile_idx: usize) {
+    match &request.effect_type {
+        EffectType::Fire { turns } => {
+            let mut map = ecs.fetch_mut::<Map>();
+            if map.tiles[tile_idx].can_burn() {
+                map.burning.insert(tile_idx, Burning { turns: *turns });
+            }
+        }
+        EffectType::Particle {
+            glyph,
+            fg,
+            bg,
+            lifetime,
+        } => {
+            let pos = {
+                let map = ecs.fetch::<Map>();
+                Position {
+                    x: tile_idx as i32 % map.width,
+                    y: tile_idx as i32 / map.width,
+                }
+            };
+            ecs.fetch_mut::<ParticleBuilder>()
+                .request(pos, *fg, *bg, *glyph, *lifetime);
+        }
+        EffectType::Projectile { glyph, fg } => {
+            if ecs.fetch::<GameSettings>().fast_animations {
+                return;
+            }
+            let positions = ecs.read_storage::<Position>();
+            let Some(from) = request.creator.and_then(|creator| positions.get(creator)) else {
+                return;
+            };
+            let map = ecs.fetch::<Map>();
+            let to = Point::new(tile_idx as i32 % map.width, tile_idx as i32 / map.width);
+            let mut particle_builder = ecs.fetch_mut::<ParticleBuilder>();
+            // The first point is the thrower's own tile
+            for (step, point) in rltk::line2d(LineAlg::Bresenham, Point::new(from.x, from.y), to)
+                .iter()
+                .skip(1)
+                .enumerate()
+            {
+                particle_builder.request_with_delay(
+                    step as f32 * PROJECTILE_STEP_MS,
+                    Position {
+                        x: point.x,
+                        y: point.y,
+                    },
+                    *fg,
+                    RGB::named(rltk::BLACK),
+                    *glyph,
+                    PROJECTILE_STEP_MS,
+                );
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,87 @@
+use super::{
+    gamelog::Gamelog, CombatStats, Encumbered, Equipped, InBackpack, Owned, Stack, Weight,
+};
+use specs::{prelude::*, storage::MaskedStorage};
+use std::ops::Deref;
+
+/// Pounds that can be carried per point of power
+const CAPACITY_PER_POWER: f32 = 10.0;
+
+pub fn carry_capacity(stats: &CombatStats) -> f32 {
+    stats.power as f32 * CAPACITY_PER_POWER
+}
+
+/// Total weight of everything `owner` holds in `owned`, e.g. their backpack or worn equipment,
+/// counting every item in a stack
+pub fn owned_weight<T, D, S>(
+    owner: Entity,
+    owned: &Storage<T, D>,
+    weights: &ReadStorage<Weight>,
+    stacks: &Storage<Stack, S>,
+) -> f32
+where
+    T: Owned + Component,
+    D: Deref<Target = MaskedStorage<T>>,
+    S: Deref<Target = MaskedStorage<Stack>>,
+{
+    (owned, weights, stacks.maybe())
+        .join()
+        .filter(|(item, _weight, _stack)| item.owned_by(&owner))
+        .map(|(_item, weight, stack)| weight.pounds * stack.map_or(1, |s| s.count) as f32)
+        .sum()
+}
+
+pub struct EncumbranceSystem;
+
+impl<'a> System<'a> for EncumbranceSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, Gamelog>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Weight>,
+        WriteStorage<'a, Encumbered>,
+        ReadStorage<'a, Stack>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            mut gamelog,
+            combat_stats,
+            backpack,
+            equipped,
+            weights,
+            mut encumbered,
+            stacks,
+        ) = data;
+
+        for (entity, stats) in (&entities, &combat_stats).join() {
+            let carried = owned_weight(entity, &backpack, &weights, &stacks)
+                + owned_weight(entity, &equipped, &weights, &stacks);
+            let overloaded = carried > carry_capacity(stats);
+            let was_overloaded = encumbered.get(entity).is_some();
+
+            if overloaded && !was_overloaded {
+                encumbered
+                    .insert(entity, Encumbered {})
+                    .expect("Unable to insert encumbrance");
+                if entity == *player_entity {
+                    gamelog
+                        .entries
+                        .push("You are overburdened, and slow down.".to_string());
+                }
+            } else if !overloaded && was_overloaded {
+                encumbered.remove(entity);
+                if entity == *player_entity {
+                    gamelog
+                        .entries
+                        .push("You are no longer overburdened.".to_string());
+                }
+            }
+        }
+    }
+}
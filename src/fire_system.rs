@@ -0,0 +1,112 @@
+use super::{
+    effects::{EffectQueue, EffectType, Targets},
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    Burning, CombatStats, DamageType, Map, Position, RunState,
+};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+
+const FIRE_DAMAGE: i32 = 2;
+const FIRE_SPREAD_CHANCE: i32 = 25;
+
+pub struct FireSystem;
+
+impl<'a> System<'a> for FireSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, Gamelog>,
+        WriteExpect<'a, EffectQueue>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, CombatStats>,
+        ReadExpect<'a, GlyphSet>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut map,
+            runstate,
+            player_entity,
+            mut rng,
+            mut gamelog,
+            mut effects,
+            entities,
+            positions,
+            combat_stats,
+            glyphs,
+        ) = data;
+
+        // Fire ticks once per round
+        if *runstate != RunState::MonsterTurn || map.burning.is_empty() {
+            return;
+        }
+
+        // Burn whoever is standing in the flames
+        for (entity, pos, _stats) in (&entities, &positions, &combat_stats).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            if !map.burning.contains_key(&idx) {
+                continue;
+            }
+            effects.add_effect(
+                None,
+                EffectType::Damage {
+                    amount: FIRE_DAMAGE,
+                    damage_type: DamageType::Fire,
+                },
+                Targets::Single { target: entity },
+            );
+            effects.add_effect(
+                None,
+                EffectType::Particle {
+                    glyph: glyphs.get(glyphs::FLAMES),
+                    fg: rltk::RGB::named(rltk::ORANGE),
+                    bg: rltk::RGB::named(rltk::BLACK),
+                    lifetime: 200.0,
+                },
+                Targets::Tile { tile_idx: idx },
+            );
+            if entity == *player_entity {
+                gamelog.entries.push("You are burned!".to_string());
+            }
+        }
+
+        // Spread to neighbouring floor with a shorter duration, so every fire eventually burns out
+        let mut spread: Vec<(usize, Burning)> = Vec::new();
+        for (idx, burning) in map.burning.iter() {
+            if burning.turns < 2 {
+                continue;
+            }
+            let x = *idx as i32 % map.width;
+            let y = *idx as i32 / map.width;
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 {
+                    continue;
+                }
+                let neighbour = map.xy_idx(nx, ny);
+                if map.tiles[neighbour].is_floor()
+                    && !map.burning.contains_key(&neighbour)
+                    && rng.roll_dice(1, 100) <= FIRE_SPREAD_CHANCE
+                {
+                    spread.push((
+                        neighbour,
+                        Burning {
+                            turns: burning.turns - 1,
+                        },
+                    ));
+                }
+            }
+        }
+
+        map.burning.retain(|_idx, burning| {
+            burning.turns -= 1;
+            burning.turns > 0
+        });
+        for (idx, burning) in spread {
+            map.burning.entry(idx).or_insert(burning);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use rltk::FontCharType;
+use std::collections::HashMap;
+
+/// Something drawn on the map. The wrapped character is how it looks in the default CP437 font.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct Glyph(char);
+
+// Creatures
+pub const PLAYER: Glyph = Glyph('@');
+pub const DOG: Glyph = Glyph('d');
+pub const SHOPKEEPER: Glyph = Glyph('@');
+pub const ORC: Glyph = Glyph('o');
+pub const GOBLIN: Glyph = Glyph('g');
+pub const GHOST: Glyph = Glyph('G');
+pub const EEL: Glyph = Glyph('e');
+pub const PORTAL: Glyph = Glyph('Ω');
+pub const CORPSE: Glyph = Glyph('%');
+
+// Items
+pub const POTION: Glyph = Glyph('¡');
+pub const SCROLL: Glyph = Glyph(')');
+pub const FLASK: Glyph = Glyph('!');
+pub const WEAPON: Glyph = Glyph('/');
+pub const SHIELD: Glyph = Glyph('(');
+pub const FOOD: Glyph = Glyph('%');
+pub const TORCH: Glyph = Glyph('☼');
+pub const RING: Glyph = Glyph('=');
+pub const TRAP: Glyph = Glyph('^');
+pub const GOLD: Glyph = Glyph('$');
+
+// Tiles
+pub const FLOOR: Glyph = Glyph('.');
+pub const GRASS: Glyph = Glyph('"');
+pub const TREE: Glyph = Glyph('♣');
+pub const DOWN_STAIRS: Glyph = Glyph('>');
+pub const EXIT: Glyph = Glyph('∩');
+pub const RUBBLE: Glyph = Glyph(';');
+pub const SHALLOW_WATER: Glyph = Glyph('~');
+pub const DEEP_WATER: Glyph = Glyph('≈');
+
+// Walls, joined up with their revealed neighbours
+pub const WALL: Glyph = Glyph('#');
+pub const WALL_PILLAR: Glyph = Glyph('○');
+pub const WALL_NS: Glyph = Glyph('║');
+pub const WALL_EW: Glyph = Glyph('═');
+pub const WALL_NW: Glyph = Glyph('╝');
+pub const WALL_SW: Glyph = Glyph('╗');
+pub const WALL_NE: Glyph = Glyph('╚');
+pub const WALL_SE: Glyph = Glyph('╔');
+pub const WALL_NSW: Glyph = Glyph('╣');
+pub const WALL_NSE: Glyph = Glyph('╠');
+pub const WALL_NEW: Glyph = Glyph('╩');
+pub const WALL_SEW: Glyph = Glyph('╦');
+pub const WALL_NSEW: Glyph = Glyph('╬');
+
+// Particles
+pub const HIT: Glyph = Glyph('‼');
+pub const BLAST: Glyph = Glyph('░');
+pub const HEAL: Glyph = Glyph('♥');
+pub const CONFUSED: Glyph = Glyph('?');
+pub const RAGE: Glyph = Glyph('!');
+pub const FLAMES: Glyph = Glyph('▲');
+pub const PROJECTILE: Glyph = Glyph('*');
+
+/// Decides what each glyph is drawn as. Anything without an override is drawn as its CP437
+/// character, so a graphical tileset only has to fill in the overrides.
+#[derive(Default)]
+pub struct GlyphSet {
+    overrides: HashMap<Glyph, FontCharType>,
+}
+
+impl GlyphSet {
+    pub fn get(&self, glyph: Glyph) -> FontCharType {
+        self.overrides
+            .get(&glyph)
+            .copied()
+            .unwrap_or_else(|| rltk::to_cp437(glyph.0))
+    }
+}
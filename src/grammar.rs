@@ -0,0 +1,56 @@
+use super::Name;
+
+/// Plural if the head noun, the part before any "of ...", ends in a lone 's', as in "Rations".
+/// Good enough for the names the game uses.
+pub fn is_plural(name: &Name) -> bool {
+    let head = name.name.split(" of ").next().unwrap_or(&name.name);
+    head.ends_with('s') && !head.ends_with("ss")
+}
+
+/// The indefinite article for a name: "a", "an", or "some" for plurals. Proper nouns take none.
+pub fn article(name: &Name) -> &'static str {
+    if name.proper {
+        ""
+    } else if is_plural(name) {
+        "some"
+    } else if name.name.starts_with(|c: char| "aeiouAEIOU".contains(c)) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+/// The name with its indefinite article, as in "a Dagger" or "some Rations"
+pub fn with_article(name: &Name) -> String {
+    match article(name) {
+        "" => name.name.clone(),
+        article => format!("{article} {}", name.name),
+    }
+}
+
+/// The name with "the" in front, unless it's a proper noun
+pub fn with_the(name: &Name) -> String {
+    if name.proper {
+        name.name.clone()
+    } else {
+        format!("the {}", name.name)
+    }
+}
+
+/// "is" or "are", to agree with the name
+pub fn is_are(name: &Name) -> &'static str {
+    if is_plural(name) {
+        "are"
+    } else {
+        "is"
+    }
+}
+
+/// Upper-cases the first letter, for names that start a sentence
+pub fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+    }
+}
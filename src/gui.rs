@@ -1,9 +1,30 @@
 use super::{
-    gamelog::Gamelog, CombatStats, Equipped, Hidden, HungerClock, HungerState, InBackpack, Map,
-    Name, Owned, Player, Position, RunState, State, Viewshed, MAPHEIGHT, MAPWIDTH,
+    ambient_system,
+    attributes::{xp_to_next_level, Attribute},
+    bestiary::Bestiary,
+    classes::{class_definitions, PlayerClass},
+    gamelog::Gamelog,
+    level_stats::LevelSummary,
+    master_dungeon_map::MasterDungeonMap,
+    AoeShape, AreaOfEffect, Asleep, CombatStats, Confusion, Consumable, DamageType, DefenseBonus,
+    Digger, Durability, Encumbered, EquipmentSlot, Equippable, Equipped, Experience, Fragile, Gold,
+    Hidden, HungerClock, HungerState, Identifier, InBackpack, InflictsDamage, Item, Knockback,
+    LightSource, MagicMapper, Map, MassEffect, MeleePowerBonus, Monster, Name, Owned,
+    ParticleLifetime, Pet, Phasing, Player, Position, ProvidesFood, ProvidesHealing, Rage, Ranged,
+    Reach, Renderable, RunState, SightBonus, Stack, StartsFire, State, StatusEffects, TurnCounter,
+    TwoHanded, Value, Vendor, Viewshed, Weakness, Weight, MAPHEIGHT, MAPWIDTH,
 };
+use crate::encumbrance_system::{carry_capacity, owned_weight};
+use crate::inventory_system::{aoe_tiles, identifiable_items};
+use crate::melee_combat_system::predict_melee;
+use crate::player::{
+    equippable_items_here, key_name, key_to_action, keymap, throwable_items, usable_items_here,
+    Action,
+};
+use crate::settings::{self, GameSettings};
 use rltk::{Point, Rltk, VirtualKeyCode, RGB};
 use specs::prelude::*;
+use std::fmt;
 
 pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
     ctx.draw_box(
@@ -38,73 +59,236 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
     let orange = RGB::named(rltk::ORANGE);
     let white = RGB::named(rltk::WHITE);
 
-    for (_player, stats, hc) in (&players, &combat_stats, &hunger).join() {
-        let health = format!(
-            " HP: {hp} / {max_hp} ",
-            hp = stats.hp,
-            max_hp = stats.max_hp
-        );
-        ctx.print_color(12, 43, yellow, black, &health);
+    if ecs.fetch::<GameSettings>().status_line {
+        ctx.print_color(2, 43, yellow, black, status_line(ecs));
+    } else {
+        for (_player, stats, hc) in (&players, &combat_stats, &hunger).join() {
+            let health = format!(
+                " HP: {hp} / {max_hp} ",
+                hp = stats.hp,
+                max_hp = stats.max_hp
+            );
+            ctx.print_color(12, 43, yellow, black, &health);
 
-        ctx.draw_bar_horizontal(28, 43, 51, stats.hp, stats.max_hp, red, black);
+            ctx.draw_bar_horizontal(28, 43, 51, stats.hp, stats.max_hp, red, black);
 
-        match hc.state {
-            HungerState::WellFed => ctx.print_color(71, 42, green, black, "Well Fed"),
-            HungerState::Normal => ctx.print_color(71, 42, white, black, "Normal"),
-            HungerState::Hungry => ctx.print_color(71, 42, orange, black, "Hungry"),
-            HungerState::Starving => ctx.print_color(71, 42, red, black, "Starving"),
+            match hc.state {
+                HungerState::WellFed => ctx.print_color(71, 42, green, black, "Well Fed"),
+                HungerState::Normal => ctx.print_color(71, 42, white, black, "Normal"),
+                HungerState::Hungry => ctx.print_color(71, 42, orange, black, "Hungry"),
+                HungerState::Starving => ctx.print_color(71, 42, red, black, "Starving"),
+            }
         }
-    }
 
-    let map = ecs.fetch::<Map>();
-    let depth = format!("Depth: {depth}", depth = map.depth);
-    ctx.print_color(2, 43, yellow, black, &depth);
+        let map = ecs.fetch::<Map>();
+        let depth = format!("Depth: {depth}", depth = map.depth);
+        ctx.print_color(2, 43, yellow, black, &depth);
+
+        let player_entity = ecs.fetch::<Entity>();
+        if let Some(gold) = ecs.read_storage::<Gold>().get(*player_entity) {
+            ctx.print_color(30, 42, yellow, black, format!("Gold: {}", gold.amount));
+        }
+        if let Some(experience) = ecs.read_storage::<Experience>().get(*player_entity) {
+            let level = format!(
+                "Level {level} ({xp}/{next} XP)",
+                level = experience.level,
+                xp = experience.xp,
+                next = xp_to_next_level(experience.level)
+            );
+            ctx.print_color(2, 42, yellow, black, &level);
+        }
+        if let Some((pet, name)) = (&ecs.read_storage::<Pet>(), &ecs.read_storage::<Name>())
+            .join()
+            .next()
+        {
+            let orders = format!("{}: {}", name.name, pet.orders.name());
+            ctx.print_color(45, 42, yellow, black, &orders);
+        }
+    }
 
     // Draw mouse cursor
     let mouse_pos = ctx.mouse_pos();
     ctx.set_bg(mouse_pos.0, mouse_pos.1, magenta);
-    draw_tooltips(ecs, ctx);
+    draw_tooltips(ecs, ctx, mouse_pos);
+}
+
+/// Everything the player needs at a glance on one line, e.g. `HP 12/30 D3 L2 T418 $57 Hungry
+/// Conf Enc`. Gold and hunger are left out for players without them, and the line is cut short
+/// to fit inside the log box.
+fn status_line(ecs: &World) -> String {
+    let player_entity = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let mut segments: Vec<String> = Vec::new();
+
+    if let Some(stats) = ecs.read_storage::<CombatStats>().get(player_entity) {
+        segments.push(format!("HP {}/{}", stats.hp, stats.max_hp));
+    }
+    segments.push(format!("D{}", map.depth));
+    if let Some(experience) = ecs.read_storage::<Experience>().get(player_entity) {
+        segments.push(format!("L{}", experience.level));
+    }
+    segments.push(format!("T{}", ecs.fetch::<TurnCounter>().turns));
+    if let Some(gold) = ecs.read_storage::<Gold>().get(player_entity) {
+        segments.push(format!("${}", gold.amount));
+    }
+    if let Some(pet) = ecs.read_storage::<Pet>().join().next() {
+        segments.push(format!("Pet {}", pet.orders.name()));
+    }
+    if let Some(hc) = ecs.read_storage::<HungerClock>().get(player_entity) {
+        segments.push(
+            match hc.state {
+                HungerState::WellFed => "Fed",
+                HungerState::Normal => "Norm",
+                HungerState::Hungry => "Hungry",
+                HungerState::Starving => "Starving",
+            }
+            .to_string(),
+        );
+    }
+
+    // Status effects
+    if let Some(status) = ecs.read_storage::<StatusEffects>().get(player_entity) {
+        for effect in status.effects.iter() {
+            segments.push(effect.kind.abbreviation().to_string());
+        }
+    }
+    if ecs
+        .read_storage::<Encumbered>()
+        .get(player_entity)
+        .is_some()
+    {
+        segments.push("Enc".to_string());
+    }
+    if ambient_system::player_is_chilled(ecs) {
+        segments.push("Chill".to_string());
+    }
+    if let Some(pos) = ecs.read_storage::<Position>().get(player_entity) {
+        if map.burning.contains_key(&map.xy_idx(pos.x, pos.y)) {
+            segments.push("Burn".to_string());
+        }
+    }
+
+    segments.join(" ").chars().take(MAPWIDTH - 4).collect()
+}
+
+/// Draws a 3-cell health bar above every visible monster, clipped to the play area.
+pub fn draw_monster_hp_bars(ecs: &World, ctx: &mut Rltk) {
+    const BAR_WIDTH: i32 = 3;
+    let map = ecs.fetch::<Map>();
+    let monsters = ecs.read_storage::<Monster>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let positions = ecs.read_storage::<Position>();
+    let phasing = ecs.read_storage::<Phasing>();
+
+    for (_monster, stats, pos, phasing) in
+        (&monsters, &combat_stats, &positions, phasing.maybe()).join()
+    {
+        // Monsters on the top row have nowhere to put their bar
+        let idx = map.xy_idx(pos.x, pos.y);
+        if pos.y < 1 || !map.visible_tiles[idx] || !map.shows_entity(idx, phasing.is_some()) {
+            continue;
+        }
+
+        let fraction = stats.hp as f32 / stats.max_hp as f32;
+        let color = if fraction > 0.66 {
+            RGB::named(rltk::GREEN)
+        } else if fraction > 0.33 {
+            RGB::named(rltk::YELLOW)
+        } else {
+            RGB::named(rltk::RED)
+        };
+        let filled = (fraction * BAR_WIDTH as f32).ceil() as i32;
+
+        for i in 0..BAR_WIDTH {
+            let x = pos.x - 1 + i;
+            if x < 0 || x >= map.width {
+                continue;
+            }
+            let fg = if i < filled {
+                color
+            } else {
+                RGB::named(rltk::DARK_GREY)
+            };
+            ctx.set(
+                x,
+                pos.y - 1,
+                fg,
+                RGB::named(rltk::BLACK),
+                rltk::to_cp437('▬'),
+            );
+        }
+    }
 }
 
-fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
+/// Describes the terrain under the cursor, be it the mouse or the look cursor, then whatever
+/// stands on it. Tiles the player only remembers show their terrain but not what's on them now,
+/// and unexplored tiles show nothing.
+fn draw_tooltips(ecs: &World, ctx: &mut Rltk, cursor: (i32, i32)) {
     let white = RGB::named(rltk::WHITE);
     let grey = RGB::named(rltk::GREY);
+    let terrain = RGB::named(rltk::LIGHT_BLUE);
+    let decal = RGB::named(rltk::ORANGE);
 
     let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
     let hidden = ecs.read_storage::<Hidden>();
+    let monsters = ecs.read_storage::<Monster>();
+    let phasing = ecs.read_storage::<Phasing>();
+    let asleep = ecs.read_storage::<Asleep>();
+    let player_entity = ecs.fetch::<Entity>();
+    let dungeon_map = ecs.fetch::<MasterDungeonMap>();
 
-    let mouse_pos = ctx.mouse_pos();
-    // Check if mouse is on map
-    if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height {
+    // Check if the cursor is on the map
+    if cursor.0 >= map.width || cursor.1 >= map.height {
+        return;
+    }
+    let cursor_idx = map.xy_idx(cursor.0, cursor.1);
+    if !map.revealed_tiles[cursor_idx] {
         return;
     }
-    let mut tooltip: Vec<String> = Vec::new();
+    let mut tooltip: Vec<(String, RGB)> =
+        vec![(map.tiles[cursor_idx].description().to_string(), terrain)];
+    if map.bloodstains.contains(&cursor_idx) {
+        tooltip.push(("Blood".to_string(), decal));
+    }
+    if map.visible_tiles[cursor_idx] && map.burning.contains_key(&cursor_idx) {
+        tooltip.push(("On fire".to_string(), decal));
+    }
 
-    for (name, pos, _hidden) in (&names, &positions, !&hidden).join() {
-        let idx = map.xy_idx(pos.x, pos.y);
-        if pos.x == mouse_pos.0 && pos.y == mouse_pos.1 && map.visible_tiles[idx] {
-            tooltip.push(name.name.to_string());
+    for (entity, name, pos, _hidden) in (&ecs.entities(), &names, &positions, !&hidden).join() {
+        if pos.x == cursor.0
+            && pos.y == cursor.1
+            && map.visible_tiles[cursor_idx]
+            && map.shows_entity(cursor_idx, phasing.get(entity).is_some())
+        {
+            tooltip.push((dungeon_map.display_name(&name.name), white));
+            if asleep.get(entity).is_some() {
+                tooltip.push(("Asleep".to_string(), grey));
+            }
+            if ecs.fetch::<GameSettings>().melee_prediction && monsters.get(entity).is_some() {
+                let (dealt, taken) = predict_melee(*player_entity, entity, ecs);
+                tooltip.push((format!("Melee: deal {dealt}, take {taken}"), white));
+            }
         }
     }
 
     if !tooltip.is_empty() {
         let mut width = 0;
-        for s in tooltip.iter() {
+        for (s, _fg) in tooltip.iter() {
             if width < s.len() {
                 width = s.len();
             }
         }
         width += 3;
 
-        if mouse_pos.0 > map.width / 2 {
+        if cursor.0 > map.width / 2 {
             // Left label
-            let arrow_pos = Point::new(mouse_pos.0 - 2, mouse_pos.1);
-            let left_x = mouse_pos.0 - width as i32;
-            let mut y = mouse_pos.1;
-            for s in tooltip.iter() {
-                ctx.print_color(left_x, y, white, grey, s);
+            let arrow_pos = Point::new(cursor.0 - 2, cursor.1);
+            let left_x = cursor.0 - width as i32;
+            let mut y = cursor.1;
+            for (s, fg) in tooltip.iter() {
+                ctx.print_color(left_x, y, *fg, grey, s);
                 let padding = (width - s.len()) - 1;
                 for i in 0..padding {
                     ctx.print_color(arrow_pos.x - i as i32, y, white, grey, &" ".to_string());
@@ -114,11 +298,11 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
             ctx.print_color(arrow_pos.x, arrow_pos.y, white, grey, &"->".to_string());
         } else {
             // Right label
-            let arrow_pos = Point::new(mouse_pos.0 + 1, mouse_pos.1);
-            let left_x = mouse_pos.0 + 3;
-            let mut y = mouse_pos.1;
-            for s in tooltip.iter() {
-                ctx.print_color(left_x + 1, y, white, grey, s);
+            let arrow_pos = Point::new(cursor.0 + 1, cursor.1);
+            let left_x = cursor.0 + 3;
+            let mut y = cursor.1;
+            for (s, fg) in tooltip.iter() {
+                ctx.print_color(left_x + 1, y, *fg, grey, s);
                 let padding = (width - s.len()) - 1;
                 for i in 0..padding {
                     ctx.print_color(arrow_pos.x + 1 + i as i32, y, white, grey, &" ".to_string());
@@ -130,6 +314,85 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
     }
 }
 
+const DEBUG_HUD_REFRESH_MS: f32 = 500.0;
+
+/// Performance counters shown by the F1 HUD. Counting walks whole storages, so the counts are
+/// cached and only refreshed a couple of times a second.
+#[derive(Default)]
+pub struct DebugHud {
+    pub visible: bool,
+    since_refresh_ms: f32,
+    entities: usize,
+    component_counts: Vec<(&'static str, usize)>,
+}
+
+impl DebugHud {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        // Show fresh numbers straight away
+        self.since_refresh_ms = DEBUG_HUD_REFRESH_MS;
+    }
+
+    fn refresh(&mut self, ecs: &World) {
+        self.since_refresh_ms = 0.0;
+        self.entities = ecs.entities().join().count();
+        self.component_counts = vec![
+            ("Positions", ecs.read_storage::<Position>().join().count()),
+            (
+                "Renderables",
+                ecs.read_storage::<Renderable>().join().count(),
+            ),
+            ("Monsters", ecs.read_storage::<Monster>().join().count()),
+            ("Items", ecs.read_storage::<Item>().join().count()),
+            (
+                "Particles",
+                ecs.read_storage::<ParticleLifetime>().join().count(),
+            ),
+        ];
+    }
+}
+
+/// Draws FPS, frame time and entity counts in the top right corner, if the HUD is on
+pub fn draw_debug_hud(ecs: &World, ctx: &mut Rltk) {
+    let mut hud = ecs.fetch_mut::<DebugHud>();
+    if !hud.visible {
+        return;
+    }
+    hud.since_refresh_ms += ctx.frame_time_ms;
+    if hud.since_refresh_ms >= DEBUG_HUD_REFRESH_MS {
+        hud.refresh(ecs);
+    }
+
+    let mut lines = vec![
+        format!("FPS: {:.0}", ctx.fps),
+        format!("Frame: {:.1} ms", ctx.frame_time_ms),
+        format!("Entities: {}", hud.entities),
+    ];
+    for (label, count) in hud.component_counts.iter() {
+        lines.push(format!("{label}: {count}"));
+    }
+
+    const WIDTH: i32 = 20;
+    let x = MAPWIDTH as i32 - WIDTH - 1;
+    ctx.draw_box(
+        x,
+        0,
+        WIDTH,
+        lines.len() as i32 + 1,
+        RGB::named(rltk::GREY),
+        RGB::named(rltk::BLACK),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print_color(
+            x + 1,
+            1 + i as i32,
+            RGB::named(rltk::CYAN),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ItemMenuResult {
     Cancel,
@@ -140,10 +403,13 @@ pub enum ItemMenuResult {
 pub fn show_menu<T: Owned + Component>(
     gs: &mut State,
     ctx: &mut Rltk,
+    title: &str,
 ) -> (ItemMenuResult, Option<Entity>) {
     let player_entity = gs.ecs.fetch::<Entity>();
     let names = gs.ecs.read_storage::<Name>();
     let backpack = gs.ecs.read_storage::<T>();
+    let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+    let stacks = gs.ecs.read_storage::<Stack>();
     let entities = gs.ecs.entities();
 
     let inventory = (&backpack, &names)
@@ -152,7 +418,7 @@ pub fn show_menu<T: Owned + Component>(
     let count = inventory.count();
 
     let mut y = (25 - (count / 2)) as i32;
-    print_item_menu(ctx, y, count, "Inventory");
+    print_item_menu(ctx, y, count, title);
 
     let mut equippable: Vec<Entity> = Vec::new();
     for (j, (entity, _pack, item_name)) in (&entities, &backpack, &names)
@@ -161,7 +427,8 @@ pub fn show_menu<T: Owned + Component>(
         .enumerate()
     {
         let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
-        print_item_label(ctx, y, label_char, item_name);
+        let shown_name = with_count(dungeon_map.shown_name(item_name), &stacks, entity);
+        print_item_label(ctx, y, label_char, &shown_name);
         equippable.push(entity);
         y += 1;
     }
@@ -170,15 +437,330 @@ pub fn show_menu<T: Owned + Component>(
 }
 
 pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    show_menu::<InBackpack>(gs, ctx)
+    let title = {
+        let player_entity = gs.ecs.fetch::<Entity>();
+        let weights = gs.ecs.read_storage::<Weight>();
+        let stacks = gs.ecs.read_storage::<Stack>();
+        let carried = owned_weight(
+            *player_entity,
+            &gs.ecs.read_storage::<InBackpack>(),
+            &weights,
+            &stacks,
+        ) + owned_weight(
+            *player_entity,
+            &gs.ecs.read_storage::<Equipped>(),
+            &weights,
+            &stacks,
+        );
+        match gs.ecs.read_storage::<CombatStats>().get(*player_entity) {
+            None => "Inventory".to_string(),
+            Some(stats) => format!(
+                "Inventory ({:.1}/{:.1} lbs)",
+                carried,
+                carry_capacity(stats)
+            ),
+        }
+    };
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let entities = gs.ecs.entities();
+    let names = gs.ecs.read_storage::<Name>();
+    let backpack = gs.ecs.read_storage::<InBackpack>();
+    let equipped = gs.ecs.read_storage::<Equipped>();
+    let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+    let stacks = gs.ecs.read_storage::<Stack>();
+
+    // (category, shown name, item, whether it's equipped)
+    let mut rows: Vec<(ItemCategory, String, Entity, bool)> = Vec::new();
+    for (entity, name) in (&entities, &names).join() {
+        let carried = backpack
+            .get(entity)
+            .is_some_and(|p| p.owned_by(&player_entity));
+        let worn = equipped
+            .get(entity)
+            .is_some_and(|e| e.owned_by(&player_entity));
+        if carried || worn {
+            let category = item_category(&gs.ecs, entity);
+            let shown = with_count(dungeon_map.shown_name(name), &stacks, entity);
+            rows.push((category, shown.name, entity, worn));
+        }
+    }
+    rows.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    // Letters go to newly carried items first come, first served, so existing ones keep theirs
+    let letters = &mut gs.inventory_letters;
+    letters.retain(|item, _letter| rows.iter().any(|row| row.2 == *item && !row.3));
+    for (_category, _name, item, worn) in rows.iter() {
+        if !*worn && !letters.contains_key(item) {
+            if let Some(free) = ('a'..='z').find(|c| !letters.values().any(|used| used == c)) {
+                letters.insert(*item, free);
+            }
+        }
+    }
+
+    let mut categories: Vec<ItemCategory> = rows.iter().map(|row| row.0).collect();
+    categories.dedup();
+    let count = rows.len() + categories.len();
+    let mut y = (25 - (count / 2)) as i32;
+    print_item_menu(ctx, y, count, &title);
+
+    let mut previous: Option<ItemCategory> = None;
+    for (category, name, item, worn) in rows.iter() {
+        if previous != Some(*category) {
+            ctx.print_color(
+                17,
+                y,
+                RGB::named(rltk::CYAN),
+                RGB::named(rltk::BLACK),
+                category.header(),
+            );
+            previous = Some(*category);
+            y += 1;
+        }
+        if *worn {
+            ctx.print(21, y, name);
+            ctx.print_color(
+                22 + name.len() as i32,
+                y,
+                RGB::named(rltk::GREY),
+                RGB::named(rltk::BLACK),
+                "(equipped)",
+            );
+        } else {
+            let label_char = letters.get(item).copied().unwrap_or(' ');
+            print_item_label(
+                ctx,
+                y,
+                label_char,
+                &Name {
+                    name: name.clone(),
+                    proper: false,
+                },
+            );
+        }
+        y += 1;
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            let picked = letters
+                .iter()
+                .find(|(_item, letter)| (**letter as i32 - 'a' as i32) == selection)
+                .map(|(item, _letter)| *item);
+            match picked {
+                Some(item) => (ItemMenuResult::Selected, Some(item)),
+                None => (ItemMenuResult::NoResponse, None),
+            }
+        }
+    }
+}
+
+/// The groups the inventory sorts items into, in the order they're listed
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum ItemCategory {
+    Weapons,
+    Armor,
+    Potions,
+    Scrolls,
+    Food,
+    Misc,
 }
 
-pub fn drop_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    show_menu::<InBackpack>(gs, ctx)
+impl ItemCategory {
+    fn header(self) -> &'static str {
+        match self {
+            ItemCategory::Weapons => "Weapons",
+            ItemCategory::Armor => "Armor",
+            ItemCategory::Potions => "Potions",
+            ItemCategory::Scrolls => "Scrolls",
+            ItemCategory::Food => "Food",
+            ItemCategory::Misc => "Misc",
+        }
+    }
+}
+
+/// Sorts an item by what it does. Anything that fits nowhere else, such as torches and flasks of
+/// oil, is misc.
+fn item_category(ecs: &World, item: Entity) -> ItemCategory {
+    if let Some(equippable) = ecs.read_storage::<Equippable>().get(item) {
+        let is_weapon = ecs.read_storage::<MeleePowerBonus>().get(item).is_some();
+        return match equippable.slot {
+            EquipmentSlot::Melee => ItemCategory::Weapons,
+            EquipmentSlot::OffHand if is_weapon => ItemCategory::Weapons,
+            EquipmentSlot::OffHand => ItemCategory::Armor,
+            EquipmentSlot::Trinket => ItemCategory::Misc,
+        };
+    }
+    if ecs.read_storage::<ProvidesFood>().get(item).is_some() {
+        return ItemCategory::Food;
+    }
+    if ecs.read_storage::<Consumable>().get(item).is_none() {
+        return ItemCategory::Misc;
+    }
+    let is_potion = ecs
+        .read_storage::<Name>()
+        .get(item)
+        .is_some_and(|name| MasterDungeonMap::is_potion(&name.name));
+    if is_potion || ecs.read_storage::<ProvidesHealing>().get(item).is_some() {
+        return ItemCategory::Potions;
+    }
+    let magical = ecs.read_storage::<InflictsDamage>().get(item).is_some()
+        || ecs.read_storage::<Confusion>().get(item).is_some()
+        || ecs.read_storage::<MagicMapper>().get(item).is_some()
+        || ecs.read_storage::<Identifier>().get(item).is_some();
+    if magical {
+        ItemCategory::Scrolls
+    } else {
+        ItemCategory::Misc
+    }
+}
+
+/// Picks which unidentified backpack item an identify scroll reveals
+pub fn identify_menu(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    scroll: Entity,
+) -> (ItemMenuResult, Option<Entity>) {
+    let items = identifiable_items(&gs.ecs, scroll);
+    show_item_list(gs, ctx, "Identify which item?", &items)
+}
+
+/// Picks which of the equippable items under the player to equip
+pub fn equip_from_ground_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let items = equippable_items_here(&gs.ecs);
+    show_item_list(gs, ctx, "Equip which item?", &items)
+}
+
+/// Picks which carried item to throw, from the backpack or in hand
+pub fn throw_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let items = throwable_items(&gs.ecs);
+    show_item_list(gs, ctx, "Throw which item?", &items)
+}
+
+/// Picks which of the consumables under the player to use
+pub fn use_from_ground_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let items = usable_items_here(&gs.ecs);
+    show_item_list(gs, ctx, "Use which item?", &items)
+}
+
+/// A menu of `items`, under the names the player knows them by
+fn show_item_list(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    title: &str,
+    items: &[Entity],
+) -> (ItemMenuResult, Option<Entity>) {
+    let names = gs.ecs.read_storage::<Name>();
+    let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+    let stacks = gs.ecs.read_storage::<Stack>();
+
+    let count = items.len();
+    let top = (25 - (count / 2)) as i32;
+    print_item_menu(ctx, top, count, title);
+
+    for (j, item) in items.iter().enumerate() {
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        let shown_name = with_count(
+            dungeon_map.shown_name(names.get(*item).unwrap()),
+            &stacks,
+            *item,
+        );
+        print_item_label(ctx, top + j as i32, label_char, &shown_name);
+    }
+
+    item_menu_input(ctx.key, items, count as i32)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DropMenuResult {
+    Cancel,
+    NoResponse,
+    /// Ticks the item, or unticks it if it already was
+    Tick(Entity),
+    Drop,
+    Destroy,
+}
+
+/// Letters tick items in the backpack, then Enter drops every ticked item and Delete destroys
+/// them. Equipped items aren't in the backpack, so they can't be ticked.
+pub fn drop_item_menu(gs: &mut State, ctx: &mut Rltk) -> DropMenuResult {
+    let items: Vec<(Entity, String)> = {
+        let player_entity = gs.ecs.fetch::<Entity>();
+        let names = gs.ecs.read_storage::<Name>();
+        let backpack = gs.ecs.read_storage::<InBackpack>();
+        let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+        let stacks = gs.ecs.read_storage::<Stack>();
+        let entities = gs.ecs.entities();
+        (&entities, &backpack, &names)
+            .join()
+            .filter(|(_entity, pack, _name)| pack.owned_by(&player_entity))
+            .map(|(entity, _pack, name)| {
+                let shown = Name {
+                    name: dungeon_map.display_name(&name.name),
+                    proper: false,
+                };
+                (entity, with_count(shown, &stacks, entity).name)
+            })
+            .collect()
+    };
+
+    let count = items.len();
+    let top = (25 - (count / 2)) as i32;
+    print_item_menu(ctx, top, count, "Enter: drop, Del: destroy");
+    for (j, (entity, name)) in items.iter().enumerate() {
+        let y = top + j as i32;
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        print_item_label(
+            ctx,
+            y,
+            label_char,
+            &Name {
+                name: name.clone(),
+                proper: false,
+            },
+        );
+        if gs.drop_selection.contains(entity) {
+            ctx.set(
+                20,
+                y,
+                RGB::named(rltk::GREEN),
+                RGB::named(rltk::BLACK),
+                rltk::to_cp437('*'),
+            );
+        }
+    }
+
+    match ctx.key {
+        None => DropMenuResult::NoResponse,
+        Some(VirtualKeyCode::Escape) => DropMenuResult::Cancel,
+        Some(VirtualKeyCode::Return) if !gs.drop_selection.is_empty() => DropMenuResult::Drop,
+        Some(VirtualKeyCode::Delete) if !gs.drop_selection.is_empty() => DropMenuResult::Destroy,
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            if selection > -1 && selection < count as i32 {
+                DropMenuResult::Tick(items[selection as usize].0)
+            } else {
+                DropMenuResult::NoResponse
+            }
+        }
+    }
 }
 
 pub fn remove_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    show_menu::<Equipped>(gs, ctx)
+    show_menu::<Equipped>(gs, ctx, "Inventory")
+}
+
+/// `name` with how many there are, if `item` is a stack of more than one
+fn with_count(name: Name, stacks: &ReadStorage<Stack>, item: Entity) -> Name {
+    match stacks.get(item) {
+        Some(stack) if stack.count > 1 => Name {
+            name: format!("{} (x{})", name.name, stack.count),
+            ..name
+        },
+        _ => name,
+    }
 }
 
 fn print_item_label(ctx: &mut Rltk, y: i32, label_char: char, name: &Name) {
@@ -231,10 +813,13 @@ pub fn ranged_target(
     gs: &mut State,
     ctx: &mut Rltk,
     range: i32,
+    preview: Option<Entity>,
 ) -> (ItemMenuResult, Option<Point>) {
     let player_entity = gs.ecs.fetch::<Entity>();
     let player_pos = gs.ecs.fetch::<Point>();
     let viewsheds = gs.ecs.read_storage::<Viewshed>();
+    let map = gs.ecs.fetch::<Map>();
+    let aoe = gs.ecs.read_storage::<AreaOfEffect>();
 
     ctx.print_color(
         5,
@@ -270,14 +855,49 @@ pub fn ranged_target(
         valid_target = true;
     }
 
-    if valid_target {
+    // Preview the shot: green while it flies clear, red once something is in the way, with the
+    // tile it would actually hit marked
+    let mut shot_blocked = false;
+    if mouse_x >= 0 && mouse_x < map.width && mouse_y >= 0 && mouse_y < map.height {
+        let (path, reached) = map.line_of_fire(*player_pos, mouse_pt);
+        for (i, pt) in path.iter().enumerate() {
+            let colour = if i < reached { rltk::GREEN } else { rltk::RED };
+            ctx.set_bg(pt.x, pt.y, RGB::named(colour));
+        }
+        if reached < path.len() {
+            // Blocked short of the cursor, so only the impact tile would be hit
+            shot_blocked = true;
+            if map.rises_between(*player_pos, mouse_pt, path[reached]) {
+                ctx.print_color(
+                    21,
+                    0,
+                    RGB::named(rltk::RED),
+                    RGB::named(rltk::BLACK),
+                    "Higher ground is in the way",
+                );
+            }
+            if reached > 0 {
+                let impact = path[reached - 1];
+                ctx.set_bg(impact.x, impact.y, RGB::named(rltk::ORANGE));
+            }
+        }
+    }
+
+    if valid_target && !shot_blocked {
+        // Show everything the effect would catch
+        if let Some(aoe) = preview.and_then(|item| aoe.get(item)) {
+            for tile in aoe_tiles(&map, *player_pos, mouse_pt, aoe) {
+                ctx.set_bg(tile.x, tile.y, RGB::named(rltk::DARK_ORANGE));
+            }
+        }
         ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::CYAN));
         if ctx.left_click {
             return (ItemMenuResult::Selected, Some(Point::new(mouse_x, mouse_y)));
         }
     } else {
         ctx.set_bg(mouse_x, mouse_y, RGB::named(rltk::RED));
-        if ctx.left_click {
+        // A blocked shot can't be confirmed, but clicking it shouldn't abandon targeting either
+        if ctx.left_click && !shot_blocked {
             return (ItemMenuResult::Cancel, None);
         }
     }
@@ -285,10 +905,169 @@ pub fn ranged_target(
     (ItemMenuResult::NoResponse, None)
 }
 
+/// Lists what an item does, read off its components. One effect per line.
+pub fn describe_item(ecs: &World, item: Entity) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    if let Some(healing) = ecs.read_storage::<ProvidesHealing>().get(item) {
+        lines.push(format!("Heals {} hp", healing.heal_amount));
+    }
+    if let Some(damage) = ecs.read_storage::<InflictsDamage>().get(item) {
+        lines.push(format!(
+            "Deals {} {} damage",
+            damage.damage,
+            damage.damage_type.name()
+        ));
+    }
+    if let Some(confusion) = ecs.read_storage::<Confusion>().get(item) {
+        lines.push(format!("Confuses for {} turns", confusion.turns));
+    }
+    if ecs.read_storage::<MassEffect>().get(item).is_some() {
+        lines.push("Reaches every hostile in sight".to_string());
+    }
+    if let Some(weakness) = ecs.read_storage::<Weakness>().get(item) {
+        lines.push(format!(
+            "-{} power for {} turns",
+            weakness.amount, weakness.turns
+        ));
+    }
+    if let Some(rage) = ecs.read_storage::<Rage>().get(item) {
+        lines.push(format!("Enrages for {} turns", rage.turns));
+    }
+    if let Some(fire) = ecs.read_storage::<StartsFire>().get(item) {
+        lines.push(format!("Starts a fire for {} turns", fire.turns));
+    }
+    if ecs.read_storage::<ProvidesFood>().get(item).is_some() {
+        lines.push("Satisfies hunger".to_string());
+    }
+    if ecs.read_storage::<MagicMapper>().get(item).is_some() {
+        lines.push("Reveals the level map".to_string());
+    }
+    if ecs.read_storage::<Identifier>().get(item).is_some() {
+        lines.push("Identifies an item".to_string());
+    }
+    if let Some(light) = ecs.read_storage::<LightSource>().get(item) {
+        lines.push(format!("Lights {} tiles around you", light.range));
+    }
+    if let Some(sight) = ecs.read_storage::<SightBonus>().get(item) {
+        lines.push(format!("+{} sight range while equipped", sight.range));
+    }
+    if let Some(ranged) = ecs.read_storage::<Ranged>().get(item) {
+        lines.push(format!("Range: {}", ranged.range));
+    }
+    if let Some(aoe) = ecs.read_storage::<AreaOfEffect>().get(item) {
+        lines.push(match aoe.shape {
+            AoeShape::Circle => format!("Blast radius: {}", aoe.radius),
+            AoeShape::Line => format!("Beam length: {}", aoe.radius),
+            AoeShape::Cone => format!("Cone length: {}", aoe.radius),
+            AoeShape::Cross => format!("Cross reach: {}", aoe.radius),
+        });
+    }
+    if let Some(equippable) = ecs.read_storage::<Equippable>().get(item) {
+        let slot = match equippable.slot {
+            EquipmentSlot::Melee if ecs.read_storage::<TwoHanded>().get(item).is_some() => {
+                "two-handed weapon"
+            }
+            EquipmentSlot::Melee => "weapon",
+            EquipmentSlot::OffHand => "shield",
+            EquipmentSlot::Trinket => "trinket",
+        };
+        lines.push(format!("Equips as a {slot}"));
+    }
+    if let Some(bonus) = ecs.read_storage::<MeleePowerBonus>().get(item) {
+        lines.push(format!("+{} power", bonus.amount));
+        if bonus.damage_type != DamageType::Physical {
+            lines.push(format!("Deals {} damage", bonus.damage_type.name()));
+        }
+    }
+    if let Some(bonus) = ecs.read_storage::<DefenseBonus>().get(item) {
+        lines.push(format!("+{} defense", bonus.amount));
+    }
+    if ecs.read_storage::<Digger>().get(item).is_some() {
+        lines.push("Digs through walls you walk into".to_string());
+    }
+    if let Some(reach) = ecs.read_storage::<Reach>().get(item) {
+        lines.push(format!("Reaches {} tiles", reach.tiles));
+    }
+    if let Some(knockback) = ecs.read_storage::<Knockback>().get(item) {
+        lines.push(match knockback.distance {
+            1 => "Knocks foes back a tile".to_string(),
+            distance => format!("Knocks foes back {distance} tiles"),
+        });
+    }
+    if ecs.read_storage::<Fragile>().get(item).is_some() {
+        lines.push("Shatters when thrown".to_string());
+    }
+    if let Some(durability) = ecs.read_storage::<Durability>().get(item) {
+        lines.push(format!(
+            "Durability: {}/{}",
+            durability.current, durability.max
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("No special effects".to_string());
+    }
+    if let Some(value) = ecs.read_storage::<Value>().get(item) {
+        lines.push(format!("Worth {} gold", value.gold));
+    }
+    if ecs.read_storage::<Consumable>().get(item).is_some() {
+        lines.push("Used up on use".to_string());
+    }
+    if let Some(weight) = ecs.read_storage::<Weight>().get(item) {
+        lines.push(format!("Weighs {:.1} lbs", weight.pounds));
+    }
+
+    lines.join("\n")
+}
+
+/// Shows an item's description in a side panel. Any key closes it.
+pub fn examine_item(gs: &mut State, ctx: &mut Rltk, item: Entity) -> ItemMenuResult {
+    let names = gs.ecs.read_storage::<Name>();
+    let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+    let (title, description) = match names.get(item) {
+        None => ("Unknown item".to_string(), describe_item(&gs.ecs, item)),
+        // Examining doesn't give away what an unidentified item does
+        Some(name) if !dungeon_map.is_identified(&name.name) => (
+            dungeon_map.display_name(&name.name),
+            "You don't know what this does.".to_string(),
+        ),
+        Some(name) => (name.name.clone(), describe_item(&gs.ecs, item)),
+    };
+
+    let lines: Vec<&str> = description.lines().collect();
+    let y = 25 - (lines.len() / 2) as i32;
+    ctx.draw_box(
+        49,
+        y - 2,
+        29,
+        (lines.len() + 3) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        51,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        &title,
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print(51, y + i as i32, line);
+    }
+
+    match ctx.key {
+        None => ItemMenuResult::NoResponse,
+        Some(_) => ItemMenuResult::Cancel,
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum MainMenuSelection {
     NewGame,
     LoadGame,
+    PlayReplay,
+    Scores,
+    Options,
     Quit,
 }
 
@@ -300,6 +1079,7 @@ pub enum MainMenuResult {
 
 pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
     let save_exists = super::saveload_system::save_exists();
+    let replay_exists = super::replay::replay_exists();
     let runstate = gs.ecs.fetch::<RunState>();
 
     ctx.print_color_centered(
@@ -328,7 +1108,22 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
                 cur_hovering == MainMenuSelection::LoadGame,
             );
         }
-        print_menu_item(ctx, "Quit", 26, cur_hovering == MainMenuSelection::Quit);
+        if replay_exists {
+            print_menu_item(
+                ctx,
+                "Play Replay",
+                26,
+                cur_hovering == MainMenuSelection::PlayReplay,
+            );
+        }
+        print_menu_item(ctx, "Scores", 27, cur_hovering == MainMenuSelection::Scores);
+        print_menu_item(
+            ctx,
+            "Options",
+            28,
+            cur_hovering == MainMenuSelection::Options,
+        );
+        print_menu_item(ctx, "Quit", 29, cur_hovering == MainMenuSelection::Quit);
 
         if let Some(key) = ctx.key {
             match key {
@@ -340,13 +1135,18 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
                 VirtualKeyCode::Up => {
                     // Cycle++
                     return MainMenuResult::NoSelection {
-                        highlighted: cycle_hovering(cur_hovering, true, save_exists),
+                        highlighted: cycle_hovering(cur_hovering, true, save_exists, replay_exists),
                     };
                 }
                 VirtualKeyCode::Down => {
                     //Cycle--
                     return MainMenuResult::NoSelection {
-                        highlighted: cycle_hovering(cur_hovering, false, save_exists),
+                        highlighted: cycle_hovering(
+                            cur_hovering,
+                            false,
+                            save_exists,
+                            replay_exists,
+                        ),
                     };
                 }
                 VirtualKeyCode::Return => {
@@ -383,34 +1183,588 @@ fn print_menu_item(ctx: &mut Rltk, text: &str, y: i32, is_highlighted: bool) {
     ctx.print_color_centered(y, fg, RGB::named(rltk::BLACK), text);
 }
 
+/// The next entry up or down the menu, wrapping around and skipping any that aren't shown
 fn cycle_hovering(
     cur_hovering: MainMenuSelection,
     is_positive_direction: bool,
     save_exists: bool,
+    replay_exists: bool,
 ) -> MainMenuSelection {
-    if is_positive_direction {
-        match cur_hovering {
-            MainMenuSelection::NewGame => MainMenuSelection::Quit,
-            MainMenuSelection::LoadGame => MainMenuSelection::NewGame,
-            MainMenuSelection::Quit => {
-                if save_exists {
-                    MainMenuSelection::LoadGame
-                } else {
-                    MainMenuSelection::NewGame
+    let shown: Vec<MainMenuSelection> = [
+        MainMenuSelection::NewGame,
+        MainMenuSelection::LoadGame,
+        MainMenuSelection::PlayReplay,
+        MainMenuSelection::Scores,
+        MainMenuSelection::Options,
+        MainMenuSelection::Quit,
+    ]
+    .into_iter()
+    .filter(|entry| match entry {
+        MainMenuSelection::LoadGame => save_exists,
+        MainMenuSelection::PlayReplay => replay_exists,
+        _ => true,
+    })
+    .collect();
+    let current = shown
+        .iter()
+        .position(|entry| *entry == cur_hovering)
+        .unwrap_or(0);
+    // Up the screen is the positive direction
+    let next = if is_positive_direction {
+        (current + shown.len() - 1) % shown.len()
+    } else {
+        (current + 1) % shown.len()
+    };
+    shown[next]
+}
+
+/// Lists the best recorded runs. Any key goes back to the main menu.
+pub fn show_scores(ctx: &mut Rltk) -> ItemMenuResult {
+    const MAX_SHOWN: usize = 15;
+    let scores = super::scores::sorted_scores();
+
+    ctx.print_color_centered(
+        10,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "High Scores",
+    );
+    if scores.is_empty() {
+        ctx.print_color_centered(
+            13,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            "No runs recorded yet.",
+        );
+    }
+    for (i, score) in scores.iter().take(MAX_SHOWN).enumerate() {
+        ctx.print_color_centered(
+            13 + i as i32,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            format!(
+                "Depth {:>2}  Level {:>2}  {:>5} turns  killed by {}",
+                score.depth, score.level, score.turns, score.killed_by
+            ),
+        );
+    }
+    ctx.print_color_centered(
+        14 + MAX_SHOWN as i32,
+        RGB::named(rltk::MAGENTA),
+        RGB::named(rltk::BLACK),
+        "Press any key to return to the menu.",
+    );
+
+    match ctx.key {
+        None => ItemMenuResult::NoResponse,
+        Some(_) => ItemMenuResult::Cancel,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LookResult {
+    NoResponse,
+    /// The cursor moved, to this offset from the player
+    Offset(Point),
+    Close,
+}
+
+/// Moves a cursor around the map with the movement keys, describing whatever is under it the
+/// way the mouse tooltip does. The cursor is kept on the map. Escape puts it back on the player.
+pub fn free_look(ecs: &World, ctx: &mut Rltk, offset: Point) -> LookResult {
+    let player_pos = *ecs.fetch::<Point>();
+    let (width, height) = {
+        let map = ecs.fetch::<Map>();
+        (map.width, map.height)
+    };
+    let cursor = Point::new(
+        (player_pos.x + offset.x).clamp(0, width - 1),
+        (player_pos.y + offset.y).clamp(0, height - 1),
+    );
+
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Looking around. Move to pan, Escape to finish.",
+    );
+    ctx.set_bg(cursor.x, cursor.y, RGB::named(rltk::CYAN));
+    draw_tooltips(ecs, ctx, (cursor.x, cursor.y));
+
+    match ctx.key {
+        None => LookResult::NoResponse,
+        Some(VirtualKeyCode::Escape) => LookResult::Close,
+        Some(key) => match key_to_action(key) {
+            Some(Action::Move { delta_x, delta_y }) => {
+                let x = (cursor.x + delta_x).clamp(0, width - 1);
+                let y = (cursor.y + delta_y).clamp(0, height - 1);
+                LookResult::Offset(Point::new(x - player_pos.x, y - player_pos.y))
+            }
+            _ => LookResult::NoResponse,
+        },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HelpResult {
+    NoResponse,
+    Page(usize),
+    Close,
+}
+
+/// Lists what each key does, straight from the key bindings, a page at a time. Left and Right
+/// turn the page, Escape goes back to the game.
+pub fn show_help(ctx: &mut Rltk, page: usize) -> HelpResult {
+    const PAGE_SIZE: usize = 15;
+
+    // One line per action, with every key bound to it
+    let mut lines: Vec<(Action, Vec<String>)> = Vec::new();
+    for (key, action) in keymap() {
+        match lines.iter_mut().find(|(listed, _keys)| *listed == action) {
+            Some((_action, keys)) => keys.push(key_name(key)),
+            None => lines.push((action, vec![key_name(key)])),
+        }
+    }
+    let pages = lines.len().div_ceil(PAGE_SIZE);
+    let page = page.min(pages - 1);
+
+    let y = 15;
+    ctx.draw_box(
+        15,
+        y - 2,
+        50,
+        PAGE_SIZE as i32 + 4,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Keys",
+    );
+    for (i, (action, keys)) in lines
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .enumerate()
+    {
+        ctx.print(17, y + i as i32, action.description());
+        ctx.print_color(
+            45,
+            y + i as i32,
+            RGB::named(rltk::CYAN),
+            RGB::named(rltk::BLACK),
+            keys.join(", "),
+        );
+    }
+    ctx.print_color(
+        18,
+        y + PAGE_SIZE as i32 + 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!(
+            "Page {}/{}, Left/Right to turn, ESCAPE to close",
+            page + 1,
+            pages
+        ),
+    );
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => HelpResult::Close,
+        Some(VirtualKeyCode::Left) if page > 0 => HelpResult::Page(page - 1),
+        Some(VirtualKeyCode::Right) if page + 1 < pages => HelpResult::Page(page + 1),
+        _ => HelpResult::NoResponse,
+    }
+}
+
+/// Lists every kind of monster, with what the player knows about those they've seen. The rest
+/// stay "???". Any key goes back to the game.
+pub fn show_bestiary(ecs: &World, ctx: &mut Rltk) -> ItemMenuResult {
+    let bestiary = ecs.fetch::<Bestiary>();
+    let species = super::spawner::monster_species();
+
+    let y = 24;
+    ctx.draw_box(
+        10,
+        y - 2,
+        62,
+        (species.len() + 3) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        13,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Bestiary",
+    );
+    for (i, name) in species.iter().enumerate() {
+        let line = match bestiary.entry(name) {
+            None => "???".to_string(),
+            Some(entry) => format!(
+                "{name:<10} HP {:>3}  Power {:>2}  Defense {:>2}  Depth {:>2}  Kills {}",
+                entry.max_hp, entry.power, entry.defense, entry.first_seen_depth, entry.kills
+            ),
+        };
+        ctx.print_color(
+            12,
+            y + i as i32,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+    ctx.print_color(
+        13,
+        y + species.len() as i32 + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Press any key to return",
+    );
+
+    match ctx.key {
+        None => ItemMenuResult::NoResponse,
+        Some(_) => ItemMenuResult::Cancel,
+    }
+}
+
+/// What the player got up to on the level they just left. Any key carries on.
+pub fn level_summary(ctx: &mut Rltk, summary: &LevelSummary) -> ItemMenuResult {
+    let lines = [
+        format!("Monsters killed: {}", summary.stats.kills),
+        format!("Items found:     {}", summary.stats.items_found),
+        format!("Turns spent:     {}", summary.turns),
+        format!("Damage taken:    {}", summary.stats.damage_taken),
+    ];
+
+    let y = 18;
+    ctx.draw_box(
+        25,
+        y - 2,
+        30,
+        lines.len() as i32 + 4,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        28,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!("Depth {} summary", summary.depth),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print_color(
+            27,
+            y + i as i32,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+    ctx.print_color(
+        28,
+        y + lines.len() as i32 + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Press any key to go on",
+    );
+
+    match ctx.key {
+        None => ItemMenuResult::NoResponse,
+        Some(_) => ItemMenuResult::Cancel,
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum OptionsMenuResult {
+    NoSelection { highlighted: usize },
+    Selected { highlighted: usize },
+    Close,
+}
+
+/// Lists the settings with their current values. Up and Down move, Enter changes the highlighted
+/// one, Escape goes back. Difficulty and time pressure can't be changed `in_game`.
+pub fn options_menu(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    highlighted: usize,
+    in_game: bool,
+) -> OptionsMenuResult {
+    let game_settings = gs.ecs.fetch::<GameSettings>();
+
+    ctx.print_color_centered(
+        15,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Options",
+    );
+    for (i, option) in settings::OPTIONS.iter().enumerate() {
+        let mut label = game_settings.label(*option);
+        if in_game && option.new_game_only() {
+            label.push_str(" (new game only)");
+        }
+        print_menu_item(ctx, &label, 18 + i as i32, i == highlighted);
+    }
+    ctx.print_color_centered(
+        19 + settings::OPTIONS.len() as i32,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Enter to change, ESCAPE to go back",
+    );
+
+    let count = settings::OPTIONS.len();
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => OptionsMenuResult::Close,
+        Some(VirtualKeyCode::Up) => OptionsMenuResult::NoSelection {
+            highlighted: (highlighted + count - 1) % count,
+        },
+        Some(VirtualKeyCode::Down) => OptionsMenuResult::NoSelection {
+            highlighted: (highlighted + 1) % count,
+        },
+        Some(VirtualKeyCode::Return) => OptionsMenuResult::Selected { highlighted },
+        _ => OptionsMenuResult::NoSelection { highlighted },
+    }
+}
+
+/// Lets the player pick a class for a new game. Escape goes back to the main menu.
+pub fn select_class(ctx: &mut Rltk) -> (ItemMenuResult, Option<PlayerClass>) {
+    let definitions = class_definitions();
+
+    ctx.print_color_centered(
+        15,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Choose your class",
+    );
+    for (j, definition) in definitions.iter().enumerate() {
+        let y = 18 + j as i32 * 3;
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        ctx.print_color(
+            20,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            format!("({label_char}) {}", definition.name),
+        );
+        ctx.print_color(
+            24,
+            y + 1,
+            RGB::named(rltk::GREY),
+            RGB::named(rltk::BLACK),
+            &definition.description,
+        );
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            match definitions.get(selection as usize) {
+                Some(definition) if selection > -1 => {
+                    (ItemMenuResult::Selected, Some(definition.class))
                 }
+                _ => (ItemMenuResult::NoResponse, None),
             }
         }
+    }
+}
+
+/// Asks the player which attribute to raise. There's no way to back out, a choice must be made.
+pub fn level_up(ctx: &mut Rltk) -> Option<Attribute> {
+    let choices = [
+        (Attribute::Might, "Might: +1 power"),
+        (Attribute::Fitness, "Fitness: +5 maximum hit points"),
+        (Attribute::Quickness, "Quickness: +1 defense"),
+    ];
+
+    let y = 24;
+    ctx.draw_box(
+        15,
+        y - 2,
+        45,
+        (choices.len() + 3) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Level up! Choose an attribute",
+    );
+    for (j, (_attribute, description)) in choices.iter().enumerate() {
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        print_item_label(
+            ctx,
+            y + j as i32,
+            label_char,
+            &Name {
+                name: description.to_string(),
+                proper: false,
+            },
+        );
+    }
+
+    let selection = rltk::letter_to_option(ctx.key?);
+    if selection > -1 {
+        choices
+            .get(selection as usize)
+            .map(|(attribute, _description)| *attribute)
     } else {
-        match cur_hovering {
-            MainMenuSelection::NewGame => {
-                if save_exists {
-                    MainMenuSelection::LoadGame
-                } else {
-                    MainMenuSelection::Quit
-                }
+        None
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum VendorMode {
+    Buy,
+    Sell,
+}
+
+pub enum VendorResult {
+    NoResponse,
+    Cancel,
+    ToggleMode,
+    /// Where the item to buy is in the vendor's list of wares
+    Buy(usize),
+    Sell(Entity),
+}
+
+/// Trades with `vendor`. In Buy mode letters pick from its wares; in Sell mode they pick from the
+/// backpack items it will take. Tab switches mode and Escape leaves.
+pub fn show_vendor(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    vendor: Entity,
+    mode: VendorMode,
+) -> VendorResult {
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let vendors = gs.ecs.read_storage::<Vendor>();
+    let names = gs.ecs.read_storage::<Name>();
+    let values = gs.ecs.read_storage::<Value>();
+    let stacks = gs.ecs.read_storage::<Stack>();
+    let backpack = gs.ecs.read_storage::<InBackpack>();
+    let dungeon_map = gs.ecs.fetch::<MasterDungeonMap>();
+    let entities = gs.ecs.entities();
+    let Some(vendor) = vendors.get(vendor) else {
+        return VendorResult::Cancel;
+    };
+    let gold = gs
+        .ecs
+        .read_storage::<Gold>()
+        .get(*player_entity)
+        .map_or(0, |gold| gold.amount);
+
+    // Each line is the name shown, the price, and what picking it does
+    let lines: Vec<(Name, i32, VendorResult)> = match mode {
+        VendorMode::Buy => vendor
+            .sells
+            .iter()
+            .enumerate()
+            .filter_map(|(ware, name)| {
+                let price = super::spawner::item_value(name)?;
+                let shown = dungeon_map.shown_name(&Name {
+                    name: name.clone(),
+                    proper: false,
+                });
+                Some((shown, price, VendorResult::Buy(ware)))
+            })
+            .collect(),
+        VendorMode::Sell => (&entities, &backpack, &names, &values)
+            .join()
+            .filter(|(_item, pack, name, _value)| {
+                pack.owner == *player_entity && vendor.buys.contains(&name.name)
+            })
+            .map(|(item, _pack, name, value)| {
+                (
+                    with_count(dungeon_map.shown_name(name), &stacks, item),
+                    value.gold / 2,
+                    VendorResult::Sell(item),
+                )
+            })
+            .collect(),
+    };
+
+    let title = match mode {
+        VendorMode::Buy => format!("Buy ({gold} gold, TAB to sell)"),
+        VendorMode::Sell => format!("Sell ({gold} gold, TAB to buy)"),
+    };
+    let count = lines.len();
+    let top = (25 - (count / 2)) as i32;
+    print_item_menu(ctx, top, count, &title);
+    for (j, (shown, price, _result)) in lines.iter().enumerate() {
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        print_item_label(ctx, top + j as i32, label_char, shown);
+        ctx.print(40, top + j as i32, format!("{price} gold"));
+    }
+
+    match ctx.key {
+        None => VendorResult::NoResponse,
+        Some(VirtualKeyCode::Escape) => VendorResult::Cancel,
+        Some(VirtualKeyCode::Tab) => VendorResult::ToggleMode,
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            if selection > -1 && (selection as usize) < count {
+                lines
+                    .into_iter()
+                    .nth(selection as usize)
+                    .map_or(VendorResult::NoResponse, |(_shown, _price, result)| result)
+            } else {
+                VendorResult::NoResponse
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum QuitChoice {
+    SaveAndQuit,
+    QuitWithoutSaving,
+    Cancel,
+}
+
+/// Asks whether to save before leaving the game. Escape counts as Cancel. Returns `None` until
+/// the player answers.
+pub fn confirm_quit(ctx: &mut Rltk) -> Option<QuitChoice> {
+    let choices = [
+        (QuitChoice::SaveAndQuit, "Save and quit"),
+        (QuitChoice::QuitWithoutSaving, "Quit without saving"),
+        (QuitChoice::Cancel, "Cancel"),
+    ];
+
+    let y = 24;
+    print_item_menu(ctx, y, choices.len(), "Quit?");
+    for (j, (_choice, label)) in choices.iter().enumerate() {
+        let label_char = char::from_u32((97 + j) as u32).expect("Invalid char");
+        print_item_label(
+            ctx,
+            y + j as i32,
+            label_char,
+            &Name {
+                name: label.to_string(),
+                proper: false,
+            },
+        );
+    }
+
+    match ctx.key? {
+        VirtualKeyCode::Escape => Some(QuitChoice::Cancel),
+        key => {
+            let selection = rltk::letter_to_option(key);
+            if selection > -1 {
+                choices
+                    .get(selection as usize)
+                    .map(|(choice, _label)| *choice)
+            } else {
+                None
             }
-            MainMenuSelection::LoadGame => MainMenuSelection::Quit,
-            MainMenuSelection::Quit => MainMenuSelection::NewGame,
         }
     }
 }
@@ -440,6 +1794,28 @@ fn print_item_menu(ctx: &mut Rltk, y: i32, count: usize, label: &str) {
     );
 }
 
+/// Explains why a save or replay couldn't be used. Any key goes back to the main menu.
+pub fn load_failed(ctx: &mut Rltk, heading: &str, error: impl fmt::Display) -> ItemMenuResult {
+    ctx.print_color_centered(15, RGB::named(rltk::RED), RGB::named(rltk::BLACK), heading);
+    ctx.print_color_centered(
+        18,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+        error.to_string(),
+    );
+    ctx.print_color_centered(
+        20,
+        RGB::named(rltk::MAGENTA),
+        RGB::named(rltk::BLACK),
+        "Press any key to return to the menu.",
+    );
+
+    match ctx.key {
+        None => ItemMenuResult::NoResponse,
+        Some(_) => ItemMenuResult::Cancel,
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum GameOverResult {
     NoSelection,
@@ -1,4 +1,4 @@
-use super::{gamelog::Gamelog, HungerClock, HungerState, RunState, SufferDamage};
+use super::{gamelog::Gamelog, DamageType, HungerClock, HungerState, RunState, SufferDamage};
 use specs::prelude::*;
 
 pub struct HungerSystem;
@@ -73,7 +73,15 @@ impl<'a> System<'a> for HungerSystem {
                                 .to_string(),
                         );
                     }
-                    SufferDamage::new_damage(&mut inflict_damage, entity, 1);
+                    SufferDamage::new_damage(
+                        &mut inflict_damage,
+                        entity,
+                        1,
+                        DamageType::Physical,
+                        None,
+                        None,
+                        false,
+                    );
                 }
             }
         }
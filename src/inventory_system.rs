@@ -1,10 +1,22 @@
 use super::{
-    gamelog::Gamelog, particle_system::ParticleBuilder, AreaOfEffect, CombatStats, Confusion,
-    Consumable, Equippable, Equipped, HungerClock, HungerState, InBackpack, InflictsDamage,
-    MagicMapper, Map, Name, Position, ProvidesFood, ProvidesHealing, RunState, SufferDamage,
-    WantsToDropItem, WantsToPickupItem, WantsToRemoveItem, WantsToUseItem,
+    effects::{EffectQueue, EffectType, Targets},
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    level_stats::LevelStats,
+    master_dungeon_map::MasterDungeonMap,
+    melee_combat_system::SNEAK_ATTACK_MULTIPLIER,
+    spawner, ActiveEffect, AoeShape, AreaOfEffect, Asleep, Coins, CombatStats, Confusion,
+    Consumable, DamageType, EquipmentSlot, Equippable, Equipped, Fragile, Gold, HungerClock,
+    InBackpack, InflictsDamage, LightSource, MagicMapper, Map, MassEffect, Monster, Name, Position,
+    ProvidesFood, ProvidesHealing, Rage, Renderable, RunState, Stack, StartsFire, StatusEffectKind,
+    SufferDamage, TwoHanded, Value, Viewshed, WantsToDropItem, WantsToIdentifyItem,
+    WantsToPickupItem, WantsToRemoveItem, WantsToThrowItem, WantsToUseItem, Weakness, Weight,
 };
-use specs::prelude::*;
+use crate::encumbrance_system::{carry_capacity, owned_weight};
+use crate::grammar::{capitalize, is_are, with_article, with_the};
+use rltk::{BaseMap, FontCharType, Point, RandomNumberGenerator, RGB};
+use specs::{prelude::*, storage::MaskedStorage};
+use std::ops::Deref;
 
 pub struct ItemCollectionSystem;
 
@@ -16,14 +28,114 @@ impl<'a> System<'a> for ItemCollectionSystem {
         WriteStorage<'a, Position>,
         ReadStorage<'a, Name>,
         WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Weight>,
+        ReadStorage<'a, CombatStats>,
+        ReadExpect<'a, MasterDungeonMap>,
+        ReadStorage<'a, LightSource>,
+        WriteStorage<'a, Viewshed>,
+        Entities<'a>,
+        ReadStorage<'a, Coins>,
+        WriteStorage<'a, Gold>,
+        WriteExpect<'a, LevelStats>,
+        WriteStorage<'a, Stack>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, Equippable>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (player_entity, mut gamelog, mut wants_pickup, mut positions, names, mut backpack) =
-            data;
+        let (
+            player_entity,
+            mut gamelog,
+            mut wants_pickup,
+            mut positions,
+            names,
+            mut backpack,
+            equipped,
+            weights,
+            combat_stats,
+            dungeon_map,
+            lights,
+            mut viewsheds,
+            entities,
+            coins,
+            mut gold,
+            mut level_stats,
+            mut stacks,
+            renderables,
+            consumables,
+            equippables,
+        ) = data;
 
         for pickup in wants_pickup.join() {
+            // Gold goes straight into the purse, all piles adding up to the one total
+            if let (Some(coins), Some(purse)) =
+                (coins.get(pickup.item), gold.get_mut(pickup.collected_by))
+            {
+                purse.amount += coins.amount;
+                entities
+                    .delete(pickup.item)
+                    .expect("Unable to delete coins");
+                if pickup.collected_by == *player_entity {
+                    gamelog
+                        .entries
+                        .push(format!("You pick up {} gold.", coins.amount));
+                }
+                continue;
+            }
+
+            // Warn before the pickup tips the player over their capacity
+            if pickup.collected_by == *player_entity {
+                if let (Some(item_weight), Some(stats)) = (
+                    weights.get(pickup.item),
+                    combat_stats.get(pickup.collected_by),
+                ) {
+                    let carried = owned_weight(pickup.collected_by, &backpack, &weights, &stacks)
+                        + owned_weight(pickup.collected_by, &equipped, &weights, &stacks);
+                    let capacity = carry_capacity(stats);
+                    let adding = item_weight.pounds * stack_count(&stacks, pickup.item) as f32;
+                    if carried <= capacity && carried + adding > capacity {
+                        let item_name = dungeon_map
+                            .shown_name(names.get(pickup.item).expect("Failed to get item name"));
+                        gamelog.entries.push(format!(
+                            "{} {} more than you can comfortably carry.",
+                            capitalize(&with_the(&item_name)),
+                            is_are(&item_name)
+                        ));
+                    }
+                }
+            }
+
+            if pickup.collected_by == *player_entity {
+                level_stats.items_found += 1;
+                let shown = dungeon_map
+                    .shown_name(names.get(pickup.item).expect("Failed to get item name"));
+                gamelog
+                    .entries
+                    .push(match stack_count(&stacks, pickup.item) {
+                        1 => format!("You pick up {}.", with_article(&shown)),
+                        count => format!("You pick up {} (x{count}).", shown.name),
+                    });
+            }
+
             positions.remove(pickup.item);
+            let kind_of =
+                |item: Entity| stack_kind(item, &names, &renderables, &consumables, &equippables);
+            if let Some(stack) = find_stack(
+                pickup.item,
+                pickup.collected_by,
+                &entities,
+                &backpack,
+                kind_of,
+            ) {
+                let count = stack_count(&stacks, pickup.item);
+                add_to_stack(&mut stacks, stack, count);
+                entities
+                    .delete(pickup.item)
+                    .expect("Unable to delete stacked item");
+                continue;
+            }
             backpack
                 .insert(
                     pickup.item,
@@ -32,15 +144,8 @@ impl<'a> System<'a> for ItemCollectionSystem {
                     },
                 )
                 .expect("Unable to insert backpack entry");
-
-            if pickup.collected_by == *player_entity {
-                gamelog.entries.push(format!(
-                    "You pick up the {}.",
-                    names
-                        .get(pickup.item)
-                        .expect("Failed to get item name")
-                        .name
-                ));
+            if lights.get(pickup.item).is_some() {
+                mark_vision_dirty(&mut viewsheds, pickup.collected_by);
             }
         }
 
@@ -54,25 +159,38 @@ impl<'a> System<'a> for ItemUseSystem {
         ReadExpect<'a, Entity>,
         WriteExpect<'a, Gamelog>,
         Entities<'a>,
-        WriteStorage<'a, Consumable>,
+        (WriteStorage<'a, Consumable>, WriteStorage<'a, Stack>),
         WriteStorage<'a, WantsToUseItem>,
         ReadStorage<'a, Name>,
         ReadStorage<'a, ProvidesHealing>,
         ReadStorage<'a, InflictsDamage>,
-        WriteStorage<'a, CombatStats>,
+        (ReadStorage<'a, CombatStats>, WriteStorage<'a, Asleep>),
         ReadExpect<'a, Map>,
-        WriteStorage<'a, SufferDamage>,
-        ReadStorage<'a, AreaOfEffect>,
-        WriteStorage<'a, Confusion>,
+        WriteExpect<'a, EffectQueue>,
+        (ReadStorage<'a, AreaOfEffect>, ReadStorage<'a, Position>),
+        (
+            ReadStorage<'a, Confusion>,
+            ReadStorage<'a, Weakness>,
+            ReadStorage<'a, Rage>,
+        ),
         WriteStorage<'a, Equipped>,
-        ReadStorage<'a, Equippable>,
+        // Paired up, as the tuple is at the most elements a system can take
+        (ReadStorage<'a, Equippable>, ReadStorage<'a, TwoHanded>),
         WriteStorage<'a, InBackpack>,
-        WriteExpect<'a, ParticleBuilder>,
-        ReadStorage<'a, Position>,
         ReadStorage<'a, ProvidesFood>,
-        WriteStorage<'a, HungerClock>,
-        ReadStorage<'a, MagicMapper>,
+        ReadStorage<'a, HungerClock>,
+        (
+            ReadStorage<'a, MagicMapper>,
+            ReadStorage<'a, MassEffect>,
+            ReadStorage<'a, Monster>,
+        ),
         WriteExpect<'a, RunState>,
+        ReadStorage<'a, StartsFire>,
+        ReadStorage<'a, Viewshed>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, MasterDungeonMap>,
+        ReadExpect<'a, GlyphSet>,
+        WriteStorage<'a, WantsToIdentifyItem>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -80,31 +198,122 @@ impl<'a> System<'a> for ItemUseSystem {
             player_entity,
             mut gamelog,
             entities,
-            consumables,
+            (consumables, mut stacks),
             mut wants_use,
             names,
             healers,
             damagers,
-            mut combat_stats,
+            (combat_stats, mut asleep),
             map,
-            mut suffer_damage,
-            aoe,
-            mut confusers,
+            mut effects,
+            (aoe, positions),
+            (confusers, weakeners, ragers),
             mut equipped,
-            equippable,
+            (equippable, two_handed),
             mut backpack,
-            mut particle_builder,
-            positions,
             feeders,
-            mut hunger_clocks,
-            magic_mapper,
+            hunger_clocks,
+            (magic_mapper, mass_effects, monsters),
             mut runstate,
+            fire_starters,
+            viewsheds,
+            mut rng,
+            mut dungeon_map,
+            glyphs,
+            mut wants_identify,
         ) = data;
 
         for (entity, wants_use) in (&entities, &wants_use).join() {
+            // Aiming needs line of sight, being within range isn't enough to shoot through a wall
+            if let Some(target) = wants_use.target {
+                let target_visible = viewsheds
+                    .get(entity)
+                    .is_none_or(|viewshed| viewshed.visible_tiles.contains(&target));
+                if !target_visible {
+                    if entity == *player_entity {
+                        gamelog
+                            .entries
+                            .push("You can't see that target.".to_string());
+                    }
+                    continue;
+                }
+            }
+
+            // Nor through a rise in the ground higher than both the user and the target
+            let user = positions.get(entity).map(|pos| Point::new(pos.x, pos.y));
+            if let (Some(user), Some(target)) = (user, wants_use.target) {
+                if map.elevation_blocks(user, target) {
+                    if entity == *player_entity {
+                        gamelog
+                            .entries
+                            .push("Higher ground is in the way.".to_string());
+                    }
+                    continue;
+                }
+            }
+
+            // Using an item is how the player learns what it is
+            if entity == *player_entity {
+                if let Some(name) = names.get(wants_use.item) {
+                    dungeon_map.identify(&name.name);
+                }
+            }
+
+            if let Some(target) = wants_use.target {
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Projectile {
+                        glyph: glyphs.get(glyphs::PROJECTILE),
+                        fg: rltk::RGB::named(rltk::YELLOW),
+                    },
+                    Targets::Tile {
+                        tile_idx: map.xy_idx(target.x, target.y),
+                    },
+                );
+            }
+
             // Targeting
             let mut targets: Vec<Entity> = Vec::new();
+            let mut target_tiles: Vec<Point> = Vec::new();
+            let mass_effect = mass_effects.get(wants_use.item);
             match wants_use.target {
+                None if mass_effect.is_some() => {
+                    targets = viewsheds.get(entity).map_or(Vec::new(), |viewshed| {
+                        hostiles_in_view(viewshed, &entities, &monsters, &positions)
+                    });
+                    target_tiles = targets
+                        .iter()
+                        .filter_map(|target| positions.get(*target))
+                        .map(|pos| Point::new(pos.x, pos.y))
+                        .collect();
+                    if targets.is_empty() {
+                        let fizzles = mass_effect.is_some_and(|mass| mass.fizzles);
+                        if entity == *player_entity {
+                            gamelog.entries.push(if fizzles {
+                                format!(
+                                    "{} fizzles with no targets in sight.",
+                                    capitalize(&with_the(names.get(wants_use.item).unwrap()))
+                                )
+                            } else {
+                                "There are no targets in sight.".to_string()
+                            });
+                        }
+                        if !fizzles {
+                            continue;
+                        }
+                    } else if entity == *player_entity {
+                        gamelog.entries.push(format!(
+                            "{} takes hold of {} {}.",
+                            capitalize(&with_the(names.get(wants_use.item).unwrap())),
+                            targets.len(),
+                            if targets.len() == 1 {
+                                "creature"
+                            } else {
+                                "creatures"
+                            }
+                        ));
+                    }
+                }
                 None => {
                     targets.push(*player_entity);
                 }
@@ -112,171 +321,311 @@ impl<'a> System<'a> for ItemUseSystem {
                     match aoe.get(wants_use.item) {
                         None => {
                             // Single target in tile
-                            let idx = map.xy_idx(target.x, target.y);
-                            for mob in map.tile_content[idx].iter() {
-                                targets.push(*mob);
-                            }
+                            targets.extend_from_slice(map.entities_at(target.x, target.y));
+                            target_tiles.push(target);
                         }
                         Some(aoe) => {
-                            // AoE
-                            let mut blast_tiles = rltk::field_of_view(target, aoe.radius, &*map);
-                            blast_tiles.retain(|p| {
-                                p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1
-                            });
-                            for tile_idx in blast_tiles.iter() {
-                                let idx = map.xy_idx(tile_idx.x, tile_idx.y);
-                                for mob in map.tile_content[idx].iter() {
-                                    targets.push(*mob);
-                                }
-                                particle_builder.request(
-                                    Position {
-                                        x: tile_idx.x,
-                                        y: tile_idx.y,
-                                    },
-                                    rltk::RGB::named(rltk::ORANGE),
-                                    rltk::RGB::named(rltk::BLACK),
-                                    rltk::to_cp437('░'),
-                                    200.0,
-                                );
+                            let user = positions
+                                .get(entity)
+                                .map_or(target, |pos| Point::new(pos.x, pos.y));
+                            let blast_tiles = aoe_tiles(&map, user, target, aoe);
+                            for tile in blast_tiles.iter() {
+                                targets.extend_from_slice(map.entities_at(tile.x, tile.y));
                             }
+                            target_tiles = blast_tiles;
+                            effects.add_effect(
+                                Some(entity),
+                                EffectType::Particle {
+                                    glyph: glyphs.get(glyphs::BLAST),
+                                    fg: rltk::RGB::named(rltk::ORANGE),
+                                    bg: rltk::RGB::named(rltk::BLACK),
+                                    lifetime: 200.0,
+                                },
+                                Targets::Tiles {
+                                    tiles: tile_indices(&map, &target_tiles),
+                                },
+                            );
                         }
                     }
                 }
             }
 
             // If it is equippable, then we want to equip it - and unequip whatever else was in that slot
-            let item_equippable = equippable.get(wants_use.item);
-            if let Some(can_equip) = item_equippable {
-                let target_slot = can_equip.slot;
+            if let Some(can_equip) = equippable.get(wants_use.item) {
                 let target = targets[0];
-
-                // Remove any items the target has in the item's slot
-                let mut to_unequip: Vec<Entity> = Vec::new();
-                for (item_entity, already_equipped, name) in (&entities, &equipped, &names).join() {
-                    if already_equipped.owner == target && already_equipped.slot == target_slot {
-                        to_unequip.push(item_entity);
-                        if target == *player_entity {
+                let unequipped = equip_item(
+                    &entities,
+                    &mut equipped,
+                    &mut backpack,
+                    &two_handed,
+                    wants_use.item,
+                    target,
+                    can_equip.slot,
+                );
+                if target == *player_entity {
+                    for item in unequipped.iter() {
+                        if let Some(name) = names.get(*item) {
                             gamelog
                                 .entries
-                                .push(format!("You unequip {item_name}.", item_name = name.name));
+                                .push(format!("You unequip {}.", with_the(name)));
                         }
                     }
-                }
-                for item in to_unequip.iter() {
-                    equipped.remove(*item);
-                    backpack
-                        .insert(*item, InBackpack { owner: target })
-                        .expect("Unable to insert backpack entry");
-                }
-
-                // Wield the item
-                equipped
-                    .insert(
-                        wants_use.item,
-                        Equipped {
-                            owner: target,
-                            slot: target_slot,
-                        },
-                    )
-                    .expect("Unable to insert equipped component");
-                backpack.remove(wants_use.item);
-                if target == *player_entity {
                     gamelog.entries.push(format!(
-                        "You equip {item_name}.",
-                        item_name = names.get(wants_use.item).unwrap().name
+                        "You equip {}.",
+                        with_the(names.get(wants_use.item).unwrap())
                     ));
                 }
             }
 
             // Damaging Item
             if let Some(damager) = damagers.get(wants_use.item) {
-                for mob in targets.iter() {
-                    if combat_stats.get(*mob).is_some() {
-                        SufferDamage::new_damage(&mut suffer_damage, *mob, damager.damage);
-                        if entity == *player_entity {
-                            gamelog.entries.push(format!(
-                                "You use {item_name} on {mob_name}, inflicting {amount} hp.",
-                                amount = damager.damage,
-                                mob_name = names.get(*mob).unwrap().name,
-                                item_name = names.get(wants_use.item).unwrap().name,
-                            ));
-                        }
-                        if let Some(pos) = positions.get(*mob) {
-                            particle_builder.request(
-                                pos.clone(),
-                                rltk::RGB::named(rltk::RED),
-                                rltk::RGB::named(rltk::BLACK),
-                                rltk::to_cp437('‼'),
-                                200.0,
-                            );
-                        }
+                // One roll for everyone caught in it
+                let amount = damager.damage.roll(&mut rng);
+                let victims: Vec<Entity> = targets
+                    .iter()
+                    .filter(|mob| combat_stats.get(**mob).is_some())
+                    .copied()
+                    .collect();
+                for mob in victims.iter() {
+                    // Anything caught sleeping takes the full force, and is woken by it
+                    let sneak = asleep.remove(*mob).is_some();
+                    let mut amount = if sneak {
+                        amount * SNEAK_ATTACK_MULTIPLIER
+                    } else {
+                        amount
+                    };
+                    if let (Some(user), Some(pos)) = (user, positions.get(*mob)) {
+                        amount += map.elevation_bonus(user, Point::new(pos.x, pos.y));
+                    }
+                    if entity == *player_entity && *mob == entity {
+                        gamelog.entries.push(format!(
+                            "You drink {item_name}, taking {amount} {damage_type} damage.",
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                            damage_type = damager.damage_type.name(),
+                        ));
+                    } else if entity == *player_entity {
+                        gamelog.entries.push(format!(
+                            "{sneak}You use {item_name} on {mob_name}, inflicting {amount} hp.",
+                            sneak = if sneak { "Sneak attack! " } else { "" },
+                            mob_name = with_the(names.get(*mob).unwrap()),
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
                     }
+                    effects.add_effect(
+                        Some(entity),
+                        EffectType::Damage {
+                            amount,
+                            damage_type: damager.damage_type,
+                        },
+                        Targets::Single { target: *mob },
+                    );
                 }
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Particle {
+                        glyph: glyphs.get(glyphs::HIT),
+                        fg: rltk::RGB::named(rltk::RED),
+                        bg: rltk::RGB::named(rltk::BLACK),
+                        lifetime: 200.0,
+                    },
+                    Targets::TargetList { targets: victims },
+                );
             }
 
             // Healing Item
             if let Some(healer) = healers.get(wants_use.item) {
-                for target in targets.iter() {
-                    if let Some(stats) = combat_stats.get_mut(*target) {
-                        let amount = if stats.hp + healer.heal_amount > stats.max_hp {
-                            stats.max_hp - stats.hp
-                        } else {
-                            healer.heal_amount
-                        };
-                        stats.hp = i32::min(stats.max_hp, stats.hp + healer.heal_amount);
-                        if entity == *player_entity {
-                            gamelog.entries.push(format!(
-                                "You drink the {potion_name}, healing {amount} hp.",
-                                potion_name = names.get(wants_use.item).unwrap().name,
-                            ));
-                        }
-                        if let Some(pos) = positions.get(*target) {
-                            particle_builder.request(
-                                pos.clone(),
-                                rltk::RGB::named(rltk::GREEN),
-                                rltk::RGB::named(rltk::BLACK),
-                                rltk::to_cp437('♥'),
-                                200.0,
-                            );
-                        }
+                let patients: Vec<Entity> = targets
+                    .iter()
+                    .filter(|target| combat_stats.get(**target).is_some())
+                    .copied()
+                    .collect();
+                for target in patients.iter() {
+                    let stats = combat_stats.get(*target).unwrap();
+                    let amount = i32::min(healer.heal_amount, stats.max_hp - stats.hp);
+                    let potion_name = with_the(names.get(wants_use.item).unwrap());
+                    if entity == *player_entity && *target == entity {
+                        gamelog
+                            .entries
+                            .push(format!("You drink {potion_name}, healing {amount} hp."));
+                    } else if entity == *player_entity {
+                        // Thrown, and splashed over someone else
+                        gamelog.entries.push(format!(
+                            "{potion_name} heals {target_name} for {amount} hp.",
+                            potion_name = capitalize(&potion_name),
+                            target_name = with_the(names.get(*target).unwrap()),
+                        ));
                     }
                 }
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Healing {
+                        amount: healer.heal_amount,
+                    },
+                    Targets::TargetList {
+                        targets: patients.clone(),
+                    },
+                );
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Particle {
+                        glyph: glyphs.get(glyphs::HEAL),
+                        fg: rltk::RGB::named(rltk::GREEN),
+                        bg: rltk::RGB::named(rltk::BLACK),
+                        lifetime: 200.0,
+                    },
+                    Targets::TargetList { targets: patients },
+                );
             }
 
             // Confusion Item
-            // map To avoid double borrow
-            if let Some(turns) = confusers.get(wants_use.item).map(|confuser| confuser.turns) {
+            if let Some(confuser) = confusers.get(wants_use.item) {
                 for mob in targets.iter() {
-                    confusers
-                        .insert(*mob, Confusion { turns })
-                        .expect("Unable to insert status");
-                    if entity == *player_entity {
+                    if entity == *player_entity && *mob == entity {
+                        gamelog.entries.push(format!(
+                            "You drink {item_name}, and the room starts to spin.",
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
+                    } else if entity == *player_entity {
                         gamelog.entries.push(format!(
                             "You use {item_name} on {mob_name}, confusing them.",
-                            mob_name = names.get(*mob).unwrap().name,
-                            item_name = names.get(wants_use.item).unwrap().name,
+                            mob_name = with_the(names.get(*mob).unwrap()),
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
                         ));
                     }
-                    if let Some(pos) = positions.get(*mob) {
-                        particle_builder.request(
-                            pos.clone(),
-                            rltk::RGB::named(rltk::MAGENTA),
-                            rltk::RGB::named(rltk::BLACK),
-                            rltk::to_cp437('?'),
-                            200.0,
-                        );
+                }
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Status {
+                        effect: ActiveEffect {
+                            kind: StatusEffectKind::Confused,
+                            magnitude: 1,
+                            turns: confuser.turns,
+                        },
+                    },
+                    Targets::TargetList {
+                        targets: targets.clone(),
+                    },
+                );
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Particle {
+                        glyph: glyphs.get(glyphs::CONFUSED),
+                        fg: rltk::RGB::named(rltk::MAGENTA),
+                        bg: rltk::RGB::named(rltk::BLACK),
+                        lifetime: 200.0,
+                    },
+                    Targets::TargetList {
+                        targets: targets.clone(),
+                    },
+                );
+            }
+
+            // Weakening Item
+            if let Some(weakener) = weakeners.get(wants_use.item) {
+                let victims: Vec<Entity> = targets
+                    .iter()
+                    .filter(|mob| combat_stats.get(**mob).is_some())
+                    .copied()
+                    .collect();
+                for mob in victims.iter() {
+                    if entity == *player_entity && *mob == entity {
+                        gamelog.entries.push(format!(
+                            "You drink {item_name}, and your strength drains away.",
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
+                    } else if entity == *player_entity {
+                        gamelog.entries.push(format!(
+                            "You use {item_name} on {mob_name}, weakening them.",
+                            mob_name = with_the(names.get(*mob).unwrap()),
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
                     }
                 }
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Status {
+                        effect: ActiveEffect {
+                            kind: StatusEffectKind::Weakened,
+                            magnitude: weakener.amount,
+                            turns: weakener.turns,
+                        },
+                    },
+                    Targets::TargetList { targets: victims },
+                );
+            }
+
+            // Rage Item
+            if let Some(rage) = ragers.get(wants_use.item) {
+                let enraged: Vec<Entity> = targets
+                    .iter()
+                    .filter(|mob| combat_stats.get(**mob).is_some())
+                    .copied()
+                    .collect();
+                for mob in enraged.iter() {
+                    if entity == *player_entity && *mob == entity {
+                        gamelog.entries.push(format!(
+                            "You drink {item_name}, and a red haze comes over you.",
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
+                    } else if entity == *player_entity {
+                        gamelog.entries.push(format!(
+                            "You use {item_name} on {mob_name}, enraging them.",
+                            mob_name = with_the(names.get(*mob).unwrap()),
+                            item_name = with_the(names.get(wants_use.item).unwrap()),
+                        ));
+                    }
+                }
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Status {
+                        effect: ActiveEffect {
+                            kind: StatusEffectKind::Raging,
+                            magnitude: 1,
+                            turns: rage.turns,
+                        },
+                    },
+                    Targets::TargetList {
+                        targets: enraged.clone(),
+                    },
+                );
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Particle {
+                        glyph: glyphs.get(glyphs::RAGE),
+                        fg: rltk::RGB::named(rltk::RED),
+                        bg: rltk::RGB::named(rltk::BLACK),
+                        lifetime: 200.0,
+                    },
+                    Targets::TargetList { targets: enraged },
+                );
             }
 
             // Edible Item
-            if feeders.get(wants_use.item).is_some() {
-                if let Some(hc) = hunger_clocks.get_mut(targets[0]) {
-                    hc.state = HungerState::WellFed;
-                    hc.duration = 20;
+            if feeders.get(wants_use.item).is_some() && hunger_clocks.get(targets[0]).is_some() {
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::WellFed,
+                    Targets::Single { target: targets[0] },
+                );
+                gamelog.entries.push(format!(
+                    "You eat {item_name}.",
+                    item_name = with_the(names.get(wants_use.item).unwrap())
+                ));
+            }
+
+            // Fire Item
+            if let Some(fire) = fire_starters.get(wants_use.item) {
+                effects.add_effect(
+                    Some(entity),
+                    EffectType::Fire { turns: fire.turns },
+                    Targets::Tiles {
+                        tiles: tile_indices(&map, &target_tiles),
+                    },
+                );
+                if entity == *player_entity {
                     gamelog.entries.push(format!(
-                        "You eat the {item_name}.",
-                        item_name = names.get(wants_use.item).unwrap().name
+                        "{item_name} bursts into flames!",
+                        item_name = capitalize(&with_the(names.get(wants_use.item).unwrap()))
                     ));
                 }
             }
@@ -289,12 +638,504 @@ impl<'a> System<'a> for ItemUseSystem {
                 *runstate = RunState::MagicMapReveal { row: 0 };
             }
 
+            // Identify Scroll
+            if let Some(identify) = wants_identify.get(entity) {
+                if let Some(name) = names.get(identify.item) {
+                    let known_as = dungeon_map.shown_name(name);
+                    dungeon_map.identify(&name.name);
+                    gamelog.entries.push(format!(
+                        "{} {} {}.",
+                        capitalize(&with_the(&known_as)),
+                        is_are(&known_as),
+                        with_article(name)
+                    ));
+                }
+            }
+
+            // One comes off a stack, and only the last is gone for good
             if consumables.get(wants_use.item).is_some() {
-                entities.delete(wants_use.item).expect("Delete failed");
+                match stacks.get_mut(wants_use.item) {
+                    Some(stack) if stack.count > 1 => stack.count -= 1,
+                    _ => entities.delete(wants_use.item).expect("Delete failed"),
+                }
             }
         }
 
         wants_use.clear();
+        wants_identify.clear();
+    }
+}
+
+/// Everything hostile standing where `viewshed` can see
+pub fn hostiles_in_view(
+    viewshed: &Viewshed,
+    entities: &Entities,
+    monsters: &ReadStorage<Monster>,
+    positions: &ReadStorage<Position>,
+) -> Vec<Entity> {
+    (entities, monsters, positions)
+        .join()
+        .filter(|(_entity, _monster, pos)| {
+            viewshed.visible_tiles.contains(&Point::new(pos.x, pos.y))
+        })
+        .map(|(entity, _monster, _pos)| entity)
+        .collect()
+}
+
+/// Unidentified items in the player's backpack, other than the identify scroll reading them
+pub fn identifiable_items(ecs: &World, scroll: Entity) -> Vec<Entity> {
+    let player_entity = ecs.fetch::<Entity>();
+    let dungeon_map = ecs.fetch::<MasterDungeonMap>();
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+
+    (&entities, &backpack, &names)
+        .join()
+        .filter(|(item, pack, name)| {
+            *item != scroll
+                && pack.owner == *player_entity
+                && !dungeon_map.is_identified(&name.name)
+        })
+        .map(|(item, _pack, _name)| item)
+        .collect()
+}
+
+/// What makes items interchangeable enough to stack: the same name, drawn the same way. Only
+/// consumables that can't be equipped stack at all.
+#[derive(PartialEq)]
+struct StackKind {
+    name: String,
+    glyph: FontCharType,
+    fg: RGB,
+}
+
+fn stack_kind(
+    item: Entity,
+    names: &ReadStorage<Name>,
+    renderables: &ReadStorage<Renderable>,
+    consumables: &ReadStorage<Consumable>,
+    equippables: &ReadStorage<Equippable>,
+) -> Option<StackKind> {
+    if consumables.get(item).is_none() || equippables.get(item).is_some() {
+        return None;
+    }
+    let (name, render) = (names.get(item)?, renderables.get(item)?);
+    Some(StackKind {
+        name: name.name.clone(),
+        glyph: render.glyph,
+        fg: render.fg,
+    })
+}
+
+/// An item already in `owner`'s backpack that `item` would stack with
+fn find_stack<D>(
+    item: Entity,
+    owner: Entity,
+    entities: &Entities,
+    backpack: &Storage<InBackpack, D>,
+    kind_of: impl Fn(Entity) -> Option<StackKind>,
+) -> Option<Entity>
+where
+    D: Deref<Target = MaskedStorage<InBackpack>>,
+{
+    let kind = kind_of(item)?;
+    (entities, backpack)
+        .join()
+        .find(|(other, pack)| {
+            *other != item && pack.owner == owner && kind_of(*other).as_ref() == Some(&kind)
+        })
+        .map(|(other, _pack)| other)
+}
+
+/// How many items `item` stands for
+fn stack_count<D>(stacks: &Storage<Stack, D>, item: Entity) -> i32
+where
+    D: Deref<Target = MaskedStorage<Stack>>,
+{
+    stacks.get(item).map_or(1, |stack| stack.count)
+}
+
+fn add_to_stack(stacks: &mut WriteStorage<Stack>, stack: Entity, count: i32) {
+    let total = stack_count(stacks, stack) + count;
+    stacks
+        .insert(stack, Stack { count: total })
+        .expect("Unable to insert stack");
+}
+
+/// Puts `item` in `owner`'s backpack, onto a stack of the same kind if they already carry one.
+/// Returns whichever entity now holds it.
+pub fn stow(ecs: &mut World, item: Entity, owner: Entity) -> Entity {
+    ecs.write_storage::<Position>().remove(item);
+    let stack = {
+        let names = ecs.read_storage::<Name>();
+        let renderables = ecs.read_storage::<Renderable>();
+        let consumables = ecs.read_storage::<Consumable>();
+        let equippables = ecs.read_storage::<Equippable>();
+        find_stack(
+            item,
+            owner,
+            &ecs.entities(),
+            &ecs.read_storage::<InBackpack>(),
+            |item| stack_kind(item, &names, &renderables, &consumables, &equippables),
+        )
+    };
+    match stack {
+        Some(stack) => {
+            let mut stacks = ecs.write_storage::<Stack>();
+            let count = stack_count(&stacks, item);
+            add_to_stack(&mut stacks, stack, count);
+            drop(stacks);
+            ecs.delete_entity(item)
+                .expect("Unable to delete stacked item");
+            stack
+        }
+        None => {
+            ecs.write_storage::<InBackpack>()
+                .insert(item, InBackpack { owner })
+                .expect("Unable to insert backpack entry");
+            item
+        }
+    }
+}
+
+/// Splits a single item off a stack and returns it, leaving the rest where they are. Anything
+/// that isn't a stack of more than one comes back as it is. Every item in a stack is alike, so
+/// the one split off is made afresh from its name. Returns `None`, and says so in the log, if
+/// the name can't be spawned, rather than handing back the whole stack.
+pub fn take_one(ecs: &mut World, item: Entity) -> Option<Entity> {
+    if stack_count(&ecs.read_storage::<Stack>(), item) < 2 {
+        return Some(item);
+    }
+    let name = ecs.read_storage::<Name>().get(item)?.clone();
+    let on_ground = ecs.read_storage::<Position>().get(item).cloned();
+    let owner = ecs.read_storage::<InBackpack>().get(item).map(|p| p.owner);
+    let pos = on_ground.clone().unwrap_or(Position { x: 0, y: 0 });
+    let Some(one) = spawner::spawn_named(ecs, &name.name, pos) else {
+        let shown = ecs.fetch::<MasterDungeonMap>().shown_name(&name);
+        ecs.fetch_mut::<Gamelog>().entries.push(format!(
+            "{} won't come apart from the rest of the stack.",
+            capitalize(&with_the(&shown))
+        ));
+        return None;
+    };
+    if on_ground.is_none() {
+        ecs.write_storage::<Position>().remove(one);
+    }
+    if let Some(owner) = owner {
+        ecs.write_storage::<InBackpack>()
+            .insert(one, InBackpack { owner })
+            .expect("Unable to insert backpack entry");
+    }
+    if let Some(stack) = ecs.write_storage::<Stack>().get_mut(item) {
+        stack.count -= 1;
+    }
+    Some(one)
+}
+
+/// Permanently deletes items from the player's backpack
+pub fn destroy_items(ecs: &mut World, items: &[Entity]) {
+    {
+        let player_entity = ecs.fetch::<Entity>();
+        let names = ecs.read_storage::<Name>();
+        let lights = ecs.read_storage::<LightSource>();
+        let mut viewsheds = ecs.write_storage::<Viewshed>();
+        let dungeon_map = ecs.fetch::<MasterDungeonMap>();
+        let mut gamelog = ecs.fetch_mut::<Gamelog>();
+        for item in items {
+            if lights.get(*item).is_some() {
+                mark_vision_dirty(&mut viewsheds, *player_entity);
+            }
+            if let Some(name) = names.get(*item) {
+                gamelog.entries.push(format!(
+                    "You destroy {}.",
+                    with_the(&dungeon_map.shown_name(name))
+                ));
+            }
+        }
+    }
+    ecs.delete_entities(items).expect("Unable to delete items");
+}
+
+/// Buys an item for the player and puts it in their backpack, if they can afford it
+pub fn buy_item(ecs: &mut World, name: &str) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let Some(price) = spawner::item_value(name) else {
+        return;
+    };
+    let gold = ecs
+        .read_storage::<Gold>()
+        .get(player_entity)
+        .map_or(0, |gold| gold.amount);
+    if gold < price {
+        let shown = ecs.fetch::<MasterDungeonMap>().shown_name(&Name {
+            name: name.to_string(),
+            proper: false,
+        });
+        ecs.fetch_mut::<Gamelog>().entries.push(format!(
+            "You can't afford {} ({price} gold).",
+            with_the(&shown)
+        ));
+        return;
+    }
+
+    let Some(pos) = ecs.read_storage::<Position>().get(player_entity).cloned() else {
+        return;
+    };
+    let Some(item) = spawner::spawn_named(ecs, name, pos) else {
+        return;
+    };
+    let item = stow(ecs, item, player_entity);
+    if let Some(gold) = ecs.write_storage::<Gold>().get_mut(player_entity) {
+        gold.amount -= price;
+    }
+    if ecs.read_storage::<LightSource>().get(item).is_some() {
+        mark_vision_dirty(&mut ecs.write_storage::<Viewshed>(), player_entity);
+    }
+
+    let names = ecs.read_storage::<Name>();
+    let dungeon_map = ecs.fetch::<MasterDungeonMap>();
+    if let Some(name) = names.get(item) {
+        ecs.fetch_mut::<Gamelog>().entries.push(format!(
+            "You buy {} for {price} gold.",
+            with_article(&dungeon_map.shown_name(name))
+        ));
+    }
+}
+
+/// Sells a backpack item for half its value. Only items in the backpack can be sold, so nothing
+/// equipped can be.
+pub fn sell_item(ecs: &mut World, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let in_backpack = ecs
+        .read_storage::<InBackpack>()
+        .get(item)
+        .is_some_and(|pack| pack.owner == player_entity);
+    let Some(price) = ecs.read_storage::<Value>().get(item).map(|v| v.gold / 2) else {
+        return;
+    };
+    if !in_backpack {
+        return;
+    }
+    // A stack is sold one at a time
+    let Some(item) = take_one(ecs, item) else {
+        return;
+    };
+
+    {
+        let names = ecs.read_storage::<Name>();
+        let dungeon_map = ecs.fetch::<MasterDungeonMap>();
+        if let Some(name) = names.get(item) {
+            ecs.fetch_mut::<Gamelog>().entries.push(format!(
+                "You sell {} for {price} gold.",
+                with_the(&dungeon_map.shown_name(name))
+            ));
+        }
+    }
+    if let Some(gold) = ecs.write_storage::<Gold>().get_mut(player_entity) {
+        gold.amount += price;
+    }
+    if ecs.read_storage::<LightSource>().get(item).is_some() {
+        mark_vision_dirty(&mut ecs.write_storage::<Viewshed>(), player_entity);
+    }
+    ecs.delete_entity(item).expect("Unable to delete item");
+}
+
+/// Gaining or losing a light changes how far `owner` can see in the dark
+fn mark_vision_dirty(viewsheds: &mut WriteStorage<Viewshed>, owner: Entity) {
+    if let Some(viewshed) = viewsheds.get_mut(owner) {
+        viewshed.dirty = true;
+    }
+}
+
+fn tile_indices(map: &Map, tiles: &[rltk::Point]) -> Vec<usize> {
+    tiles
+        .iter()
+        .map(|tile| map.xy_idx(tile.x, tile.y))
+        .collect()
+}
+
+/// How many tiles away anything can be thrown
+pub const THROW_RANGE: i32 = 6;
+/// Something thrown that has no effect of its own hits for a point of damage per this many
+/// pounds it weighs, and never for less than one
+const POUNDS_PER_THROWN_DAMAGE: f32 = 2.0;
+
+/// Throws items along a line until they hit a wall or a creature. Fragile items shatter and are
+/// used where they break. Anything else thumps whoever it hits and lands on the ground intact.
+pub struct ItemThrowSystem;
+
+impl<'a> System<'a> for ItemThrowSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, Gamelog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToThrowItem>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, Equipped>,
+        ReadStorage<'a, Fragile>,
+        ReadStorage<'a, Weight>,
+        ReadStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, EffectQueue>,
+        ReadExpect<'a, GlyphSet>,
+        ReadStorage<'a, Renderable>,
+        ReadExpect<'a, MasterDungeonMap>,
+        ReadStorage<'a, LightSource>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, Asleep>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut gamelog,
+            entities,
+            mut wants_throw,
+            mut wants_use,
+            names,
+            mut positions,
+            mut backpack,
+            mut equipped,
+            fragile,
+            weights,
+            combat_stats,
+            mut suffer_damage,
+            map,
+            mut effects,
+            glyphs,
+            renderables,
+            dungeon_map,
+            lights,
+            mut viewsheds,
+            mut asleep,
+        ) = data;
+
+        for (entity, throw) in (&entities, &wants_throw).join() {
+            let Some(from) = positions.get(entity).map(|pos| Point::new(pos.x, pos.y)) else {
+                continue;
+            };
+            let item = throw.item;
+            let is_player = entity == *player_entity;
+
+            // It flies until the next tile is a wall, or it hits someone
+            let mut landing = from;
+            for tile in rltk::line2d(rltk::LineAlg::Bresenham, from, throw.target)
+                .into_iter()
+                .skip(1)
+            {
+                let in_bounds =
+                    tile.x >= 0 && tile.x < map.width && tile.y >= 0 && tile.y < map.height;
+                if !in_bounds
+                    || map.tiles[map.xy_idx(tile.x, tile.y)].blocks_movement()
+                    || map.rises_between(from, throw.target, tile)
+                {
+                    break;
+                }
+                landing = tile;
+                let occupied = map
+                    .entities_at(tile.x, tile.y)
+                    .iter()
+                    .any(|e| combat_stats.get(*e).is_some());
+                if occupied {
+                    break;
+                }
+            }
+
+            if equipped.remove(item).is_some() && lights.get(item).is_some() {
+                mark_vision_dirty(&mut viewsheds, entity);
+            }
+            let item_name = names
+                .get(item)
+                .map(|name| with_the(&dungeon_map.shown_name(name)))
+                .unwrap_or_else(|| "something".to_string());
+
+            if fragile.get(item).is_some() {
+                // Using it where it breaks takes care of the effect, and of used up items
+                if is_player {
+                    gamelog
+                        .entries
+                        .push(format!("{} shatters!", capitalize(&item_name)));
+                }
+                wants_use
+                    .insert(
+                        entity,
+                        WantsToUseItem {
+                            item,
+                            target: Some(landing),
+                        },
+                    )
+                    .expect("Unable to insert intent");
+                continue;
+            }
+
+            effects.add_effect(
+                Some(entity),
+                EffectType::Projectile {
+                    glyph: renderables
+                        .get(item)
+                        .map_or(glyphs.get(glyphs::PROJECTILE), |r| r.glyph),
+                    fg: rltk::RGB::named(rltk::WHITE),
+                },
+                Targets::Tile {
+                    tile_idx: map.xy_idx(landing.x, landing.y),
+                },
+            );
+            backpack.remove(item);
+            positions
+                .insert(
+                    item,
+                    Position {
+                        x: landing.x,
+                        y: landing.y,
+                    },
+                )
+                .expect("Unable to insert position");
+
+            let victim = map
+                .entities_at(landing.x, landing.y)
+                .iter()
+                .find(|e| **e != entity && combat_stats.get(**e).is_some())
+                .copied();
+            match victim {
+                Some(victim) => {
+                    let pounds = weights.get(item).map_or(0.0, |w| w.pounds);
+                    let mut damage =
+                        i32::max(1, (pounds / POUNDS_PER_THROWN_DAMAGE).round() as i32);
+                    let sneak = asleep.remove(victim).is_some();
+                    if sneak {
+                        damage *= SNEAK_ATTACK_MULTIPLIER;
+                    }
+                    damage += map.elevation_bonus(from, landing);
+                    if is_player {
+                        gamelog.entries.push(format!(
+                            "{sneak}You throw {item_name} at {victim_name}, hitting for {damage} hp.",
+                            sneak = if sneak { "Sneak attack! " } else { "" },
+                            victim_name = with_the(names.get(victim).unwrap())
+                        ));
+                    }
+                    SufferDamage::new_damage(
+                        &mut suffer_damage,
+                        victim,
+                        damage,
+                        DamageType::Physical,
+                        names.get(entity).map(|name| name.name.as_str()),
+                        Some(entity),
+                        is_player,
+                    );
+                }
+                None if is_player => {
+                    gamelog.entries.push(format!("You throw {item_name}."));
+                }
+                None => {}
+            }
+        }
+
+        wants_throw.clear();
     }
 }
 
@@ -309,6 +1150,9 @@ impl<'a> System<'a> for ItemDropSystem {
         ReadStorage<'a, Name>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, InBackpack>,
+        ReadExpect<'a, MasterDungeonMap>,
+        ReadStorage<'a, LightSource>,
+        WriteStorage<'a, Viewshed>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -320,20 +1164,26 @@ impl<'a> System<'a> for ItemDropSystem {
             names,
             mut positions,
             mut backpack,
+            dungeon_map,
+            lights,
+            mut viewsheds,
         ) = data;
 
-        for (entity, to_drop) in (&entities, &wants_drop).join() {
-            let dropper_pos = positions.get(entity).unwrap().clone();
+        for (item, to_drop) in (&entities, &wants_drop).join() {
+            let dropper_pos = positions.get(to_drop.dropped_by).unwrap().clone();
 
             positions
-                .insert(to_drop.item, dropper_pos)
+                .insert(item, dropper_pos)
                 .expect("Unable to insert position");
-            backpack.remove(to_drop.item);
+            backpack.remove(item);
+            if lights.get(item).is_some() {
+                mark_vision_dirty(&mut viewsheds, to_drop.dropped_by);
+            }
 
-            if entity == *player_entity {
+            if to_drop.dropped_by == *player_entity {
                 gamelog.entries.push(format!(
-                    "You drop the {item_name}.",
-                    item_name = names.get(to_drop.item).unwrap().name
+                    "You drop {item_name}.",
+                    item_name = with_the(&dungeon_map.shown_name(names.get(item).unwrap()))
                 ));
             }
         }
@@ -372,8 +1222,8 @@ impl<'a> System<'a> for ItemRemoveSystem {
                 .expect("Unable to insert backpack");
             if entity == *player_entity {
                 gamelog.entries.push(format!(
-                    "You unequip the {item_name}.",
-                    item_name = names.get(to_remove.item).unwrap().name
+                    "You unequip {item_name}.",
+                    item_name = with_the(names.get(to_remove.item).unwrap())
                 ));
             }
         }
@@ -381,3 +1231,122 @@ impl<'a> System<'a> for ItemRemoveSystem {
         wants_remove.clear();
     }
 }
+
+/// Half the angle a cone spreads over, as its cosine. Tiles within 45 degrees either side of the
+/// aim are caught.
+const CONE_SPREAD_COS: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The tiles an area effect covers when `user` aims it at `target`. A circle spreads from where
+/// it lands, so it can reach around corners the user can't see past. A line runs from the user
+/// through the target, and a cone fans out from the user towards it. A cross reaches straight
+/// out from where it lands. Every shape stops at walls the same way sight does, and the map's
+/// edge is never caught.
+pub fn aoe_tiles(map: &Map, user: Point, target: Point, aoe: &AreaOfEffect) -> Vec<Point> {
+    let in_bounds = |p: &Point| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height;
+    let open = |p: &Point| in_bounds(p) && !map.is_opaque(map.xy_idx(p.x, p.y));
+
+    let mut tiles = match aoe.shape {
+        AoeShape::Circle => rltk::field_of_view(target, aoe.radius, map),
+        AoeShape::Line => {
+            // Aim far enough past the target for the beam to reach its full length
+            let (dx, dy) = (target.x - user.x, target.y - user.y);
+            let steps = i32::max(1, i32::max(dx.abs(), dy.abs()));
+            let scale = (aoe.radius + steps - 1) / steps;
+            let end = Point::new(user.x + dx * scale, user.y + dy * scale);
+            rltk::line2d(rltk::LineAlg::Bresenham, user, end)
+                .into_iter()
+                .skip(1)
+                .take(aoe.radius as usize)
+                .take_while(open)
+                .collect()
+        }
+        AoeShape::Cone => {
+            let aim = ((target.x - user.x) as f32, (target.y - user.y) as f32);
+            let aim_length = (aim.0 * aim.0 + aim.1 * aim.1).sqrt();
+            rltk::field_of_view(user, aoe.radius, map)
+                .into_iter()
+                .filter(|p| {
+                    let offset = ((p.x - user.x) as f32, (p.y - user.y) as f32);
+                    let length = (offset.0 * offset.0 + offset.1 * offset.1).sqrt();
+                    length > 0.0
+                        && aim_length > 0.0
+                        && (offset.0 * aim.0 + offset.1 * aim.1) / (length * aim_length)
+                            >= CONE_SPREAD_COS
+                })
+                .collect()
+        }
+        AoeShape::Cross => {
+            let mut tiles = vec![target];
+            for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0)] {
+                tiles.extend(
+                    (1..=aoe.radius)
+                        .map(|step| Point::new(target.x + dx * step, target.y + dy * step))
+                        .take_while(open),
+                );
+            }
+            tiles
+        }
+    };
+    tiles.retain(|p| p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1);
+    tiles
+}
+
+/// Equips `item` on `owner`, moving whatever was already in that slot back to the owner's
+/// backpack. Returns the items that were unequipped.
+///
+/// A one-handed weapon goes in the off hand if the main hand already holds one and the off hand
+/// is free. A two-handed weapon also empties the off hand, and anything put in the off hand
+/// displaces a two-handed weapon.
+pub fn equip_item(
+    entities: &Entities,
+    equipped: &mut WriteStorage<Equipped>,
+    backpack: &mut WriteStorage<InBackpack>,
+    two_handed: &ReadStorage<TwoHanded>,
+    item: Entity,
+    owner: Entity,
+    slot: EquipmentSlot,
+) -> Vec<Entity> {
+    let held_in = |slot: EquipmentSlot| {
+        (entities, &*equipped)
+            .join()
+            .find(|(_item, held)| held.owner == owner && held.slot == slot)
+            .map(|(item, _held)| item)
+    };
+    let is_two_handed = two_handed.get(item).is_some();
+    let slot = match (
+        held_in(EquipmentSlot::Melee),
+        held_in(EquipmentSlot::OffHand),
+    ) {
+        (Some(main_hand), None)
+            if slot == EquipmentSlot::Melee
+                && !is_two_handed
+                && two_handed.get(main_hand).is_none() =>
+        {
+            EquipmentSlot::OffHand
+        }
+        _ => slot,
+    };
+
+    let to_unequip: Vec<Entity> = (entities, &*equipped)
+        .join()
+        .filter(|(held, already_equipped)| {
+            already_equipped.owner == owner
+                && (already_equipped.slot == slot
+                    || (is_two_handed && already_equipped.slot == EquipmentSlot::OffHand)
+                    || (slot == EquipmentSlot::OffHand && two_handed.get(*held).is_some()))
+        })
+        .map(|(item, _already_equipped)| item)
+        .collect();
+    for unequipped in to_unequip.iter() {
+        equipped.remove(*unequipped);
+        backpack
+            .insert(*unequipped, InBackpack { owner })
+            .expect("Unable to insert backpack entry");
+    }
+
+    equipped
+        .insert(item, Equipped { owner, slot })
+        .expect("Unable to insert equipped component");
+    backpack.remove(item);
+    to_unequip
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// What the player has done on the current level. Saved with the game, so a level played across a
+/// save keeps its count, and started afresh on arriving at a level.
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Default)]
+pub struct LevelStats {
+    /// Monsters the player finished off
+    pub kills: i32,
+    /// Items picked up, not counting gold
+    pub items_found: i32,
+    /// After resistances
+    pub damage_taken: i32,
+}
+
+/// A finished level, as shown on the summary after going down the stairs
+#[derive(PartialEq, Copy, Clone)]
+pub struct LevelSummary {
+    pub depth: i32,
+    pub turns: i32,
+    pub stats: LevelStats,
+}
@@ -1,7 +1,8 @@
-use rltk::{GameState, Point, Rltk};
+use rltk::{GameState, Point, RandomNumberGenerator, Rltk, VirtualKeyCode};
 use specs::{
     prelude::*,
     saveload::{SimpleMarker, SimpleMarkerAllocator},
+    shrev::EventChannel,
 };
 
 pub mod map;
@@ -12,29 +13,67 @@ pub mod player;
 use player::*;
 pub mod rect;
 use rect::Rect;
+mod ai_log;
+mod ambient_system;
+pub mod attributes;
+mod bestiary;
+pub mod classes;
+use classes::PlayerClass;
 pub mod visibility_system;
 use visibility_system::VisibilitySystem;
 pub mod monster_ai_system;
 use monster_ai_system::MonsterAI;
+pub mod pet_ai_system;
+use pet_ai_system::PetAI;
 pub mod map_indexing_system;
 use map_indexing_system::MapIndexingSystem;
 pub mod melee_combat_system;
 use melee_combat_system::MeleeCombatSystem;
 pub mod damage_system;
+pub mod dice;
 use damage_system::DamageSystem;
+#[cfg(debug_assertions)]
+mod debug_console;
+pub mod effects;
+pub mod encumbrance_system;
+use encumbrance_system::EncumbranceSystem;
+mod fire_system;
 mod gamelog;
+pub mod glyphs;
+mod grammar;
 mod gui;
 pub mod inventory_system;
 pub mod spawner;
-use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemRemoveSystem, ItemUseSystem};
+use inventory_system::{
+    ItemCollectionSystem, ItemDropSystem, ItemRemoveSystem, ItemThrowSystem, ItemUseSystem,
+};
 mod hunger_system;
+mod level_stats;
 pub mod map_builders;
+pub mod map_themes;
+pub mod map_transitions;
+use map_transitions::{MapEdge, MapTransitions};
+pub mod master_dungeon_map;
+use master_dungeon_map::MasterDungeonMap;
+mod monster_memory;
+mod morale_system;
 mod particle_system;
 mod random_table;
+mod replay;
 mod saveload_system;
+mod scent_system;
+mod scores;
+mod settings;
+mod sight_system;
+mod spawn_portal_system;
+mod status_system;
+mod time_pressure_system;
 mod trigger_system;
+mod water_system;
 
 const SHOW_MAPGEN_VISUALIZER: bool = true;
+/// How long the level summary stays up if no key is pressed
+const LEVEL_SUMMARY_MS: f32 = 4000.0;
 
 // --- State Start ---
 #[derive(PartialEq, Clone, Copy)]
@@ -44,63 +83,267 @@ pub enum RunState {
     PlayerTurn,
     MonsterTurn,
     ShowInventory,
+    ExamineItem {
+        item: Entity,
+    },
     ShowDropItem,
     ShowRemoveItem,
     ShowTargeting {
         range: i32,
         item: Entity,
     },
+    ShowThrowItem,
+    ShowThrowTargeting {
+        item: Entity,
+    },
+    /// Surveying the map with a cursor, `offset` away from the player. No time passes.
+    FreeLook {
+        offset: Point,
+    },
+    ShowIdentify {
+        item: Entity,
+    },
+    ShowEquipFromGround,
+    ShowUseFromGround,
     MainMenu {
         menu_selection: gui::MainMenuSelection,
     },
+    SelectClass,
+    /// The save couldn't be loaded. Shows why, then returns to the main menu.
+    LoadFailed {
+        error: saveload_system::LoadError,
+    },
+    /// Feeds the recorded actions of a replay through the game, one a frame
+    PlayingReplay,
+    /// The replay couldn't be loaded or stopped matching the game. Shows why, then returns to the
+    /// main menu.
+    ReplayFailed {
+        error: replay::ReplayError,
+    },
+    LevelUp,
+    /// Asks whether to save before quitting. Takes no turn either way.
+    ConfirmQuit,
+    ShowVendor {
+        vendor: Entity,
+        mode: gui::VendorMode,
+    },
     SaveGame,
     NextLevel,
+    /// Sums up the level just left, before play carries on from `PreRun` on the new one
+    LevelSummary {
+        summary: level_stats::LevelSummary,
+    },
+    /// The player stepped onto an exit on this edge of the map
+    MapTransition {
+        edge: MapEdge,
+    },
     GameOver,
     MagicMapReveal {
         row: i32,
     },
     MapGeneration,
+    ShowScores,
+    ShowBestiary,
+    /// Lists the key bindings. Takes no turn.
+    ShowHelp {
+        page: usize,
+    },
+    Options {
+        highlighted: usize,
+        /// Opened from the game rather than the main menu
+        in_game: bool,
+    },
+    #[cfg(debug_assertions)]
+    DebugConsole,
+}
+
+/// Rounds played this run, counted at the end of each monster turn
+pub struct TurnCounter {
+    pub turns: i32,
+    /// The round the player arrived on the current level
+    pub level_start: i32,
+    /// Whether this run is played under time pressure. Set when the run starts and saved with it.
+    pub time_pressure: bool,
 }
 
 pub struct State {
     pub ecs: World,
+    morale_system: morale_system::MoraleSystem,
     mapgen_next_state: Option<RunState>,
     mapgen_history: Vec<Map>,
     mapgen_index: usize,
     mapgen_timer: f32,
+    /// How long the level summary has been up
+    summary_timer: f32,
+    /// Items ticked in the drop menu, kept between frames until the menu closes
+    drop_selection: Vec<Entity>,
+    /// Hotkeys of the items in the backpack. An item keeps its letter for as long as it's carried,
+    /// so sorting the inventory never moves it to another key.
+    inventory_letters: std::collections::HashMap<Entity, char>,
+    /// The run being recorded, if it was started from a new game
+    recording: Option<replay::Replay>,
+    /// The replay being played back, one action a frame
+    playback: Option<replay::Playback>,
 }
 
 impl State {
-    fn generate_world_map(&mut self, new_depth: i32) {
+    /// Builds and populates the level at `new_depth`. The player starts on the exit on
+    /// `arrive_by` if there is one, or wherever the builder chose otherwise.
+    fn generate_world_map(&mut self, new_depth: i32, arrive_by: Option<MapEdge>) {
         self.mapgen_index = 0;
         self.mapgen_timer = 0.0;
         self.mapgen_history.clear();
-        let mut builder = map_builders::random_builder(new_depth);
-        builder.build_map();
+        let mut builder = {
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            let mut builder = map_builders::random_builder(new_depth, &mut rng);
+            builder.build_map(&mut rng);
+            builder
+        };
         self.mapgen_history = builder.get_snapshot_history();
         let player_start = {
-            let mut worldmap_resource = self.ecs.write_resource::<Map>();
-            *worldmap_resource = builder.get_map();
-
-            builder.get_starting_position()
+            let mut map = builder.get_map();
+            self.ecs.fetch::<MapTransitions>().carve_exits(&mut map);
+            let start = arrive_by
+                .and_then(|edge| map_transitions::exit_on(&map, edge))
+                .unwrap_or_else(|| builder.get_starting_position());
+            *self.ecs.write_resource::<Map>() = map;
+            start
         };
 
         // Spawn bad guys
         builder.spawn_entities(&mut self.ecs);
+        {
+            let mut turn_counter = self.ecs.fetch_mut::<TurnCounter>();
+            turn_counter.level_start = turn_counter.turns;
+        }
+        *self.ecs.write_resource::<ambient_system::AmbientState>() =
+            ambient_system::AmbientState::default();
+        *self.ecs.write_resource::<level_stats::LevelStats>() = level_stats::LevelStats::default();
+        *self.ecs.write_resource::<monster_memory::MonsterMemory>() =
+            monster_memory::MonsterMemory::default();
+        if new_depth == 0 {
+            self.place_shopkeeper();
+        }
 
         // Place the player and update resources
-        let mut player_position = self.ecs.write_resource::<Point>();
-        *player_position = Point::new(player_start.x, player_start.y);
-        let mut position_components = self.ecs.write_storage::<Position>();
-        let player_entity = self.ecs.fetch::<Entity>();
-        if let Some(player_pos_comp) = position_components.get_mut(*player_entity) {
-            *player_pos_comp = player_start;
+        {
+            let mut player_position = self.ecs.write_resource::<Point>();
+            *player_position = Point::new(player_start.x, player_start.y);
+            let mut position_components = self.ecs.write_storage::<Position>();
+            let player_entity = self.ecs.fetch::<Entity>();
+            if let Some(player_pos_comp) = position_components.get_mut(*player_entity) {
+                *player_pos_comp = player_start.clone();
+            }
+
+            // Mark the player's visibility as dirty
+            let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
+            if let Some(player_vs) = viewshed_components.get_mut(*player_entity) {
+                player_vs.dirty = true;
+            }
+        }
+
+        self.place_pets_near(&player_start);
+        self.ecs.maintain();
+    }
+
+    /// The surface has a shop. Its keeper sets up on the free floor tile nearest the middle of
+    /// the map.
+    fn place_shopkeeper(&mut self) {
+        let spot = {
+            let map = self.ecs.fetch::<Map>();
+            let positions = self.ecs.read_storage::<Position>();
+            let occupied: Vec<(i32, i32)> = positions.join().map(|pos| (pos.x, pos.y)).collect();
+            let middle = Point::new(map.width / 2, map.height / 2);
+            map.tiles
+                .iter()
+                .enumerate()
+                .filter(|(_idx, tile)| tile.is_floor())
+                .map(|(idx, _tile)| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+                .filter(|pt| !occupied.contains(&(pt.x, pt.y)))
+                .min_by_key(|pt| (pt.x - middle.x).pow(2) + (pt.y - middle.y).pow(2))
+        };
+        if let Some(spot) = spot {
+            spawner::shopkeeper(
+                &mut self.ecs,
+                Position {
+                    x: spot.x,
+                    y: spot.y,
+                },
+            );
+        }
+    }
+
+    /// Puts the player's pets on free tiles next to `start`. A pet with no room is left behind.
+    fn place_pets_near(&mut self, start: &Position) {
+        let entities = self.ecs.entities();
+        let pets = self.ecs.read_storage::<Pet>();
+        let names = self.ecs.read_storage::<Name>();
+        let blockers = self.ecs.read_storage::<BlocksTile>();
+        let mut positions = self.ecs.write_storage::<Position>();
+        let mut viewsheds = self.ecs.write_storage::<Viewshed>();
+        let map = self.ecs.fetch::<Map>();
+        let mut gamelog = self.ecs.fetch_mut::<gamelog::Gamelog>();
+
+        let mut occupied: Vec<(i32, i32)> = (&blockers, &positions, !&pets)
+            .join()
+            .map(|(_blocker, pos, _)| (pos.x, pos.y))
+            .collect();
+        occupied.push((start.x, start.y));
+
+        let pet_entities: Vec<Entity> = (&entities, &pets).join().map(|(e, _pet)| e).collect();
+        for pet in pet_entities {
+            let free_tile = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (start.x + dx, start.y + dy)))
+                .find(|&(x, y)| {
+                    x > 0
+                        && x < map.width - 1
+                        && y > 0
+                        && y < map.height - 1
+                        && map.tiles[map.xy_idx(x, y)] != TileType::Wall
+                        && !occupied.contains(&(x, y))
+                });
+
+            match free_tile {
+                Some((x, y)) => {
+                    positions
+                        .insert(pet, Position { x, y })
+                        .expect("Unable to insert position");
+                    if let Some(viewshed) = viewsheds.get_mut(pet) {
+                        viewshed.dirty = true;
+                    }
+                    occupied.push((x, y));
+                }
+                None => {
+                    if let Some(name) = names.get(pet) {
+                        gamelog.entries.push(format!(
+                            "There is no room for {}, it is left behind.",
+                            name.name
+                        ));
+                    }
+                    entities.delete(pet).expect("Unable to delete entity");
+                }
+            }
+        }
+    }
+
+    /// Saves without leaving the game. Done before a level is left, so the save is complete
+    /// before the next level is built. A failed save is noted in the log and play carries on.
+    fn autosave(&mut self) {
+        if let Err(e) = saveload_system::save_game(&mut self.ecs) {
+            self.ecs
+                .fetch_mut::<gamelog::Gamelog>()
+                .entries
+                .push(format!("Autosave failed: {e}"));
         }
+    }
 
-        // Mark the player's visibility as dirty
-        let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
-        if let Some(player_vs) = viewshed_components.get_mut(*player_entity) {
-            player_vs.dirty = true;
+    /// The level the player is on, as it stands
+    fn level_summary(&self) -> level_stats::LevelSummary {
+        let turn_counter = self.ecs.fetch::<TurnCounter>();
+        level_stats::LevelSummary {
+            depth: self.ecs.fetch::<Map>().depth,
+            turns: turn_counter.turns - turn_counter.level_start,
+            stats: *self.ecs.fetch::<level_stats::LevelStats>(),
         }
     }
 
@@ -115,7 +358,7 @@ impl State {
             let worldmap_resource = self.ecs.fetch::<Map>();
             worldmap_resource.depth
         };
-        self.generate_world_map(current_depth + 1);
+        self.generate_world_map(current_depth + 1, None);
 
         // Notify the player and give them some health
         let player_entity = self.ecs.fetch::<Entity>();
@@ -131,12 +374,41 @@ impl State {
         }
     }
 
+    /// Leaves the level by an edge exit. The player keeps everything they would on the stairs,
+    /// and arrives on the matching exit of the level it leads to.
+    fn goto_connected_map(&mut self, edge: MapEdge) {
+        let current_depth = self.ecs.fetch::<Map>().depth;
+        let Some(destination) = self
+            .ecs
+            .fetch::<MapTransitions>()
+            .destination(current_depth, edge)
+        else {
+            return;
+        };
+
+        let to_delete = self.entities_to_remove_on_level_change();
+        for e in to_delete {
+            self.ecs.delete_entity(e).expect("Unable to delete entity");
+        }
+        self.generate_world_map(destination, Some(edge.opposite()));
+
+        self.ecs
+            .fetch_mut::<gamelog::Gamelog>()
+            .entries
+            .push(format!("You head {} into a new area.", edge.name()));
+    }
+
     fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
         let entities = self.ecs.entities();
         let player = self.ecs.read_storage::<Player>();
         let backpack = self.ecs.read_storage::<InBackpack>();
         let player_entity = self.ecs.fetch::<Entity>();
         let equipped = self.ecs.read_storage::<Equipped>();
+        let pets = self.ecs.read_storage::<Pet>();
+        let positions = self.ecs.read_storage::<Position>();
+        let names = self.ecs.read_storage::<Name>();
+        let player_pos = self.ecs.fetch::<Point>();
+        let mut gamelog = self.ecs.fetch_mut::<gamelog::Gamelog>();
 
         let mut to_delete: Vec<Entity> = Vec::new();
 
@@ -150,8 +422,26 @@ impl State {
                 Some(e) => e.owner == *player_entity,
             };
 
-            // Don't delete player or their items
-            if is_in_player_backpack || player.get(e).is_some() || is_equipped {
+            // Pets only follow the player down the stairs if they're right next to them
+            let is_pet_following = pets.get(e).is_some()
+                && match positions.get(e) {
+                    None => false,
+                    Some(pos) => {
+                        rltk::DistanceAlg::Pythagoras
+                            .distance2d(Point::new(pos.x, pos.y), *player_pos)
+                            < 1.5
+                    }
+                };
+            if pets.get(e).is_some() && !is_pet_following {
+                if let Some(name) = names.get(e) {
+                    gamelog
+                        .entries
+                        .push(format!("{} is too far away and is left behind.", name.name));
+                }
+            }
+
+            // Don't delete player, their items or pets at their side
+            if is_in_player_backpack || player.get(e).is_some() || is_equipped || is_pet_following {
                 continue;
             } else {
                 to_delete.push(e);
@@ -162,28 +452,424 @@ impl State {
 
     fn game_over_cleanup(&mut self) {
         self.ecs.delete_all();
+        {
+            let mut turn_counter = self.ecs.fetch_mut::<TurnCounter>();
+            turn_counter.turns = 0;
+            turn_counter.level_start = 0;
+        }
 
         // Spawn a new player
         {
-            let player_entity = spawner::player(&mut self.ecs, Position { x: 0, y: 0 });
+            let player_entity =
+                spawner::player(&mut self.ecs, Position { x: 0, y: 0 }, PlayerClass::Fighter);
+            spawner::dog(&mut self.ecs, Position { x: 0, y: 0 });
             let mut player_entity_writer = self.ecs.write_resource::<Entity>();
             *player_entity_writer = player_entity;
         }
 
         // Build a new map and place the player
-        self.generate_world_map(1);
+        self.generate_world_map(1, None);
+    }
+
+    /// The world is built before the player picks a class, so the placeholder player is swapped
+    /// for one of the chosen class standing in the same spot. Their starting kit goes with them.
+    /// Item disguises are rolled afresh, so only the starting kit is identified, and the bestiary
+    /// starts empty.
+    fn start_new_game(&mut self, class: PlayerClass) {
+        let old_player = *self.ecs.fetch::<Entity>();
+        let mut to_delete: Vec<Entity> = {
+            let entities = self.ecs.entities();
+            let backpack = self.ecs.read_storage::<InBackpack>();
+            let equipped = self.ecs.read_storage::<Equipped>();
+            entities
+                .join()
+                .filter(|e| {
+                    backpack.get(*e).is_some_and(|b| b.owner == old_player)
+                        || equipped.get(*e).is_some_and(|eq| eq.owner == old_player)
+                })
+                .collect()
+        };
+        to_delete.push(old_player);
+        for e in to_delete {
+            self.ecs.delete_entity(e).expect("Unable to delete entity");
+        }
+
+        let player_pos = *self.ecs.fetch::<Point>();
+        let player_entity = spawner::player(
+            &mut self.ecs,
+            Position {
+                x: player_pos.x,
+                y: player_pos.y,
+            },
+            class,
+        );
+        *self.ecs.write_resource::<Entity>() = player_entity;
+        let difficulty = self.ecs.fetch::<settings::GameSettings>().difficulty;
+        attributes::apply_difficulty(&mut self.ecs, difficulty);
+        self.ecs.fetch_mut::<TurnCounter>().time_pressure =
+            self.ecs.fetch::<settings::GameSettings>().time_pressure;
+
+        // The player knows what they packed
+        let mut dungeon_map =
+            MasterDungeonMap::new(&mut self.ecs.fetch_mut::<RandomNumberGenerator>());
+        let names = self.ecs.read_storage::<Name>();
+        let backpack = self.ecs.read_storage::<InBackpack>();
+        for (pack, name) in (&backpack, &names).join() {
+            if pack.owner == player_entity {
+                dungeon_map.identify(&name.name);
+            }
+        }
+        *self.ecs.write_resource::<MasterDungeonMap>() = dungeon_map;
+        *self.ecs.write_resource::<bestiary::Bestiary>() = bestiary::Bestiary::default();
+    }
+
+    /// Starts a run on a fresh world, so the same seed, class and settings always play out the
+    /// same way. That's what lets a recorded run be replayed.
+    fn begin_run(&mut self, seed: u64, class: PlayerClass, settings: settings::GameSettings) {
+        let (ecs, morale_system) = new_world(RandomNumberGenerator::seeded(seed));
+        self.ecs = ecs;
+        self.morale_system = morale_system;
+        self.ecs.insert(scores::RunSeed { seed });
+        self.ecs.insert(settings);
+        self.drop_selection.clear();
+        self.inventory_letters.clear();
+        self.recording = None;
+        self.playback = None;
+        self.generate_world_map(1, None);
+        self.start_new_game(class);
+    }
+
+    /// A fingerprint of everything play depends on: the round, the level's tiles, where every
+    /// creature stands and how hurt it is, and the state of the random number generator. Two
+    /// worlds with the same hash play out the same from here. Creatures are sorted by position
+    /// first, so entity ids and storage order don't come into it, and particles and frame timers
+    /// are left out.
+    pub fn world_hash(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let positions = self.ecs.read_storage::<Position>();
+        let combat_stats = self.ecs.read_storage::<CombatStats>();
+        let mut creatures: Vec<(i32, i32, i32)> = (&positions, &combat_stats)
+            .join()
+            .map(|(pos, stats)| (pos.x, pos.y, stats.hp))
+            .collect();
+        creatures.sort_unstable();
+
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let player = (
+            positions.get(player_entity),
+            combat_stats.get(player_entity),
+        );
+
+        let mut hasher = replay::FnvHasher::default();
+        self.ecs.fetch::<TurnCounter>().turns.hash(&mut hasher);
+        {
+            let map = self.ecs.fetch::<Map>();
+            map.depth.hash(&mut hasher);
+            map.tiles.hash(&mut hasher);
+        }
+        if let (Some(pos), Some(stats)) = player {
+            (pos.x, pos.y, stats.hp).hash(&mut hasher);
+        }
+        creatures.hash(&mut hasher);
+        // What the generator would roll next stands in for how far along it is, without using it up
+        let mut lookahead = (*self.ecs.fetch::<RandomNumberGenerator>()).clone();
+        lookahead.next_u64().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Writes out the replay of the run being recorded, if any, and stops recording
+    fn finish_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            recording.save();
+        }
+    }
+
+    /// Plays the next action of the replay through the headless tick. Returns the state to carry
+    /// on from once the replay is over, or `None` while there's more to play.
+    fn step_replay(&mut self) -> Result<Option<RunState>, replay::ReplayError> {
+        let Some(mut playback) = self.playback.take() else {
+            return Ok(Some(RunState::AwaitingInput));
+        };
+
+        // Finish off whatever the last action started
+        *self.ecs.write_resource::<RunState>() = playback.runstate;
+        self.tick_headless(None);
+        let runstate = *self.ecs.fetch::<RunState>();
+        if runstate == RunState::GameOver {
+            return Ok(Some(runstate));
+        }
+
+        let turn = self.ecs.fetch::<TurnCounter>().turns;
+        match playback.next_action(turn, self.world_hash())? {
+            None => Ok(Some(runstate)),
+            Some(action) => {
+                self.tick_headless(Some(action));
+                playback.runstate = *self.ecs.fetch::<RunState>();
+                self.playback = Some(playback);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Records `action` if the run is being recorded, then takes it from `runstate`
+    pub fn take_action(&mut self, runstate: RunState, action: Action) -> RunState {
+        let turn = self.ecs.fetch::<TurnCounter>().turns;
+        let world_hash = self.world_hash();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record(turn, action, world_hash);
+        }
+        self.apply_action(runstate, action)
+    }
+
+    /// Takes `action` on the main game screen if the player is awaiting input, or as a choice in
+    /// the menu `runstate` shows otherwise. Returns the state the game should move to.
+    fn apply_action(&mut self, runstate: RunState, action: Action) -> RunState {
+        if runstate == RunState::AwaitingInput {
+            perform_action(&mut self.ecs, action)
+        } else {
+            self.perform_menu_action(runstate, action)
+        }
+    }
+
+    /// Takes a choice made in the menu `runstate` shows and returns the state to move to. A choice
+    /// that doesn't belong in that menu, or names an item that's gone, changes nothing.
+    fn perform_menu_action(&mut self, runstate: RunState, action: Action) -> RunState {
+        match (runstate, action) {
+            (RunState::ShowDropItem, Action::CloseMenu) => {
+                self.drop_selection.clear();
+                RunState::AwaitingInput
+            }
+            #[cfg(debug_assertions)]
+            (RunState::DebugConsole, Action::CloseMenu) => RunState::PreRun,
+            (_, Action::CloseMenu) => RunState::AwaitingInput,
+            (_, Action::ChooseItem { item }) => match player::marked_entity(&self.ecs, item) {
+                Some(item) => self.choose_item(runstate, item),
+                None => runstate,
+            },
+            (RunState::ShowTargeting { item, .. }, Action::ChooseTarget { x, y }) => {
+                self.ecs
+                    .write_storage::<WantsToUseItem>()
+                    .insert(
+                        *self.ecs.fetch::<Entity>(),
+                        WantsToUseItem {
+                            item,
+                            target: Some(Point::new(x, y)),
+                        },
+                    )
+                    .expect("Unable to insert intent");
+                player::remember_use(&self.ecs, item);
+                RunState::PlayerTurn
+            }
+            (RunState::ShowThrowTargeting { item }, Action::ChooseTarget { x, y }) => {
+                match inventory_system::take_one(&mut self.ecs, item) {
+                    None => RunState::AwaitingInput,
+                    Some(item) => {
+                        self.ecs
+                            .write_storage::<WantsToThrowItem>()
+                            .insert(
+                                *self.ecs.fetch::<Entity>(),
+                                WantsToThrowItem {
+                                    item,
+                                    target: Point::new(x, y),
+                                },
+                            )
+                            .expect("Unable to insert intent");
+                        RunState::PlayerTurn
+                    }
+                }
+            }
+            // Monsters wait until every point is spent
+            (RunState::LevelUp, Action::ChooseAttribute { attribute }) => {
+                attributes::spend_point(&mut self.ecs, attribute);
+                if attributes::player_has_unspent_points(&self.ecs) {
+                    RunState::LevelUp
+                } else {
+                    RunState::MonsterTurn
+                }
+            }
+            (
+                RunState::ShowVendor {
+                    vendor,
+                    mode: gui::VendorMode::Buy,
+                },
+                Action::ChooseWare { ware },
+            ) => {
+                let name = self
+                    .ecs
+                    .read_storage::<Vendor>()
+                    .get(vendor)
+                    .and_then(|vendor| vendor.sells.get(ware).cloned());
+                if let Some(name) = name {
+                    inventory_system::buy_item(&mut self.ecs, &name);
+                }
+                runstate
+            }
+            (RunState::ShowVendor { vendor, mode }, Action::SwitchVendorMode) => {
+                let mode = match mode {
+                    gui::VendorMode::Buy => gui::VendorMode::Sell,
+                    gui::VendorMode::Sell => gui::VendorMode::Buy,
+                };
+                RunState::ShowVendor { vendor, mode }
+            }
+            (RunState::ShowDropItem, Action::DropChosen) => {
+                let player_entity = *self.ecs.fetch::<Entity>();
+                // Only one comes off each stack, the rest stay in the backpack
+                let items: Vec<Entity> = std::mem::take(&mut self.drop_selection)
+                    .into_iter()
+                    .filter_map(|item| inventory_system::take_one(&mut self.ecs, item))
+                    .collect();
+                let mut intent = self.ecs.write_storage::<WantsToDropItem>();
+                for item in items {
+                    intent
+                        .insert(
+                            item,
+                            WantsToDropItem {
+                                dropped_by: player_entity,
+                            },
+                        )
+                        .expect("Unable to insert intent");
+                }
+                RunState::PlayerTurn
+            }
+            (RunState::ShowDropItem, Action::DestroyChosen) => {
+                let items: Vec<Entity> = std::mem::take(&mut self.drop_selection)
+                    .into_iter()
+                    .filter_map(|item| inventory_system::take_one(&mut self.ecs, item))
+                    .collect();
+                inventory_system::destroy_items(&mut self.ecs, &items);
+                RunState::PlayerTurn
+            }
+            // The rules of a run are fixed once it has started
+            (RunState::Options { in_game, .. }, Action::ChangeOption { option })
+                if !(in_game && option.new_game_only()) =>
+            {
+                self.ecs
+                    .fetch_mut::<settings::GameSettings>()
+                    .change(option);
+                if option.changes_sight() {
+                    for viewshed in (&mut self.ecs.write_storage::<Viewshed>()).join() {
+                        viewshed.dirty = true;
+                    }
+                }
+                runstate
+            }
+            _ => runstate,
+        }
+    }
+
+    /// The action an item menu's result amounts to, if the player did anything
+    fn item_menu_action(
+        &self,
+        (item_menu_result, item): (gui::ItemMenuResult, Option<Entity>),
+    ) -> Option<Action> {
+        match item_menu_result {
+            gui::ItemMenuResult::NoResponse => None,
+            gui::ItemMenuResult::Cancel => Some(Action::CloseMenu),
+            gui::ItemMenuResult::Selected => Some(Action::choose_item(&self.ecs, item.unwrap())),
+        }
+    }
+
+    /// What picking `item` does depends on the menu it was picked from
+    fn choose_item(&mut self, runstate: RunState, item: Entity) -> RunState {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        match runstate {
+            RunState::ShowInventory | RunState::ShowUseFromGround => {
+                player::use_item(&self.ecs, item)
+            }
+            RunState::ShowEquipFromGround => {
+                player::equip_from_ground(&self.ecs, item);
+                RunState::PlayerTurn
+            }
+            RunState::ShowRemoveItem => {
+                self.ecs
+                    .write_storage::<WantsToRemoveItem>()
+                    .insert(player_entity, WantsToRemoveItem { item })
+                    .expect("Unable to insert intent");
+                RunState::PlayerTurn
+            }
+            RunState::ShowThrowItem => RunState::ShowThrowTargeting { item },
+            RunState::ShowIdentify { item: scroll } => {
+                self.ecs
+                    .write_storage::<WantsToIdentifyItem>()
+                    .insert(player_entity, WantsToIdentifyItem { item })
+                    .expect("Unable to insert intent");
+                self.ecs
+                    .write_storage::<WantsToUseItem>()
+                    .insert(
+                        player_entity,
+                        WantsToUseItem {
+                            item: scroll,
+                            target: None,
+                        },
+                    )
+                    .expect("Unable to insert intent");
+                player::remember_use(&self.ecs, scroll);
+                RunState::PlayerTurn
+            }
+            RunState::ShowDropItem => {
+                match self.drop_selection.iter().position(|e| *e == item) {
+                    Some(ticked) => {
+                        self.drop_selection.remove(ticked);
+                    }
+                    None => self.drop_selection.push(item),
+                }
+                runstate
+            }
+            RunState::ShowVendor {
+                mode: gui::VendorMode::Sell,
+                ..
+            } => {
+                inventory_system::sell_item(&mut self.ecs, item);
+                runstate
+            }
+            _ => runstate,
+        }
     }
 
     fn run_systems(&mut self) {
+        self.run_game_systems();
+
+        let mut particle_system = particle_system::ParticleSpawnSystem;
+        particle_system.run_now(&self.ecs);
+
+        self.ecs.maintain();
+    }
+
+    /// Runs every system that affects gameplay. Purely visual systems are left to the caller.
+    fn run_game_systems(&mut self) {
+        let mut sight = sight_system::SightSystem;
+        sight.run_now(&self.ecs);
+
         let mut vis = VisibilitySystem;
         vis.run_now(&self.ecs);
+        monster_memory::remember_monsters(&self.ecs);
+
+        let mut scent = scent_system::ScentSystem;
+        scent.run_now(&self.ecs);
+
+        self.morale_system.run_now(&self.ecs);
 
         let mut mob = MonsterAI;
         mob.run_now(&self.ecs);
 
+        let mut pet_ai = PetAI;
+        pet_ai.run_now(&self.ecs);
+
+        let mut status_system = status_system::StatusSystem;
+        status_system.run_now(&self.ecs);
+
         let mut trigger_system = trigger_system::TriggerSystem;
         trigger_system.run_now(&self.ecs);
 
+        let mut fire_system = fire_system::FireSystem;
+        fire_system.run_now(&self.ecs);
+
+        let mut water_system = water_system::WaterSystem;
+        water_system.run_now(&self.ecs);
+
+        effects::run_effects_queue(&mut self.ecs);
+
         let mut mapindex = MapIndexingSystem;
         mapindex.run_now(&self.ecs);
 
@@ -196,6 +882,10 @@ impl State {
         let mut item_collection_system = ItemCollectionSystem;
         item_collection_system.run_now(&self.ecs);
 
+        // Anything shattering as it lands is used there, so this goes first
+        let mut item_throw_system = ItemThrowSystem;
+        item_throw_system.run_now(&self.ecs);
+
         let mut item_use_system = ItemUseSystem;
         item_use_system.run_now(&self.ecs);
 
@@ -205,12 +895,96 @@ impl State {
         let mut item_remove_system = ItemRemoveSystem;
         item_remove_system.run_now(&self.ecs);
 
+        effects::run_effects_queue(&mut self.ecs);
+
+        let mut encumbrance_system = EncumbranceSystem;
+        encumbrance_system.run_now(&self.ecs);
+
         let mut hunger_system = hunger_system::HungerSystem;
         hunger_system.run_now(&self.ecs);
+    }
 
-        let mut particle_system = particle_system::ParticleSpawnSystem;
-        particle_system.run_now(&self.ecs);
+    /// Advances the game without a window: takes `input` on the main game screen or in whatever
+    /// menu is open, then resolves turns until the game needs input again. Particles are
+    /// discarded. A trip to an item only takes a step for each `Action::TravelToLoot`, the way
+    /// the game records it, so a trip called off part way plays back the same.
+    pub fn tick_headless(&mut self, input: Option<Action>) {
+        let mut runstate = *self.ecs.fetch::<RunState>();
+        if let Some(action) = input {
+            runstate = self.apply_action(runstate, action);
+        }
 
+        loop {
+            runstate = match runstate {
+                RunState::PreRun => {
+                    self.run_headless_systems();
+                    RunState::AwaitingInput
+                }
+                RunState::PlayerTurn => {
+                    self.run_headless_systems();
+                    match *self.ecs.fetch::<RunState>() {
+                        RunState::MagicMapReveal { .. } => RunState::MagicMapReveal { row: 0 },
+                        _ if attributes::player_has_unspent_points(&self.ecs) => RunState::LevelUp,
+                        _ => RunState::MonsterTurn,
+                    }
+                }
+                RunState::MonsterTurn => {
+                    self.run_headless_systems();
+                    if self.player_is_slowed() {
+                        self.run_headless_systems();
+                    }
+                    self.ecs.fetch_mut::<TurnCounter>().turns += 1;
+                    time_pressure_system::apply_time_pressure(&mut self.ecs);
+                    ambient_system::run_ambient_events(&mut self.ecs);
+                    spawn_portal_system::run_portals(&mut self.ecs);
+                    RunState::AwaitingInput
+                }
+                RunState::MagicMapReveal { .. } => {
+                    self.ecs
+                        .write_resource::<ambient_system::AmbientState>()
+                        .keep_revealed();
+                    let mut map = self.ecs.fetch_mut::<Map>();
+                    for revealed in map.revealed_tiles.iter_mut() {
+                        *revealed = true;
+                    }
+                    RunState::MonsterTurn
+                }
+                RunState::NextLevel => {
+                    self.goto_next_level();
+                    RunState::PreRun
+                }
+                RunState::MapTransition { edge } => {
+                    self.goto_connected_map(edge);
+                    RunState::PreRun
+                }
+                // Everything else waits on a menu or the player
+                _ => break,
+            };
+
+            *self.ecs.write_resource::<RunState>() = runstate;
+            damage_system::delete_the_dead(&mut self.ecs);
+            runstate = *self.ecs.fetch::<RunState>();
+        }
+
+        *self.ecs.write_resource::<RunState>() = runstate;
+    }
+
+    /// An overburdened player is slow, as is one caught in a cold snap without a light, so
+    /// monsters get a second turn for each of theirs
+    fn player_is_slowed(&self) -> bool {
+        let player_entity = self.ecs.fetch::<Entity>();
+        self.ecs
+            .read_storage::<Encumbered>()
+            .get(*player_entity)
+            .is_some()
+            || ambient_system::player_is_chilled(&self.ecs)
+    }
+
+    fn run_headless_systems(&mut self) {
+        self.run_game_systems();
+        self.ecs
+            .fetch_mut::<particle_system::ParticleBuilder>()
+            .clear();
         self.ecs.maintain();
     }
 }
@@ -219,35 +993,77 @@ impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
         let mut newrunstate = { *self.ecs.fetch::<RunState>() };
         ctx.cls();
+        if ctx.key == Some(VirtualKeyCode::F1) {
+            self.ecs.fetch_mut::<gui::DebugHud>().toggle();
+        }
         particle_system::cull_dead_particles(&mut self.ecs, ctx);
 
         // Either draw Main Menu or draw map
         match newrunstate {
             RunState::MainMenu { .. } => {}
+            RunState::SelectClass => {}
+            RunState::ShowScores => {}
+            RunState::LoadFailed { .. } => {}
+            RunState::ReplayFailed { .. } => {}
+            RunState::Options { in_game: false, .. } => {}
             RunState::GameOver => {}
             _ => {
-                draw_map(&self.ecs.fetch::<Map>(), ctx);
+                draw_map(
+                    &self.ecs.fetch::<Map>(),
+                    &self.ecs.fetch::<glyphs::GlyphSet>(),
+                    ctx,
+                );
+                monster_memory::draw_ghosts(&self.ecs, ctx);
                 {
                     let map = self.ecs.fetch::<Map>();
                     let positions = self.ecs.read_storage::<Position>();
                     let renderables = self.ecs.read_storage::<Renderable>();
                     let hidden = self.ecs.read_storage::<Hidden>();
+                    let particles = self.ecs.read_storage::<ParticleLifetime>();
+                    let phasing = self.ecs.read_storage::<Phasing>();
+                    let statuses = self.ecs.read_storage::<StatusEffects>();
 
-                    let mut data = (&positions, &renderables, !&hidden)
+                    // Delayed particles wait their turn before showing up
+                    let mut data = (
+                        &positions,
+                        &renderables,
+                        !&hidden,
+                        particles.maybe(),
+                        phasing.maybe(),
+                        statuses.maybe(),
+                    )
                         .join()
+                        .filter(|(pos, _render, _hidden, particle, phasing, _status)| {
+                            particle.is_none_or(|particle| particle.delay_ms <= 0.)
+                                && map.shows_entity(map.xy_idx(pos.x, pos.y), phasing.is_some())
+                        })
+                        .map(|(pos, render, _hidden, _particle, _phasing, status)| {
+                            let raging =
+                                status.is_some_and(|status| status.has(StatusEffectKind::Raging));
+                            (pos, render, raging)
+                        })
                         .collect::<Vec<_>>();
                     data.sort_by(
-                        |&(_a_pos, a_rndr, _a_hidden), &(_b_pos, b_rndr, _b_hidden)| {
+                        |&(_a_pos, a_rndr, _a_raging), &(_b_pos, b_rndr, _b_raging)| {
                             b_rndr.render_order.cmp(&a_rndr.render_order)
                         },
                     );
-                    for (pos, render, _hidden) in data.iter() {
+                    for (pos, render, raging) in data.iter() {
                         let idx = map.xy_idx(pos.x, pos.y);
+                        // Anything in a rage shows red with it
+                        let fg = if *raging {
+                            render.fg.lerp(rltk::RGB::named(rltk::RED), 0.6)
+                        } else {
+                            render.fg
+                        };
                         if map.visible_tiles[idx] {
-                            ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph)
+                            ctx.set(pos.x, pos.y, fg, render.bg, render.glyph)
                         }
                     }
 
+                    if self.ecs.fetch::<settings::GameSettings>().monster_hp_bars {
+                        gui::draw_monster_hp_bars(&self.ecs, ctx);
+                    }
                     gui::draw_ui(&self.ecs, ctx);
                 }
             }
@@ -261,7 +1077,14 @@ impl GameState for State {
                 newrunstate = RunState::AwaitingInput;
             }
             RunState::AwaitingInput => {
-                newrunstate = player_input(self, ctx);
+                newrunstate = if !self.ecs.fetch::<LootTravel>().active {
+                    player_input(self, ctx)
+                } else if ctx.key.is_some() {
+                    // Any key calls off the trip
+                    self.take_action(newrunstate, Action::StopTravel)
+                } else {
+                    self.take_action(newrunstate, Action::TravelToLoot)
+                };
             }
             RunState::PlayerTurn => {
                 self.run_systems();
@@ -270,98 +1093,117 @@ impl GameState for State {
                     RunState::MagicMapReveal { .. } => {
                         newrunstate = RunState::MagicMapReveal { row: 0 }
                     }
+                    _ if attributes::player_has_unspent_points(&self.ecs) => {
+                        newrunstate = RunState::LevelUp
+                    }
                     _ => newrunstate = RunState::MonsterTurn,
                 }
             }
+            RunState::LevelUp => {
+                if let Some(attribute) = gui::level_up(ctx) {
+                    newrunstate =
+                        self.take_action(newrunstate, Action::ChooseAttribute { attribute });
+                }
+            }
             RunState::MonsterTurn => {
                 self.run_systems();
                 self.ecs.maintain();
+                if self.player_is_slowed() {
+                    self.run_systems();
+                    self.ecs.maintain();
+                }
+                self.ecs.fetch_mut::<TurnCounter>().turns += 1;
+                time_pressure_system::apply_time_pressure(&mut self.ecs);
+                ambient_system::run_ambient_events(&mut self.ecs);
+                spawn_portal_system::run_portals(&mut self.ecs);
+                let turns = self.ecs.fetch::<TurnCounter>().turns;
+                if self
+                    .ecs
+                    .fetch::<settings::GameSettings>()
+                    .autosave
+                    .on_turn(turns)
+                {
+                    self.autosave();
+                }
                 newrunstate = RunState::AwaitingInput;
             }
             RunState::ShowDropItem => {
-                let (item_menu_result, item_entity) = gui::drop_item_menu(self, ctx);
-                match item_menu_result {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let item_entity = item_entity.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToDropItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToDropItem { item: item_entity },
-                            )
-                            .expect("Unable to insert intent");
-                        newrunstate = RunState::PlayerTurn;
-                    }
+                let action = match gui::drop_item_menu(self, ctx) {
+                    gui::DropMenuResult::NoResponse => None,
+                    gui::DropMenuResult::Cancel => Some(Action::CloseMenu),
+                    gui::DropMenuResult::Tick(item) => Some(Action::choose_item(&self.ecs, item)),
+                    gui::DropMenuResult::Drop => Some(Action::DropChosen),
+                    gui::DropMenuResult::Destroy => Some(Action::DestroyChosen),
+                };
+                if let Some(action) = action {
+                    newrunstate = self.take_action(newrunstate, action);
                 }
             }
             RunState::ShowRemoveItem => {
-                let (item_menu_result, item_entity) = gui::remove_item_menu(self, ctx);
-                match item_menu_result {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let item_entity = item_entity.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToRemoveItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToRemoveItem { item: item_entity },
-                            )
-                            .expect("Unable to insert intent");
-                        newrunstate = RunState::PlayerTurn;
-                    }
+                let picked = gui::remove_item_menu(self, ctx);
+                if let Some(action) = self.item_menu_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
                 }
             }
             RunState::ShowInventory => {
                 let (item_menu_result, item_entity) = gui::show_inventory(self, ctx);
-                match item_menu_result {
-                    gui::ItemMenuResult::Cancel => {
-                        newrunstate = RunState::AwaitingInput;
-                    }
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let item_entity = item_entity.unwrap();
-                        if let Some(item) = self.ecs.read_storage::<Ranged>().get(item_entity) {
-                            newrunstate = RunState::ShowTargeting {
-                                range: item.range,
-                                item: item_entity,
-                            }
-                        } else {
-                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                            intent
-                                .insert(
-                                    *self.ecs.fetch::<Entity>(),
-                                    WantsToUseItem {
-                                        item: item_entity,
-                                        target: None,
-                                    },
-                                )
-                                .expect("Unable to insert intent");
-                            newrunstate = RunState::PlayerTurn;
-                        }
-                    }
+                // Shift+letter examines the item instead of using it. That changes nothing, so
+                // unlike the choices made in menus it isn't recorded.
+                if item_menu_result == gui::ItemMenuResult::Selected && ctx.shift {
+                    newrunstate = RunState::ExamineItem {
+                        item: item_entity.unwrap(),
+                    };
+                } else if let Some(action) = self.item_menu_action((item_menu_result, item_entity))
+                {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ExamineItem { item } => {
+                if gui::examine_item(self, ctx, item) == gui::ItemMenuResult::Cancel {
+                    newrunstate = RunState::ShowInventory;
                 }
             }
             RunState::ShowTargeting { range, item } => {
-                let (item_menu_result, item_entity) = gui::ranged_target(self, ctx, range);
-                match item_menu_result {
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToUseItem {
-                                    item,
-                                    target: item_entity,
-                                },
-                            )
-                            .expect("Unable to insert intent");
-                        newrunstate = RunState::PlayerTurn;
-                    }
+                let picked = gui::ranged_target(self, ctx, range, Some(item));
+                if let Some(action) = target_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ShowThrowItem => {
+                let picked = gui::throw_item_menu(self, ctx);
+                if let Some(action) = self.item_menu_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ShowThrowTargeting { item } => {
+                // Only something that shatters spreads its effect where it lands
+                let shatters = self.ecs.read_storage::<Fragile>().get(item).is_some();
+                let picked = gui::ranged_target(
+                    self,
+                    ctx,
+                    inventory_system::THROW_RANGE,
+                    shatters.then_some(item),
+                );
+                if let Some(action) = target_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ShowEquipFromGround => {
+                let picked = gui::equip_from_ground_menu(self, ctx);
+                if let Some(action) = self.item_menu_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ShowUseFromGround => {
+                let picked = gui::use_from_ground_menu(self, ctx);
+                if let Some(action) = self.item_menu_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
+            RunState::ShowIdentify { item } => {
+                let picked = gui::identify_menu(self, ctx, item);
+                if let Some(action) = self.item_menu_action(picked) {
+                    newrunstate = self.take_action(newrunstate, action);
                 }
             }
             RunState::MainMenu { .. } => {
@@ -373,11 +1215,34 @@ impl GameState for State {
                         }
                     }
                     gui::MainMenuResult::Selected { highlighted } => match highlighted {
-                        gui::MainMenuSelection::NewGame => newrunstate = RunState::PreRun,
+                        gui::MainMenuSelection::NewGame => newrunstate = RunState::SelectClass,
                         gui::MainMenuSelection::LoadGame => {
-                            saveload_system::load_game(&mut self.ecs);
-                            newrunstate = RunState::AwaitingInput;
-                            saveload_system::delete_save();
+                            match saveload_system::load_game(&mut self.ecs) {
+                                Ok(()) => {
+                                    newrunstate = RunState::AwaitingInput;
+                                    saveload_system::delete_save();
+                                }
+                                Err(error) => {
+                                    // A failed load leaves nothing behind, so start over
+                                    self.game_over_cleanup();
+                                    newrunstate = RunState::LoadFailed { error };
+                                }
+                            }
+                        }
+                        gui::MainMenuSelection::PlayReplay => match replay::load_replay() {
+                            Ok(replay) => {
+                                self.begin_run(replay.seed, replay.class, replay.settings.clone());
+                                self.playback = Some(replay::Playback::new(replay));
+                                newrunstate = RunState::PlayingReplay;
+                            }
+                            Err(error) => newrunstate = RunState::ReplayFailed { error },
+                        },
+                        gui::MainMenuSelection::Scores => newrunstate = RunState::ShowScores,
+                        gui::MainMenuSelection::Options => {
+                            newrunstate = RunState::Options {
+                                highlighted: 0,
+                                in_game: false,
+                            }
                         }
                         gui::MainMenuSelection::Quit => {
                             ::std::process::exit(0);
@@ -385,17 +1250,195 @@ impl GameState for State {
                     },
                 }
             }
+            RunState::SelectClass => {
+                let (menu_result, class) = gui::select_class(ctx);
+                match menu_result {
+                    gui::ItemMenuResult::Cancel => {
+                        newrunstate = RunState::MainMenu {
+                            menu_selection: gui::MainMenuSelection::NewGame,
+                        }
+                    }
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        let class = class.unwrap();
+                        let seed = self.ecs.fetch_mut::<RandomNumberGenerator>().next_u64();
+                        let settings = (*self.ecs.fetch::<settings::GameSettings>()).clone();
+                        self.begin_run(seed, class, settings.clone());
+                        self.recording = Some(replay::Replay::new(seed, class, settings));
+                        newrunstate = RunState::PreRun;
+                    }
+                }
+            }
+            RunState::LoadFailed { error } => {
+                if gui::load_failed(ctx, "Unable to load the game", error)
+                    == gui::ItemMenuResult::Cancel
+                {
+                    newrunstate = RunState::MainMenu {
+                        menu_selection: gui::MainMenuSelection::LoadGame,
+                    };
+                }
+            }
+            RunState::PlayingReplay => {
+                newrunstate = if ctx.key.is_some() {
+                    self.playback = None;
+                    self.ecs
+                        .fetch_mut::<gamelog::Gamelog>()
+                        .entries
+                        .push("You take over from the replay.".to_string());
+                    RunState::AwaitingInput
+                } else {
+                    match self.step_replay() {
+                        Ok(None) => RunState::PlayingReplay,
+                        Ok(Some(runstate)) => {
+                            self.ecs
+                                .fetch_mut::<gamelog::Gamelog>()
+                                .entries
+                                .push("The replay is over.".to_string());
+                            runstate
+                        }
+                        Err(error) => {
+                            self.playback = None;
+                            RunState::ReplayFailed { error }
+                        }
+                    }
+                };
+            }
+            RunState::ReplayFailed { error } => {
+                if gui::load_failed(ctx, "Unable to play the replay", error)
+                    == gui::ItemMenuResult::Cancel
+                {
+                    newrunstate = RunState::MainMenu {
+                        menu_selection: gui::MainMenuSelection::PlayReplay,
+                    };
+                }
+            }
+            RunState::ShowScores => {
+                if gui::show_scores(ctx) == gui::ItemMenuResult::Cancel {
+                    newrunstate = RunState::MainMenu {
+                        menu_selection: gui::MainMenuSelection::Scores,
+                    };
+                }
+            }
+            RunState::ShowHelp { page } => match gui::show_help(ctx, page) {
+                gui::HelpResult::NoResponse => {}
+                gui::HelpResult::Page(page) => newrunstate = RunState::ShowHelp { page },
+                gui::HelpResult::Close => {
+                    newrunstate = self.take_action(newrunstate, Action::CloseMenu)
+                }
+            },
+            RunState::FreeLook { offset } => match gui::free_look(&self.ecs, ctx, offset) {
+                gui::LookResult::NoResponse => {}
+                gui::LookResult::Offset(offset) => newrunstate = RunState::FreeLook { offset },
+                gui::LookResult::Close => {
+                    newrunstate = self.take_action(newrunstate, Action::CloseMenu)
+                }
+            },
+            RunState::ShowBestiary => {
+                if gui::show_bestiary(&self.ecs, ctx) == gui::ItemMenuResult::Cancel {
+                    newrunstate = self.take_action(newrunstate, Action::CloseMenu);
+                }
+            }
+            RunState::Options {
+                highlighted,
+                in_game,
+            } => match gui::options_menu(self, ctx, highlighted, in_game) {
+                gui::OptionsMenuResult::NoSelection { highlighted } => {
+                    newrunstate = RunState::Options {
+                        highlighted,
+                        in_game,
+                    }
+                }
+                gui::OptionsMenuResult::Selected { highlighted } => {
+                    let option = settings::OPTIONS[highlighted];
+                    self.take_action(newrunstate, Action::ChangeOption { option });
+                    settings::save_settings(&self.ecs.fetch::<settings::GameSettings>());
+                    newrunstate = RunState::Options {
+                        highlighted,
+                        in_game,
+                    }
+                }
+                gui::OptionsMenuResult::Close => {
+                    newrunstate = if in_game {
+                        self.take_action(newrunstate, Action::CloseMenu)
+                    } else {
+                        RunState::MainMenu {
+                            menu_selection: gui::MainMenuSelection::Options,
+                        }
+                    }
+                }
+            },
+            RunState::ConfirmQuit => match gui::confirm_quit(ctx) {
+                None => {}
+                Some(gui::QuitChoice::SaveAndQuit) => newrunstate = RunState::SaveGame,
+                Some(gui::QuitChoice::QuitWithoutSaving) => {
+                    self.finish_recording();
+                    ctx.quit();
+                }
+                Some(gui::QuitChoice::Cancel) => {
+                    newrunstate = self.take_action(newrunstate, Action::CloseMenu)
+                }
+            },
+            RunState::ShowVendor { vendor, mode } => {
+                let action = match gui::show_vendor(self, ctx, vendor, mode) {
+                    gui::VendorResult::NoResponse => None,
+                    gui::VendorResult::Cancel => Some(Action::CloseMenu),
+                    gui::VendorResult::ToggleMode => Some(Action::SwitchVendorMode),
+                    gui::VendorResult::Buy(ware) => Some(Action::ChooseWare { ware }),
+                    gui::VendorResult::Sell(item) => Some(Action::choose_item(&self.ecs, item)),
+                };
+                if let Some(action) = action {
+                    newrunstate = self.take_action(newrunstate, action);
+                }
+            }
             RunState::SaveGame => {
-                saveload_system::save_game(&mut self.ecs);
+                self.finish_recording();
+                if let Err(e) = saveload_system::save_game(&mut self.ecs) {
+                    rltk::console::log(format!("Unable to save: {e}"));
+                }
                 newrunstate = RunState::MainMenu {
                     menu_selection: gui::MainMenuSelection::Quit,
                 };
             }
             RunState::NextLevel => {
+                if self
+                    .ecs
+                    .fetch::<settings::GameSettings>()
+                    .autosave
+                    .on_level_change()
+                {
+                    self.autosave();
+                }
+                let summary = self.level_summary();
                 self.goto_next_level();
+                newrunstate = if self.ecs.fetch::<settings::GameSettings>().level_summary {
+                    self.summary_timer = 0.0;
+                    RunState::LevelSummary { summary }
+                } else {
+                    RunState::PreRun
+                };
+            }
+            RunState::LevelSummary { summary } => {
+                self.summary_timer += ctx.frame_time_ms;
+                if gui::level_summary(ctx, &summary) == gui::ItemMenuResult::Cancel
+                    || self.summary_timer > LEVEL_SUMMARY_MS
+                {
+                    newrunstate = RunState::PreRun;
+                }
+            }
+            RunState::MapTransition { edge } => {
+                if self
+                    .ecs
+                    .fetch::<settings::GameSettings>()
+                    .autosave
+                    .on_level_change()
+                {
+                    self.autosave();
+                }
+                self.goto_connected_map(edge);
                 newrunstate = RunState::PreRun;
             }
             RunState::GameOver => {
+                self.finish_recording();
                 let game_over_result = gui::game_over(ctx);
                 match game_over_result {
                     gui::GameOverResult::NoSelection => {}
@@ -408,6 +1451,11 @@ impl GameState for State {
                 }
             }
             RunState::MagicMapReveal { row } => {
+                if row == 0 {
+                    self.ecs
+                        .write_resource::<ambient_system::AmbientState>()
+                        .keep_revealed();
+                }
                 let mut map = self.ecs.fetch_mut::<Map>();
                 for x in 0..map.width {
                     let idx = map.xy_idx(x, row);
@@ -419,12 +1467,22 @@ impl GameState for State {
                     newrunstate = RunState::MagicMapReveal { row: row + 1 };
                 }
             }
+            #[cfg(debug_assertions)]
+            RunState::DebugConsole => {
+                if debug_console::debug_console(self, ctx) == debug_console::ConsoleResult::Close {
+                    newrunstate = self.take_action(newrunstate, Action::CloseMenu);
+                }
+            }
             RunState::MapGeneration => {
                 if !SHOW_MAPGEN_VISUALIZER {
                     newrunstate = self.mapgen_next_state.unwrap();
                 }
                 ctx.cls();
-                draw_map(&self.mapgen_history[self.mapgen_index], ctx);
+                draw_map(
+                    &self.mapgen_history[self.mapgen_index],
+                    &self.ecs.fetch::<glyphs::GlyphSet>(),
+                    ctx,
+                );
 
                 self.mapgen_timer += ctx.frame_time_ms;
                 if self.mapgen_timer > 300.0 {
@@ -442,72 +1500,186 @@ impl GameState for State {
             *runwriter = newrunstate;
         }
         damage_system::delete_the_dead(&mut self.ecs);
+
+        gui::draw_debug_hud(&self.ecs, ctx);
     }
 }
 // --- State End ---
 
+/// The action a targeting cursor's result amounts to, if the player did anything
+fn target_action(
+    (item_menu_result, target): (gui::ItemMenuResult, Option<Point>),
+) -> Option<Action> {
+    match item_menu_result {
+        gui::ItemMenuResult::NoResponse => None,
+        gui::ItemMenuResult::Cancel => Some(Action::CloseMenu),
+        gui::ItemMenuResult::Selected => {
+            let target = target.unwrap();
+            Some(Action::ChooseTarget {
+                x: target.x,
+                y: target.y,
+            })
+        }
+    }
+}
+
+/// A world with every component registered and the resources a run starts from, holding a
+/// placeholder player and their dog. The level itself is left to `generate_world_map`. Everything
+/// random is drawn from `rng`, so the same seed always builds the same world.
+fn new_world(mut rng: RandomNumberGenerator) -> (World, morale_system::MoraleSystem) {
+    // Systems that subscribe to events need the channels in place before they're created
+    let mut ecs = World::new();
+    ecs.insert(EventChannel::<damage_system::DeathEvent>::new());
+    let morale_system = morale_system::MoraleSystem::new(&mut ecs);
+
+    // Component registration
+    ecs.register::<Position>();
+    ecs.register::<Renderable>();
+    ecs.register::<Player>();
+    ecs.register::<Viewshed>();
+    ecs.register::<Monster>();
+    ecs.register::<Name>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<CombatStats>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<Confusion>();
+    ecs.register::<AreaOfEffect>();
+    ecs.register::<Consumable>();
+    ecs.register::<Ranged>();
+    ecs.register::<Item>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<InBackpack>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<WantsToUseItem>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<WantsToRemoveItem>();
+    ecs.register::<Equippable>();
+    ecs.register::<Equipped>();
+    ecs.register::<SimpleMarker<IsSerialized>>();
+    ecs.register::<SerializationHelper>();
+    ecs.register::<MeleePowerBonus>();
+    ecs.register::<DefenseBonus>();
+    ecs.register::<ParticleLifetime>();
+    ecs.register::<HungerClock>();
+    ecs.register::<ProvidesFood>();
+    ecs.register::<MagicMapper>();
+    ecs.register::<Identifier>();
+    ecs.register::<WantsToIdentifyItem>();
+    ecs.register::<WantsToThrowItem>();
+    ecs.register::<Hidden>();
+    ecs.register::<EntryTrigger>();
+    ecs.register::<EntityMoved>();
+    ecs.register::<SingleActivation>();
+    ecs.register::<Reach>();
+    ecs.register::<Pet>();
+    ecs.register::<StartsFire>();
+    ecs.register::<Weight>();
+    ecs.register::<Encumbered>();
+    ecs.register::<Corpse>();
+    ecs.register::<Scavenger>();
+    ecs.register::<Tracker>();
+    ecs.register::<Morale>();
+    ecs.register::<Fearless>();
+    ecs.register::<Fleeing>();
+    ecs.register::<LastHitBy>();
+    ecs.register::<LastAttacker>();
+    ecs.register::<CharacterClass>();
+    ecs.register::<Attributes>();
+    ecs.register::<Experience>();
+    ecs.register::<CanSwim>();
+    ecs.register::<LightSource>();
+    ecs.register::<Home>();
+    ecs.register::<Durability>();
+    ecs.register::<Vendor>();
+    ecs.register::<Value>();
+    ecs.register::<Gold>();
+    ecs.register::<Coins>();
+    ecs.register::<Knockback>();
+    ecs.register::<TwoHanded>();
+    ecs.register::<Fragile>();
+    ecs.register::<StatusEffects>();
+    ecs.register::<Phasing>();
+    ecs.register::<Resistances>();
+    ecs.register::<Portal>();
+    ecs.register::<SightBonus>();
+    ecs.register::<ExtendedSight>();
+    ecs.register::<Asleep>();
+    ecs.register::<Digger>();
+    ecs.register::<Weakness>();
+    ecs.register::<Aquatic>();
+    ecs.register::<Facing>();
+    ecs.register::<Senses>();
+    ecs.register::<MassEffect>();
+    ecs.register::<Rage>();
+    ecs.register::<Berserker>();
+    ecs.register::<Stack>();
+
+    ecs.insert(SimpleMarkerAllocator::<IsSerialized>::new());
+    // Spawning looks up entity glyphs, so this goes in first
+    ecs.insert(glyphs::GlyphSet::default());
+
+    // Resource Insertion
+    let player_entity = spawner::player(&mut ecs, Position { x: 0, y: 0 }, PlayerClass::Fighter);
+    spawner::dog(&mut ecs, Position { x: 0, y: 0 });
+    ecs.insert(MasterDungeonMap::new(&mut rng));
+    ecs.insert(bestiary::Bestiary::default());
+    ecs.insert(rng);
+    ecs.insert(Map::new(1));
+    ecs.insert(Point::new(0, 0));
+    ecs.insert(player_entity);
+    ecs.insert(RunState::PreRun);
+    ecs.insert(gamelog::Gamelog {
+        entries: vec!["Welcome to Rusty Rougelike".to_string()],
+    });
+    ecs.insert(particle_system::ParticleBuilder::new());
+    ecs.insert(effects::EffectQueue::default());
+    ecs.insert(SearchProgress::default());
+    ecs.insert(LootTravel::default());
+    ecs.insert(player::LastUsedItem::default());
+    ecs.insert(ambient_system::AmbientState::default());
+    ecs.insert(level_stats::LevelStats::default());
+    ecs.insert(scores::RunSeed::default());
+    ecs.insert(monster_memory::MonsterMemory::default());
+    ecs.insert(TurnCounter {
+        turns: 0,
+        level_start: 0,
+        time_pressure: false,
+    });
+    ecs.insert(settings::load_settings());
+    ecs.insert(gui::DebugHud::default());
+    ecs.insert(ai_log::AiLog::default());
+    #[cfg(debug_assertions)]
+    ecs.insert(debug_console::DebugConsole::default());
+
+    ecs.insert(MapTransitions::default());
+
+    (ecs, morale_system)
+}
+
 fn main() -> rltk::BError {
     use rltk::RltkBuilder;
     let context = RltkBuilder::simple80x50()
         .with_title("Roguelike Tutorial")
         .build()?;
 
+    let (ecs, morale_system) = new_world(RandomNumberGenerator::new());
     let mut gs = State {
-        ecs: World::new(),
+        ecs,
+        morale_system,
         mapgen_next_state: Some(RunState::MainMenu {
             menu_selection: gui::MainMenuSelection::NewGame,
         }),
         mapgen_index: 0,
         mapgen_history: Vec::new(),
         mapgen_timer: 0.0,
+        summary_timer: 0.0,
+        drop_selection: Vec::new(),
+        inventory_letters: std::collections::HashMap::new(),
+        recording: None,
+        playback: None,
     };
-
-    // Component registration
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<InflictsDamage>();
-    gs.ecs.register::<Confusion>();
-    gs.ecs.register::<AreaOfEffect>();
-    gs.ecs.register::<Consumable>();
-    gs.ecs.register::<Ranged>();
-    gs.ecs.register::<Item>();
-    gs.ecs.register::<ProvidesHealing>();
-    gs.ecs.register::<InBackpack>();
-    gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<WantsToUseItem>();
-    gs.ecs.register::<WantsToDropItem>();
-    gs.ecs.register::<WantsToRemoveItem>();
-    gs.ecs.register::<Equippable>();
-    gs.ecs.register::<Equipped>();
-    gs.ecs.register::<SimpleMarker<IsSerialized>>();
-    gs.ecs.register::<SerializationHelper>();
-    gs.ecs.register::<MeleePowerBonus>();
-    gs.ecs.register::<DefenseBonus>();
-    gs.ecs.register::<ParticleLifetime>();
-    gs.ecs.register::<HungerClock>();
-    gs.ecs.register::<ProvidesFood>();
-    gs.ecs.register::<MagicMapper>();
-    gs.ecs.register::<Hidden>();
-    gs.ecs.register::<EntryTrigger>();
-    gs.ecs.register::<EntityMoved>();
-    gs.ecs.register::<SingleActivation>();
-
-    gs.ecs.insert(SimpleMarkerAllocator::<IsSerialized>::new());
-
-    // Resource Insertion
-    let player_entity = spawner::player(&mut gs.ecs, Position { x: 0, y: 0 });
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
-    gs.ecs.insert(Map::new(1));
-    gs.ecs.insert(Point::new(0, 0));
-    gs.ecs.insert(player_entity);
     if SHOW_MAPGEN_VISUALIZER {
         gs.ecs.insert(RunState::MapGeneration {});
     } else {
@@ -515,12 +1687,171 @@ fn main() -> rltk::BError {
             menu_selection: gui::MainMenuSelection::NewGame,
         });
     }
-    gs.ecs.insert(gamelog::Gamelog {
-        entries: vec!["Welcome to Rusty Rougelike".to_string()],
-    });
-    gs.ecs.insert(particle_system::ParticleBuilder::new());
 
-    gs.generate_world_map(1);
+    gs.generate_world_map(1, None);
 
     rltk::main_loop(context, gs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        let (ecs, morale_system) = new_world(RandomNumberGenerator::seeded(0));
+        State {
+            ecs,
+            morale_system,
+            mapgen_next_state: None,
+            mapgen_index: 0,
+            mapgen_history: Vec::new(),
+            mapgen_timer: 0.0,
+            summary_timer: 0.0,
+            drop_selection: Vec::new(),
+            inventory_letters: std::collections::HashMap::new(),
+            recording: None,
+            playback: None,
+        }
+    }
+
+    fn carried(gs: &State, name: &str) -> Option<Entity> {
+        let player_entity = *gs.ecs.fetch::<Entity>();
+        let entities = gs.ecs.entities();
+        let names = gs.ecs.read_storage::<Name>();
+        let backpack = gs.ecs.read_storage::<InBackpack>();
+        (&entities, &names, &backpack)
+            .join()
+            .find(|(_item, item_name, pack)| pack.owner == player_entity && item_name.name == name)
+            .map(|(item, _name, _pack)| item)
+    }
+
+    fn take(gs: &mut State, action: Action) {
+        let runstate = *gs.ecs.fetch::<RunState>();
+        *gs.ecs.write_resource::<RunState>() = gs.take_action(runstate, action);
+        gs.tick_headless(None);
+    }
+
+    fn choose(gs: &mut State, name: &str) {
+        let item = carried(gs, name).unwrap();
+        take(gs, Action::choose_item(&gs.ecs, item));
+    }
+
+    #[test]
+    fn replays_choices_made_in_menus_and_travel() {
+        let seed = 7;
+        let settings = settings::GameSettings::default();
+        let mut gs = state();
+        gs.begin_run(seed, PlayerClass::Rogue, settings.clone());
+        gs.recording = Some(replay::Replay::new(seed, PlayerClass::Rogue, settings));
+        gs.tick_headless(None);
+
+        take(&mut gs, Action::ShowInventory);
+        choose(&mut gs, "Health Potion");
+        take(&mut gs, Action::ShowThrowItem);
+        choose(&mut gs, "Flask of Oil");
+        let target = *gs.ecs.fetch::<Point>();
+        take(
+            &mut gs,
+            Action::ChooseTarget {
+                x: target.x + 1,
+                y: target.y,
+            },
+        );
+        take(&mut gs, Action::ShowDropItem);
+        choose(&mut gs, "Rations");
+        take(&mut gs, Action::DropChosen);
+        take(&mut gs, Action::ShowHelp);
+        take(&mut gs, Action::CloseMenu);
+        take(&mut gs, Action::TravelToLoot);
+        take(&mut gs, Action::TravelToLoot);
+        take(&mut gs, Action::StopTravel);
+
+        for name in ["Health Potion", "Flask of Oil", "Rations"] {
+            assert_eq!(carried(&gs, name), None, "{name} is still carried");
+        }
+        let recorded_hash = gs.world_hash();
+        let recording = gs.recording.take().unwrap();
+
+        let mut replayed = state();
+        replayed.begin_run(recording.seed, recording.class, recording.settings.clone());
+        replayed.playback = Some(replay::Playback::new(recording));
+        let runstate = loop {
+            if let Some(runstate) = replayed.step_replay().unwrap() {
+                break runstate;
+            }
+        };
+        assert!(runstate == RunState::AwaitingInput);
+        assert_eq!(replayed.world_hash(), recorded_hash);
+    }
+
+    fn last_log(gs: &State) -> String {
+        gs.ecs
+            .fetch::<gamelog::Gamelog>()
+            .entries
+            .last()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn only_queued_uses_count_as_the_last_item_used() {
+        let mut gs = state();
+        gs.begin_run(3, PlayerClass::Rogue, settings::GameSettings::default());
+        gs.tick_headless(None);
+        let monsters: Vec<Entity> = (&gs.ecs.entities(), &gs.ecs.read_storage::<Monster>())
+            .join()
+            .map(|(monster, _)| monster)
+            .collect();
+        gs.ecs.delete_entities(&monsters).unwrap();
+        let player_entity = *gs.ecs.fetch::<Entity>();
+        let pos = *gs.ecs.fetch::<Point>();
+        for name in [
+            "Magic Missile Scroll",
+            "Magic Missile Scroll",
+            "Mass Confusion Scroll",
+        ] {
+            let item = spawner::spawn_named(&mut gs.ecs, name, Position { x: pos.x, y: pos.y });
+            let item = item.unwrap();
+            gs.ecs.write_storage::<Position>().remove(item);
+            gs.ecs
+                .write_storage::<InBackpack>()
+                .insert(
+                    item,
+                    InBackpack {
+                        owner: player_entity,
+                    },
+                )
+                .unwrap();
+        }
+        let nothing_used = "You haven't used anything yet.";
+
+        // Targeting called off
+        take(&mut gs, Action::ShowInventory);
+        choose(&mut gs, "Magic Missile Scroll");
+        take(&mut gs, Action::CloseMenu);
+        take(&mut gs, Action::UseLastItem);
+        assert_eq!(last_log(&gs), nothing_used);
+
+        // Kept for want of anything to read it at
+        take(&mut gs, Action::ShowInventory);
+        choose(&mut gs, "Mass Confusion Scroll");
+        assert_eq!(last_log(&gs), "There are no targets in sight.");
+        take(&mut gs, Action::UseLastItem);
+        assert_eq!(last_log(&gs), nothing_used);
+
+        take(&mut gs, Action::ShowInventory);
+        choose(&mut gs, "Magic Missile Scroll");
+        take(
+            &mut gs,
+            Action::ChooseTarget {
+                x: pos.x + 1,
+                y: pos.y,
+            },
+        );
+        take(&mut gs, Action::UseLastItem);
+        assert!(matches!(
+            *gs.ecs.fetch::<RunState>(),
+            RunState::ShowTargeting { .. }
+        ));
+    }
+}
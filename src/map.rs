@@ -1,16 +1,82 @@
+use crate::components::BlocksTile;
+use crate::glyphs::GlyphSet;
+use crate::map_themes::{MapTheme, MapThemeKind};
+use crate::rect::Rect;
 use rltk::{Point, RandomNumberGenerator, Rltk, RGB};
-use specs::Entity;
-use std::collections::HashSet;
+use specs::{Entity, ReadStorage};
+use std::collections::{BTreeMap, HashSet};
 
 pub const MAPWIDTH: usize = 80;
 pub const MAPHEIGHT: usize = 43;
 pub const MAPCOUNT: usize = MAPWIDTH * MAPHEIGHT;
+/// Extra damage a shot does for each step of height it comes down
+const ELEVATION_DAMAGE_BONUS: i32 = 1;
 
-#[derive(PartialEq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
     DownStairs,
+    /// Loose stone. Passable, but slow going, so pathfinding avoids it when it can.
+    Rubble,
+    /// Wading depth. Slows movement and puts out fires.
+    ShallowWater,
+    /// Anything that can't swim drowns here.
+    DeepWater,
+    /// Unlit floor. Anyone standing here sees only as far as the light they carry.
+    DarkFloor,
+    /// A way off the edge of the map, leading to another level
+    Exit,
+}
+
+impl TileType {
+    pub fn blocks_movement(self) -> bool {
+        self == TileType::Wall
+    }
+
+    /// Multiplier on the cost of stepping onto this tile. Always finite, so any reachable tile
+    /// still has a path to it.
+    pub fn movement_cost(self) -> f32 {
+        match self {
+            TileType::Rubble => 2.0,
+            TileType::ShallowWater => 1.5,
+            TileType::DeepWater => 5.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Open ground, lit or not
+    pub fn is_floor(self) -> bool {
+        matches!(self, TileType::Floor | TileType::DarkFloor)
+    }
+
+    pub fn is_water(self) -> bool {
+        matches!(self, TileType::ShallowWater | TileType::DeepWater)
+    }
+
+    pub fn can_burn(self) -> bool {
+        !self.blocks_movement() && !self.is_water()
+    }
+
+    /// What the tile is called in tooltips, with a hint about anything special it does
+    pub fn description(self) -> &'static str {
+        match self {
+            TileType::Wall => "Wall",
+            TileType::Floor => "Floor",
+            TileType::DownStairs => "Down Staircase",
+            TileType::Rubble => "Rubble (slows)",
+            TileType::ShallowWater => "Shallow Water (slows)",
+            TileType::DeepWater => "Deep Water (drowns)",
+            TileType::DarkFloor => "Dark Floor (unlit)",
+            TileType::Exit => "Way Out",
+        }
+    }
+}
+
+/// A tile on fire. Burns for `turns` more monster turns.
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Burning {
+    pub turns: i32,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -23,6 +89,24 @@ pub struct Map {
     pub blocked: Vec<bool>,
     pub depth: i32,
     pub bloodstains: HashSet<usize>,
+    /// Kept in tile order, since fire spreads by dice rolled tile by tile
+    pub burning: BTreeMap<usize, Burning>,
+    /// How fresh the player's trail is on each tile, from 1.0 (just left) down to 0.0 (none)
+    pub scent: Vec<f32>,
+    pub theme: MapThemeKind,
+    /// For levels built from two halves: the column the east half starts at, and the theme it's
+    /// drawn in instead of `theme`
+    #[serde(default)]
+    pub east_theme: Option<(i32, MapThemeKind)>,
+    /// Whether pathfinding may step diagonally
+    pub diagonal_movement: bool,
+    /// The rooms the builder laid out, if it works in rooms. Caves and the like leave this empty.
+    #[serde(default)]
+    pub rooms: Vec<Rect>,
+    /// How high each tile stands. Only builders that raise ground set it, so most levels are
+    /// flat, and saves from before it existed load with it empty.
+    #[serde(default)]
+    pub heightmap: Vec<u8>,
 
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
@@ -40,6 +124,8 @@ impl rltk::BaseMap for Map {
         self.tiles[idx] == TileType::Wall
     }
 
+    /// The A* heuristic. Stays a straight-line distance because no step costs less than that,
+    /// the terrain costs come from `get_available_exits`.
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
         let w = self.width as usize;
         let p1 = Point::new(idx1 % w, idx1 / w);
@@ -51,36 +137,83 @@ impl rltk::BaseMap for Map {
         let mut exits = rltk::SmallVec::new();
         let x = idx as i32 % self.width;
         let y = idx as i32 / self.width;
-        let w = self.width as usize;
 
         // Cardinal directions
-        if self.is_exit_valid(x - 1, y) {
-            exits.push((idx - 1, 1.0))
-        };
-        if self.is_exit_valid(x + 1, y) {
-            exits.push((idx + 1, 1.0))
-        };
-        if self.is_exit_valid(x, y - 1) {
-            exits.push((idx - w, 1.0))
-        };
-        if self.is_exit_valid(x, y + 1) {
-            exits.push((idx + w, 1.0))
-        };
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            if self.is_exit_valid(x + dx, y + dy) {
+                let exit = self.xy_idx(x + dx, y + dy);
+                exits.push((exit, self.tiles[exit].movement_cost()));
+            }
+        }
 
-        // Diagonal directions
-        if self.is_exit_valid(x - 1, y - 1) {
-            exits.push((idx - w - 1, 1.45))
-        };
-        if self.is_exit_valid(x + 1, y - 1) {
-            exits.push((idx - w + 1, 1.45))
-        };
-        if self.is_exit_valid(x - 1, y + 1) {
-            exits.push((idx + w - 1, 1.45))
-        };
-        if self.is_exit_valid(x + 1, y + 1) {
-            exits.push((idx + w + 1, 1.45))
-        };
+        // Diagonal directions, unless squeezing between two walls
+        if self.diagonal_movement {
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                if !self.is_exit_valid(x + dx, y + dy) {
+                    continue;
+                }
+                let squeezed = self.tiles[self.xy_idx(x + dx, y)].blocks_movement()
+                    && self.tiles[self.xy_idx(x, y + dy)].blocks_movement();
+                if !squeezed {
+                    let exit = self.xy_idx(x + dx, y + dy);
+                    exits.push((
+                        exit,
+                        std::f32::consts::SQRT_2 * self.tiles[exit].movement_cost(),
+                    ));
+                }
+            }
+        }
+
+        exits
+    }
+}
+
+/// The map as something that drifts through walls sees it. Every tile inside the outer edge can
+/// be entered at the same cost, apart from open ones another creature is standing on.
+pub struct PhasingMap<'a> {
+    pub map: &'a Map,
+}
+
+impl rltk::Algorithm2D for PhasingMap<'_> {
+    fn dimensions(&self) -> rltk::Point {
+        self.map.dimensions()
+    }
+}
+
+impl rltk::BaseMap for PhasingMap<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.map.is_opaque(idx)
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
 
+    fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let map = self.map;
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        let mut exits = rltk::SmallVec::new();
+        for (dx, dy) in [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ] {
+            let (x, y) = (x + dx, y + dy);
+            if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+                continue;
+            }
+            let exit = map.xy_idx(x, y);
+            let occupied = map.blocked[exit] && !map.tiles[exit].blocks_movement();
+            if !occupied {
+                exits.push((exit, 1.0));
+            }
+        }
         exits
     }
 }
@@ -98,6 +231,13 @@ impl Map {
             tile_content: vec![Vec::new(); MAPCOUNT],
             depth: new_depth,
             bloodstains: HashSet::new(),
+            burning: BTreeMap::new(),
+            scent: vec![0.0; MAPCOUNT],
+            theme: MapThemeKind::Dungeon,
+            east_theme: None,
+            diagonal_movement: true,
+            rooms: Vec::new(),
+            heightmap: vec![0; MAPCOUNT],
         }
     }
 
@@ -113,6 +253,56 @@ impl Map {
         }
     }
 
+    /// Everything on the tile as of the last `MapIndexingSystem` run. Entities that moved, spawned
+    /// or died since then aren't reflected until it runs again. Out-of-bounds tiles hold nothing.
+    pub fn entities_at(&self, x: i32, y: i32) -> &[Entity] {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return &[];
+        }
+        self.tile_content
+            .get(self.xy_idx(x, y))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The first entity on the tile that blocks movement, with the same staleness as
+    /// `entities_at`
+    pub fn first_blocker_at(
+        &self,
+        x: i32,
+        y: i32,
+        blockers: &ReadStorage<BlocksTile>,
+    ) -> Option<Entity> {
+        self.entities_at(x, y)
+            .iter()
+            .find(|e| blockers.get(**e).is_some())
+            .copied()
+    }
+
+    /// Whether something standing on the tile can be seen. Only phasing creatures can stand inside
+    /// a wall, and they're hidden while they do.
+    pub fn shows_entity(&self, idx: usize, phasing: bool) -> bool {
+        !phasing || !self.tiles[idx].blocks_movement()
+    }
+
+    /// Every tile of the room the tile is in, walls included. Empty outside of any room, such as
+    /// in a corridor or on a level without rooms.
+    pub fn room_tiles_at(&self, x: i32, y: i32) -> Vec<Point> {
+        let Some(room) = self.rooms.iter().find(|room| room.contains(x, y)) else {
+            return Vec::new();
+        };
+        let (x1, x2) = (
+            i32::max(0, room.x1 - 1),
+            i32::min(self.width - 1, room.x2 + 1),
+        );
+        let (y1, y2) = (
+            i32::max(0, room.y1 - 1),
+            i32::min(self.height - 1, room.y2 + 1),
+        );
+        (y1..=y2)
+            .flat_map(|y| (x1..=x2).map(move |x| Point::new(x, y)))
+            .collect()
+    }
+
     /// Returns if a tile can be entered and is within bounds
     fn is_exit_valid(&self, x: i32, y: i32) -> bool {
         // Check boundaries & out of bounds
@@ -123,13 +313,60 @@ impl Map {
         !self.blocked[idx]
     }
 
-    /// Sets tile as blocked if Wall tile.
+    /// Sets tile as blocked if it can't be walked through.
     pub fn populate_blocked(&mut self) {
         for (i, tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+            self.blocked[i] = tile.blocks_movement();
         }
     }
 
+    pub fn elevation(&self, pt: Point) -> u8 {
+        self.heightmap
+            .get(self.xy_idx(pt.x, pt.y))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Whether `pt` stands above both ends of a shot from `from` to `to`, so the shot can't pass
+    pub fn rises_between(&self, from: Point, to: Point, pt: Point) -> bool {
+        self.elevation(pt) > u8::max(self.elevation(from), self.elevation(to))
+    }
+
+    /// Whether higher ground anywhere along the way stops a shot from `from` reaching `to`
+    pub fn elevation_blocks(&self, from: Point, to: Point) -> bool {
+        rltk::line2d(rltk::LineAlg::Bresenham, from, to)
+            .into_iter()
+            .any(|pt| self.rises_between(from, to, pt))
+    }
+
+    /// Extra damage a shot from `from` does to whatever stands at `to`, for coming down on it
+    /// from higher ground
+    pub fn elevation_bonus(&self, from: Point, to: Point) -> i32 {
+        i32::max(0, self.elevation(from) as i32 - self.elevation(to) as i32)
+            * ELEVATION_DAMAGE_BONUS
+    }
+
+    /// The tiles a shot from `from` to `to` flies over, not counting `from`, and how many of them
+    /// it gets through. A wall or higher ground stops the shot short of it, while a creature stops
+    /// it on its own tile. The last tile reached is where the shot lands.
+    pub fn line_of_fire(&self, from: Point, to: Point) -> (Vec<Point>, usize) {
+        let path: Vec<Point> = rltk::line2d(rltk::LineAlg::Bresenham, from, to)
+            .into_iter()
+            .skip(1)
+            .collect();
+        for (i, pt) in path.iter().enumerate() {
+            let idx = self.xy_idx(pt.x, pt.y);
+            if self.tiles[idx].blocks_movement() || self.rises_between(from, to, *pt) {
+                return (path, i);
+            }
+            if self.blocked[idx] {
+                return (path, i + 1);
+            }
+        }
+        let reached = path.len();
+        (path, reached)
+    }
+
     /// Returns a map with solid boundaries and 400 randomly placed wall tiles
     pub fn new_map_test(&self) -> Vec<TileType> {
         let mut map = vec![TileType::Floor; MAPCOUNT];
@@ -159,39 +396,41 @@ impl Map {
         map
     }
 
-    fn is_revealed_and_wall(&self, x: i32, y: i32) -> bool {
+    /// The theme the tile at `idx` is drawn in
+    pub fn theme_at(&self, idx: usize) -> MapThemeKind {
+        match self.east_theme {
+            Some((seam, theme)) if idx as i32 % self.width >= seam => theme,
+            _ => self.theme,
+        }
+    }
+
+    pub fn is_revealed_and_wall(&self, x: i32, y: i32) -> bool {
         let idx = self.xy_idx(x, y);
         self.tiles[idx] == TileType::Wall && self.revealed_tiles[idx]
     }
 }
 
-pub fn draw_map(map: &Map, ctx: &mut Rltk) {
+pub fn draw_map(map: &Map, glyphs: &GlyphSet, ctx: &mut Rltk) {
     let mut y = 0;
     let mut x = 0;
-    for (idx, tile) in map.tiles.iter().enumerate() {
+    for idx in 0..map.tiles.len() {
         if map.revealed_tiles[idx] {
-            let glyph;
-            let mut fg;
-            let mut bg = RGB::from_f32(0., 0., 0.);
-            match tile {
-                TileType::Floor => {
-                    glyph = rltk::to_cp437('.');
-                    fg = RGB::from_f32(0.0, 0.5, 0.5);
-                }
-                TileType::Wall => {
-                    glyph = get_wall_glyph(map, x, y);
-                    fg = RGB::from_f32(0., 1.0, 0.);
-                }
-                TileType::DownStairs => {
-                    glyph = rltk::to_cp437('>');
-                    fg = RGB::from_f32(0.0, 1.0, 1.0);
-                }
-            }
+            let (glyph, mut fg, mut bg) = map.theme_at(idx).tile_glyph(idx, map, glyphs);
             if map.bloodstains.contains(&idx) {
                 bg = RGB::from_f32(0.75, 0.0, 0.0)
             }
+            if map.burning.contains_key(&idx) {
+                fg = RGB::named(rltk::YELLOW);
+                bg = RGB::named(rltk::ORANGE);
+            }
+            // Raised ground catches more light
+            if map.heightmap.get(idx).is_some_and(|height| *height > 0) {
+                let height = map.heightmap[idx] as f32;
+                fg = fg.lerp(RGB::named(rltk::WHITE), 0.2 * height);
+            }
             if !map.visible_tiles[idx] {
-                fg = fg.to_greyscale()
+                fg = fg.to_greyscale();
+                bg = bg.to_greyscale();
             }
             ctx.set(x, y, fg, bg, glyph);
         }
@@ -205,43 +444,55 @@ pub fn draw_map(map: &Map, ctx: &mut Rltk) {
     }
 }
 
-fn get_wall_glyph(map: &Map, x: i32, y: i32) -> rltk::FontCharType {
-    if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
-        return 35;
-    }
-    let mut mask: u8 = 0;
-
-    if map.is_revealed_and_wall(x, y - 1) {
-        mask += 1;
-    }
-    if map.is_revealed_and_wall(x, y + 1) {
-        mask += 2;
-    }
-    if map.is_revealed_and_wall(x - 1, y) {
-        mask += 4;
-    }
-    if map.is_revealed_and_wall(x + 1, y) {
-        mask += 8;
-    }
-
-    // Uses <http://dwarffortresswiki.org/index.php/Character_table>
-    match mask {
-        0 => 9,    // Pillar because we can't see neighbors
-        1 => 186,  // Wall only to the north
-        2 => 186,  // Wall only to the south
-        3 => 186,  // Wall to the north and south
-        4 => 205,  // Wall only to the west
-        5 => 188,  // Wall to the north and west
-        6 => 187,  // Wall to the south and west
-        7 => 185,  // Wall to the north, south and west
-        8 => 205,  // Wall only to the east
-        9 => 200,  // Wall to the north and east
-        10 => 201, // Wall to the south and east
-        11 => 204, // Wall to the north, south and east
-        12 => 205, // Wall to the east and west
-        13 => 202, // Wall to the east, west, and south
-        14 => 203, // Wall to the east, west, and north
-        15 => 206, // ╬ Wall on all sides
-        _ => 35,   // Fallthrough...
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, World, WorldExt};
+
+    #[test]
+    fn finds_entities_and_blockers_on_a_tile() {
+        let mut ecs = World::new();
+        ecs.register::<BlocksTile>();
+        let item = ecs.create_entity().build();
+        let blocker = ecs.create_entity().with(BlocksTile {}).build();
+
+        let mut map = Map::new(1);
+        let idx = map.xy_idx(3, 4);
+        map.tile_content[idx] = vec![item, blocker];
+
+        let blockers = ecs.read_storage::<BlocksTile>();
+        assert_eq!(map.entities_at(3, 4), &[item, blocker]);
+        assert_eq!(map.first_blocker_at(3, 4, &blockers), Some(blocker));
+        assert!(map.entities_at(4, 4).is_empty());
+        assert_eq!(map.first_blocker_at(4, 4, &blockers), None);
+
+        map.tile_content[idx] = vec![item];
+        assert_eq!(map.first_blocker_at(3, 4, &blockers), None);
+    }
+
+    #[test]
+    fn out_of_bounds_tiles_hold_nothing() {
+        let mut ecs = World::new();
+        ecs.register::<BlocksTile>();
+        let blocker = ecs.create_entity().with(BlocksTile {}).build();
+
+        let mut map = Map::new(1);
+        // Would alias onto the next row's first tile if bounds weren't checked per axis
+        let idx = map.xy_idx(0, 1);
+        map.tile_content[idx] = vec![blocker];
+
+        let blockers = ecs.read_storage::<BlocksTile>();
+        let (width, height) = (map.width, map.height);
+        for (x, y) in [
+            (-1, 0),
+            (width, 0),
+            (0, -1),
+            (0, height),
+            (-1, -1),
+            (width, height),
+        ] {
+            assert!(map.entities_at(x, y).is_empty());
+            assert_eq!(map.first_blocker_at(x, y, &blockers), None);
+        }
     }
 }
@@ -1,8 +1,18 @@
-use super::{common::apply_room_to_map, Map, MapBuilder};
+use super::{
+    common::{
+        apply_room_to_map, apply_wide_horizontal_tunnel, apply_wide_vertical_tunnel,
+        wall_off_unreachable,
+    },
+    Map, MapBuilder,
+};
 use crate::{spawner, Position, Rect, TileType, SHOW_MAPGEN_VISUALIZER};
 use rltk::RandomNumberGenerator;
 use specs::World;
 
+/// Widest a corridor can be. Smaller maps get narrower ones still, so the corridors don't swallow
+/// the walls between rooms.
+const MAX_CORRIDOR_WIDTH: i32 = 3;
+
 /*
 Binary Space Partitioning
 
@@ -21,6 +31,7 @@ pub struct BspDungeonBuilder {
     rooms: Vec<Rect>,
     history: Vec<Map>,
     rects: Vec<Rect>,
+    corridor_width: i32,
 }
 
 impl MapBuilder for BspDungeonBuilder {
@@ -36,14 +47,16 @@ impl MapBuilder for BspDungeonBuilder {
         self.history.clone()
     }
 
-    fn build_map(&mut self) {
-        self.build();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for room in self.rooms.iter().skip(1) {
-            spawner::spawn_room(ecs, room, self.depth);
+            spawner::spawn_room(ecs, room, self.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
     }
 
     fn take_snapshot(&mut self) {
@@ -59,13 +72,22 @@ impl MapBuilder for BspDungeonBuilder {
 
 impl BspDungeonBuilder {
     pub fn new(new_depth: i32) -> BspDungeonBuilder {
+        BspDungeonBuilder::with_corridor_width(new_depth, 1)
+    }
+
+    /// Joins the rooms with corridors `corridor_width` tiles wide, from 1 up to
+    /// `MAX_CORRIDOR_WIDTH`
+    pub fn with_corridor_width(new_depth: i32, corridor_width: i32) -> BspDungeonBuilder {
+        let map = Map::new(new_depth);
+        let widest = i32::max(1, i32::min(map.width, map.height) / 12).min(MAX_CORRIDOR_WIDTH);
         BspDungeonBuilder {
-            map: Map::new(new_depth),
+            map,
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             rooms: Vec::new(),
             history: Vec::new(),
             rects: Vec::new(),
+            corridor_width: corridor_width.clamp(1, widest),
         }
     }
 
@@ -154,9 +176,7 @@ impl BspDungeonBuilder {
         true
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
-
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         self.rects.clear();
         self.rects
             .push(Rect::new(2, 2, self.map.width - 5, self.map.height - 5));
@@ -165,8 +185,8 @@ impl BspDungeonBuilder {
 
         // Get random rect and divide it 240 times. Add a room if possible, then add it to rooms list
         for _n_rooms in 0..240 {
-            let rect = self.get_random_rect(&mut rng);
-            let candidate = self.get_random_sub_rect(rect, &mut rng);
+            let rect = self.get_random_rect(rng);
+            let candidate = self.get_random_sub_rect(rect, rng);
 
             if self.is_possible(candidate) {
                 apply_room_to_map(&mut self.map, &candidate);
@@ -192,31 +212,24 @@ impl BspDungeonBuilder {
             self.take_snapshot();
         }
 
+        self.starting_position = self.rooms[0].center();
+        // Every room should already be joined up, but anything cut off is walled in for certain
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        wall_off_unreachable(&mut self.map, start_idx);
+
         let stairs_pos = self.rooms.last().unwrap().center();
         let stairs_idx = self.map.xy_idx(stairs_pos.x, stairs_pos.y);
         self.map.tiles[stairs_idx] = TileType::DownStairs;
         self.take_snapshot();
 
-        self.starting_position = self.rooms[0].center();
+        self.map.rooms = self.rooms.clone();
     }
 
+    /// Across to `x2` along `y1`, then along to `y2`
     fn draw_corridor(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
-        let mut x = x1;
-        let mut y = y1;
-
-        while x != x2 || y != y2 {
-            if x < x2 {
-                x += 1;
-            } else if x > x2 {
-                x -= 1;
-            } else if y < y2 {
-                y += 1;
-            } else if y > y2 {
-                y -= 1;
-            }
-
-            let idx = self.map.xy_idx(x, y);
-            self.map.tiles[idx] = TileType::Floor;
-        }
+        apply_wide_horizontal_tunnel(&mut self.map, x1, x2, y1, self.corridor_width);
+        apply_wide_vertical_tunnel(&mut self.map, y1, y2, x2, self.corridor_width);
     }
 }
@@ -27,14 +27,16 @@ impl MapBuilder for BspInteriorBuilder {
         self.history.clone()
     }
 
-    fn build_map(&mut self) {
-        self.build();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for room in self.rooms.iter().skip(1) {
-            spawner::spawn_room(ecs, room, self.depth);
+            spawner::spawn_room(ecs, room, self.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
     }
 
     fn take_snapshot(&mut self) {
@@ -60,14 +62,12 @@ impl BspInteriorBuilder {
         }
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
-
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         self.rects.clear();
         self.rects
             .push(Rect::new(1, 1, self.map.width - 2, self.map.height - 2));
         let first_room = self.rects[0];
-        self.add_subrects(first_room, &mut rng);
+        self.add_subrects(first_room, rng);
 
         let rooms = self.rects.clone();
         for r in rooms.iter() {
@@ -104,6 +104,7 @@ impl BspInteriorBuilder {
         self.take_snapshot();
 
         self.starting_position = self.rooms[0].center();
+        self.map.rooms = self.rooms.clone();
     }
 
     /*
@@ -1,11 +1,12 @@
 use super::{
-    common::generate_voronoi_spawn_regions, common::remove_unreachable_areas_get_most_distant, Map,
-    MapBuilder,
+    common::darken_region, common::flood_low_region, common::generate_voronoi_spawn_regions,
+    common::raise_hill, common::remove_unreachable_areas_get_most_distant, common::scatter_rubble,
+    Map, MapBuilder,
 };
-use crate::{spawner, Position, TileType, SHOW_MAPGEN_VISUALIZER};
+use crate::{map_themes::MapThemeKind, spawner, Position, TileType, SHOW_MAPGEN_VISUALIZER};
 use rltk::RandomNumberGenerator;
 use specs::World;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 /// Inspired by: <http://www.roguebasin.com/index.php?title=Cellular_Automata_Method_for_Generating_Random_Cave-Like_Levels>
 pub struct CellularAutomataBuilder {
@@ -13,7 +14,9 @@ pub struct CellularAutomataBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
+    /// Raises a hill somewhere in the caves
+    hilly: bool,
 }
 
 impl MapBuilder for CellularAutomataBuilder {
@@ -29,14 +32,16 @@ impl MapBuilder for CellularAutomataBuilder {
         self.history.clone()
     }
 
-    fn build_map(&mut self) {
-        self.build();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for (_area_id, tile_ids) in self.noise_areas.iter() {
-            spawner::spawn_region(ecs, tile_ids, self.depth);
+            spawner::spawn_region(ecs, tile_ids, self.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
     }
 
     fn take_snapshot(&mut self) {
@@ -52,18 +57,27 @@ impl MapBuilder for CellularAutomataBuilder {
 
 impl CellularAutomataBuilder {
     pub fn new(new_depth: i32) -> CellularAutomataBuilder {
+        let mut map = Map::new(new_depth);
+        map.theme = MapThemeKind::Forest;
         CellularAutomataBuilder {
-            map: Map::new(new_depth),
+            map,
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
+            hilly: false,
         }
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
+    /// Caves with a hill to shoot down from
+    pub fn highlands(new_depth: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            hilly: true,
+            ..CellularAutomataBuilder::new(new_depth)
+        }
+    }
 
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         // Create random map, 55% floor. Cellular automata are designed to make a level out of noise
         for y in 1..self.map.height - 1 {
             for x in 1..self.map.width - 1 {
@@ -150,7 +164,22 @@ impl CellularAutomataBuilder {
         self.map.tiles[exit_tile_idx] = TileType::DownStairs;
         self.take_snapshot();
 
+        // Caves are littered with fallen stone, and water collects in the hollows
+        scatter_rubble(&mut self.map, rng, 15);
+        self.take_snapshot();
+        flood_low_region(&mut self.map, rng, start_idx);
+        self.take_snapshot();
+
+        // Deep in the caves the light doesn't reach
+        darken_region(&mut self.map, rng, start_idx);
+        self.take_snapshot();
+
+        if self.hilly {
+            raise_hill(&mut self.map, rng, start_idx);
+            self.take_snapshot();
+        }
+
         //Build noise map for spawning entities later
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 }
@@ -0,0 +1,224 @@
+use super::{
+    common::{
+        apply_horizontal_tunnel, apply_vertical_tunnel, generate_voronoi_spawn_regions,
+        remove_unreachable_areas_get_most_distant, wall_off_unreachable,
+    },
+    Map, MapBuilder,
+};
+use crate::{spawner, Position, TileType, SHOW_MAPGEN_VISUALIZER};
+use rltk::{Point, RandomNumberGenerator};
+use specs::World;
+
+/// Builds a level out of two others: the west half from one builder and the east half from the
+/// other, each drawn in its own theme, joined by a corridor across the seam
+pub struct CombinedBuilder {
+    map: Map,
+    starting_position: Position,
+    depth: i32,
+    history: Vec<Map>,
+    region_a: Box<dyn MapBuilder>,
+    region_b: Box<dyn MapBuilder>,
+    /// Spawn areas, each lying wholly in one half so it takes on that half's theme
+    spawn_areas: Vec<Vec<usize>>,
+}
+
+impl MapBuilder for CombinedBuilder {
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_snapshot_history(&self) -> Vec<Map> {
+        self.history.clone()
+    }
+
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
+        for area in self.spawn_areas.iter() {
+            spawner::spawn_region(ecs, area, self.depth, &mut spawn_list);
+        }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
+    }
+
+    fn take_snapshot(&mut self) {
+        if SHOW_MAPGEN_VISUALIZER {
+            let mut snapshot = self.map.clone();
+            for v in snapshot.revealed_tiles.iter_mut() {
+                *v = true;
+            }
+            self.history.push(snapshot);
+        }
+    }
+}
+
+impl CombinedBuilder {
+    /// `region_a` lays out the west half and `region_b` the east, both at `region_a`'s depth
+    pub fn new(region_a: Box<dyn MapBuilder>, region_b: Box<dyn MapBuilder>) -> CombinedBuilder {
+        let depth = region_a.get_map().depth;
+        CombinedBuilder {
+            map: Map::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            depth,
+            history: Vec::new(),
+            region_a,
+            region_b,
+            spawn_areas: Vec::new(),
+        }
+    }
+
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
+        self.region_a.build_map(rng);
+        self.region_b.build_map(rng);
+        self.history = self.region_a.get_snapshot_history();
+        self.history.extend(self.region_b.get_snapshot_history());
+
+        let west = self.region_a.get_map();
+        let east = self.region_b.get_map();
+        let width = self.map.width;
+        let seam = width / 2;
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                let idx = self.map.xy_idx(x, y);
+                let tile = if x < seam {
+                    west.tiles[idx]
+                } else {
+                    east.tiles[idx]
+                };
+                // Each half brought its own stairs, the level only gets the one
+                self.map.tiles[idx] = match tile {
+                    TileType::DownStairs => TileType::Floor,
+                    tile => tile,
+                };
+            }
+        }
+        self.map.theme = west.theme;
+        self.map.east_theme = Some((seam, east.theme));
+        self.map.diagonal_movement = west.diagonal_movement && east.diagonal_movement;
+        self.map.rooms = west
+            .rooms
+            .iter()
+            .filter(|room| room.x2 < seam)
+            .chain(east.rooms.iter().filter(|room| room.x1 >= seam))
+            .copied()
+            .collect();
+        self.take_snapshot();
+
+        // Start wherever the west half would have, or on its nearest floor past the seam
+        let point = |idx: usize| Point::new(idx as i32 % width, idx as i32 / width);
+        let start = self.region_a.get_starting_position();
+        let start = Point::new(start.x, start.y);
+        let start_idx = (0..self.map.tiles.len())
+            .filter(|idx| self.map.tiles[*idx] == TileType::Floor)
+            .filter(|idx| point(*idx).x < seam)
+            .min_by(|a, b| {
+                let distance =
+                    |idx: usize| rltk::DistanceAlg::Pythagoras.distance2d(point(idx), start);
+                distance(*a).total_cmp(&distance(*b))
+            })
+            .unwrap_or(self.map.xy_idx(start.x, start.y));
+        self.starting_position = Position {
+            x: point(start_idx).x,
+            y: point(start_idx).y,
+        };
+
+        // Join the floor nearest the seam on either side, stepping across along the seam itself.
+        // Those tiles can sit in pockets of their own, so the corridor carries on to the start
+        // in the west and to where the east half would have started
+        let open = |idx: &usize| !self.map.tiles[*idx].blocks_movement();
+        let west_end = (0..self.map.tiles.len())
+            .filter(open)
+            .map(point)
+            .filter(|p| p.x < seam)
+            .max_by_key(|p| p.x);
+        let east_end = (0..self.map.tiles.len())
+            .filter(open)
+            .map(point)
+            .filter(|p| p.x >= seam)
+            .min_by_key(|p| p.x);
+        if let (Some(west_end), Some(east_end)) = (west_end, east_end) {
+            let east_start = self.region_b.get_starting_position();
+            let start = point(start_idx);
+            apply_horizontal_tunnel(&mut self.map, start.x, west_end.x, start.y);
+            apply_vertical_tunnel(&mut self.map, start.y, west_end.y, west_end.x);
+            apply_horizontal_tunnel(&mut self.map, west_end.x, seam, west_end.y);
+            apply_vertical_tunnel(&mut self.map, west_end.y, east_end.y, seam);
+            apply_horizontal_tunnel(&mut self.map, seam, east_end.x, east_end.y);
+            if east_start.x >= seam {
+                apply_vertical_tunnel(&mut self.map, east_end.y, east_start.y, east_end.x);
+                apply_horizontal_tunnel(&mut self.map, east_end.x, east_start.x, east_start.y);
+            }
+        }
+        self.take_snapshot();
+
+        // Whatever the corridor didn't reach is filled in, so every open tile is reachable
+        wall_off_unreachable(&mut self.map, start_idx);
+        let exit_tile_idx = remove_unreachable_areas_get_most_distant(&mut self.map, start_idx);
+        self.take_snapshot();
+
+        self.map.tiles[exit_tile_idx] = TileType::DownStairs;
+        self.take_snapshot();
+
+        self.spawn_areas = generate_voronoi_spawn_regions(&self.map, rng)
+            .into_values()
+            .flat_map(|area| {
+                let (west, east): (Vec<usize>, Vec<usize>) =
+                    area.into_iter().partition(|idx| *idx as i32 % width < seam);
+                [west, east]
+            })
+            .filter(|area| !area.is_empty())
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::static_map::StaticMapBuilder;
+
+    /// Lays out `rooms` of floor, each given as `(x1, y1, x2, y2)`, with the start at `start`
+    fn layout(rooms: &[(usize, usize, usize, usize)], start: (usize, usize)) -> String {
+        let mut rows = vec![vec!['#'; crate::map::MAPWIDTH]; crate::map::MAPHEIGHT];
+        for &(x1, y1, x2, y2) in rooms {
+            for row in rows.iter_mut().take(y2 + 1).skip(y1) {
+                for tile in row.iter_mut().take(x2 + 1).skip(x1) {
+                    *tile = '.';
+                }
+            }
+        }
+        rows[start.1][start.0] = '@';
+        rows.iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn both_halves_stay_reachable_when_the_seam_floor_is_cut_off() {
+        // The floor nearest the seam on each side is a pocket away from where that half starts
+        let west = layout(&[(2, 2, 8, 6), (30, 20, 34, 22)], (4, 4));
+        let east = layout(&[(45, 30, 47, 31), (60, 10, 70, 15)], (65, 12));
+        let mut builder = CombinedBuilder::new(
+            Box::new(StaticMapBuilder::from_ascii(1, &west)),
+            Box::new(StaticMapBuilder::from_ascii(1, &east)),
+        );
+        builder.build_map(&mut RandomNumberGenerator::seeded(1));
+
+        let map = builder.get_map();
+        let start = builder.get_starting_position();
+        assert_eq!((start.x, start.y), (4, 4));
+        for (x, y) in [(4, 4), (32, 21), (46, 30), (65, 12)] {
+            assert!(
+                !map.tiles[map.xy_idx(x, y)].blocks_movement(),
+                "({x}, {y}) was walled off"
+            );
+        }
+        assert!(map.tiles.contains(&TileType::DownStairs));
+    }
+}
@@ -3,7 +3,7 @@ use rltk::RandomNumberGenerator;
 use crate::{Map, Rect, TileType};
 use std::{
     cmp::{max, min},
-    collections::HashMap,
+    collections::BTreeMap,
 };
 
 pub fn apply_room_to_map(map: &mut Map, room: &Rect) {
@@ -32,6 +32,26 @@ pub fn apply_vertical_tunnel(map: &mut Map, y1: i32, y2: i32, x: i32) {
     }
 }
 
+/// Carves a horizontal corridor `width` tiles across, centred on row `y`. Rows that would reach
+/// the edge of the map are left out, so the border stays solid.
+pub fn apply_wide_horizontal_tunnel(map: &mut Map, x1: i32, x2: i32, y: i32, width: i32) {
+    for row in y - (width - 1) / 2..=y + width / 2 {
+        if row > 0 && row < map.height - 1 {
+            apply_horizontal_tunnel(map, x1, x2, row);
+        }
+    }
+}
+
+/// Carves a vertical corridor `width` tiles across, centred on column `x`, keeping clear of the
+/// map's edge
+pub fn apply_wide_vertical_tunnel(map: &mut Map, y1: i32, y2: i32, x: i32, width: i32) {
+    for column in x - (width - 1) / 2..=x + width / 2 {
+        if column > 0 && column < map.width - 1 {
+            apply_vertical_tunnel(map, y1, y2, column);
+        }
+    }
+}
+
 /// Searches a map via Dijkstra's, removes unreachable areas and returns the most distant tile.
 pub fn remove_unreachable_areas_get_most_distant(map: &mut Map, start_idx: usize) -> usize {
     map.populate_blocked();
@@ -55,12 +75,174 @@ pub fn remove_unreachable_areas_get_most_distant(map: &mut Map, start_idx: usize
     exit_tile_idx
 }
 
+/// Walls in every open tile, whatever its kind, that can't be walked to from `start_idx`
+pub fn wall_off_unreachable(map: &mut Map, start_idx: usize) {
+    map.populate_blocked();
+    let dijkstra_map = rltk::DijkstraMap::new(map.width, map.height, &[start_idx], map, 1000.0);
+    for (idx, tile) in map.tiles.iter_mut().enumerate() {
+        if !tile.blocks_movement() && dijkstra_map.map[idx] == f32::MAX {
+            *tile = TileType::Wall;
+        }
+    }
+    map.populate_blocked();
+}
+
+/// Turns some of the floor hugging the walls into rubble, `chance` percent of the time
+pub fn scatter_rubble(map: &mut Map, rng: &mut RandomNumberGenerator, chance: i32) {
+    for y in 1..map.height - 1 {
+        for x in 1..map.width - 1 {
+            let idx = map.xy_idx(x, y);
+            let next_to_wall = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .any(|(dx, dy)| map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall);
+            if map.tiles[idx] == TileType::Floor && next_to_wall && rng.roll_dice(1, 100) <= chance
+            {
+                map.tiles[idx] = TileType::Rubble;
+            }
+        }
+    }
+}
+
+/// Leaves a patch of floor unlit, somewhere well away from `start_idx` so the player starts in
+/// the light
+pub fn darken_region(map: &mut Map, rng: &mut RandomNumberGenerator, start_idx: usize) {
+    const DARK_RADIUS: f32 = 8.0;
+    const MIN_DISTANCE_FROM_START: f32 = 16.0;
+
+    let tile_point = |idx: usize| rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+    let start = tile_point(start_idx);
+    let candidates: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor)
+        .filter(|idx| {
+            rltk::DistanceAlg::Pythagoras.distance2d(tile_point(*idx), start)
+                > MIN_DISTANCE_FROM_START
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let centre = tile_point(candidates[rng.roll_dice(1, candidates.len() as i32) as usize - 1]);
+
+    let dark: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor)
+        .filter(|idx| {
+            rltk::DistanceAlg::Pythagoras.distance2d(tile_point(*idx), centre) <= DARK_RADIUS
+        })
+        .collect();
+    for idx in dark {
+        map.tiles[idx] = TileType::DarkFloor;
+    }
+}
+
+/// Raises a hill on open ground somewhere away from `start_idx`: a wide shoulder one step up, with
+/// a crest another step higher in the middle
+pub fn raise_hill(map: &mut Map, rng: &mut RandomNumberGenerator, start_idx: usize) {
+    const HILL_RADIUS: f32 = 6.0;
+    const CREST_RADIUS: f32 = 3.0;
+    const MIN_DISTANCE_FROM_START: f32 = 12.0;
+
+    let tile_point = |idx: usize| rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+    let start = tile_point(start_idx);
+    let candidates: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx].is_floor())
+        .filter(|idx| {
+            rltk::DistanceAlg::Pythagoras.distance2d(tile_point(*idx), start)
+                > MIN_DISTANCE_FROM_START
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let centre = tile_point(candidates[rng.roll_dice(1, candidates.len() as i32) as usize - 1]);
+
+    for idx in 0..map.tiles.len() {
+        if map.tiles[idx].blocks_movement() {
+            continue;
+        }
+        let distance = rltk::DistanceAlg::Pythagoras.distance2d(tile_point(idx), centre);
+        if distance <= CREST_RADIUS {
+            map.heightmap[idx] = 2;
+        } else if distance <= HILL_RADIUS {
+            map.heightmap[idx] = 1;
+        }
+    }
+}
+
+/// Floods a low-lying stretch of floor away from `start_idx`: a deep pool ringed by shallows.
+/// If the deep water would cut off any part of the level, it's all left shallow instead.
+pub fn flood_low_region(map: &mut Map, rng: &mut RandomNumberGenerator, start_idx: usize) {
+    const POOL_SIZE: usize = 40;
+    const DEEP_RADIUS: i32 = 2;
+    const MIN_DISTANCE_FROM_START: f32 = 10.0;
+
+    let start = rltk::Point::new(start_idx as i32 % map.width, start_idx as i32 / map.width);
+    let candidates: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor)
+        .filter(|idx| {
+            let p = rltk::Point::new(*idx as i32 % map.width, *idx as i32 / map.width);
+            rltk::DistanceAlg::Pythagoras.distance2d(p, start) > MIN_DISTANCE_FROM_START
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let centre = candidates[rng.roll_dice(1, candidates.len() as i32) as usize - 1];
+
+    // Water spreads out over the floor from the lowest point
+    let mut pool: Vec<(usize, i32)> = vec![(centre, 0)];
+    let mut open = std::collections::VecDeque::from([(centre, 0)]);
+    while let Some((idx, depth)) = open.pop_front() {
+        if pool.len() >= POOL_SIZE {
+            break;
+        }
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let next = map.xy_idx(x + dx, y + dy);
+            if map.tiles[next] == TileType::Floor
+                && next != start_idx
+                && pool.len() < POOL_SIZE
+                && !pool.iter().any(|(tile, _depth)| *tile == next)
+            {
+                pool.push((next, depth + 1));
+                open.push_back((next, depth + 1));
+            }
+        }
+    }
+    for (idx, depth) in pool.iter() {
+        map.tiles[*idx] = if *depth < DEEP_RADIUS {
+            TileType::DeepWater
+        } else {
+            TileType::ShallowWater
+        };
+    }
+
+    // Treat deep water as a wall and check everything else is still reachable
+    map.populate_blocked();
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        if *tile == TileType::DeepWater {
+            map.blocked[idx] = true;
+        }
+    }
+    let dijkstra_map = rltk::DijkstraMap::new(map.width, map.height, &[start_idx], map, 1000.0);
+    let cut_off = map.tiles.iter().enumerate().any(|(idx, tile)| {
+        !tile.blocks_movement() && *tile != TileType::DeepWater && dijkstra_map.map[idx] == f32::MAX
+    });
+    if cut_off {
+        for (idx, _depth) in pool.iter() {
+            map.tiles[*idx] = TileType::ShallowWater;
+        }
+    }
+    map.populate_blocked();
+}
+
 /// Generates a Voronoi/cellular noise map of a region, and divides it into spawn regions. <https://thebookofshaders.com/12/>
+/// The regions are kept in order, so the same seed fills them the same way every time.
 pub fn generate_voronoi_spawn_regions(
     map: &Map,
     rng: &mut RandomNumberGenerator,
-) -> HashMap<i32, Vec<usize>> {
-    let mut noise_areas: HashMap<i32, Vec<usize>> = HashMap::new();
+) -> BTreeMap<i32, Vec<usize>> {
+    let mut noise_areas: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
     let mut noise = rltk::FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
     noise.set_noise_type(rltk::NoiseType::Cellular);
     noise.set_frequency(0.08);
@@ -69,7 +251,8 @@ pub fn generate_voronoi_spawn_regions(
     for y in 1..map.height - 1 {
         for x in 1..map.width - 1 {
             let idx = map.xy_idx(x, y);
-            if map.tiles[idx] == TileType::Floor {
+            // Water is kept too, for whatever lives in it
+            if map.tiles[idx].is_floor() || map.tiles[idx].is_water() {
                 let cell_value_f = noise.get_noise(x as f32, y as f32) * 10240.0;
 
                 noise_areas
@@ -5,14 +5,14 @@ use super::{
 use crate::{spawner, Position, TileType, SHOW_MAPGEN_VISUALIZER};
 use rltk::RandomNumberGenerator;
 use specs::World;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 pub struct DrunkardsWalkBuilder {
     map: Map,
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
     settings: DrunkardSettings,
 }
 
@@ -29,14 +29,16 @@ impl MapBuilder for DrunkardsWalkBuilder {
         self.history.clone()
     }
 
-    fn build_map(&mut self) {
-        self.build();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for (_area_id, tile_ids) in self.noise_areas.iter() {
-            spawner::spawn_region(ecs, tile_ids, self.depth);
+            spawner::spawn_region(ecs, tile_ids, self.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
     }
 
     fn take_snapshot(&mut self) {
@@ -57,7 +59,7 @@ impl DrunkardsWalkBuilder {
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
             settings,
         }
     }
@@ -93,9 +95,7 @@ impl DrunkardsWalkBuilder {
     // count floor space %, iterate till desired floor space %.
     // Spawn a drunkard at the starting point with "lifetime" and "position".
     // Decrement the drunkard's lifetime, have them move in random dir (4-sided), convert tile to floor.
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
-
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         // Set starting point; start at the middle
         self.starting_position = Position {
             x: self.map.width / 2,
@@ -193,7 +193,7 @@ impl DrunkardsWalkBuilder {
         self.map.tiles[exit_tile_idx] = TileType::DownStairs;
         self.take_snapshot();
 
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 }
 
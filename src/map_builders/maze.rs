@@ -5,7 +5,7 @@ use super::{
 use crate::{spawner, Position, TileType, SHOW_MAPGEN_VISUALIZER};
 use rltk::RandomNumberGenerator;
 use specs::World;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Copy, Clone)]
 struct NeighborWalls {
@@ -201,7 +201,7 @@ pub struct MazeBuilder {
     starting_position: Position,
     depth: i32,
     history: Vec<Map>,
-    noise_areas: HashMap<i32, Vec<usize>>,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
 }
 
 impl MapBuilder for MazeBuilder {
@@ -217,14 +217,16 @@ impl MapBuilder for MazeBuilder {
         self.history.clone()
     }
 
-    fn build_map(&mut self) {
-        self.build();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.build(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for (_area_id, tile_ids) in self.noise_areas.iter() {
-            spawner::spawn_region(ecs, tile_ids, self.depth);
+            spawner::spawn_region(ecs, tile_ids, self.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.depth);
     }
 
     fn take_snapshot(&mut self) {
@@ -245,19 +247,13 @@ impl MazeBuilder {
             starting_position: Position { x: 0, y: 0 },
             depth: new_depth,
             history: Vec::new(),
-            noise_areas: HashMap::new(),
+            noise_areas: BTreeMap::new(),
         }
     }
 
-    fn build(&mut self) {
-        let mut rng = RandomNumberGenerator::new();
-
+    fn build(&mut self, rng: &mut RandomNumberGenerator) {
         // Maze gen
-        let mut maze = Grid::new(
-            (self.map.width / 2) - 2,
-            (self.map.height / 2) - 2,
-            &mut rng,
-        );
+        let mut maze = Grid::new((self.map.width / 2) - 2, (self.map.height / 2) - 2, rng);
         maze.generate_maze(self);
 
         // Start at top-left of map
@@ -274,6 +270,6 @@ impl MazeBuilder {
         self.take_snapshot();
 
         //Build noise map for spawning entities later
-        self.noise_areas = generate_voronoi_spawn_regions(&self.map, &mut rng);
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
     }
 }
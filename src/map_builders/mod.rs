@@ -11,11 +11,16 @@ mod drunkard;
 use drunkard::DrunkardsWalkBuilder;
 mod maze;
 use maze::MazeBuilder;
+mod combined;
+use combined::CombinedBuilder;
+mod static_map;
+use static_map::StaticMapBuilder;
 mod common;
+use rltk::RandomNumberGenerator;
 use specs::World;
 
 pub trait MapBuilder {
-    fn build_map(&mut self);
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
     fn spawn_entities(&mut self, ecs: &mut World);
     fn get_map(&self) -> Map;
     fn get_starting_position(&self) -> Position;
@@ -23,15 +28,36 @@ pub trait MapBuilder {
     fn take_snapshot(&mut self);
 }
 
-pub fn random_builder(new_depth: i32) -> Box<dyn MapBuilder> {
-    let mut rng = rltk::RandomNumberGenerator::new();
-    let builder = rng.roll_dice(1, 6);
+/// Picks a builder for a new level. The first level comes from the ASCII file named by
+/// `STATIC_MAP` when that's set and readable, so a bug can be reproduced on the same layout.
+pub fn random_builder(new_depth: i32, rng: &mut RandomNumberGenerator) -> Box<dyn MapBuilder> {
+    if new_depth == 1 {
+        let ascii = std::env::var(static_map::STATIC_MAP_VAR)
+            .ok()
+            .and_then(|path| std::fs::read_to_string(path).ok());
+        if let Some(ascii) = ascii {
+            return Box::new(StaticMapBuilder::from_ascii(new_depth, &ascii));
+        }
+    }
+
+    let builder = rng.roll_dice(1, 7);
     match builder {
         1 => Box::new(SimpleMapBuilder::new(new_depth)),
-        2 => Box::new(BspDungeonBuilder::new(new_depth)),
+        2 => Box::new(BspDungeonBuilder::with_corridor_width(
+            new_depth,
+            rng.roll_dice(1, 3),
+        )),
         3 => Box::new(BspInteriorBuilder::new(new_depth)),
-        4 => Box::new(CellularAutomataBuilder::new(new_depth)),
+        4 => match rng.roll_dice(1, 2) {
+            1 => Box::new(CellularAutomataBuilder::new(new_depth)),
+            _ => Box::new(CellularAutomataBuilder::highlands(new_depth)),
+        },
         5 => Box::new(MazeBuilder::new(new_depth)),
+        // A flooded cave giving way to built halls
+        6 => Box::new(CombinedBuilder::new(
+            Box::new(CellularAutomataBuilder::new(new_depth)),
+            Box::new(BspDungeonBuilder::new(new_depth)),
+        )),
         _ => match rng.roll_dice(1, 3) {
             1 => Box::new(DrunkardsWalkBuilder::open_area(new_depth)),
             2 => Box::new(DrunkardsWalkBuilder::open_halls(new_depth)),
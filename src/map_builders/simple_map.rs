@@ -14,14 +14,16 @@ pub struct SimpleMapBuilder {
 }
 
 impl MapBuilder for SimpleMapBuilder {
-    fn build_map(&mut self) {
-        self.rooms_and_corridors();
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.rooms_and_corridors(rng);
     }
 
     fn spawn_entities(&mut self, ecs: &mut World) {
+        let mut spawn_list: Vec<(usize, String)> = Vec::new();
         for room in self.rooms.iter().skip(1) {
-            spawner::spawn_room(ecs, room, self.map.depth);
+            spawner::spawn_room(ecs, room, self.map.depth, &mut spawn_list);
         }
+        spawner::spawn_all(ecs, &spawn_list, self.map.depth);
     }
 
     fn get_map(&self) -> Map {
@@ -59,13 +61,11 @@ impl SimpleMapBuilder {
 
     /// Makes a new map using the algorithm from <http://rogueliketutorials.com/tutorials/tcod/part-3/>
     /// Returns map with random rooms and corridors to join them.
-    pub fn rooms_and_corridors(&mut self) {
+    pub fn rooms_and_corridors(&mut self, rng: &mut RandomNumberGenerator) {
         const MAX_ROOMS: i32 = 30;
         const MIN_SIZE: i32 = 6;
         const MAX_SIZE: i32 = 10;
 
-        let mut rng = RandomNumberGenerator::new();
-
         for _ in 0..MAX_ROOMS {
             let w = rng.range(MIN_SIZE, MAX_SIZE);
             let h = rng.range(MIN_SIZE, MAX_SIZE);
@@ -126,5 +126,6 @@ impl SimpleMapBuilder {
         self.map.tiles[stairs_idx] = TileType::DownStairs;
 
         self.starting_position = self.rooms[0].center();
+        self.map.rooms = self.rooms.clone();
     }
 }
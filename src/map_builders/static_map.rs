@@ -0,0 +1,149 @@
+use super::{Map, MapBuilder};
+use crate::{Position, TileType};
+use rltk::RandomNumberGenerator;
+use specs::World;
+
+/// Environment variable naming an ASCII map file to use for the first level
+pub const STATIC_MAP_VAR: &str = "STATIC_MAP";
+
+/// Builds a fixed level from an ASCII layout, for reproducing bugs on known geometry. Nothing is
+/// spawned, so the level plays out the same every time.
+pub struct StaticMapBuilder {
+    map: Map,
+    starting_position: Position,
+    history: Vec<Map>,
+}
+
+impl MapBuilder for StaticMapBuilder {
+    fn build_map(&mut self, _rng: &mut RandomNumberGenerator) {
+        // The layout is parsed up front, so there's only the finished map to show
+        self.take_snapshot();
+    }
+
+    fn spawn_entities(&mut self, _ecs: &mut World) {}
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+
+    fn get_snapshot_history(&self) -> Vec<Map> {
+        self.history.clone()
+    }
+
+    fn take_snapshot(&mut self) {
+        if crate::SHOW_MAPGEN_VISUALIZER {
+            let mut snapshot = self.map.clone();
+            for v in snapshot.revealed_tiles.iter_mut() {
+                *v = true;
+            }
+            self.history.push(snapshot);
+        }
+    }
+}
+
+impl StaticMapBuilder {
+    /// Reads one tile per character: `#` wall, `.` floor, `>` down stairs, `;` rubble, `~`
+    /// shallow water, `≈` deep water and `@` the player's start. Anything else, including short
+    /// lines and missing rows, is wall, and anything past the edge of the map is ignored. Without
+    /// an `@` the player starts on the first open tile.
+    pub fn from_ascii(new_depth: i32, ascii: &str) -> StaticMapBuilder {
+        let mut map = Map::new(new_depth);
+        let mut start: Option<Position> = None;
+
+        for (y, line) in ascii.lines().take(map.height as usize).enumerate() {
+            for (x, c) in line.chars().take(map.width as usize).enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                let tile = match c {
+                    '.' => TileType::Floor,
+                    '>' => TileType::DownStairs,
+                    ';' => TileType::Rubble,
+                    '~' => TileType::ShallowWater,
+                    '≈' => TileType::DeepWater,
+                    '@' => {
+                        start = Some(Position { x, y });
+                        TileType::Floor
+                    }
+                    _ => TileType::Wall,
+                };
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = tile;
+            }
+        }
+
+        let starting_position = start
+            .or_else(|| {
+                map.tiles
+                    .iter()
+                    .position(|tile| !tile.blocks_movement())
+                    .map(|idx| Position {
+                        x: idx as i32 % map.width,
+                        y: idx as i32 / map.width,
+                    })
+            })
+            .unwrap_or(Position { x: 0, y: 0 });
+
+        StaticMapBuilder {
+            map,
+            starting_position,
+            history: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(builder: &StaticMapBuilder, x: i32, y: i32) -> TileType {
+        builder.map.tiles[builder.map.xy_idx(x, y)]
+    }
+
+    fn start(builder: &StaticMapBuilder) -> (i32, i32) {
+        (builder.starting_position.x, builder.starting_position.y)
+    }
+
+    #[test]
+    fn reads_a_valid_layout() {
+        let builder = StaticMapBuilder::from_ascii(1, "#####\n#@.>#\n#;~≈#\n#####");
+        assert_eq!(start(&builder), (1, 1));
+        assert!(tile(&builder, 0, 0) == TileType::Wall);
+        assert!(tile(&builder, 1, 1) == TileType::Floor);
+        assert!(tile(&builder, 2, 1) == TileType::Floor);
+        assert!(tile(&builder, 3, 1) == TileType::DownStairs);
+        assert!(tile(&builder, 1, 2) == TileType::Rubble);
+        assert!(tile(&builder, 2, 2) == TileType::ShallowWater);
+        assert!(tile(&builder, 3, 2) == TileType::DeepWater);
+    }
+
+    #[test]
+    fn short_lines_are_walled_in() {
+        let builder = StaticMapBuilder::from_ascii(1, "#....\n#.\n#...");
+        assert!(tile(&builder, 4, 0) == TileType::Floor);
+        assert!(tile(&builder, 1, 1) == TileType::Floor);
+        assert!(tile(&builder, 2, 1) == TileType::Wall);
+        assert!(tile(&builder, 4, 1) == TileType::Wall);
+        assert!(tile(&builder, 3, 2) == TileType::Floor);
+        // Missing rows are wall too
+        assert!(tile(&builder, 1, 3) == TileType::Wall);
+    }
+
+    #[test]
+    fn starts_on_the_first_open_tile_without_an_at() {
+        let builder = StaticMapBuilder::from_ascii(1, "####\n##..\n#...");
+        assert_eq!(start(&builder), (2, 1));
+    }
+
+    #[test]
+    fn unknown_characters_are_wall() {
+        let builder = StaticMapBuilder::from_ascii(1, "#.x?\n.@Z.");
+        assert!(tile(&builder, 2, 0) == TileType::Wall);
+        assert!(tile(&builder, 3, 0) == TileType::Wall);
+        assert!(tile(&builder, 2, 1) == TileType::Wall);
+        assert!(tile(&builder, 3, 1) == TileType::Floor);
+        assert_eq!(start(&builder), (1, 1));
+    }
+}
@@ -1,4 +1,4 @@
-use super::{BlocksTile, Map, Position};
+use super::{BlocksTile, Map, Phasing, Position};
 use specs::prelude::*;
 
 pub struct MapIndexingSystem;
@@ -8,19 +8,23 @@ impl<'a> System<'a> for MapIndexingSystem {
         WriteExpect<'a, Map>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, Phasing>,
         Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, positions, blockers, entities) = data;
+        let (mut map, positions, blockers, phasing, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
         for (position, entity) in (&positions, &entities).join() {
             let idx = map.xy_idx(position.x, position.y);
 
-            // Update blocked_tiles if theres a blocking entity
-            map.blocked[idx] = blockers.get(entity).is_some();
+            // Update blocked_tiles if theres a blocking entity. Phasing ones never block, and
+            // nothing standing on a tile unblocks it for whatever else is there.
+            if blockers.get(entity).is_some() && phasing.get(entity).is_none() {
+                map.blocked[idx] = true;
+            }
 
             // Push the entity to appropriate index slot. Its a copy type (we dont want to move in or the ECS will lose it).
             map.tile_content[idx].push(entity);
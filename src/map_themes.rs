@@ -0,0 +1,128 @@
+use super::{
+    glyphs::{self, GlyphSet},
+    Map, TileType,
+};
+use rltk::{FontCharType, RGB};
+use serde::{Deserialize, Serialize};
+
+/// Selects which theme a map is drawn with. Stored on the map so it survives save/load.
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum MapThemeKind {
+    Dungeon,
+    Forest,
+}
+
+/// Decides the glyph, foreground and background of a revealed tile.
+/// Fog-of-war and decals are applied afterwards by `draw_map`.
+pub trait MapTheme {
+    fn tile_glyph(&self, idx: usize, map: &Map, glyphs: &GlyphSet) -> (FontCharType, RGB, RGB);
+}
+
+pub struct DungeonTheme;
+
+impl MapTheme for DungeonTheme {
+    fn tile_glyph(&self, idx: usize, map: &Map, glyphs: &GlyphSet) -> (FontCharType, RGB, RGB) {
+        let bg = RGB::from_f32(0., 0., 0.);
+        match map.tiles[idx] {
+            TileType::Floor => (glyphs.get(glyphs::FLOOR), RGB::from_f32(0.0, 0.5, 0.5), bg),
+            TileType::DarkFloor => (glyphs.get(glyphs::FLOOR), RGB::from_f32(0.0, 0.2, 0.2), bg),
+            TileType::Wall => {
+                let x = idx as i32 % map.width;
+                let y = idx as i32 / map.width;
+                let glyph = glyphs.get(get_wall_glyph(map, x, y));
+                (glyph, RGB::from_f32(0., 1.0, 0.), bg)
+            }
+            TileType::DownStairs => (
+                glyphs.get(glyphs::DOWN_STAIRS),
+                RGB::from_f32(0.0, 1.0, 1.0),
+                bg,
+            ),
+            TileType::Rubble => (glyphs.get(glyphs::RUBBLE), RGB::from_f32(0.5, 0.5, 0.4), bg),
+            TileType::ShallowWater | TileType::DeepWater => water_glyph(map.tiles[idx], glyphs),
+            TileType::Exit => (glyphs.get(glyphs::EXIT), RGB::from_f32(1.0, 0.8, 0.0), bg),
+        }
+    }
+}
+
+pub struct ForestTheme;
+
+impl MapTheme for ForestTheme {
+    fn tile_glyph(&self, idx: usize, map: &Map, glyphs: &GlyphSet) -> (FontCharType, RGB, RGB) {
+        let bg = RGB::from_f32(0., 0., 0.);
+        match map.tiles[idx] {
+            TileType::Floor => (glyphs.get(glyphs::GRASS), RGB::from_f32(0.0, 0.6, 0.0), bg),
+            TileType::DarkFloor => (glyphs.get(glyphs::GRASS), RGB::from_f32(0.0, 0.25, 0.0), bg),
+            TileType::Wall => (glyphs.get(glyphs::TREE), RGB::from_f32(0.0, 0.4, 0.0), bg),
+            TileType::DownStairs => (
+                glyphs.get(glyphs::DOWN_STAIRS),
+                RGB::from_f32(0.0, 1.0, 1.0),
+                bg,
+            ),
+            TileType::Rubble => (glyphs.get(glyphs::RUBBLE), RGB::from_f32(0.5, 0.4, 0.2), bg),
+            TileType::ShallowWater | TileType::DeepWater => water_glyph(map.tiles[idx], glyphs),
+            TileType::Exit => (glyphs.get(glyphs::EXIT), RGB::from_f32(1.0, 0.8, 0.0), bg),
+        }
+    }
+}
+
+impl MapTheme for MapThemeKind {
+    fn tile_glyph(&self, idx: usize, map: &Map, glyphs: &GlyphSet) -> (FontCharType, RGB, RGB) {
+        match self {
+            MapThemeKind::Dungeon => DungeonTheme.tile_glyph(idx, map, glyphs),
+            MapThemeKind::Forest => ForestTheme.tile_glyph(idx, map, glyphs),
+        }
+    }
+}
+
+/// Water looks the same whatever the theme, so it always reads as water
+fn water_glyph(tile: TileType, glyphs: &GlyphSet) -> (FontCharType, RGB, RGB) {
+    match tile {
+        TileType::DeepWater => (
+            glyphs.get(glyphs::DEEP_WATER),
+            RGB::from_f32(0.3, 0.5, 1.0),
+            RGB::from_f32(0.0, 0.0, 0.5),
+        ),
+        _ => (
+            glyphs.get(glyphs::SHALLOW_WATER),
+            RGB::from_f32(0.5, 0.8, 1.0),
+            RGB::from_f32(0.0, 0.2, 0.4),
+        ),
+    }
+}
+
+fn get_wall_glyph(map: &Map, x: i32, y: i32) -> glyphs::Glyph {
+    if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+        return glyphs::WALL;
+    }
+    let mut mask: u8 = 0;
+
+    if map.is_revealed_and_wall(x, y - 1) {
+        mask += 1;
+    }
+    if map.is_revealed_and_wall(x, y + 1) {
+        mask += 2;
+    }
+    if map.is_revealed_and_wall(x - 1, y) {
+        mask += 4;
+    }
+    if map.is_revealed_and_wall(x + 1, y) {
+        mask += 8;
+    }
+
+    // Uses <http://dwarffortresswiki.org/index.php/Character_table>
+    match mask {
+        0 => glyphs::WALL_PILLAR,      // Pillar because we can't see neighbors
+        1..=3 => glyphs::WALL_NS,      // Wall to the north and/or south
+        4 | 8 | 12 => glyphs::WALL_EW, // Wall to the east and/or west
+        5 => glyphs::WALL_NW,
+        6 => glyphs::WALL_SW,
+        7 => glyphs::WALL_NSW,
+        9 => glyphs::WALL_NE,
+        10 => glyphs::WALL_SE,
+        11 => glyphs::WALL_NSE,
+        13 => glyphs::WALL_NEW,
+        14 => glyphs::WALL_SEW,
+        15 => glyphs::WALL_NSEW,
+        _ => glyphs::WALL, // Fallthrough...
+    }
+}
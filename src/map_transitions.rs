@@ -0,0 +1,133 @@
+use super::{Map, Position, TileType};
+use std::collections::HashMap;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum MapEdge {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl MapEdge {
+    /// The edge someone leaving by this one arrives on
+    pub fn opposite(self) -> MapEdge {
+        match self {
+            MapEdge::North => MapEdge::South,
+            MapEdge::South => MapEdge::North,
+            MapEdge::East => MapEdge::West,
+            MapEdge::West => MapEdge::East,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MapEdge::North => "north",
+            MapEdge::South => "south",
+            MapEdge::East => "east",
+            MapEdge::West => "west",
+        }
+    }
+
+    /// The edge a tile lies on, if it's on one
+    pub fn of_tile(map: &Map, x: i32, y: i32) -> Option<MapEdge> {
+        if y == 0 {
+            Some(MapEdge::North)
+        } else if y == map.height - 1 {
+            Some(MapEdge::South)
+        } else if x == 0 {
+            Some(MapEdge::West)
+        } else if x == map.width - 1 {
+            Some(MapEdge::East)
+        } else {
+            None
+        }
+    }
+}
+
+/// Which map edges lead to which levels, by depth. Every link works both ways: leaving by one
+/// edge arrives on the opposite edge of the destination, which leads back.
+pub struct MapTransitions {
+    links: HashMap<(i32, MapEdge), i32>,
+}
+
+impl Default for MapTransitions {
+    fn default() -> MapTransitions {
+        let mut transitions = MapTransitions {
+            links: HashMap::new(),
+        };
+        // The first level opens onto the surface above it
+        transitions.link(1, MapEdge::North, 0);
+        transitions
+    }
+}
+
+impl MapTransitions {
+    pub fn link(&mut self, depth: i32, edge: MapEdge, to_depth: i32) {
+        self.links.insert((depth, edge), to_depth);
+        self.links.insert((to_depth, edge.opposite()), depth);
+    }
+
+    pub fn destination(&self, depth: i32, edge: MapEdge) -> Option<i32> {
+        self.links.get(&(depth, edge)).copied()
+    }
+
+    /// Cuts an exit through to every edge of `map` that leads somewhere
+    pub fn carve_exits(&self, map: &mut Map) {
+        let depth = map.depth;
+        for (_depth, edge) in self.links.keys().filter(|(from, _)| *from == depth) {
+            carve_exit(map, *edge);
+        }
+    }
+}
+
+/// The exit tile on `edge`, which is where anyone arriving by that edge starts
+pub fn exit_on(map: &Map, edge: MapEdge) -> Option<Position> {
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_idx, tile)| **tile == TileType::Exit)
+        .map(|(idx, _tile)| Position {
+            x: idx as i32 % map.width,
+            y: idx as i32 / map.width,
+        })
+        .find(|pos| MapEdge::of_tile(map, pos.x, pos.y) == Some(edge))
+}
+
+/// Puts an exit on `edge` as near its middle as possible, with a corridor in from it to the
+/// nearest open ground. Leaves the map alone if there's no open ground to reach.
+fn carve_exit(map: &mut Map, edge: MapEdge) {
+    let (width, height) = (map.width, map.height);
+    let (along, across) = match edge {
+        MapEdge::North | MapEdge::South => (width, height),
+        MapEdge::East | MapEdge::West => (height, width),
+    };
+    // The tile `depth` steps in from the edge, on the line `offset` along it
+    let tile = |offset: i32, depth: i32| match edge {
+        MapEdge::North => (offset, depth),
+        MapEdge::South => (offset, height - 1 - depth),
+        MapEdge::West => (depth, offset),
+        MapEdge::East => (width - 1 - depth, offset),
+    };
+
+    // Try lines outwards from the middle, skipping the corners
+    let mut offsets: Vec<i32> = (1..along - 1).collect();
+    offsets.sort_by_key(|offset| (offset - along / 2).abs());
+    for offset in offsets {
+        let open = (1..across - 1).find(|depth| {
+            let (x, y) = tile(offset, *depth);
+            !map.tiles[map.xy_idx(x, y)].blocks_movement()
+        });
+        if let Some(open) = open {
+            for depth in 1..open {
+                let (x, y) = tile(offset, depth);
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            }
+            let (x, y) = tile(offset, 0);
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Exit;
+            return;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::Name;
+use rltk::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Magic items that look alike until the player has used one
+const SCROLLS: &[&str] = &[
+    "Magic Missile Scroll",
+    "Fireball Scroll",
+    "Lightning Scroll",
+    "Dragon Breath Scroll",
+    "Thunderclap Scroll",
+    "Frost Scroll",
+    "Confusion Scroll",
+    "Mass Confusion Scroll",
+    "Magic Mapping Scroll",
+    "Identify Scroll",
+];
+const POTIONS: &[&str] = &[
+    "Health Potion",
+    "Poison Potion",
+    "Confusion Potion",
+    "Weakness Potion",
+    "Rage Potion",
+];
+
+const SYLLABLES: &[&str] = &[
+    "FIZ", "ZAB", "KOR", "ULM", "NEX", "BRA", "THO", "VEL", "MIR", "GAX", "PLU", "SNO",
+];
+const POTION_COLOURS: &[&str] = &["Red", "Blue", "Green", "Murky", "Golden", "Silver", "Black"];
+const POTION_LOOKS: &[&str] = &["Bubbling", "Cloudy", "Viscous", "Sparkling", "Smoking"];
+
+/// Knowledge that lasts for a whole game: what unidentified items look like, and which of them
+/// the player has worked out. Saved with the game and rolled afresh for each new one.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MasterDungeonMap {
+    /// Real item name to the name it goes by until identified
+    pub obfuscated_names: HashMap<String, String>,
+    pub identified_items: HashSet<String>,
+}
+
+impl MasterDungeonMap {
+    /// A fresh set of disguises, with nothing identified
+    pub fn new(rng: &mut RandomNumberGenerator) -> MasterDungeonMap {
+        let mut obfuscated_names = HashMap::new();
+
+        let mut labels: Vec<String> = Vec::new();
+        for scroll in SCROLLS.iter() {
+            let label = loop {
+                let label = format!(
+                    "{}{}",
+                    SYLLABLES[rng.roll_dice(1, SYLLABLES.len() as i32) as usize - 1],
+                    SYLLABLES[rng.roll_dice(1, SYLLABLES.len() as i32) as usize - 1]
+                );
+                if !labels.contains(&label) {
+                    break label;
+                }
+            };
+            obfuscated_names.insert(scroll.to_string(), format!("Scroll labeled {label}"));
+            labels.push(label);
+        }
+
+        let mut looks: Vec<String> = POTION_COLOURS
+            .iter()
+            .flat_map(|colour| {
+                POTION_LOOKS
+                    .iter()
+                    .map(move |look| format!("{look} {colour}"))
+            })
+            .collect();
+        for potion in POTIONS.iter() {
+            let look = looks.remove(rng.roll_dice(1, looks.len() as i32) as usize - 1);
+            obfuscated_names.insert(potion.to_string(), format!("{look} Potion"));
+        }
+
+        MasterDungeonMap {
+            obfuscated_names,
+            identified_items: HashSet::new(),
+        }
+    }
+
+    pub fn is_potion(name: &str) -> bool {
+        POTIONS.contains(&name)
+    }
+
+    pub fn is_identified(&self, name: &str) -> bool {
+        !self.obfuscated_names.contains_key(name) || self.identified_items.contains(name)
+    }
+
+    /// What the player knows an item as
+    pub fn display_name(&self, name: &str) -> String {
+        match self.obfuscated_names.get(name) {
+            Some(obfuscated) if !self.identified_items.contains(name) => obfuscated.clone(),
+            _ => name.to_string(),
+        }
+    }
+
+    /// `display_name` for a whole `Name`, keeping whether it's a proper noun
+    pub fn shown_name(&self, name: &Name) -> Name {
+        Name {
+            name: self.display_name(&name.name),
+            proper: name.proper,
+        }
+    }
+
+    pub fn identify(&mut self, name: &str) {
+        if self.obfuscated_names.contains_key(name) {
+            self.identified_items.insert(name.to_string());
+        }
+    }
+}
@@ -1,9 +1,27 @@
 use super::{
-    gamelog::Gamelog, particle_system::ParticleBuilder, CombatStats, DefenseBonus, Equipped,
-    HungerClock, HungerState, MeleePowerBonus, Name, Position, SufferDamage, WantsToMelee,
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    particle_system::ParticleBuilder,
+    Asleep, BlocksTile, CombatStats, DamageType, DefenseBonus, Durability, EntityMoved,
+    EquipmentSlot, Equipped, Facing, HungerClock, HungerState, Knockback, Map, MeleePowerBonus,
+    Name, Position, StatusEffectKind, StatusEffects, SufferDamage, Viewshed, WantsToMelee,
 };
+use rltk::Point;
 use specs::prelude::*;
 
+/// Damage taken by something knocked back into a wall, or by both creatures when it's knocked
+/// into another
+const COLLISION_DAMAGE: i32 = 2;
+/// How hard an off-hand weapon strikes, as a percentage of a main-hand blow
+const OFF_HAND_POWER_PERCENT: i32 = 50;
+/// How much harder a blow lands against something that never saw it coming
+pub const SNEAK_ATTACK_MULTIPLIER: i32 = 2;
+/// How hard a blow from directly behind a monster lands, as a percentage of a normal one
+const BACKSTAB_PERCENT: i32 = 150;
+/// Extra power and defense while raging
+const RAGE_POWER_BONUS: i32 = 2;
+const RAGE_DEFENSE_BONUS: i32 = 1;
+
 pub struct MeleeCombatSystem;
 
 impl<'a> System<'a> for MeleeCombatSystem {
@@ -18,8 +36,20 @@ impl<'a> System<'a> for MeleeCombatSystem {
         ReadStorage<'a, DefenseBonus>,
         ReadStorage<'a, Equipped>,
         WriteExpect<'a, ParticleBuilder>,
-        ReadStorage<'a, Position>,
+        WriteStorage<'a, Position>,
         ReadStorage<'a, HungerClock>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, GlyphSet>,
+        WriteStorage<'a, Durability>,
+        ReadStorage<'a, Knockback>,
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, BlocksTile>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, EntityMoved>,
+        WriteExpect<'a, Point>,
+        WriteStorage<'a, Asleep>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Facing>,
     );
     fn run(&mut self, data: Self::SystemData) {
         let (
@@ -33,74 +63,448 @@ impl<'a> System<'a> for MeleeCombatSystem {
             defense_bonuses,
             equipped,
             mut particle_builder,
-            positions,
+            mut positions,
             hunger_clock,
+            player_entity,
+            glyphs,
+            mut durability,
+            knockbacks,
+            mut map,
+            blockers,
+            mut viewsheds,
+            mut entity_moved,
+            mut player_pos,
+            mut asleep,
+            statuses,
+            facings,
         ) = data;
 
+        let mut broken: Vec<(Entity, Entity)> = Vec::new();
+        // (attacker, target, distance) for every blow that lands with a knockback weapon
+        let mut knocked: Vec<(Entity, Entity, i32)> = Vec::new();
+
         for (entity, wants_melee, name, stats) in
             (&entities, &wants_melee, &names, &combat_stats).join()
         {
             if stats.hp > 0 {
-                let mut offensive_bonus = 0;
-                for (_item_entity, power_bonus, equipped_by) in
-                    (&entities, &melee_power_bonuses, &equipped).join()
-                {
-                    if equipped_by.owner == entity {
-                        offensive_bonus += power_bonus.amount;
-                    }
-                }
-
-                let target_stats = combat_stats.get(wants_melee.target).unwrap(); // TODO: Error handling
+                // Anything with combat stats can be attacked, whoever is swinging
+                let Some(target_stats) = combat_stats.get(wants_melee.target) else {
+                    continue;
+                };
                 if target_stats.hp > 0 {
-                    let target_name = names.get(wants_melee.target).unwrap();
-                    let mut defensive_bonus = 0;
-                    for (_item_entity, defense_bonus, equipped_by) in
-                        (&entities, &defense_bonuses, &equipped).join()
-                    {
-                        if equipped_by.owner == wants_melee.target {
-                            defensive_bonus += defense_bonus.amount;
-                        }
-                    }
-
-                    if let Some(hc) = hunger_clock.get(entity) {
-                        if hc.state == HungerState::WellFed {
-                            offensive_bonus += 1;
-                        }
-                    }
-
+                    let target_name = names
+                        .get(wants_melee.target)
+                        .map_or("something", |n| n.name.as_str());
+                    // Fights out of the player's sight still happen, just without the commentary
+                    let seen = in_view(&map, *player_entity, &positions, entity)
+                        || in_view(&map, *player_entity, &positions, wants_melee.target);
                     if let Some(pos) = positions.get(wants_melee.target) {
                         particle_builder.request(
                             pos.clone(),
                             rltk::RGB::named(rltk::ORANGE),
                             rltk::RGB::named(rltk::BLACK),
-                            rltk::to_cp437('‼'),
+                            glyphs.get(glyphs::HIT),
                             200.0,
                         );
                     }
 
-                    let damage = i32::max(
-                        0,
-                        (stats.power + offensive_bonus) - (target_stats.defense + defensive_bonus),
-                    );
+                    // Only the opening blow catches a sleeper unaware, as it wakes them
+                    let unaware = asleep.remove(wants_melee.target).is_some();
+                    let behind = from_behind(entity, wants_melee.target, &positions, &facings);
+
+                    // A weapon in the off hand follows up the main hand's blow
+                    let off_hand =
+                        off_hand_weapon(entity, &entities, &melee_power_bonuses, &equipped);
+                    let hands = std::iter::once(EquipmentSlot::Melee)
+                        .chain(off_hand.map(|_weapon| EquipmentSlot::OffHand));
+                    for hand in hands {
+                        // The weapon in this hand and the defender's armour both take the strain
+                        for (item, equipped_by, wear) in
+                            (&entities, &equipped, &mut durability).join()
+                        {
+                            let worn = (equipped_by.owner == entity
+                                && equipped_by.slot == hand
+                                && melee_power_bonuses.get(item).is_some())
+                                || (equipped_by.owner == wants_melee.target
+                                    && defense_bonuses.get(item).is_some());
+                            if worn && !broken.iter().any(|(b, _)| *b == item) {
+                                wear.current -= 1;
+                                if wear.current < 1 {
+                                    broken.push((item, equipped_by.owner));
+                                }
+                            }
+                        }
+
+                        let mut damage = melee_damage(
+                            attack_power(
+                                entity,
+                                stats,
+                                hand,
+                                &entities,
+                                &melee_power_bonuses,
+                                &equipped,
+                                condition_bonus(entity, &hunger_clock, &statuses),
+                            ),
+                            defense(
+                                wants_melee.target,
+                                target_stats,
+                                &entities,
+                                &defense_bonuses,
+                                &equipped,
+                                &statuses,
+                            ),
+                        );
+
+                        let sneak = unaware && hand == EquipmentSlot::Melee && damage > 0;
+                        // A sleeper doesn't care where the blow comes from, so the two don't stack
+                        let backstab =
+                            !sneak && behind && hand == EquipmentSlot::Melee && damage > 0;
+                        if sneak {
+                            damage *= SNEAK_ATTACK_MULTIPLIER;
+                        } else if backstab {
+                            damage = damage * BACKSTAB_PERCENT / 100;
+                        }
+
+                        let damage_type = (&melee_power_bonuses, &equipped)
+                            .join()
+                            .find(|(_bonus, equipped_by)| {
+                                equipped_by.owner == entity && equipped_by.slot == hand
+                            })
+                            .map_or(DamageType::Physical, |(bonus, _equipped_by)| {
+                                bonus.damage_type
+                            });
+                        let with = match off_hand.and_then(|weapon| names.get(weapon)) {
+                            Some(weapon) if hand == EquipmentSlot::OffHand => {
+                                format!(" with the off-hand {}", weapon.name)
+                            }
+                            _ => String::new(),
+                        };
+                        if damage == 0 {
+                            if seen {
+                                log.entries.push(format!(
+                                    "{name} is unable to hurt {target_name}{with}",
+                                    name = &name.name
+                                ));
+                            }
+                        } else {
+                            if seen {
+                                log.entries.push(format!(
+                                    "{opening}{name} hits {target_name}{with} for {damage} hp.",
+                                    opening = if sneak {
+                                        "Sneak attack! "
+                                    } else if backstab {
+                                        "Backstab! "
+                                    } else {
+                                        ""
+                                    },
+                                    name = &name.name
+                                ));
+                            }
+                            SufferDamage::new_damage(
+                                &mut inflict_damage,
+                                wants_melee.target,
+                                damage,
+                                damage_type,
+                                Some(&name.name),
+                                Some(entity),
+                                entity == *player_entity,
+                            );
+
+                            let distance = (&knockbacks, &equipped)
+                                .join()
+                                .filter(|(_knockback, equipped_by)| {
+                                    equipped_by.owner == entity && equipped_by.slot == hand
+                                })
+                                .map(|(knockback, _equipped_by)| knockback.distance)
+                                .max();
+                            if let Some(distance) = distance {
+                                knocked.push((entity, wants_melee.target, distance));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (attacker, target, distance) in knocked {
+            let (Some(from), Some(to)) = (positions.get(attacker), positions.get(target)) else {
+                continue;
+            };
+            let (dx, dy) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+            let target_name = names.get(target).map_or("Something", |n| n.name.as_str());
+            let seen = in_view(&map, *player_entity, &positions, attacker)
+                || in_view(&map, *player_entity, &positions, target);
 
-                    if damage == 0 {
+            // Slide one tile at a time until the distance is used up or something is in the way.
+            // Hitting a creature stops the slide and hurts both, so shoving into a crowd never
+            // moves more than one body.
+            let mut pos = to.clone();
+            let mut moved = false;
+            for _ in 0..distance {
+                let (x, y) = (pos.x + dx, pos.y + dy);
+                let in_bounds = x >= 0 && x < map.width && y >= 0 && y < map.height;
+                if !in_bounds || map.tiles[map.xy_idx(x, y)].blocks_movement() {
+                    if seen {
                         log.entries.push(format!(
-                            "{name} is unable to hurt {target_name}",
-                            name = &name.name,
-                            target_name = &target_name.name
+                            "{target_name} slams into the wall for {COLLISION_DAMAGE} hp."
                         ));
-                    } else {
+                    }
+                    SufferDamage::new_damage(
+                        &mut inflict_damage,
+                        target,
+                        COLLISION_DAMAGE,
+                        DamageType::Physical,
+                        Some("a wall"),
+                        Some(attacker),
+                        attacker == *player_entity,
+                    );
+                    break;
+                }
+                if let Some(other) = map.first_blocker_at(x, y, &blockers) {
+                    let other_name = names.get(other).map_or("something", |n| n.name.as_str());
+                    if seen {
                         log.entries.push(format!(
-                            "{name} hits {target_name} for {damage} hp.",
-                            name = &name.name,
-                            target_name = &target_name.name
+                            "{target_name} slams into {other_name}, hurting both for {COLLISION_DAMAGE} hp."
                         ));
-                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
                     }
+                    for victim in [target, other] {
+                        SufferDamage::new_damage(
+                            &mut inflict_damage,
+                            victim,
+                            COLLISION_DAMAGE,
+                            DamageType::Physical,
+                            Some(target_name),
+                            Some(attacker),
+                            attacker == *player_entity,
+                        );
+                    }
+                    break;
+                }
+                if map.blocked[map.xy_idx(x, y)] {
+                    break;
+                }
+
+                // Keep the index current so later knockbacks this turn see the new spot
+                let (old_idx, new_idx) = (map.xy_idx(pos.x, pos.y), map.xy_idx(x, y));
+                map.tile_content[old_idx].retain(|e| *e != target);
+                map.tile_content[new_idx].push(target);
+                if blockers.get(target).is_some() {
+                    map.blocked[old_idx] = false;
+                    map.blocked[new_idx] = true;
                 }
+                pos = Position { x, y };
+                moved = true;
+            }
+
+            if moved {
+                if seen {
+                    log.entries.push(format!("{target_name} is knocked back."));
+                }
+                if target == *player_entity {
+                    *player_pos = Point::new(pos.x, pos.y);
+                }
+                if let Some(viewshed) = viewsheds.get_mut(target) {
+                    viewshed.dirty = true;
+                }
+                entity_moved
+                    .insert(target, EntityMoved {})
+                    .expect("Unable to insert marker");
+                positions
+                    .insert(target, pos)
+                    .expect("Unable to insert position");
             }
         }
 
+        // Deleting the item takes its Equipped and its bonus with it
+        for (item, owner) in broken {
+            let seen = in_view(&map, *player_entity, &positions, owner);
+            if let (Some(item_name), Some(owner_name), true) =
+                (names.get(item), names.get(owner), seen)
+            {
+                log.entries.push(if owner == *player_entity {
+                    format!("Your {} breaks!", item_name.name)
+                } else {
+                    format!("{}'s {} breaks!", owner_name.name, item_name.name)
+                });
+            }
+            entities.delete(item).expect("Unable to delete broken item");
+        }
+
         wants_melee.clear();
     }
 }
+
+/// Whether `attacker` stands straight behind `target`, opposite the way it faces. Anything
+/// without a facing sees all around it, so can't be caught this way.
+fn from_behind(
+    attacker: Entity,
+    target: Entity,
+    positions: &WriteStorage<Position>,
+    facings: &ReadStorage<Facing>,
+) -> bool {
+    let (Some(from), Some(to), Some(facing)) = (
+        positions.get(attacker),
+        positions.get(target),
+        facings.get(target),
+    ) else {
+        return false;
+    };
+    let approach = Point::new((from.x - to.x).signum(), (from.y - to.y).signum());
+    approach == Point::new(-facing.direction.x, -facing.direction.y)
+}
+
+/// Whether the player is `entity` or can see where it stands
+fn in_view(
+    map: &Map,
+    player_entity: Entity,
+    positions: &WriteStorage<Position>,
+    entity: Entity,
+) -> bool {
+    entity == player_entity
+        || positions
+            .get(entity)
+            .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+}
+
+/// The weapon `entity` holds in its off hand, if it holds one there rather than a shield
+fn off_hand_weapon(
+    entity: Entity,
+    entities: &Entities,
+    melee_power_bonuses: &ReadStorage<MeleePowerBonus>,
+    equipped: &ReadStorage<Equipped>,
+) -> Option<Entity> {
+    (entities, melee_power_bonuses, equipped)
+        .join()
+        .find(|(_item, _bonus, equipped_by)| {
+            equipped_by.owner == entity && equipped_by.slot == EquipmentSlot::OffHand
+        })
+        .map(|(item, _bonus, _equipped_by)| item)
+}
+
+/// Power gained or lost from the state the attacker is in: a boost from being well fed or
+/// raging, less whatever weakness they're under. Worked out afresh for every blow, so nothing
+/// lingers once the state wears off.
+fn condition_bonus(
+    entity: Entity,
+    hunger_clock: &ReadStorage<HungerClock>,
+    statuses: &ReadStorage<StatusEffects>,
+) -> i32 {
+    let well_fed = hunger_clock
+        .get(entity)
+        .is_some_and(|hc| hc.state == HungerState::WellFed);
+    let weakness = statuses
+        .get(entity)
+        .map_or(0, |status| status.magnitude(StatusEffectKind::Weakened));
+    i32::from(well_fed) + raging(entity, statuses) * RAGE_POWER_BONUS - weakness
+}
+
+/// 1 if `entity` is raging, otherwise 0
+fn raging(entity: Entity, statuses: &ReadStorage<StatusEffects>) -> i32 {
+    i32::from(
+        statuses
+            .get(entity)
+            .is_some_and(|status| status.has(StatusEffectKind::Raging)),
+    )
+}
+
+/// Power of a blow struck with `hand`, including the bonus of the weapon held in it and
+/// `condition_bonus`. Off-hand blows land with reduced force.
+fn attack_power(
+    entity: Entity,
+    stats: &CombatStats,
+    hand: EquipmentSlot,
+    entities: &Entities,
+    melee_power_bonuses: &ReadStorage<MeleePowerBonus>,
+    equipped: &ReadStorage<Equipped>,
+    condition: i32,
+) -> i32 {
+    let mut offensive_bonus = 0;
+    for (_item_entity, power_bonus, equipped_by) in (entities, melee_power_bonuses, equipped).join()
+    {
+        if equipped_by.owner == entity && equipped_by.slot == hand {
+            offensive_bonus += power_bonus.amount;
+        }
+    }
+
+    let power = i32::max(0, stats.power + offensive_bonus + condition);
+    match hand {
+        EquipmentSlot::Melee => power,
+        EquipmentSlot::OffHand => power * OFF_HAND_POWER_PERCENT / 100,
+        // Nothing strikes from a trinket slot
+        EquipmentSlot::Trinket => 0,
+    }
+}
+
+/// Defense including equipment bonuses and any rage
+fn defense(
+    entity: Entity,
+    stats: &CombatStats,
+    entities: &Entities,
+    defense_bonuses: &ReadStorage<DefenseBonus>,
+    equipped: &ReadStorage<Equipped>,
+    statuses: &ReadStorage<StatusEffects>,
+) -> i32 {
+    let mut defensive_bonus = 0;
+    for (_item_entity, defense_bonus, equipped_by) in (entities, defense_bonuses, equipped).join() {
+        if equipped_by.owner == entity {
+            defensive_bonus += defense_bonus.amount;
+        }
+    }
+    stats.defense + defensive_bonus + raging(entity, statuses) * RAGE_DEFENSE_BONUS
+}
+
+/// A blow never heals, so anything the defense soaks up fully does nothing
+fn melee_damage(power: i32, defense: i32) -> i32 {
+    i32::max(0, power - defense)
+}
+
+/// Predicts one exchange of blows as (damage `attacker` deals, damage `attacker` takes back),
+/// counting off-hand blows. Anything without combat stats neither deals nor takes damage.
+pub fn predict_melee(attacker: Entity, defender: Entity, ecs: &World) -> (i32, i32) {
+    let entities = ecs.entities();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let melee_power_bonuses = ecs.read_storage::<MeleePowerBonus>();
+    let defense_bonuses = ecs.read_storage::<DefenseBonus>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let hunger_clock = ecs.read_storage::<HungerClock>();
+    let statuses = ecs.read_storage::<StatusEffects>();
+
+    let (Some(attacker_stats), Some(defender_stats)) =
+        (combat_stats.get(attacker), combat_stats.get(defender))
+    else {
+        return (0, 0);
+    };
+    let defense_of = |entity, stats| {
+        defense(
+            entity,
+            stats,
+            &entities,
+            &defense_bonuses,
+            &equipped,
+            &statuses,
+        )
+    };
+    let damage_dealt = |entity, stats, target, target_stats| {
+        let off_hand = off_hand_weapon(entity, &entities, &melee_power_bonuses, &equipped);
+        std::iter::once(EquipmentSlot::Melee)
+            .chain(off_hand.map(|_weapon| EquipmentSlot::OffHand))
+            .map(|hand| {
+                let power = attack_power(
+                    entity,
+                    stats,
+                    hand,
+                    &entities,
+                    &melee_power_bonuses,
+                    &equipped,
+                    condition_bonus(entity, &hunger_clock, &statuses),
+                );
+                melee_damage(power, defense_of(target, target_stats))
+            })
+            .sum()
+    };
+
+    (
+        damage_dealt(attacker, attacker_stats, defender, defender_stats),
+        damage_dealt(defender, defender_stats, attacker, attacker_stats),
+    )
+}
@@ -1,10 +1,28 @@
 use super::{
-    particle_system::ParticleBuilder, Confusion, EntityMoved, Map, Monster, Position, RunState,
-    Viewshed, WantsToMelee,
+    ai_log::AiLog,
+    ambient_system::AmbientState,
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    particle_system::ParticleBuilder,
+    Aquatic, Asleep, CombatStats, Corpse, EntityMoved, Facing, Fleeing, Home, Map, Monster, Name,
+    Pet, Phasing, PhasingMap, Position, RunState, Scavenger, Senses, StatusEffectKind,
+    StatusEffects, TileType, Tracker, TurnCounter, Viewshed, WantsToMelee,
 };
-use rltk::Point;
+use rltk::{BaseMap, Point, RandomNumberGenerator};
 use specs::prelude::*;
 
+const CORPSE_HEAL: i32 = 5;
+/// Turns a monster keeps hunting after losing the player before it heads home
+const CHASE_TIMEOUT: i32 = 5;
+/// How far from home a monster wanders while patrolling
+const PATROL_RADIUS: f32 = 3.0;
+/// One in this many turns a patrolling monster takes a step
+const PATROL_CHANCE: i32 = 3;
+/// One in this many turns a sleeping monster with the player in sight wakes up
+const WAKE_CHANCE: i32 = 6;
+/// How far from a blocked home a monster looks for somewhere else to settle
+const SETTLE_RADIUS: i32 = 6;
+
 pub struct MonsterAI;
 
 type MonsterAIData<'a> = (
@@ -17,9 +35,29 @@ type MonsterAIData<'a> = (
     WriteStorage<'a, Position>,
     WriteStorage<'a, WantsToMelee>,
     ReadExpect<'a, RunState>,
-    WriteStorage<'a, Confusion>,
+    ReadStorage<'a, StatusEffects>,
     WriteExpect<'a, ParticleBuilder>,
     WriteStorage<'a, EntityMoved>,
+    ReadStorage<'a, Pet>,
+    WriteStorage<'a, CombatStats>,
+    ReadStorage<'a, Scavenger>,
+    ReadStorage<'a, Corpse>,
+    ReadStorage<'a, Name>,
+    WriteExpect<'a, Gamelog>,
+    ReadStorage<'a, Tracker>,
+    ReadExpect<'a, GlyphSet>,
+    ReadStorage<'a, Fleeing>,
+    WriteStorage<'a, Home>,
+    WriteExpect<'a, RandomNumberGenerator>,
+    (ReadExpect<'a, AmbientState>, ReadExpect<'a, TurnCounter>),
+    (
+        ReadStorage<'a, Phasing>,
+        WriteStorage<'a, Asleep>,
+        ReadStorage<'a, Aquatic>,
+        WriteStorage<'a, Facing>,
+        ReadStorage<'a, Senses>,
+    ),
+    WriteExpect<'a, AiLog>,
 );
 impl<'a> System<'a> for MonsterAI {
     type SystemData = MonsterAIData<'a>;
@@ -35,39 +73,160 @@ impl<'a> System<'a> for MonsterAI {
             mut position,
             mut wants_to_melee,
             runstate,
-            mut confused,
+            statuses,
             mut particle_builder,
             mut entity_moved,
+            pets,
+            mut combat_stats,
+            scavengers,
+            corpses,
+            names,
+            mut gamelog,
+            trackers,
+            glyphs,
+            fleeing,
+            mut homes,
+            mut rng,
+            (ambient, turn_counter),
+            (phasing, mut asleep, aquatic, mut facings, senses),
+            mut ai_log,
         ) = data;
 
-        if *runstate != RunState::MonsterTurn {
+        if *runstate != RunState::MonsterTurn || ambient.sluggish(turn_counter.turns) {
             return;
         }
 
+        let pet_positions: Vec<(Entity, Point)> = (&entities, &pets, &position)
+            .join()
+            .map(|(entity, _pet, pos)| (entity, Point::new(pos.x, pos.y)))
+            .collect();
+        let mut eaten: Vec<Entity> = Vec::new();
+
         for (entity, mut viewshed, _monster, mut pos) in
             (&entities, &mut viewshed, &monster, &mut position).join()
         {
             let mut can_act = true;
+            let who = || {
+                let name = names
+                    .get(entity)
+                    .map_or("Monster", |name| name.name.as_str());
+                format!("T{} {name} #{}", turn_counter.turns, entity.id())
+            };
+            // Phasing monsters never block a tile, so they leave `map.blocked` alone as they move
+            let phases = phasing.get(entity).is_some();
+            let aquatic = aquatic.get(entity).is_some();
+            let notices_player = notices_player(
+                senses.get(entity),
+                viewshed,
+                Point::new(pos.x, pos.y),
+                *player_pos,
+            );
 
-            if let Some(am_confused) = confused.get_mut(entity) {
-                am_confused.turns -= 1;
-                if am_confused.turns < 1 {
-                    confused.remove(entity);
+            if asleep.get(entity).is_some() {
+                // Sneaking past in plain sight only works for so long
+                if notices_player && rng.roll_dice(1, WAKE_CHANCE) == 1 {
+                    asleep.remove(entity);
+                    ai_log.record(|| format!("{}: woke up", who()));
+                    if map.visible_tiles[map.xy_idx(pos.x, pos.y)] {
+                        if let Some(name) = names.get(entity) {
+                            gamelog.entries.push(format!("The {} wakes up.", name.name));
+                        }
+                    }
+                } else {
+                    ai_log.record(|| format!("{}: asleep", who()));
                 }
+                continue;
+            }
+
+            let confused = statuses
+                .get(entity)
+                .is_some_and(|status| status.has(StatusEffectKind::Confused));
+            if confused {
                 can_act = false;
+                ai_log.record(|| format!("{}: confused, lost its turn", who()));
                 particle_builder.request(
                     pos.clone(),
                     rltk::RGB::named(rltk::MAGENTA),
                     rltk::RGB::named(rltk::BLACK),
-                    rltk::to_cp437('?'),
+                    glyphs.get(glyphs::CONFUSED),
                     200.0,
                 );
             }
 
+            // A raging monster stands and fights even while routed
+            let raging = statuses
+                .get(entity)
+                .is_some_and(|status| status.has(StatusEffectKind::Raging));
+            if can_act && fleeing.get(entity).is_some() && !raging {
+                // Routed monsters back away from the player, and only fight if cornered
+                let here = map.xy_idx(pos.x, pos.y);
+                let player_distance = |idx: usize| {
+                    let tile = Point::new(idx as i32 % map.width, idx as i32 / map.width);
+                    rltk::DistanceAlg::Pythagoras.distance2d(tile, *player_pos)
+                };
+                let escape = map
+                    .get_available_exits(here)
+                    .iter()
+                    .map(|(exit, _cost)| *exit)
+                    .filter(|exit| can_enter(&map, *exit, aquatic))
+                    .filter(|exit| player_distance(*exit) > player_distance(here))
+                    .max_by(|a, b| player_distance(*a).total_cmp(&player_distance(*b)));
+                if let Some(next) = escape {
+                    if !phases {
+                        map.blocked[here] = false;
+                        map.blocked[next] = true;
+                    }
+                    turn_towards(&mut facings, entity, pos, next, map.width);
+                    pos.x = next as i32 % map.width;
+                    pos.y = next as i32 / map.width;
+                    entity_moved
+                        .insert(entity, EntityMoved {})
+                        .expect("Unable to insert marker");
+                    viewshed.dirty = true;
+                    ai_log.record(|| format!("{}: flee, moved to ({},{})", who(), pos.x, pos.y));
+                    continue;
+                }
+            }
+
             if can_act {
+                // Wounded scavengers would rather eat than fight
+                let wounded = combat_stats
+                    .get(entity)
+                    .is_some_and(|stats| stats.hp < stats.max_hp);
+                if wounded && scavengers.get(entity).is_some() {
+                    let (x, y) = (pos.x, pos.y);
+                    let meal = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                        .flat_map(|(x, y)| map.entities_at(x, y).iter())
+                        .find(|e| corpses.get(**e).is_some() && !eaten.contains(*e))
+                        .copied();
+
+                    if let Some(corpse) = meal {
+                        if let Some(stats) = combat_stats.get_mut(entity) {
+                            stats.hp = i32::min(stats.max_hp, stats.hp + CORPSE_HEAL);
+                        }
+                        entities.delete(corpse).expect("Unable to delete corpse");
+                        eaten.push(corpse);
+                        ai_log.record(|| format!("{}: eat corpse", who()));
+                        if map.visible_tiles[map.xy_idx(pos.x, pos.y)] {
+                            if let (Some(name), Some(corpse_name)) =
+                                (names.get(entity), names.get(corpse))
+                            {
+                                gamelog.entries.push(format!(
+                                    "The {} devours the {}.",
+                                    name.name, corpse_name.name
+                                ));
+                            }
+                        }
+                        continue;
+                    }
+                }
+
                 let distance =
                     rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
                 if distance < 1.5 {
+                    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+                    turn_towards(&mut facings, entity, pos, player_idx, map.width);
                     wants_to_melee
                         .insert(
                             entity,
@@ -76,17 +235,41 @@ impl<'a> System<'a> for MonsterAI {
                             },
                         )
                         .expect("Unable to insert attack");
-                } else if viewshed.visible_tiles.contains(&*player_pos) {
-                    let path = rltk::a_star_search(
-                        map.xy_idx(pos.x, pos.y) as i32,
-                        map.xy_idx(player_pos.x, player_pos.y) as i32,
-                        &*map,
-                    );
-                    if path.success && path.steps.len() > 1 {
+                    ai_log.record(|| format!("{}: attack player", who()));
+                } else if let Some((pet, pet_pos)) = pet_positions.iter().find(|(_, pet_pos)| {
+                    rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *pet_pos)
+                        < 1.5
+                }) {
+                    // The player's companion is fair game when the player is out of reach
+                    let pet_idx = map.xy_idx(pet_pos.x, pet_pos.y);
+                    turn_towards(&mut facings, entity, pos, pet_idx, map.width);
+                    wants_to_melee
+                        .insert(entity, WantsToMelee { target: *pet })
+                        .expect("Unable to insert attack");
+                    ai_log.record(|| format!("{}: attack pet", who()));
+                } else if notices_player || (phases && distance <= viewshed.range as f32) {
+                    // Walls don't stop a phasing monster sensing the player, or going after them
+                    if let Some(home) = homes.get_mut(entity) {
+                        home.turns_lost = 0;
+                    }
+                    let start = map.xy_idx(pos.x, pos.y) as i32;
+                    let end = map.xy_idx(player_pos.x, player_pos.y) as i32;
+                    let path = if phases {
+                        rltk::a_star_search(start, end, &PhasingMap { map: &map })
+                    } else {
+                        rltk::a_star_search(start, end, &*map)
+                    };
+                    if path.success
+                        && path.steps.len() > 1
+                        && can_enter(&map, path.steps[1], aquatic)
+                    {
                         // Clear old pos
                         let mut idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = false;
+                        if !phases {
+                            map.blocked[idx] = false;
+                        }
                         // Calc new pos
+                        turn_towards(&mut facings, entity, pos, path.steps[1], map.width);
                         pos.x = path.steps[1] as i32 % map.width;
                         pos.y = path.steps[1] as i32 / map.width;
                         // Set new pos
@@ -94,11 +277,153 @@ impl<'a> System<'a> for MonsterAI {
                             .insert(entity, EntityMoved {})
                             .expect("Unable to insert marker");
                         idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = true;
+                        if !phases {
+                            map.blocked[idx] = true;
+                        }
                         viewshed.dirty = true;
+                        ai_log
+                            .record(|| format!("{}: chase, moved to ({},{})", who(), pos.x, pos.y));
+                    } else {
+                        ai_log.record(|| format!("{}: chase, no path", who()));
+                    }
+                } else {
+                    let here = map.xy_idx(pos.x, pos.y);
+                    let mut next = None;
+                    let mut action = "wait";
+                    if trackers.get(entity).is_some() {
+                        // Out of sight, so follow the trail to wherever the scent is freshest
+                        next = map
+                            .get_available_exits(here)
+                            .iter()
+                            .map(|(exit, _cost)| *exit)
+                            .filter(|exit| map.scent[*exit] > map.scent[here])
+                            .max_by(|a, b| map.scent[*a].total_cmp(&map.scent[*b]));
+                        if next.is_some() {
+                            action = "follow scent";
+                        }
+                    }
+                    // With the player lost for good, head home and patrol around it
+                    if let (None, Some(home)) = (next, homes.get_mut(entity)) {
+                        home.turns_lost += 1;
+                        if home.turns_lost > CHASE_TIMEOUT {
+                            next = if rltk::DistanceAlg::Pythagoras
+                                .distance2d(Point::new(pos.x, pos.y), home.spawn_pos)
+                                <= PATROL_RADIUS
+                            {
+                                action = "patrol";
+                                patrol_step(&map, here, home.spawn_pos, &mut rng)
+                            } else {
+                                action = "head home";
+                                step_towards_home(&map, here, home.spawn_pos)
+                            };
+                        }
+                    }
+                    if let Some(next) = next.filter(|next| can_enter(&map, *next, aquatic)) {
+                        if !phases {
+                            map.blocked[here] = false;
+                            map.blocked[next] = true;
+                        }
+                        turn_towards(&mut facings, entity, pos, next, map.width);
+                        pos.x = next as i32 % map.width;
+                        pos.y = next as i32 / map.width;
+                        entity_moved
+                            .insert(entity, EntityMoved {})
+                            .expect("Unable to insert marker");
+                        viewshed.dirty = true;
+                        ai_log.record(|| {
+                            format!("{}: {action}, moved to ({},{})", who(), pos.x, pos.y)
+                        });
+                    } else {
+                        ai_log.record(|| format!("{}: {action}, stayed put", who()));
                     }
                 }
             }
         }
     }
 }
+
+/// Whether a monster at `pos` notices the player, by seeing them within its sight or hearing them
+/// within earshot. Without `Senses` it notices whatever its viewshed takes in.
+fn notices_player(senses: Option<&Senses>, viewshed: &Viewshed, pos: Point, player: Point) -> bool {
+    let in_view = viewshed.visible_tiles.contains(&player);
+    let Some(senses) = senses else {
+        return in_view;
+    };
+    let distance = rltk::DistanceAlg::Pythagoras.distance2d(pos, player);
+    (in_view && distance <= senses.sight as f32) || distance <= senses.hearing as f32
+}
+
+/// Turns a monster that faces one way to look from where it stands towards the tile at `idx`
+fn turn_towards(
+    facings: &mut WriteStorage<Facing>,
+    entity: Entity,
+    pos: &Position,
+    idx: usize,
+    width: i32,
+) {
+    let direction = Point::new(
+        (idx as i32 % width - pos.x).signum(),
+        (idx as i32 / width - pos.y).signum(),
+    );
+    if let (Some(facing), false) = (facings.get_mut(entity), direction == Point::new(0, 0)) {
+        facing.direction = direction;
+    }
+}
+
+/// Whether a monster may step onto `idx`. Aquatic ones never leave deep water.
+fn can_enter(map: &Map, idx: usize, aquatic: bool) -> bool {
+    !aquatic || map.tiles[idx] == TileType::DeepWater
+}
+
+/// The next step back towards `home`. If home is blocked or can't be reached from here, the
+/// monster settles for the reachable tile closest to it instead.
+fn step_towards_home(map: &Map, here: usize, home: Point) -> Option<usize> {
+    let reachable = rltk::DijkstraMap::new(map.width, map.height, &[here], map, 200.0);
+    let goal = (home.y - SETTLE_RADIUS..=home.y + SETTLE_RADIUS)
+        .flat_map(|y| (home.x - SETTLE_RADIUS..=home.x + SETTLE_RADIUS).map(move |x| (x, y)))
+        .filter(|&(x, y)| x >= 0 && x < map.width && y >= 0 && y < map.height)
+        .map(|(x, y)| map.xy_idx(x, y))
+        .filter(|idx| *idx == here || reachable.map[*idx] < f32::MAX)
+        .min_by(|a, b| {
+            let distance = |idx: usize| {
+                let tile = Point::new(idx as i32 % map.width, idx as i32 / map.width);
+                rltk::DistanceAlg::Pythagoras.distance2d(tile, home)
+            };
+            distance(*a).total_cmp(&distance(*b))
+        })?;
+    if goal == here {
+        return None;
+    }
+
+    let path = rltk::a_star_search(here as i32, goal as i32, map);
+    if path.success && path.steps.len() > 1 {
+        Some(path.steps[1])
+    } else {
+        None
+    }
+}
+
+/// Now and then, a random step that keeps the monster within `PATROL_RADIUS` of home
+fn patrol_step(
+    map: &Map,
+    here: usize,
+    home: Point,
+    rng: &mut RandomNumberGenerator,
+) -> Option<usize> {
+    if rng.roll_dice(1, PATROL_CHANCE) != 1 {
+        return None;
+    }
+    let exits: Vec<usize> = map
+        .get_available_exits(here)
+        .iter()
+        .map(|(exit, _cost)| *exit)
+        .filter(|exit| {
+            let tile = Point::new(*exit as i32 % map.width, *exit as i32 / map.width);
+            rltk::DistanceAlg::Pythagoras.distance2d(tile, home) <= PATROL_RADIUS
+        })
+        .collect();
+    if exits.is_empty() {
+        return None;
+    }
+    Some(exits[(rng.roll_dice(1, exits.len() as i32) - 1) as usize])
+}
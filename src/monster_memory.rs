@@ -0,0 +1,90 @@
+use super::{settings::GameSettings, Map, Monster, Phasing, Position, Renderable, TurnCounter};
+use rltk::{FontCharType, Rltk, RGB};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// Rounds a ghost lingers where its monster was last seen
+const GHOST_TURNS: i32 = 10;
+
+/// Where, and as what, the player last saw a monster
+#[derive(Clone)]
+struct Sighting {
+    x: i32,
+    y: i32,
+    glyph: FontCharType,
+    fg: RGB,
+}
+
+/// Where the player last saw monsters that have since gone out of sight. Ghosts are only a
+/// picture on the screen, not entities, so nothing can target them or bump into them. Not saved,
+/// and forgotten on arriving at a level.
+#[derive(Default)]
+pub struct MonsterMemory {
+    /// Monsters in view at the last update
+    in_view: HashMap<Entity, Sighting>,
+    /// Monsters out of view, with the round their ghost fades
+    ghosts: HashMap<Entity, (Sighting, i32)>,
+}
+
+/// Leaves a ghost behind for each monster that has gone out of sight since the last update.
+/// Seeing the monster again clears its ghost, as does the monster dying.
+pub fn remember_monsters(ecs: &World) {
+    let entities = ecs.entities();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let phasing = ecs.read_storage::<Phasing>();
+    let map = ecs.fetch::<Map>();
+    let round = ecs.fetch::<TurnCounter>().turns;
+    let mut memory = ecs.fetch_mut::<MonsterMemory>();
+
+    let in_view: HashMap<Entity, Sighting> = (&entities, &monsters, &positions, &renderables)
+        .join()
+        .filter(|(entity, _monster, pos, _render)| {
+            let idx = map.xy_idx(pos.x, pos.y);
+            map.visible_tiles[idx] && map.shows_entity(idx, phasing.get(*entity).is_some())
+        })
+        .map(|(entity, _monster, pos, render)| {
+            let sighting = Sighting {
+                x: pos.x,
+                y: pos.y,
+                glyph: render.glyph,
+                fg: render.fg,
+            };
+            (entity, sighting)
+        })
+        .collect();
+
+    let lost: Vec<(Entity, Sighting)> = memory
+        .in_view
+        .drain()
+        .filter(|(entity, _sighting)| !in_view.contains_key(entity))
+        .collect();
+    for (entity, sighting) in lost {
+        memory
+            .ghosts
+            .insert(entity, (sighting, round + GHOST_TURNS));
+    }
+    memory.ghosts.retain(|entity, (_sighting, fades)| {
+        *fades > round && entities.is_alive(*entity) && !in_view.contains_key(entity)
+    });
+    memory.in_view = in_view;
+}
+
+/// Draws each ghost as a dimmed copy of its monster. Goes under the real entities, so anything
+/// now standing on the spot is drawn over it.
+pub fn draw_ghosts(ecs: &World, ctx: &mut Rltk) {
+    if !ecs.fetch::<GameSettings>().monster_ghosts {
+        return;
+    }
+    let memory = ecs.fetch::<MonsterMemory>();
+    for (sighting, _fades) in memory.ghosts.values() {
+        ctx.set(
+            sighting.x,
+            sighting.y,
+            sighting.fg.lerp(RGB::named(rltk::BLACK), 0.6),
+            RGB::named(rltk::BLACK),
+            sighting.glyph,
+        );
+    }
+}
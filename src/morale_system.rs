@@ -0,0 +1,200 @@
+use super::{
+    damage_system::DeathEvent,
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    particle_system::ParticleBuilder,
+    ActiveEffect, Berserker, CombatStats, Fearless, Fleeing, Map, Morale, Name, Position, RunState,
+    StatusEffectKind, StatusEffects,
+};
+use rltk::Point;
+use specs::{prelude::*, shrev::EventChannel};
+
+pub const STARTING_MORALE: i32 = 10;
+/// Monsters whose morale drops below this break and run
+const ROUT_THRESHOLD: i32 = 4;
+const ROUT_TURNS: i32 = 6;
+/// Morale after rallying. Far enough above the threshold that a rallied monster doesn't break
+/// again straight away.
+const RALLY_MORALE: i32 = 8;
+const ALLY_DEATH_PENALTY: i32 = 4;
+const ALLY_DEATH_RADIUS: f32 = 8.0;
+pub const WOUND_PENALTY: i32 = 4;
+
+/// Below a third of their health
+pub fn is_badly_wounded(hp: i32, max_hp: i32) -> bool {
+    hp * 3 <= max_hp
+}
+
+/// Shakes monsters near where an ally died, routs monsters whose morale has broken, and rallies
+/// them once they've run for long enough. Fearless monsters are left alone. Berserkers fly into a
+/// rage once badly wounded, and anything raging stops running and is never routed.
+pub struct MoraleSystem {
+    deaths: ReaderId<DeathEvent>,
+}
+
+impl MoraleSystem {
+    /// Subscribes to the `DeathEvent` channel, which must already be in `ecs`
+    pub fn new(ecs: &mut World) -> MoraleSystem {
+        MoraleSystem {
+            deaths: ecs
+                .write_resource::<EventChannel<DeathEvent>>()
+                .register_reader(),
+        }
+    }
+}
+
+impl<'a> System<'a> for MoraleSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, RunState>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, Gamelog>,
+        WriteStorage<'a, Morale>,
+        WriteStorage<'a, Fleeing>,
+        ReadStorage<'a, Fearless>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        Read<'a, EventChannel<DeathEvent>>,
+        (
+            WriteStorage<'a, Berserker>,
+            ReadStorage<'a, CombatStats>,
+            WriteStorage<'a, StatusEffects>,
+        ),
+        (WriteExpect<'a, ParticleBuilder>, ReadExpect<'a, GlyphSet>),
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            runstate,
+            map,
+            mut gamelog,
+            mut morale,
+            mut fleeing,
+            fearless,
+            positions,
+            names,
+            death_events,
+            (mut berserkers, combat_stats, mut statuses),
+            (mut particle_builder, glyphs),
+        ) = data;
+
+        // Read every turn so the channel can recycle old events
+        for death in death_events.read(&mut self.deaths) {
+            if !death.hostile {
+                continue;
+            }
+            for (pos, morale) in (&positions, &mut morale).join() {
+                if rltk::DistanceAlg::Pythagoras
+                    .distance2d(Point::new(pos.x, pos.y), death.position)
+                    <= ALLY_DEATH_RADIUS
+                {
+                    morale.value -= ALLY_DEATH_PENALTY;
+                }
+            }
+        }
+
+        if *runstate != RunState::MonsterTurn {
+            return;
+        }
+
+        let berserk: Vec<Entity> = (&entities, &berserkers, &combat_stats)
+            .join()
+            .filter(|(_entity, _berserker, stats)| is_badly_wounded(stats.hp, stats.max_hp))
+            .map(|(entity, _berserker, _stats)| entity)
+            .collect();
+        let mut enraged: Vec<Entity> = Vec::new();
+        for entity in berserk {
+            let Some(berserker) = berserkers.remove(entity) else {
+                continue;
+            };
+            let rage = ActiveEffect {
+                kind: StatusEffectKind::Raging,
+                magnitude: 1,
+                turns: berserker.turns,
+            };
+            match statuses.get_mut(entity) {
+                Some(status) => status.apply(rage),
+                None => {
+                    statuses
+                        .insert(
+                            entity,
+                            StatusEffects {
+                                effects: vec![rage],
+                            },
+                        )
+                        .expect("Unable to insert status");
+                }
+            }
+            if let Some(pos) = positions.get(entity) {
+                particle_builder.request(
+                    pos.clone(),
+                    rltk::RGB::named(rltk::RED),
+                    rltk::RGB::named(rltk::BLACK),
+                    glyphs.get(glyphs::RAGE),
+                    200.0,
+                );
+            }
+            enraged.push(entity);
+        }
+
+        let raging = |entity: Entity| {
+            statuses
+                .get(entity)
+                .is_some_and(|status| status.has(StatusEffectKind::Raging))
+        };
+        // Rage outlasts fear, so anything running turns to charge
+        let charging: Vec<Entity> = (&entities, &fleeing)
+            .join()
+            .map(|(entity, _flight)| entity)
+            .filter(|entity| raging(*entity))
+            .collect();
+
+        let mut routed: Vec<Entity> = Vec::new();
+        let mut rallied: Vec<Entity> = Vec::new();
+        for (entity, morale, _fearless) in (&entities, &mut morale, !&fearless).join() {
+            if raging(entity) {
+                continue;
+            }
+            match fleeing.get_mut(entity) {
+                Some(flight) => {
+                    flight.turns -= 1;
+                    if flight.turns < 1 {
+                        morale.value = i32::max(morale.value, RALLY_MORALE);
+                        rallied.push(entity);
+                    }
+                }
+                None if morale.value < ROUT_THRESHOLD => routed.push(entity),
+                None => {}
+            }
+        }
+
+        let mut announce = |entity: Entity, message: &str| {
+            let visible = positions
+                .get(entity)
+                .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+            if let (true, Some(name)) = (visible, names.get(entity)) {
+                gamelog.entries.push(format!("The {} {message}", name.name));
+            }
+        };
+        for entity in routed {
+            fleeing
+                .insert(entity, Fleeing { turns: ROUT_TURNS })
+                .expect("Unable to insert fleeing");
+            announce(entity, "flees in terror!");
+        }
+        for entity in rallied {
+            fleeing.remove(entity);
+            announce(entity, "rallies!");
+        }
+        for entity in enraged {
+            if !charging.contains(&entity) {
+                announce(entity, "flies into a rage!");
+            }
+        }
+        for entity in charging {
+            fleeing.remove(entity);
+            announce(entity, "turns and charges in a rage!");
+        }
+    }
+}
@@ -1,14 +1,35 @@
 use super::{ParticleLifetime, Position, Renderable, Rltk};
+use rltk::RGB;
 use specs::prelude::*;
 
+/// How long a projectile spends on each tile of its flight
+pub const PROJECTILE_STEP_MS: f32 = 30.0;
+/// How long a floating combat number stays up
+const FLOATING_TEXT_MS: f32 = 600.0;
+/// How fast floating combat numbers rise, in tiles per second
+const FLOATING_TEXT_RISE: f32 = 3.0;
+
 pub fn cull_dead_particles(ecs: &mut World, ctx: &Rltk) {
     let mut dead_particles: Vec<Entity> = Vec::new();
     {
         let mut particles = ecs.write_storage::<ParticleLifetime>();
+        let mut positions = ecs.write_storage::<Position>();
         let entities = ecs.entities();
-        for (entity, mut particle) in (&entities, &mut particles).join() {
+        for (entity, mut particle, pos) in (&entities, &mut particles, &mut positions).join() {
+            if particle.delay_ms > 0. {
+                particle.delay_ms -= ctx.frame_time_ms;
+                continue;
+            }
             particle.lifetime_ms -= ctx.frame_time_ms;
-            if particle.lifetime_ms < 0. {
+
+            particle.risen += particle.rise_per_sec * ctx.frame_time_ms / 1000.;
+            while particle.risen >= 1. {
+                particle.risen -= 1.;
+                pos.y -= 1;
+            }
+
+            // Anything that drifts off the top of the map can't be drawn
+            if particle.lifetime_ms < 0. || pos.y < 0 {
                 dead_particles.push(entity);
             }
         }
@@ -25,6 +46,8 @@ struct ParticleRequest {
     bg: rltk::RGB,
     glyph: rltk::FontCharType,
     lifetime: f32,
+    delay: f32,
+    rise: f32,
 }
 
 #[derive(Default)]
@@ -46,6 +69,20 @@ impl ParticleBuilder {
         bg: rltk::RGB,
         glyph: rltk::FontCharType,
         lifetime: f32,
+    ) {
+        self.request_with_delay(0.0, pos, fg, bg, glyph, lifetime);
+    }
+
+    /// Like `request`, but the particle stays hidden for `delay_ms` first. Staggering the delays
+    /// lights up a row of tiles one after the other.
+    pub fn request_with_delay(
+        &mut self,
+        delay_ms: f32,
+        pos: Position,
+        fg: rltk::RGB,
+        bg: rltk::RGB,
+        glyph: rltk::FontCharType,
+        lifetime: f32,
     ) {
         self.requests.push(ParticleRequest {
             pos,
@@ -53,8 +90,38 @@ impl ParticleBuilder {
             bg,
             glyph,
             lifetime,
+            delay: delay_ms,
+            rise: 0.0,
         });
     }
+
+    /// Floats `text` up from `pos`, one particle per character. `stack` starts it that many rows
+    /// higher, so numbers spawned on the same tile at once sit above each other.
+    pub fn request_text(&mut self, pos: &Position, text: &str, fg: RGB, stack: i32) {
+        let y = pos.y - stack;
+        if y < 0 {
+            return;
+        }
+        for (i, c) in text.chars().enumerate() {
+            self.requests.push(ParticleRequest {
+                pos: Position {
+                    x: pos.x + i as i32,
+                    y,
+                },
+                fg,
+                bg: RGB::named(rltk::BLACK),
+                glyph: rltk::to_cp437(c),
+                lifetime: FLOATING_TEXT_MS,
+                delay: 0.0,
+                rise: FLOATING_TEXT_RISE,
+            });
+        }
+    }
+
+    /// Drops pending requests without spawning them
+    pub fn clear(&mut self) {
+        self.requests.clear();
+    }
 }
 
 pub struct ParticleSpawnSystem;
@@ -93,6 +160,9 @@ impl<'a> System<'a> for ParticleSpawnSystem {
                     particle,
                     ParticleLifetime {
                         lifetime_ms: new_particle.lifetime,
+                        delay_ms: new_particle.delay,
+                        rise_per_sec: new_particle.rise,
+                        risen: 0.0,
                     },
                 )
                 .expect("Unable to insert lifetime");
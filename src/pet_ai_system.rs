@@ -0,0 +1,121 @@
+use super::{
+    ambient_system::AmbientState, EntityMoved, Map, Monster, Pet, PetOrders, Position, RunState,
+    StatusEffectKind, StatusEffects, TurnCounter, Viewshed, WantsToMelee,
+};
+use rltk::{DistanceAlg, Point};
+use specs::prelude::*;
+
+pub struct PetAI;
+
+type PetAIData<'a> = (
+    WriteExpect<'a, Map>,
+    ReadExpect<'a, Point>,
+    ReadExpect<'a, RunState>,
+    Entities<'a>,
+    WriteStorage<'a, Viewshed>,
+    ReadStorage<'a, Pet>,
+    ReadStorage<'a, Monster>,
+    WriteStorage<'a, Position>,
+    WriteStorage<'a, WantsToMelee>,
+    ReadStorage<'a, StatusEffects>,
+    WriteStorage<'a, EntityMoved>,
+    ReadExpect<'a, AmbientState>,
+    ReadExpect<'a, TurnCounter>,
+);
+
+impl<'a> System<'a> for PetAI {
+    type SystemData = PetAIData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut map,
+            player_pos,
+            runstate,
+            entities,
+            mut viewsheds,
+            pets,
+            monsters,
+            mut positions,
+            mut wants_to_melee,
+            statuses,
+            mut entity_moved,
+            ambient,
+            turn_counter,
+        ) = data;
+
+        if *runstate != RunState::MonsterTurn || ambient.sluggish(turn_counter.turns) {
+            return;
+        }
+
+        let hostiles: Vec<(Entity, Point)> = (&entities, &monsters, &positions)
+            .join()
+            .map(|(entity, _monster, pos)| (entity, Point::new(pos.x, pos.y)))
+            .collect();
+        let is_adjacent = |a: Point, b: Point| DistanceAlg::Pythagoras.distance2d(a, b) < 1.5;
+
+        for (entity, viewshed, pet, pos) in
+            (&entities, &mut viewsheds, &pets, &mut positions).join()
+        {
+            let confused = statuses
+                .get(entity)
+                .is_some_and(|status| status.has(StatusEffectKind::Confused));
+            if confused {
+                continue;
+            }
+
+            // Fight anything next to the pet first, even when told to stay
+            let pet_pos = Point::new(pos.x, pos.y);
+            if let Some((target, _)) = hostiles.iter().find(|(_, h)| is_adjacent(pet_pos, *h)) {
+                wants_to_melee
+                    .insert(entity, WantsToMelee { target: *target })
+                    .expect("Unable to insert attack");
+                continue;
+            }
+
+            if pet.orders == PetOrders::Stay {
+                continue;
+            }
+
+            // Then go for anything threatening the player, or anything in sight when told to
+            // attack, otherwise stay at the player's side
+            let goal = match pet.orders {
+                PetOrders::Attack => hostiles
+                    .iter()
+                    .filter(|(_, h)| viewshed.visible_tiles.contains(h))
+                    .min_by(|(_, a), (_, b)| {
+                        let distance = |h: &Point| DistanceAlg::Pythagoras.distance2d(pet_pos, *h);
+                        distance(a).total_cmp(&distance(b))
+                    }),
+                _ => hostiles.iter().find(|(_, h)| {
+                    is_adjacent(*player_pos, *h) && viewshed.visible_tiles.contains(h)
+                }),
+            }
+            .map(|(_, h)| *h);
+            let goal = match goal {
+                Some(hostile_pos) => hostile_pos,
+                None if is_adjacent(pet_pos, *player_pos) => continue,
+                None => *player_pos,
+            };
+
+            // The goal may hold a blocking creature, which would make it unreachable
+            let goal_idx = map.xy_idx(goal.x, goal.y);
+            let goal_blocked = map.blocked[goal_idx];
+            map.blocked[goal_idx] = false;
+            let path = rltk::a_star_search(map.xy_idx(pos.x, pos.y) as i32, goal_idx as i32, &*map);
+            map.blocked[goal_idx] = goal_blocked;
+
+            if path.success && path.steps.len() > 2 {
+                let mut idx = map.xy_idx(pos.x, pos.y);
+                map.blocked[idx] = false;
+                pos.x = path.steps[1] as i32 % map.width;
+                pos.y = path.steps[1] as i32 / map.width;
+                entity_moved
+                    .insert(entity, EntityMoved {})
+                    .expect("Unable to insert marker");
+                idx = map.xy_idx(pos.x, pos.y);
+                map.blocked[idx] = true;
+                viewshed.dirty = true;
+            }
+        }
+    }
+}
@@ -1,112 +1,922 @@
 use super::{
-    EntityMoved, HungerClock, HungerState, Item, Map, Monster, Player, Position, RunState, State,
-    Viewshed, WantsToPickupItem,
+    attributes::Attribute,
+    effects::{EffectQueue, EffectType, Targets},
+    glyphs::{self, GlyphSet},
+    inventory_system::{hostiles_in_view, identifiable_items},
+    AreaOfEffect, Attributes, BlocksTile, CanSwim, Consumable, Digger, Durability, Encumbered,
+    EntityMoved, EntryTrigger, Equippable, Equipped, Hidden, HungerClock, HungerState, Identifier,
+    InBackpack, InflictsDamage, IsSerialized, Item, Map, MassEffect, Monster, Name, Owned, Pet,
+    PetOrders, Position, Ranged, Reach, RunState, SingleActivation, State, StatusEffectKind,
+    StatusEffects, Vendor, Viewshed, WantsToPickupItem, WantsToUseItem,
 };
 use crate::components::CombatStats;
 use crate::components::WantsToMelee;
 use crate::gamelog::Gamelog;
+use crate::grammar::{capitalize, with_the};
+use crate::gui::VendorMode;
 use crate::map::TileType;
-use rltk::{Point, Rltk, VirtualKeyCode};
+use crate::map_transitions::MapEdge;
+use crate::master_dungeon_map::MasterDungeonMap;
+use crate::settings::{GameSettings, SettingsOption};
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode};
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
+use specs::saveload::{Marker, SimpleMarker};
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+/// Percent chance to disarm a trap, before the player's quickness is added
+const DISARM_CHANCE: i32 = 50;
+const DISARM_CHANCE_PER_QUICKNESS: i32 = 10;
+
+/// Percent chance a search turns up each hidden thing next to the player
+const SEARCH_CHANCE: i32 = 25;
+/// Added for each earlier search from the same spot, so enough searching always finds everything
+const SEARCH_CHANCE_PER_ATTEMPT: i32 = 25;
+
+/// Durability a digging tool loses for every wall it opens up
+const DIG_WEAR: i32 = 5;
+
+/// How many times in a row the player has searched, and from where. Moving starts it over.
+#[derive(Default)]
+pub struct SearchProgress {
+    spot: Option<Point>,
+    attempts: i32,
+}
+
+/// Whether the player is on their way to the nearest item, one step a turn
+#[derive(Default)]
+pub struct LootTravel {
+    pub active: bool,
+}
+
+/// The kind of item the player last used, for using another with a single key
+#[derive(Default)]
+pub struct LastUsedItem {
+    name: Option<String>,
+}
+
+/// Moves the player, queues a melee attack on a hostile in the way, or swaps places with a
+/// non-hostile creature. Returns `AwaitingInput` when nothing happened, so bumping into a wall
+/// doesn't cost a turn.
+pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &World) -> RunState {
     let mut ppos = ecs.write_resource::<Point>();
     let mut positions = ecs.write_storage::<Position>();
-    let mut players = ecs.write_storage::<Player>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let combat_stats = ecs.read_storage::<CombatStats>();
+    let monsters = ecs.read_storage::<Monster>();
     let map = ecs.fetch::<Map>();
-    let entities = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
     let mut entity_moved = ecs.write_storage::<EntityMoved>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let reach_weapons = ecs.read_storage::<Reach>();
+    let blockers = ecs.read_storage::<BlocksTile>();
 
-    for (_player, pos, viewshed, entity) in
-        (&mut players, &mut positions, &mut viewsheds, &entities).join()
+    let entity = *ecs.fetch::<Entity>();
+    let Some(pos) = positions.get(entity).cloned() else {
+        return RunState::AwaitingInput;
+    };
+
+    // Check bounds. The edge itself is walled off apart from any exits.
+    if pos.x + delta_x < 0
+        || pos.x + delta_x > map.width - 1
+        || pos.y + delta_y < 0
+        || pos.y + delta_y > map.height - 1
     {
-        // Check bounds
-        if pos.x + delta_x < 1
-            || pos.x + delta_x > map.width - 1
-            || pos.y + delta_y < 1
-            || pos.y + delta_y > map.height - 1
-        {
-            return;
-        }
-        let dest_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
-
-        for potential_target in map.tile_content[dest_idx].iter() {
-            let target = combat_stats.get(*potential_target);
-            if target.is_some() {
-                wants_to_melee
-                    .insert(
-                        entity,
-                        WantsToMelee {
-                            target: *potential_target,
-                        },
-                    )
-                    .expect("Add target failed");
-                return; // So we don't move after attacking
+        return RunState::AwaitingInput;
+    }
+    let dest_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
+
+    let vendors = ecs.read_storage::<Vendor>();
+    if let Some(vendor) = map
+        .entities_at(pos.x + delta_x, pos.y + delta_y)
+        .iter()
+        .find(|e| vendors.get(**e).is_some())
+    {
+        return RunState::ShowVendor {
+            vendor: *vendor,
+            mode: VendorMode::Buy,
+        };
+    }
+
+    let at_dest = map.entities_at(pos.x + delta_x, pos.y + delta_y);
+    if let Some(target) = select_melee_target(at_dest, &monsters, &combat_stats) {
+        wants_to_melee
+            .insert(entity, WantsToMelee { target })
+            .expect("Add target failed");
+        return RunState::PlayerTurn; // So we don't move after attacking
+    }
+    // Only friendly creatures are in the way, so the first one trades places instead
+    let swap_with = at_dest
+        .iter()
+        .find(|e| combat_stats.get(**e).is_some() && monsters.get(**e).is_none())
+        .copied();
+
+    let reach = (&equipped, &reach_weapons)
+        .join()
+        .filter(|(equipped, _reach)| equipped.owner == entity)
+        .map(|(_equipped, reach)| reach.tiles)
+        .max()
+        .unwrap_or(1);
+    if let Some(target) = reach_target(&map, &blockers, &monsters, &pos, delta_x, delta_y, reach) {
+        wants_to_melee
+            .insert(entity, WantsToMelee { target })
+            .expect("Add target failed");
+        return RunState::PlayerTurn;
+    }
+
+    if let Some(other) = swap_with {
+        // The other creature steps into the tile the player is leaving
+        match positions.get_mut(other) {
+            None => return RunState::AwaitingInput,
+            Some(other_pos) => {
+                other_pos.x = pos.x;
+                other_pos.y = pos.y;
             }
         }
+        if let Some(other_viewshed) = viewsheds.get_mut(other) {
+            other_viewshed.dirty = true;
+        }
+        entity_moved
+            .insert(other, EntityMoved {})
+            .expect("Unable to insert marker");
+    } else if map.blocked[dest_idx] {
+        return RunState::AwaitingInput;
+    }
 
-        if !map.blocked[dest_idx] {
-            pos.x = (pos.x + delta_x).clamp(0, map.width - 1);
-            pos.y = (pos.y + delta_y).clamp(0, map.height - 1);
-            ppos.x = pos.x;
-            ppos.y = pos.y;
+    // Wading out of your depth, the pack is left on the last dry tile rather than lost
+    let start_idx = map.xy_idx(pos.x, pos.y);
+    if map.tiles[dest_idx] == TileType::DeepWater
+        && map.tiles[start_idx] != TileType::DeepWater
+        && ecs.read_storage::<CanSwim>().get(entity).is_none()
+    {
+        let mut backpack = ecs.write_storage::<InBackpack>();
+        let dropped: Vec<Entity> = (&ecs.entities(), &backpack)
+            .join()
+            .filter(|(_item, pack)| pack.owner == entity)
+            .map(|(item, _pack)| item)
+            .collect();
+        for item in dropped.iter() {
+            backpack.remove(*item);
+            positions
+                .insert(*item, pos.clone())
+                .expect("Unable to insert position");
+        }
+        if !dropped.is_empty() {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("You drop your pack on the bank before wading in.".to_string());
+        }
+    }
 
-            viewshed.dirty = true;
+    if let Some(pos) = positions.get_mut(entity) {
+        pos.x = (pos.x + delta_x).clamp(0, map.width - 1);
+        pos.y = (pos.y + delta_y).clamp(0, map.height - 1);
+        ppos.x = pos.x;
+        ppos.y = pos.y;
+    }
+    if let Some(viewshed) = viewsheds.get_mut(entity) {
+        viewshed.dirty = true;
+    }
+    entity_moved
+        .insert(entity, EntityMoved {})
+        .expect("Unable to insert marker");
+
+    if ecs.fetch::<GameSettings>().auto_pickup {
+        let items = ecs.read_storage::<Item>();
+        if let Some(item) = map
+            .entities_at(pos.x + delta_x, pos.y + delta_y)
+            .iter()
+            .find(|e| items.get(**e).is_some())
+        {
+            ecs.write_storage::<WantsToPickupItem>()
+                .insert(
+                    entity,
+                    WantsToPickupItem {
+                        collected_by: entity,
+                        item: *item,
+                    },
+                )
+                .expect("Unable to insert want to pickup");
         }
+    }
 
-        entity_moved
-            .insert(entity, EntityMoved {})
-            .expect("Unable to insert marker");
+    if map.tiles[dest_idx] == TileType::Exit {
+        if let Some(edge) = MapEdge::of_tile(&map, pos.x + delta_x, pos.y + delta_y) {
+            return RunState::MapTransition { edge };
+        }
     }
+    RunState::PlayerTurn
+}
+
+/// Picks which of the entities on a tile a bump attacks: the living hostile closest to death, so
+/// blows aren't spread across a crowd. Items, corpses and friendly creatures are never chosen, so
+/// a tile holding none of them gives `None`.
+fn select_melee_target(
+    entities_at_tile: &[Entity],
+    monsters: &ReadStorage<Monster>,
+    combat_stats: &ReadStorage<CombatStats>,
+) -> Option<Entity> {
+    entities_at_tile
+        .iter()
+        .filter(|e| monsters.get(**e).is_some())
+        .filter_map(|e| combat_stats.get(*e).map(|stats| (*e, stats.hp)))
+        .filter(|(_e, hp)| *hp > 0)
+        .min_by_key(|(_e, hp)| *hp)
+        .map(|(e, _hp)| e)
+}
+
+/// Looks along (delta_x, delta_y) for a hostile that a reach weapon can hit.
+/// Adjacent targets are handled by the regular bump attack; walls and other creatures block the line.
+fn reach_target(
+    map: &Map,
+    blockers: &ReadStorage<BlocksTile>,
+    monsters: &ReadStorage<Monster>,
+    pos: &Position,
+    delta_x: i32,
+    delta_y: i32,
+    reach: i32,
+) -> Option<Entity> {
+    for step in 1..=reach {
+        let x = pos.x + delta_x * step;
+        let y = pos.y + delta_y * step;
+        if x < 1 || x > map.width - 1 || y < 1 || y > map.height - 1 {
+            return None;
+        }
+        let idx = map.xy_idx(x, y);
+
+        if let Some(target) = map.first_blocker_at(x, y, blockers) {
+            let is_hostile = monsters.get(target).is_some();
+            return if step > 1 && is_hostile {
+                Some(target)
+            } else {
+                None
+            };
+        }
+        if map.blocked[idx] {
+            return None;
+        }
+    }
+    None
+}
+
+/// Everything the player can ask for, independent of the keyboard. Most are taken from the main
+/// game screen; the rest are choices made in a menu, and only mean something while it's open.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Move {
+        delta_x: i32,
+        delta_y: i32,
+    },
+    SkipTurn,
+    PickUp,
+    EquipFromGround,
+    UseFromGround,
+    ShowInventory,
+    ShowDropItem,
+    ShowRemoveItem,
+    ConfirmQuit,
+    ShowOptions,
+    ShowBestiary,
+    ShowHelp,
+    Descend,
+    DisarmTrap,
+    Search,
+    TravelToLoot,
+    /// Calls off a trip to an item part way
+    StopTravel,
+    ShowThrowItem,
+    CyclePetOrders,
+    FreeLook,
+    UseLastItem,
+    #[cfg(debug_assertions)]
+    OpenDebugConsole,
+    /// Leaves the menu without choosing anything
+    CloseMenu,
+    /// Picks an item from the menu, named by its marker. What that does depends on the menu.
+    ChooseItem {
+        item: u64,
+    },
+    ChooseTarget {
+        x: i32,
+        y: i32,
+    },
+    ChooseAttribute {
+        attribute: Attribute,
+    },
+    /// Buys the ware at this place in the vendor's list
+    ChooseWare {
+        ware: usize,
+    },
+    SwitchVendorMode,
+    DropChosen,
+    DestroyChosen,
+    ChangeOption {
+        option: SettingsOption,
+    },
 }
 
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
+    match ctx.key.and_then(key_to_action) {
+        None => RunState::AwaitingInput,
+        Some(action) => gs.take_action(RunState::AwaitingInput, action),
+    }
+}
+
+impl Action {
+    /// What the action does, as listed on the help screen
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Move { delta_x, delta_y } => match (delta_x, delta_y) {
+                (0, -1) => "Move north",
+                (0, 1) => "Move south",
+                (-1, 0) => "Move west",
+                (1, 0) => "Move east",
+                (-1, -1) => "Move north-west",
+                (1, -1) => "Move north-east",
+                (-1, 1) => "Move south-west",
+                (1, 1) => "Move south-east",
+                _ => "Move",
+            },
+            Action::SkipTurn => "Wait a turn",
+            Action::PickUp => "Pick up an item",
+            Action::EquipFromGround => "Equip an item from the ground",
+            Action::UseFromGround => "Use an item from the ground",
+            Action::ShowInventory => "Inventory",
+            Action::ShowDropItem => "Drop items",
+            Action::ShowRemoveItem => "Unequip an item",
+            Action::ConfirmQuit => "Save and quit",
+            Action::ShowOptions => "Options",
+            Action::ShowBestiary => "Bestiary",
+            Action::ShowHelp => "This help",
+            Action::Descend => "Go down stairs",
+            Action::DisarmTrap => "Disarm a trap",
+            Action::Search => "Search nearby",
+            Action::TravelToLoot => "Travel to the nearest item",
+            Action::StopTravel => "Stop travelling",
+            Action::ShowThrowItem => "Throw an item",
+            Action::CyclePetOrders => "Change your pet's orders",
+            Action::FreeLook => "Look around the map",
+            Action::UseLastItem => "Use another of the last item used",
+            #[cfg(debug_assertions)]
+            Action::OpenDebugConsole => "Debug console",
+            Action::CloseMenu => "Close the menu",
+            Action::ChooseItem { .. } => "Choose an item",
+            Action::ChooseTarget { .. } => "Choose a target",
+            Action::ChooseAttribute { .. } => "Raise an attribute",
+            Action::ChooseWare { .. } => "Buy an item",
+            Action::SwitchVendorMode => "Switch between buying and selling",
+            Action::DropChosen => "Drop the chosen items",
+            Action::DestroyChosen => "Destroy the chosen items",
+            Action::ChangeOption { .. } => "Change an option",
+        }
+    }
+
+    /// Picks `item` from a menu. Items are named by their save marker rather than the entity,
+    /// since markers are handed out the same way every time a run is replayed while entity ids
+    /// are also used up by particles, which a replay played without a window never makes.
+    pub fn choose_item(ecs: &World, item: Entity) -> Action {
+        let marker = ecs
+            .read_storage::<SimpleMarker<IsSerialized>>()
+            .get(item)
+            .map(|marker| marker.id())
+            .expect("Anything in a menu is saved with the game, so it has a marker");
+        Action::ChooseItem { item: marker }
+    }
+}
+
+/// The entity an action names by its marker, if it's still around
+pub fn marked_entity(ecs: &World, marker: u64) -> Option<Entity> {
+    let entities = ecs.entities();
+    let markers = ecs.read_storage::<SimpleMarker<IsSerialized>>();
+    (&entities, &markers)
+        .join()
+        .find(|(_entity, entity_marker)| entity_marker.id() == marker)
+        .map(|(entity, _marker)| entity)
+}
+
+/// Every key binding, in the order the help screen lists them
+pub fn keymap() -> Vec<(VirtualKeyCode, Action)> {
     use VirtualKeyCode::*;
-    // TODO: Replace with if let
-    match ctx.key {
-        None => return RunState::AwaitingInput,
-        Some(key) => match key {
-            // Skip turn
-            Space | Numpad5 => return skip_turn(&mut gs.ecs),
-
-            // Cardinal
-            Left | Numpad4 | H => try_move_player(-1, 0, &mut gs.ecs),
-            Right | Numpad6 | L => try_move_player(1, 0, &mut gs.ecs),
-            Up | Numpad8 | K => try_move_player(0, -1, &mut gs.ecs),
-            Down | Numpad2 | J => try_move_player(0, 1, &mut gs.ecs),
-
-            //Diagonal
-            Numpad1 | Y => try_move_player(-1, -1, &mut gs.ecs),
-            Numpad9 | N => try_move_player(1, 1, &mut gs.ecs),
-            Numpad7 | B => try_move_player(-1, 1, &mut gs.ecs),
-            Numpad3 | U => try_move_player(1, -1, &mut gs.ecs),
-
-            // Item
-            G => get_item(&mut gs.ecs),
-            I => return RunState::ShowInventory,
-            D => return RunState::ShowDropItem,
-            R => return RunState::ShowRemoveItem,
-
-            // Main Menu
-            Escape => return RunState::SaveGame,
-
-            // Stairs
-            Period => {
-                if is_down_stairs(&mut gs.ecs) {
-                    return RunState::NextLevel;
+    let north = Action::Move {
+        delta_x: 0,
+        delta_y: -1,
+    };
+    let south = Action::Move {
+        delta_x: 0,
+        delta_y: 1,
+    };
+    let west = Action::Move {
+        delta_x: -1,
+        delta_y: 0,
+    };
+    let east = Action::Move {
+        delta_x: 1,
+        delta_y: 0,
+    };
+    let north_west = Action::Move {
+        delta_x: -1,
+        delta_y: -1,
+    };
+    let north_east = Action::Move {
+        delta_x: 1,
+        delta_y: -1,
+    };
+    let south_west = Action::Move {
+        delta_x: -1,
+        delta_y: 1,
+    };
+    let south_east = Action::Move {
+        delta_x: 1,
+        delta_y: 1,
+    };
+
+    #[allow(unused_mut)]
+    let mut keys = vec![
+        // Cardinal
+        (Up, north),
+        (Numpad8, north),
+        (K, north),
+        (Down, south),
+        (Numpad2, south),
+        (J, south),
+        (Left, west),
+        (Numpad4, west),
+        (H, west),
+        (Right, east),
+        (Numpad6, east),
+        (L, east),
+        // Diagonal
+        (Numpad1, north_west),
+        (Y, north_west),
+        (Numpad3, north_east),
+        (U, north_east),
+        (Numpad7, south_west),
+        (B, south_west),
+        (Numpad9, south_east),
+        (N, south_east),
+        // Skip turn
+        (Space, Action::SkipTurn),
+        (Numpad5, Action::SkipTurn),
+        // Item
+        (G, Action::PickUp),
+        (E, Action::EquipFromGround),
+        (Q, Action::UseFromGround),
+        (I, Action::ShowInventory),
+        (D, Action::ShowDropItem),
+        (R, Action::ShowRemoveItem),
+        (T, Action::ShowThrowItem),
+        (A, Action::UseLastItem),
+        // Stairs
+        (Period, Action::Descend),
+        // Traps
+        (X, Action::DisarmTrap),
+        (S, Action::Search),
+        // Travel
+        (F, Action::TravelToLoot),
+        // Pets
+        (P, Action::CyclePetOrders),
+        // Main Menu
+        (Escape, Action::ConfirmQuit),
+        (O, Action::ShowOptions),
+        (M, Action::ShowBestiary),
+        (V, Action::FreeLook),
+        (Slash, Action::ShowHelp),
+    ];
+    // Developer tools
+    #[cfg(debug_assertions)]
+    keys.push((Grave, Action::OpenDebugConsole));
+    keys
+}
+
+pub fn key_to_action(key: VirtualKeyCode) -> Option<Action> {
+    keymap()
+        .into_iter()
+        .find(|(bound, _action)| *bound == key)
+        .map(|(_key, action)| action)
+}
+
+/// How a key is shown on the help screen
+pub fn key_name(key: VirtualKeyCode) -> String {
+    match key {
+        VirtualKeyCode::Period => ".".to_string(),
+        VirtualKeyCode::Slash => "?".to_string(),
+        VirtualKeyCode::Grave => "`".to_string(),
+        _ => format!("{key:?}"),
+    }
+}
+
+/// Applies an action to the world and returns the state the game should move to
+pub fn perform_action(ecs: &mut World, action: Action) -> RunState {
+    match action {
+        Action::SkipTurn => skip_turn(ecs),
+        Action::Move { delta_x, delta_y } => {
+            let (stagger_x, stagger_y) = stagger(ecs, delta_x, delta_y);
+            let runstate = try_dig(stagger_x, stagger_y, ecs)
+                .unwrap_or_else(|| try_move_player(stagger_x, stagger_y, ecs));
+            // Lurching into a wall still wastes the turn
+            let staggered = (stagger_x, stagger_y) != (delta_x, delta_y);
+            if staggered && runstate == RunState::AwaitingInput {
+                RunState::PlayerTurn
+            } else {
+                runstate
+            }
+        }
+        Action::PickUp => try_pickup(ecs),
+        Action::EquipFromGround => try_equip_from_ground(ecs),
+        Action::UseFromGround => try_use_from_ground(ecs),
+        Action::ShowInventory => RunState::ShowInventory,
+        Action::ShowDropItem => RunState::ShowDropItem,
+        Action::ShowRemoveItem => RunState::ShowRemoveItem,
+        Action::ShowThrowItem if too_enraged(ecs) => RunState::AwaitingInput,
+        Action::ShowThrowItem => RunState::ShowThrowItem,
+        Action::ConfirmQuit => RunState::ConfirmQuit,
+        Action::ShowBestiary => RunState::ShowBestiary,
+        Action::ShowHelp => RunState::ShowHelp { page: 0 },
+        Action::ShowOptions => RunState::Options {
+            highlighted: 0,
+            in_game: true,
+        },
+        Action::Descend => {
+            if is_down_stairs(ecs) {
+                RunState::NextLevel
+            } else {
+                RunState::PlayerTurn
+            }
+        }
+        Action::DisarmTrap => try_disarm(ecs),
+        Action::Search => search(ecs),
+        Action::TravelToLoot => {
+            ecs.fetch_mut::<LootTravel>().active = true;
+            travel_to_loot(ecs)
+        }
+        Action::StopTravel => {
+            ecs.fetch_mut::<LootTravel>().active = false;
+            RunState::AwaitingInput
+        }
+        Action::CyclePetOrders => cycle_pet_orders(ecs),
+        Action::FreeLook => RunState::FreeLook {
+            offset: Point::new(0, 0),
+        },
+        Action::UseLastItem => use_last_item(ecs),
+        #[cfg(debug_assertions)]
+        Action::OpenDebugConsole => RunState::DebugConsole,
+        // Only mean something in a menu, where `State::perform_menu_action` takes them
+        Action::CloseMenu
+        | Action::ChooseItem { .. }
+        | Action::ChooseTarget { .. }
+        | Action::ChooseAttribute { .. }
+        | Action::ChooseWare { .. }
+        | Action::SwitchVendorMode
+        | Action::DropChosen
+        | Action::DestroyChosen
+        | Action::ChangeOption { .. } => RunState::AwaitingInput,
+    }
+}
+
+/// Whether the player is raging, which leaves no patience for drinking, reading or throwing
+/// things. Says so in the log if they are.
+fn too_enraged(ecs: &World) -> bool {
+    let raging = ecs
+        .read_storage::<StatusEffects>()
+        .get(*ecs.fetch::<Entity>())
+        .is_some_and(|status| status.has(StatusEffectKind::Raging));
+    if raging {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push("You are too enraged for that!".to_string());
+    }
+    raging
+}
+
+/// A confused player lurches in a random direction rather than the one they chose
+fn stagger(ecs: &mut World, delta_x: i32, delta_y: i32) -> (i32, i32) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let confused = ecs
+        .read_storage::<StatusEffects>()
+        .get(player_entity)
+        .is_some_and(|status| status.has(StatusEffectKind::Confused));
+    if !confused {
+        return (delta_x, delta_y);
+    }
+    let directions = [
+        (0, -1),
+        (0, 1),
+        (-1, 0),
+        (1, 0),
+        (-1, -1),
+        (1, -1),
+        (-1, 1),
+        (1, 1),
+    ];
+    let roll = ecs
+        .fetch_mut::<RandomNumberGenerator>()
+        .roll_dice(1, directions.len() as i32);
+    let lurch = directions[roll as usize - 1];
+    if lurch != (delta_x, delta_y) {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push("You stumble about in confusion.".to_string());
+    }
+    lurch
+}
+
+/// Digs out the wall at (delta_x, delta_y) from the player with an equipped digging tool, leaving
+/// floor that can be walked and seen through at once. Gives `None` when there's no wall there or
+/// nothing to dig with, so the move goes ahead as usual. The walls around the edge of the map
+/// can't be dug, and trying takes no turn.
+fn try_dig(delta_x: i32, delta_y: i32, ecs: &mut World) -> Option<RunState> {
+    let player_entity = *ecs.fetch::<Entity>();
+    let player_pos = *ecs.fetch::<Point>();
+    let (x, y) = (player_pos.x + delta_x, player_pos.y + delta_y);
+
+    let tool = {
+        let entities = ecs.entities();
+        let equipped = ecs.read_storage::<Equipped>();
+        let diggers = ecs.read_storage::<Digger>();
+        (&entities, &equipped, &diggers)
+            .join()
+            .find(|(_item, equipped, _digger)| equipped.owner == player_entity)
+            .map(|(item, _equipped, _digger)| item)
+    }?;
+
+    {
+        let mut map = ecs.fetch_mut::<Map>();
+        if x < 0 || x >= map.width || y < 0 || y >= map.height {
+            return None;
+        }
+        let idx = map.xy_idx(x, y);
+        if map.tiles[idx] != TileType::Wall {
+            return None;
+        }
+        if x == 0 || x == map.width - 1 || y == 0 || y == map.height - 1 {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("The rock at the edge of the level is too hard to dig.".to_string());
+            return Some(RunState::AwaitingInput);
+        }
+        map.tiles[idx] = TileType::Floor;
+        map.blocked[idx] = false;
+    }
+
+    // Anyone who could see the wall may now see past it
+    let dug = Point::new(x, y);
+    for viewshed in (&mut ecs.write_storage::<Viewshed>()).join() {
+        if viewshed.visible_tiles.contains(&dug) {
+            viewshed.dirty = true;
+        }
+    }
+
+    let tool_name = ecs
+        .read_storage::<Name>()
+        .get(tool)
+        .map_or("tool".to_string(), |name| name.name.clone());
+    ecs.fetch_mut::<Gamelog>()
+        .entries
+        .push(format!("You dig through the wall with your {tool_name}."));
+    let broken = ecs
+        .write_storage::<Durability>()
+        .get_mut(tool)
+        .is_some_and(|wear| {
+            wear.current -= DIG_WEAR;
+            wear.current < 1
+        });
+    if broken {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push(format!("Your {tool_name} breaks!"));
+        ecs.delete_entity(tool)
+            .expect("Unable to delete broken tool");
+    }
+    Some(RunState::PlayerTurn)
+}
+
+/// Moves every pet on to its next orders. Giving orders doesn't cost a turn.
+fn cycle_pet_orders(ecs: &mut World) -> RunState {
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let mut pets = ecs.write_storage::<Pet>();
+    let mut log = ecs.fetch_mut::<Gamelog>();
+
+    let mut commanded = false;
+    for (entity, pet) in (&entities, &mut pets).join() {
+        pet.orders = pet.orders.next();
+        let name = names
+            .get(entity)
+            .map_or("Your pet".to_string(), |name| capitalize(&with_the(name)));
+        let instruction = match pet.orders {
+            PetOrders::Follow => "follows you",
+            PetOrders::Attack => "goes after anything in sight",
+            PetOrders::Stay => "stays put",
+        };
+        log.entries.push(format!("{name} {instruction}."));
+        commanded = true;
+    }
+    if !commanded {
+        log.entries
+            .push("You have no companion to command.".to_string());
+    }
+    RunState::AwaitingInput
+}
+
+/// Tries to disarm a revealed trap next to the player. Success removes it and failure sets it
+/// off on the player; either way it costs a turn. Returns `AwaitingInput` if there's no trap.
+fn try_disarm(ecs: &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let player_pos = *ecs.fetch::<Point>();
+
+    let trap = {
+        let map = ecs.fetch::<Map>();
+        let entities = ecs.entities();
+        let positions = ecs.read_storage::<Position>();
+        let triggers = ecs.read_storage::<EntryTrigger>();
+        let hidden = ecs.read_storage::<Hidden>();
+        (&entities, &positions, &triggers, !&hidden)
+            .join()
+            .find(|(_entity, pos, _trigger, _hidden)| {
+                rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), player_pos) < 1.5
+            })
+            .map(|(entity, pos, _trigger, _hidden)| (entity, map.xy_idx(pos.x, pos.y)))
+    };
+    let Some((trap, trap_idx)) = trap else {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push("There is no trap nearby to disarm.".to_string());
+        return RunState::AwaitingInput;
+    };
+
+    let trap_name = ecs
+        .read_storage::<Name>()
+        .get(trap)
+        .map_or("trap".to_string(), |name| name.name.clone());
+    let quickness = ecs
+        .read_storage::<Attributes>()
+        .get(player_entity)
+        .map_or(0, |attributes| attributes.quickness);
+    let chance = DISARM_CHANCE + quickness * DISARM_CHANCE_PER_QUICKNESS;
+    let roll = ecs.fetch_mut::<RandomNumberGenerator>().roll_dice(1, 100);
+
+    if roll <= chance {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push(format!("You disarm the {trap_name}."));
+        ecs.delete_entity(trap).expect("Unable to delete trap");
+        return RunState::PlayerTurn;
+    }
+
+    ecs.fetch_mut::<Gamelog>()
+        .entries
+        .push(format!("You fumble and set off the {trap_name}!"));
+    let damage = ecs
+        .read_storage::<InflictsDamage>()
+        .get(trap)
+        .map(|inflicts| (inflicts.damage, inflicts.damage_type));
+    if let Some((damage, damage_type)) = damage {
+        let amount = damage.roll(&mut ecs.fetch_mut::<RandomNumberGenerator>());
+        let mut effects = ecs.fetch_mut::<EffectQueue>();
+        effects.add_effect(
+            Some(trap),
+            EffectType::Particle {
+                glyph: ecs.fetch::<GlyphSet>().get(glyphs::HIT),
+                fg: rltk::RGB::named(rltk::ORANGE),
+                bg: rltk::RGB::named(rltk::BLACK),
+                lifetime: 200.0,
+            },
+            Targets::Tile { tile_idx: trap_idx },
+        );
+        effects.add_effect(
+            Some(trap),
+            EffectType::Damage {
+                amount,
+                damage_type,
+            },
+            Targets::Single {
+                target: player_entity,
+            },
+        );
+    }
+    if ecs.read_storage::<SingleActivation>().get(trap).is_some() {
+        ecs.delete_entity(trap).expect("Unable to delete trap");
+    }
+    RunState::PlayerTurn
+}
+
+/// Spends a turn searching the eight tiles around the player, with a chance to reveal each hidden
+/// thing there. The chance grows with every search from the same spot.
+fn search(ecs: &mut World) -> RunState {
+    let player_pos = *ecs.fetch::<Point>();
+    let chance = {
+        let mut progress = ecs.fetch_mut::<SearchProgress>();
+        if progress.spot != Some(player_pos) {
+            *progress = SearchProgress {
+                spot: Some(player_pos),
+                attempts: 0,
+            };
+        }
+        progress.attempts += 1;
+        SEARCH_CHANCE + SEARCH_CHANCE_PER_ATTEMPT * (progress.attempts - 1)
+    };
+
+    let found: Vec<Entity> = {
+        let map = ecs.fetch::<Map>();
+        let hidden = ecs.read_storage::<Hidden>();
+        let mut rng = ecs.fetch_mut::<RandomNumberGenerator>();
+        let mut found = Vec::new();
+        for y in player_pos.y - 1..=player_pos.y + 1 {
+            for x in player_pos.x - 1..=player_pos.x + 1 {
+                if (x, y) == (player_pos.x, player_pos.y) {
+                    continue;
+                }
+                for e in map.entities_at(x, y).iter() {
+                    if hidden.get(*e).is_some() && rng.roll_dice(1, 100) <= chance {
+                        found.push(*e);
+                    }
                 }
             }
+        }
+        found
+    };
 
-            _ => return RunState::AwaitingInput,
-        },
+    let mut log = ecs.fetch_mut::<Gamelog>();
+    if found.is_empty() {
+        log.entries.push("You search but find nothing.".to_string());
+    }
+    let names = ecs.read_storage::<Name>();
+    let mut hidden = ecs.write_storage::<Hidden>();
+    for e in found {
+        if let Some(name) = names.get(e) {
+            log.entries
+                .push(format!("You found a {name}.", name = &name.name));
+        }
+        hidden.remove(e);
     }
     RunState::PlayerTurn
 }
 
-fn get_item(ecs: &mut World) {
+/// Takes one step towards the nearest item the player knows is lying on the ground, and keeps
+/// the trip going until it ends. It ends on arriving at an item, when a hostile comes into view,
+/// or when no known item can be reached. The tile the player stands on doesn't count, so a trip
+/// can start from on top of an item.
+pub fn travel_to_loot(ecs: &mut World) -> RunState {
+    let player_pos = *ecs.fetch::<Point>();
+    let step = {
+        let map = ecs.fetch::<Map>();
+        let entities = ecs.entities();
+        let monsters = ecs.read_storage::<Monster>();
+        let items = ecs.read_storage::<Item>();
+        let hidden = ecs.read_storage::<Hidden>();
+        let positions = ecs.read_storage::<Position>();
+
+        let hostile_in_view = (&monsters, &positions)
+            .join()
+            .any(|(_monster, pos)| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+        if hostile_in_view {
+            Err("You stop, a monster is in view.")
+        } else {
+            let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+            let goals: Vec<usize> = (&entities, &items, &positions, !&hidden)
+                .join()
+                .map(|(_item, _is_item, pos, _hidden)| map.xy_idx(pos.x, pos.y))
+                .filter(|idx| *idx != player_idx && map.revealed_tiles[*idx])
+                .collect();
+            let distances = rltk::DijkstraMap::new(map.width, map.height, &goals, &*map, 200.0);
+            // With no path to any goal, every tile is as far as any other, and the lowest exit
+            // would be a step in no particular direction
+            if goals.is_empty() || distances.map[player_idx] >= f32::MAX {
+                Err("There are no reachable items.")
+            } else {
+                rltk::DijkstraMap::find_lowest_exit(&distances, player_idx, &*map)
+                    .map(|idx| (idx as i32 % map.width, idx as i32 / map.width))
+                    .ok_or("There are no reachable items.")
+            }
+        }
+    };
+
+    let (x, y) = match step {
+        Ok(tile) => tile,
+        Err(reason) => {
+            ecs.fetch_mut::<LootTravel>().active = false;
+            ecs.fetch_mut::<Gamelog>().entries.push(reason.to_string());
+            return RunState::AwaitingInput;
+        }
+    };
+    let runstate = try_move_player(x - player_pos.x, y - player_pos.y, ecs);
+
+    // Arrived, or couldn't move after all
+    let player_pos = *ecs.fetch::<Point>();
+    let arrived = {
+        let items = ecs.read_storage::<Item>();
+        let map = ecs.fetch::<Map>();
+        map.entities_at(player_pos.x, player_pos.y)
+            .iter()
+            .any(|e| items.get(*e).is_some())
+    };
+    if arrived || runstate == RunState::AwaitingInput {
+        ecs.fetch_mut::<LootTravel>().active = false;
+    }
+    runstate
+}
+
+/// Queues picking up the item under the player. Returns `AwaitingInput` if there is none.
+pub fn try_pickup(ecs: &World) -> RunState {
     // TODO: Can't we grab pos from player entity?
     let player_pos = ecs.fetch::<Point>();
     let player_entity = ecs.fetch::<Entity>();
@@ -124,9 +934,12 @@ fn get_item(ecs: &mut World) {
     }
 
     match target_item {
-        None => gamelog
-            .entries
-            .push("There is nothing here to pick up.".to_string()),
+        None => {
+            gamelog
+                .entries
+                .push("There is nothing here to pick up.".to_string());
+            RunState::AwaitingInput
+        }
         Some(item) => {
             let mut pickup = ecs.write_storage::<WantsToPickupItem>();
             pickup
@@ -138,10 +951,215 @@ fn get_item(ecs: &mut World) {
                     },
                 )
                 .expect("Unable to insert want to pickup");
+            RunState::PlayerTurn
         }
     }
 }
 
+/// Everything the player carries, backpack first, then what they have equipped
+pub fn throwable_items(ecs: &World) -> Vec<Entity> {
+    let player_entity = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let equipped = ecs.read_storage::<Equipped>();
+
+    let carried = (&entities, &backpack)
+        .join()
+        .filter(|(_item, pack)| pack.owned_by(&player_entity))
+        .map(|(item, _pack)| item);
+    let worn = (&entities, &equipped)
+        .join()
+        .filter(|(_item, equipped_by)| equipped_by.owned_by(&player_entity))
+        .map(|(item, _equipped_by)| item);
+    carried.chain(worn).collect()
+}
+
+/// Equippable items lying under the player
+pub fn equippable_items_here(ecs: &World) -> Vec<Entity> {
+    let player_pos = ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let equippables = ecs.read_storage::<Equippable>();
+    let positions = ecs.read_storage::<Position>();
+
+    (&entities, &equippables, &positions)
+        .join()
+        .filter(|(_item, _equippable, pos)| pos.x == player_pos.x && pos.y == player_pos.y)
+        .map(|(item, _equippable, _pos)| item)
+        .collect()
+}
+
+/// Equips an item under the player without a separate pickup. Asks which one if there are
+/// several, and returns `AwaitingInput` if there are none.
+fn try_equip_from_ground(ecs: &World) -> RunState {
+    match equippable_items_here(ecs).as_slice() {
+        [] => {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("There is nothing here to equip.".to_string());
+            RunState::AwaitingInput
+        }
+        [item] => {
+            equip_from_ground(ecs, *item);
+            RunState::PlayerTurn
+        }
+        _ => RunState::ShowEquipFromGround,
+    }
+}
+
+/// Queues picking `item` up and then equipping it. Collection runs before item use, so both
+/// happen in the same turn and the item goes through the usual equip path.
+pub fn equip_from_ground(ecs: &World, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.write_storage::<WantsToPickupItem>()
+        .insert(
+            player_entity,
+            WantsToPickupItem {
+                collected_by: player_entity,
+                item,
+            },
+        )
+        .expect("Unable to insert want to pickup");
+    ecs.write_storage::<WantsToUseItem>()
+        .insert(player_entity, WantsToUseItem { item, target: None })
+        .expect("Unable to insert intent");
+}
+
+/// Equippables are equipped, targeted items open the targeting UI, identify scrolls ask which
+/// item to identify and anything else is used on the player straight away.
+pub fn use_item(ecs: &World, item: Entity) -> RunState {
+    if ecs.read_storage::<Equippable>().get(item).is_none() && too_enraged(ecs) {
+        return RunState::AwaitingInput;
+    }
+    if ecs.read_storage::<Identifier>().get(item).is_some() {
+        if identifiable_items(ecs, item).is_empty() {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("There is nothing to identify.".to_string());
+            return RunState::AwaitingInput;
+        }
+        return RunState::ShowIdentify { item };
+    }
+
+    // Kept for later, and no turn lost, when there's nothing to read it at
+    let keeps = ecs
+        .read_storage::<MassEffect>()
+        .get(item)
+        .is_some_and(|mass| !mass.fizzles);
+    if keeps {
+        let player_entity = ecs.fetch::<Entity>();
+        let in_sight =
+            ecs.read_storage::<Viewshed>()
+                .get(*player_entity)
+                .map_or(Vec::new(), |viewshed| {
+                    hostiles_in_view(
+                        viewshed,
+                        &ecs.entities(),
+                        &ecs.read_storage::<Monster>(),
+                        &ecs.read_storage::<Position>(),
+                    )
+                });
+        if in_sight.is_empty() {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("There are no targets in sight.".to_string());
+            return RunState::AwaitingInput;
+        }
+    }
+
+    let is_equippable = ecs.read_storage::<Equippable>().get(item).is_some();
+    let range = ecs.read_storage::<Ranged>().get(item).map(|r| r.range);
+    let is_aoe = ecs.read_storage::<AreaOfEffect>().get(item).is_some();
+
+    let target = match (is_equippable, range) {
+        (false, Some(range)) => return RunState::ShowTargeting { range, item },
+        // An area effect without range is centered on the player
+        (false, None) if is_aoe => Some(*ecs.fetch::<Point>()),
+        _ => None,
+    };
+
+    let mut intent = ecs.write_storage::<WantsToUseItem>();
+    intent
+        .insert(*ecs.fetch::<Entity>(), WantsToUseItem { item, target })
+        .expect("Unable to insert intent");
+    remember_use(ecs, item);
+    RunState::PlayerTurn
+}
+
+/// Records `item`'s kind for `use_last_item`, once its use is queued. Equipment isn't recorded,
+/// there being nothing to use again.
+pub fn remember_use(ecs: &World, item: Entity) {
+    if ecs.read_storage::<Equippable>().get(item).is_some() {
+        return;
+    }
+    if let Some(name) = ecs.read_storage::<Name>().get(item) {
+        ecs.fetch_mut::<LastUsedItem>().name = Some(name.name.clone());
+    }
+}
+
+/// Uses any item in the backpack of the same kind as the last one used, going through `use_item`
+/// so targeted items still ask for a target
+fn use_last_item(ecs: &World) -> RunState {
+    let Some(name) = ecs.fetch::<LastUsedItem>().name.clone() else {
+        ecs.fetch_mut::<Gamelog>()
+            .entries
+            .push("You haven't used anything yet.".to_string());
+        return RunState::AwaitingInput;
+    };
+    let item = {
+        let player_entity = ecs.fetch::<Entity>();
+        let entities = ecs.entities();
+        let backpack = ecs.read_storage::<InBackpack>();
+        let names = ecs.read_storage::<Name>();
+        (&entities, &backpack, &names)
+            .join()
+            .filter(|(_item, pack, item_name)| {
+                pack.owner == *player_entity && item_name.name == name
+            })
+            .map(|(item, _pack, _name)| item)
+            .max_by_key(|item| item.id())
+    };
+    match item {
+        Some(item) => use_item(ecs, item),
+        None => {
+            let shown = ecs.fetch::<MasterDungeonMap>().display_name(&name);
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push(format!("You have no {shown} left."));
+            RunState::AwaitingInput
+        }
+    }
+}
+
+/// Consumables lying under the player
+pub fn usable_items_here(ecs: &World) -> Vec<Entity> {
+    let player_pos = ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let consumables = ecs.read_storage::<Consumable>();
+    let positions = ecs.read_storage::<Position>();
+
+    (&entities, &consumables, &positions)
+        .join()
+        .filter(|(_item, _consumable, pos)| pos.x == player_pos.x && pos.y == player_pos.y)
+        .map(|(item, _consumable, _pos)| item)
+        .collect()
+}
+
+/// Quaffs, reads or eats an item under the player without it ever going into the backpack, so
+/// it works with a full pack. Targeted items still open the targeting UI, and cancelling leaves
+/// the item where it lies.
+fn try_use_from_ground(ecs: &World) -> RunState {
+    match usable_items_here(ecs).as_slice() {
+        [] => {
+            ecs.fetch_mut::<Gamelog>()
+                .entries
+                .push("There is nothing here to use.".to_string());
+            RunState::AwaitingInput
+        }
+        [item] => use_item(ecs, *item),
+        _ => RunState::ShowUseFromGround,
+    }
+}
+
 pub fn is_down_stairs(ecs: &mut World) -> bool {
     let p_pos = ecs.fetch::<Point>();
     let map = ecs.fetch::<Map>();
@@ -165,14 +1183,22 @@ fn skip_turn(ecs: &mut World) -> RunState {
     // Check that no monsters in player viewshed
     let viewshed = viewsheds.get(*player_entity).unwrap();
     for tile in viewshed.visible_tiles.iter() {
-        let idx = map.xy_idx(tile.x, tile.y);
-        for entity in map.tile_content[idx].iter() {
+        for entity in map.entities_at(tile.x, tile.y).iter() {
             if monsters.get(*entity).is_some() {
                 return RunState::PlayerTurn;
             }
         }
     }
 
+    // Carrying too much is too tiring to recover
+    if ecs
+        .read_storage::<Encumbered>()
+        .get(*player_entity)
+        .is_some()
+    {
+        return RunState::PlayerTurn;
+    }
+
     let hunger_clocks = ecs.read_storage::<HungerClock>();
     if let Some(hc) = hunger_clocks.get(*player_entity) {
         match hc.state {
@@ -188,3 +1214,46 @@ fn skip_turn(ecs: &mut World) -> RunState {
     player_stats.hp = i32::min(player_stats.hp + 1, player_stats.max_hp);
     RunState::PlayerTurn
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The player in a two-tile pocket at (2, 2), and an item on the other side of a wall
+    fn walled_off_item() -> World {
+        let mut ecs = World::new();
+        ecs.register::<Monster>();
+        ecs.register::<Item>();
+        ecs.register::<Hidden>();
+        ecs.register::<Position>();
+
+        let mut map = Map::new(1);
+        for (x, y) in [(2, 2), (3, 2), (5, 2)] {
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Floor;
+            map.revealed_tiles[idx] = true;
+        }
+        map.populate_blocked();
+        ecs.insert(map);
+        ecs.insert(Point::new(2, 2));
+        ecs.insert(Gamelog { entries: vec![] });
+        ecs.insert(LootTravel { active: true });
+        ecs.create_entity()
+            .with(Item {})
+            .with(Position { x: 5, y: 2 })
+            .build();
+        ecs
+    }
+
+    #[test]
+    fn travel_stops_when_no_item_is_reachable() {
+        let mut ecs = walled_off_item();
+        assert!(travel_to_loot(&mut ecs) == RunState::AwaitingInput);
+        assert!(!ecs.fetch::<LootTravel>().active);
+        assert_eq!(
+            ecs.fetch::<Gamelog>().entries.last().map(String::as_str),
+            Some("There are no reachable items.")
+        );
+        assert!(*ecs.fetch::<Point>() == Point::new(2, 2));
+    }
+}
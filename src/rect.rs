@@ -23,6 +23,11 @@ impl Rect {
         self.x1 <= other.x2 && self.x2 >= other.x1 && self.y1 <= other.y2 && self.y2 >= other.y1
     }
 
+    /// Whether the tile lies within the rectangle, edges included
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x1 && x <= self.x2 && y >= self.y1 && y <= self.y2
+    }
+
     /// Returns center position of a Rectangle
     pub fn center(&self) -> Position {
         Position {
@@ -0,0 +1,178 @@
+use super::{classes::PlayerClass, player::Action, settings::GameSettings, RunState};
+use serde::{Deserialize, Serialize};
+use std::{fmt, hash::Hasher};
+
+const REPLAY_FILE: &str = "./replay.ron";
+
+/// A run recorded from a new game: the seed, class and settings it started with, then every
+/// action the player took, whether from the main game screen or as a choice in a menu
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    /// The version of the game that recorded it. Any other version may play out differently.
+    game_version: String,
+    pub seed: u64,
+    pub class: PlayerClass,
+    pub settings: GameSettings,
+    steps: Vec<ReplayStep>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+struct ReplayStep {
+    turn: i32,
+    action: Action,
+    /// `State::world_hash` just before the action was taken
+    world_hash: u64,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ReplayError {
+    /// There's no replay, or the file doesn't hold one
+    Unreadable,
+    /// Recorded by a different version of the game
+    WrongVersion,
+    /// The run stopped matching the recording before this round
+    Desync { turn: i32 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Unreadable => write!(f, "No readable replay in {REPLAY_FILE}"),
+            ReplayError::WrongVersion => {
+                write!(
+                    f,
+                    "The replay was recorded by a different version of the game"
+                )
+            }
+            ReplayError::Desync { turn } => write!(
+                f,
+                "The replay stopped matching the recorded run at turn {turn}"
+            ),
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, class: PlayerClass, settings: GameSettings) -> Replay {
+        Replay {
+            game_version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            class,
+            settings,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Notes an action the player is about to take, along with the round and world hash they
+    /// took it in
+    pub fn record(&mut self, turn: i32, action: Action, world_hash: u64) {
+        self.steps.push(ReplayStep {
+            turn,
+            action,
+            world_hash,
+        });
+    }
+
+    /// Writes the recording, replacing any earlier one. A replay that can't be written is only
+    /// logged, since losing it is no reason to stop the game.
+    pub fn save(&self) {
+        let written = ron::ser::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(REPLAY_FILE, data).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            rltk::console::log(format!("Unable to write {REPLAY_FILE}: {e}"));
+        }
+    }
+}
+
+pub fn replay_exists() -> bool {
+    std::path::Path::new(REPLAY_FILE).exists()
+}
+
+pub fn load_replay() -> Result<Replay, ReplayError> {
+    let replay: Replay = std::fs::read_to_string(REPLAY_FILE)
+        .ok()
+        .and_then(|data| ron::from_str(&data).ok())
+        .ok_or(ReplayError::Unreadable)?;
+    if replay.game_version != env!("CARGO_PKG_VERSION") {
+        return Err(ReplayError::WrongVersion);
+    }
+    Ok(replay)
+}
+
+/// 64-bit FNV-1a. World hashes are written into replays and checked by later builds, so they
+/// need an algorithm that can't change between Rust releases the way `DefaultHasher`'s can.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A replay part way through being played back
+pub struct Playback {
+    replay: Replay,
+    next: usize,
+    /// Where the headless game was left after the last action
+    pub runstate: RunState,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            next: 0,
+            runstate: RunState::PreRun,
+        }
+    }
+
+    /// The next recorded action, or `None` once they've all been played. The game has to be at
+    /// the same round and world hash it was recorded at, or the rest of the recording means
+    /// nothing.
+    pub fn next_action(
+        &mut self,
+        turn: i32,
+        world_hash: u64,
+    ) -> Result<Option<Action>, ReplayError> {
+        let Some(step) = self.replay.steps.get(self.next) else {
+            return Ok(None);
+        };
+        if turn != step.turn || world_hash != step.world_hash {
+            return Err(ReplayError::Desync { turn: step.turn });
+        }
+        self.next += 1;
+        Ok(Some(step.action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FnvHasher;
+    use std::hash::Hasher;
+
+    #[test]
+    fn fnv_matches_published_vectors() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = FnvHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
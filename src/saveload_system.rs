@@ -5,9 +5,52 @@ use specs::saveload::{DeserializeComponents, SimpleMarker, SimpleMarkerAllocator
 use specs::saveload::{MarkedBuilder, SerializeComponents};
 
 use super::components::*;
+use serde::{Deserialize, Serialize};
 use std::convert::Infallible as NoError;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Write;
 use std::path::Path;
 
+#[cfg(not(test))]
+const SAVE_FILE: &str = "./savegame.json";
+/// Tests save into the build directory, so running them never touches a real save
+#[cfg(test)]
+const SAVE_FILE: &str = "./target/savegame-test.json";
+
+/// Bumped whenever a change to the saved components or `SerializationHelper` means older saves
+/// need migrating. Saves from before versioning count as version 0.
+const SAVE_VERSION: u32 = 1;
+
+/// Written after everything else, so a save cut short can be told apart from a complete one
+const SAVE_END: &str = "end of save";
+
+/// Leads every save, ahead of the components
+#[derive(Serialize, Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum LoadError {
+    /// The file is unreadable, cut short or doesn't hold what a save should
+    Corrupt,
+    /// Written by a newer version of the game than this one
+    Incompatible { version: u32 },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Corrupt => write!(f, "Corrupt save"),
+            LoadError::Incompatible { version } => write!(
+                f,
+                "Save is from a newer version of the game (save version {version}, expected {SAVE_VERSION} or older)"
+            ),
+        }
+    }
+}
+
 // Used to avoid compile fail when over 16 component types...
 #[cfg(not(target_arch = "wasm32"))]
 macro_rules! serialize_individually {
@@ -19,92 +62,240 @@ macro_rules! serialize_individually {
             &$data.1,
             &mut $ser,
         )
-        .unwrap();
+        .map_err(|e| e.to_string())?;
         )*
     };
 }
 
+// Components are read back in the order they were written. A save that runs out early is from
+// before the remaining component types were added, so it simply has none of them.
 macro_rules! deserialize_individually {
-    ($ecs:expr, $de:expr, $data:expr, $( $type:ty),*) => {
+    ($ecs:expr, $components:expr, $data:expr, $( $type:ty),*) => {
         $(
-        DeserializeComponents::<NoError, _>::deserialize(
-            &mut ( &mut $ecs.write_storage::<$type>(), ),
-            &    $data.0, // entities
-            &mut $data.1, // marker
-            &mut $data.2, // allocater
-            &mut $de,
-        )
-        .unwrap();
+        if let Some(component) = $components.next() {
+            DeserializeComponents::<NoError, _>::deserialize(
+                &mut ( &mut $ecs.write_storage::<$type>(), ),
+                &    $data.0, // entities
+                &mut $data.1, // marker
+                &mut $data.2, // allocater
+                component,
+            )
+            .map_err(|_| LoadError::Corrupt)?;
+        }
         )*
     };
 }
 
+/// Saves the game, overwriting any earlier save. Returns why if the file couldn't be written.
+/// The save is written alongside the old one and only replaces it once complete, so a crash part
+/// way through loses nothing.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_game(ecs: &mut World) -> Result<(), String> {
+    let partial = format!("{SAVE_FILE}.partial");
+    let writer = std::fs::File::create(&partial).map_err(|e| e.to_string())?;
+    serialize_game(ecs, std::io::BufWriter::new(writer))?;
+    std::fs::rename(&partial, SAVE_FILE).map_err(|e| e.to_string())
+}
+
 #[cfg(not(target_arch = "wasm32"))]
-pub fn save_game(ecs: &mut World) {
+fn serialize_game<W: Write>(ecs: &mut World, writer: W) -> Result<(), String> {
     // Create helper
     let mapcopy = ecs.get_mut::<super::map::Map>().unwrap().clone();
+    let (turns, level_start, time_pressure) = {
+        let turn_counter = ecs.fetch::<super::TurnCounter>();
+        (
+            turn_counter.turns,
+            turn_counter.level_start,
+            turn_counter.time_pressure,
+        )
+    };
+    let dungeon_map = (*ecs.fetch::<super::master_dungeon_map::MasterDungeonMap>()).clone();
+    let bestiary = (*ecs.fetch::<super::bestiary::Bestiary>()).clone();
+    let ambient = (*ecs.fetch::<super::ambient_system::AmbientState>()).clone();
+    let level_stats = *ecs.fetch::<super::level_stats::LevelStats>();
+    let run_seed = *ecs.fetch::<super::scores::RunSeed>();
+    unmark_transient(ecs);
     let savehelper = ecs
         .create_entity()
-        .with(SerializationHelper { map: mapcopy })
+        .with(SerializationHelper {
+            map: mapcopy,
+            turns,
+            level_start,
+            time_pressure,
+            dungeon_map,
+            bestiary,
+            ambient,
+            level_stats,
+            run_seed,
+        })
         .marked::<SimpleMarker<IsSerialized>>()
         .build();
 
-    // Actually serialize
-    {
-        let data = (
-            ecs.entities(),
-            ecs.read_storage::<SimpleMarker<IsSerialized>>(),
-        );
-
-        let writer = std::fs::File::create("./savegame.json").unwrap();
-        let mut serializer = serde_json::Serializer::new(writer);
-        serialize_individually!(
-            ecs,
-            serializer,
-            data,
-            Position,
-            Renderable,
-            Player,
-            Viewshed,
-            Monster,
-            Name,
-            BlocksTile,
-            CombatStats,
-            SufferDamage,
-            WantsToMelee,
-            Item,
-            Consumable,
-            Ranged,
-            InflictsDamage,
-            AreaOfEffect,
-            Confusion,
-            ProvidesHealing,
-            InBackpack,
-            WantsToPickupItem,
-            WantsToUseItem,
-            WantsToDropItem,
-            SerializationHelper,
-            Equippable,
-            ParticleLifetime,
-            HungerClock,
-            ProvidesFood,
-            MagicMapper,
-            Hidden,
-            EntryTrigger,
-            EntityMoved,
-            SingleActivation
-        );
-    }
+    let written = write_save(ecs, writer);
 
     // Clean up
     ecs.delete_entity(savehelper).expect("Crash on cleanup");
+    written
+}
+
+/// Writes the header, every marked entity and the end marker
+#[cfg(not(target_arch = "wasm32"))]
+fn write_save<W: Write>(ecs: &World, writer: W) -> Result<(), String> {
+    let data = (
+        ecs.entities(),
+        ecs.read_storage::<SimpleMarker<IsSerialized>>(),
+    );
+
+    let mut serializer = serde_json::Serializer::new(writer);
+    SaveHeader {
+        version: SAVE_VERSION,
+    }
+    .serialize(&mut serializer)
+    .map_err(|e| e.to_string())?;
+    serialize_individually!(
+        ecs,
+        serializer,
+        data,
+        Position,
+        Renderable,
+        Player,
+        Viewshed,
+        Monster,
+        Name,
+        BlocksTile,
+        CombatStats,
+        WantsToMelee,
+        Item,
+        Consumable,
+        Ranged,
+        InflictsDamage,
+        AreaOfEffect,
+        Confusion,
+        ProvidesHealing,
+        InBackpack,
+        WantsToPickupItem,
+        WantsToUseItem,
+        WantsToDropItem,
+        SerializationHelper,
+        Equippable,
+        // Never saved, since particles are never marked. Kept so older saves still line up.
+        ParticleLifetime,
+        HungerClock,
+        ProvidesFood,
+        MagicMapper,
+        Identifier,
+        Hidden,
+        EntryTrigger,
+        EntityMoved,
+        SingleActivation,
+        Reach,
+        Pet,
+        StartsFire,
+        Weight,
+        Encumbered,
+        Corpse,
+        Scavenger,
+        Tracker,
+        Morale,
+        Fearless,
+        Fleeing,
+        LastHitBy,
+        Equipped,
+        MeleePowerBonus,
+        DefenseBonus,
+        CharacterClass,
+        Attributes,
+        Experience,
+        CanSwim,
+        LightSource,
+        Home,
+        Durability,
+        Vendor,
+        Value,
+        Gold,
+        Coins,
+        Knockback,
+        TwoHanded,
+        Fragile,
+        StatusEffects,
+        Phasing,
+        Resistances,
+        Portal,
+        SightBonus,
+        ExtendedSight,
+        Asleep,
+        Digger,
+        Weakness,
+        Aquatic,
+        Facing,
+        Senses,
+        MassEffect,
+        Rage,
+        Berserker,
+        Stack
+    );
+    SAVE_END
+        .serialize(&mut serializer)
+        .map_err(|e| e.to_string())?;
+    serializer.into_inner().flush().map_err(|e| e.to_string())
+}
+
+/// Particles are only ever part way through an effect, so they're left out of the save even if
+/// one somehow picked up a marker. Loading then starts with none on screen.
+#[cfg(not(target_arch = "wasm32"))]
+fn unmark_transient(ecs: &mut World) {
+    let entities = ecs.entities();
+    let particles = ecs.read_storage::<ParticleLifetime>();
+    let mut markers = ecs.write_storage::<SimpleMarker<IsSerialized>>();
+    for (entity, _particle) in (&entities, &particles).join() {
+        markers.remove(entity);
+    }
 }
 
 pub fn save_exists() -> bool {
-    Path::new("./savegame.json").exists()
+    Path::new(SAVE_FILE).exists()
+}
+
+/// Checks the whole save before anything is loaded, so a bad one leaves the world alone.
+/// Returns the saved components, in the order they were written.
+fn check_save(data: &str) -> Result<Vec<serde_json::Value>, LoadError> {
+    let mut values = serde_json::Deserializer::from_str(data).into_iter::<serde_json::Value>();
+    let first = values
+        .next()
+        .ok_or(LoadError::Corrupt)?
+        .map_err(|_| LoadError::Corrupt)?;
+    // Saves from before versioning start straight in with the components
+    let version = SaveHeader::deserialize(&first).map_or(0, |header| header.version);
+    if version > SAVE_VERSION {
+        return Err(LoadError::Incompatible { version });
+    }
+
+    let mut components = values
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| LoadError::Corrupt)?;
+    if version == 0 {
+        components.insert(0, first);
+    } else if components.pop() != Some(serde_json::Value::from(SAVE_END)) {
+        return Err(LoadError::Corrupt);
+    }
+    Ok(components)
+}
+
+/// Replaces the world with the saved game. Older saves are migrated as far as possible: anything
+/// they don't have takes its default. On failure the world is left empty rather than partly
+/// loaded, and the save file is kept.
+pub fn load_game(ecs: &mut World) -> Result<(), LoadError> {
+    let data = std::fs::read_to_string(SAVE_FILE).map_err(|_| LoadError::Corrupt)?;
+    let components = check_save(&data)?;
+
+    let loaded = load_world(ecs, components);
+    if loaded.is_err() {
+        ecs.delete_all();
+    }
+    loaded
 }
 
-pub fn load_game(ecs: &mut World) {
+fn load_world(ecs: &mut World, components: Vec<serde_json::Value>) -> Result<(), LoadError> {
     {
         // Delete everything
         let mut to_delete = Vec::new();
@@ -114,11 +305,12 @@ pub fn load_game(ecs: &mut World) {
         for del in to_delete.iter() {
             ecs.delete_entity(*del).expect("Deletion failed");
         }
+        // Effects queued in the game being replaced shouldn't play out in the loaded one
+        ecs.fetch_mut::<super::particle_system::ParticleBuilder>()
+            .clear();
     }
 
-    let data = std::fs::read_to_string("./savegame.json").unwrap();
-    let mut de = serde_json::Deserializer::from_str(&data);
-
+    let mut components = components.into_iter();
     {
         let mut d = (
             &mut ecs.entities(),
@@ -128,7 +320,7 @@ pub fn load_game(ecs: &mut World) {
 
         deserialize_individually!(
             ecs,
-            de,
+            components,
             d,
             Position,
             Renderable,
@@ -138,7 +330,6 @@ pub fn load_game(ecs: &mut World) {
             Name,
             BlocksTile,
             CombatStats,
-            SufferDamage,
             WantsToMelee,
             Item,
             Consumable,
@@ -153,14 +344,61 @@ pub fn load_game(ecs: &mut World) {
             WantsToDropItem,
             SerializationHelper,
             Equippable,
+            // Never saved, since particles are never marked. Kept so older saves still line up.
             ParticleLifetime,
             HungerClock,
             ProvidesFood,
             MagicMapper,
+            Identifier,
             Hidden,
             EntryTrigger,
             EntityMoved,
-            SingleActivation
+            SingleActivation,
+            Reach,
+            Pet,
+            StartsFire,
+            Weight,
+            Encumbered,
+            Corpse,
+            Scavenger,
+            Tracker,
+            Morale,
+            Fearless,
+            Fleeing,
+            LastHitBy,
+            Equipped,
+            MeleePowerBonus,
+            DefenseBonus,
+            CharacterClass,
+            Attributes,
+            Experience,
+            CanSwim,
+            LightSource,
+            Home,
+            Durability,
+            Vendor,
+            Value,
+            Gold,
+            Coins,
+            Knockback,
+            TwoHanded,
+            Fragile,
+            StatusEffects,
+            Phasing,
+            Resistances,
+            Portal,
+            SightBonus,
+            ExtendedSight,
+            Asleep,
+            Digger,
+            Weakness,
+            Aquatic,
+            Facing,
+            Senses,
+            MassEffect,
+            Rage,
+            Berserker,
+            Stack
         );
     }
 
@@ -174,6 +412,18 @@ pub fn load_game(ecs: &mut World) {
             let mut worldmap = ecs.write_resource::<super::map::Map>();
             *worldmap = h.map.clone();
             worldmap.tile_content = vec![Vec::new(); super::map::MAPCOUNT];
+            *ecs.write_resource::<super::TurnCounter>() = super::TurnCounter {
+                turns: h.turns,
+                level_start: h.level_start,
+                time_pressure: h.time_pressure,
+            };
+            // Replaced wholesale, so nothing identified in another game carries over
+            *ecs.write_resource::<super::master_dungeon_map::MasterDungeonMap>() =
+                h.dungeon_map.clone();
+            *ecs.write_resource::<super::bestiary::Bestiary>() = h.bestiary.clone();
+            *ecs.write_resource::<super::ambient_system::AmbientState>() = h.ambient.clone();
+            *ecs.write_resource::<super::level_stats::LevelStats>() = h.level_stats;
+            *ecs.write_resource::<super::scores::RunSeed>() = h.run_seed;
             deleteme = Some(e);
         }
         for (e, _p, pos) in (&entities, &player, &position).join() {
@@ -183,16 +433,108 @@ pub fn load_game(ecs: &mut World) {
             *player_resource = e;
         }
     }
-    ecs.delete_entity(deleteme.unwrap())
-        .expect("Unable to delete helper");
+    let Some(helper) = deleteme else {
+        return Err(LoadError::Corrupt);
+    };
+    ecs.delete_entity(helper).expect("Unable to delete helper");
+    if ecs.read_storage::<Player>().is_empty() {
+        return Err(LoadError::Corrupt);
+    }
+    Ok(())
 }
 
 pub fn delete_save() {
-    if Path::new("./savegame.json").exists() {
-        std::fs::remove_file("./savegame.json").expect("Unable to delete file");
+    if Path::new(SAVE_FILE).exists() {
+        std::fs::remove_file(SAVE_FILE).expect("Unable to delete file");
     }
 }
 
 // TODO: Impl via web-sys crate and localStorage on browser
 #[cfg(target_arch = "wasm32")]
-pub fn save_game(_ecs: &mut World) {}
+pub fn save_game(_ecs: &mut World) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::RandomNumberGenerator;
+
+    /// Component types added to the save since Knockback, which version 1 saves can lack
+    const NEWER_TYPES: usize = 19;
+
+    fn saved_game() -> (World, Vec<serde_json::Value>) {
+        let (mut ecs, _) = crate::new_world(RandomNumberGenerator::seeded(1));
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Position>()
+            .insert(player, Position { x: 5, y: 6 })
+            .unwrap();
+        ecs.write_storage::<Rage>()
+            .insert(player, Rage { turns: 3 })
+            .unwrap();
+
+        let mut saved = Vec::new();
+        serialize_game(&mut ecs, &mut saved).unwrap();
+        let values = serde_json::Deserializer::from_slice(&saved)
+            .into_iter::<serde_json::Value>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        (ecs, values)
+    }
+
+    fn to_save(values: &[serde_json::Value]) -> String {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn loaded_player(ecs: &World) -> (i32, i32, bool) {
+        let player = *ecs.fetch::<Entity>();
+        let pos = ecs.read_storage::<Position>().get(player).cloned().unwrap();
+        let raging = ecs.read_storage::<Rage>().contains(player);
+        (pos.x, pos.y, raging)
+    }
+
+    #[test]
+    fn loads_current_save() {
+        let (mut ecs, values) = saved_game();
+        let components = check_save(&to_save(&values)).unwrap();
+        load_world(&mut ecs, components).unwrap();
+        assert_eq!(loaded_player(&ecs), (5, 6, true));
+    }
+
+    /// Drops every `damage_type` field, as saves from before damage types lack them
+    fn strip_damage_types(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                fields.remove("damage_type");
+                fields.values_mut().for_each(strip_damage_types);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip_damage_types),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn loads_save_missing_newer_components() {
+        let (mut ecs, mut values) = saved_game();
+        // Header, every component type, then the end marker
+        let end = values.len() - 1;
+        values.drain(end - NEWER_TYPES..end);
+        values.iter_mut().for_each(strip_damage_types);
+
+        let components = check_save(&to_save(&values)).unwrap();
+        load_world(&mut ecs, components).unwrap();
+        assert_eq!(loaded_player(&ecs), (5, 6, false));
+        let bonuses = ecs.read_storage::<MeleePowerBonus>();
+        assert!(!bonuses.is_empty());
+        assert!((&bonuses)
+            .join()
+            .all(|bonus| bonus.damage_type == DamageType::Physical));
+    }
+
+    #[test]
+    fn rejects_save_without_end_marker() {
+        let (_, mut values) = saved_game();
+        values.pop();
+        assert!(check_save(&to_save(&values)) == Err(LoadError::Corrupt));
+    }
+}
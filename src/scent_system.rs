@@ -0,0 +1,32 @@
+use super::{Map, RunState};
+use rltk::Point;
+use specs::prelude::*;
+
+/// Turns until a trail has faded away completely
+const SCENT_DURATION: f32 = 20.0;
+
+pub struct ScentSystem;
+
+impl<'a> System<'a> for ScentSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadExpect<'a, Point>,
+        ReadExpect<'a, RunState>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, player_pos, runstate) = data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+
+        for scent in map.scent.iter_mut() {
+            *scent = f32::max(0.0, *scent - 1.0 / SCENT_DURATION);
+        }
+
+        // The player only ever stands on walkable tiles, so that's the only place scent is laid
+        let idx = map.xy_idx(player_pos.x, player_pos.y);
+        map.scent[idx] = 1.0;
+    }
+}
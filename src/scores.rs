@@ -0,0 +1,82 @@
+use super::{Experience, LastHitBy, Map, TurnCounter};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+#[cfg(not(test))]
+const SCORES_FILE: &str = "./scores.ron";
+/// Tests that end a run record it in the build directory, away from the real scores
+#[cfg(test)]
+const SCORES_FILE: &str = "./target/scores-test.ron";
+
+/// The seed the current run's world was built from, kept so its score can say how to play it
+/// again
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct RunSeed {
+    pub seed: u64,
+}
+
+/// One finished run, as recorded in the morgue file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScoreEntry {
+    pub depth: i32,
+    pub turns: i32,
+    /// Missing from entries recorded before levels existed
+    #[serde(default)]
+    pub level: i32,
+    pub killed_by: String,
+    /// Seconds since the Unix epoch, or 0 where there's no clock to read, as in the browser
+    pub timestamp: u64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+/// Reads the morgue file. A missing or unreadable file counts as no scores.
+pub fn load_scores() -> Vec<ScoreEntry> {
+    std::fs::read_to_string(SCORES_FILE)
+        .ok()
+        .and_then(|data| ron::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Best runs first: deepest, then quickest
+pub fn sorted_scores() -> Vec<ScoreEntry> {
+    let mut scores = load_scores();
+    scores.sort_by(|a, b| b.depth.cmp(&a.depth).then(a.turns.cmp(&b.turns)));
+    scores
+}
+
+/// Appends the player's run to the morgue file
+pub fn record_death(ecs: &World) {
+    let player_entity = ecs.fetch::<Entity>();
+    let killed_by = ecs
+        .read_storage::<LastHitBy>()
+        .get(*player_entity)
+        .map_or("misadventure".to_string(), |hit| hit.name.clone());
+    let level = ecs
+        .read_storage::<Experience>()
+        .get(*player_entity)
+        .map_or(1, |experience| experience.level);
+    #[cfg(not(target_arch = "wasm32"))]
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    #[cfg(target_arch = "wasm32")]
+    let timestamp = 0;
+
+    let mut scores = load_scores();
+    scores.push(ScoreEntry {
+        depth: ecs.fetch::<Map>().depth,
+        turns: ecs.fetch::<TurnCounter>().turns,
+        level,
+        killed_by,
+        timestamp,
+        seed: ecs.fetch::<RunSeed>().seed,
+    });
+
+    let written = ron::ser::to_string(&scores)
+        .map_err(|e| e.to_string())
+        .and_then(|data| std::fs::write(SCORES_FILE, data).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        rltk::console::log(format!("Unable to write {SCORES_FILE}: {e}"));
+    }
+}
@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "./settings.ron";
+
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Added to each of the player's attributes when a run starts
+    pub fn attribute_bonus(self) -> i32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 0,
+            Difficulty::Hard => -1,
+        }
+    }
+
+    fn next(self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+}
+
+/// When the game saves itself without being asked
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum Autosave {
+    Off,
+    /// Whenever the player leaves a level
+    LevelChange,
+    /// On leaving a level, and every `AUTOSAVE_TURNS` rounds besides
+    Frequent,
+}
+
+/// Rounds between saves with `Autosave::Frequent`
+pub const AUTOSAVE_TURNS: i32 = 100;
+
+impl Autosave {
+    pub fn on_level_change(self) -> bool {
+        self != Autosave::Off
+    }
+
+    /// Whether the round just finished is due a save
+    pub fn on_turn(self, turns: i32) -> bool {
+        self == Autosave::Frequent && turns % AUTOSAVE_TURNS == 0
+    }
+
+    fn next(self) -> Autosave {
+        match self {
+            Autosave::Off => Autosave::LevelChange,
+            Autosave::LevelChange => Autosave::Frequent,
+            Autosave::Frequent => Autosave::Off,
+        }
+    }
+}
+
+/// How line of sight is worked out
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+pub enum FovAlgorithm {
+    /// Recursive shadowcasting. Quick, but a tile can be in view without it seeing back.
+    Standard,
+    /// Symmetric shadowcasting. Whenever one tile can see another, the other can see it too.
+    Symmetric,
+}
+
+impl FovAlgorithm {
+    fn next(self) -> FovAlgorithm {
+        match self {
+            FovAlgorithm::Standard => FovAlgorithm::Symmetric,
+            FovAlgorithm::Symmetric => FovAlgorithm::Standard,
+        }
+    }
+}
+
+/// Player preferences, kept in `settings.ron` between runs. Fields missing from the file take
+/// their default, so older files still load.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GameSettings {
+    /// Walking onto an item picks it up
+    pub auto_pickup: bool,
+    pub monster_hp_bars: bool,
+    /// Shows expected melee damage in monster tooltips
+    pub melee_prediction: bool,
+    /// Skips purely cosmetic animations, such as projectiles in flight
+    pub fast_animations: bool,
+    /// Only read when a new run starts
+    pub difficulty: Difficulty,
+    /// Lingering on a level brings more monsters. Only read when a new run starts.
+    pub time_pressure: bool,
+    /// Occasional level-wide events such as tremors and cold snaps
+    pub ambient_events: bool,
+    /// Replaces the separate readouts under the map with one abbreviated line
+    pub status_line: bool,
+    /// Leaves a fading marker where a monster was last seen
+    pub monster_ghosts: bool,
+    /// Stepping into a room lights all of it, rather than only what's in line of sight
+    pub room_lighting: bool,
+    pub autosave: Autosave,
+    pub fov_algorithm: FovAlgorithm,
+    /// Sums up each level on the way down the stairs
+    pub level_summary: bool,
+}
+
+impl Default for GameSettings {
+    fn default() -> GameSettings {
+        GameSettings {
+            auto_pickup: false,
+            monster_hp_bars: true,
+            melee_prediction: true,
+            fast_animations: false,
+            difficulty: Difficulty::Normal,
+            time_pressure: false,
+            ambient_events: true,
+            status_line: false,
+            monster_ghosts: true,
+            room_lighting: false,
+            autosave: Autosave::LevelChange,
+            fov_algorithm: FovAlgorithm::Standard,
+            level_summary: true,
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum SettingsOption {
+    AutoPickup,
+    MonsterHpBars,
+    MeleePrediction,
+    FastAnimations,
+    Difficulty,
+    TimePressure,
+    AmbientEvents,
+    StatusLine,
+    MonsterGhosts,
+    RoomLighting,
+    Autosave,
+    FovAlgorithm,
+    LevelSummary,
+}
+
+impl SettingsOption {
+    /// Options that set the rules of a run, so can't be changed once it has started
+    pub fn new_game_only(self) -> bool {
+        matches!(
+            self,
+            SettingsOption::Difficulty | SettingsOption::TimePressure
+        )
+    }
+
+    /// Options that change what can be seen, so every viewshed needs working out again
+    pub fn changes_sight(self) -> bool {
+        matches!(
+            self,
+            SettingsOption::RoomLighting | SettingsOption::FovAlgorithm
+        )
+    }
+}
+
+/// Every option, in the order the options menu lists them
+pub const OPTIONS: [SettingsOption; 13] = [
+    SettingsOption::AutoPickup,
+    SettingsOption::MonsterHpBars,
+    SettingsOption::MeleePrediction,
+    SettingsOption::FastAnimations,
+    SettingsOption::Difficulty,
+    SettingsOption::TimePressure,
+    SettingsOption::AmbientEvents,
+    SettingsOption::StatusLine,
+    SettingsOption::MonsterGhosts,
+    SettingsOption::RoomLighting,
+    SettingsOption::Autosave,
+    SettingsOption::FovAlgorithm,
+    SettingsOption::LevelSummary,
+];
+
+impl GameSettings {
+    /// The option's name and current value, as shown in the options menu
+    pub fn label(&self, option: SettingsOption) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match option {
+            SettingsOption::AutoPickup => format!("Auto-pickup: {}", on_off(self.auto_pickup)),
+            SettingsOption::MonsterHpBars => {
+                format!("Monster HP bars: {}", on_off(self.monster_hp_bars))
+            }
+            SettingsOption::MeleePrediction => {
+                format!("Melee prediction: {}", on_off(self.melee_prediction))
+            }
+            SettingsOption::FastAnimations => {
+                format!("Fast animations: {}", on_off(self.fast_animations))
+            }
+            SettingsOption::Difficulty => format!("Difficulty: {:?}", self.difficulty),
+            SettingsOption::TimePressure => {
+                format!("Time pressure: {}", on_off(self.time_pressure))
+            }
+            SettingsOption::AmbientEvents => {
+                format!("Ambient events: {}", on_off(self.ambient_events))
+            }
+            SettingsOption::StatusLine => {
+                format!("Compact status line: {}", on_off(self.status_line))
+            }
+            SettingsOption::MonsterGhosts => {
+                format!("Monster ghosts: {}", on_off(self.monster_ghosts))
+            }
+            SettingsOption::RoomLighting => {
+                format!("Room lighting: {}", on_off(self.room_lighting))
+            }
+            SettingsOption::Autosave => match self.autosave {
+                Autosave::Off => "Autosave: Off".to_string(),
+                Autosave::LevelChange => "Autosave: Between levels".to_string(),
+                Autosave::Frequent => format!("Autosave: Every {AUTOSAVE_TURNS} turns"),
+            },
+            SettingsOption::FovAlgorithm => {
+                format!("Field of view: {:?}", self.fov_algorithm)
+            }
+            SettingsOption::LevelSummary => {
+                format!("Level summary: {}", on_off(self.level_summary))
+            }
+        }
+    }
+
+    /// Flips a toggle, or moves a multiple-choice option on to the next choice
+    pub fn change(&mut self, option: SettingsOption) {
+        match option {
+            SettingsOption::AutoPickup => self.auto_pickup = !self.auto_pickup,
+            SettingsOption::MonsterHpBars => self.monster_hp_bars = !self.monster_hp_bars,
+            SettingsOption::MeleePrediction => self.melee_prediction = !self.melee_prediction,
+            SettingsOption::FastAnimations => self.fast_animations = !self.fast_animations,
+            SettingsOption::Difficulty => self.difficulty = self.difficulty.next(),
+            SettingsOption::TimePressure => self.time_pressure = !self.time_pressure,
+            SettingsOption::AmbientEvents => self.ambient_events = !self.ambient_events,
+            SettingsOption::StatusLine => self.status_line = !self.status_line,
+            SettingsOption::MonsterGhosts => self.monster_ghosts = !self.monster_ghosts,
+            SettingsOption::RoomLighting => self.room_lighting = !self.room_lighting,
+            SettingsOption::Autosave => self.autosave = self.autosave.next(),
+            SettingsOption::FovAlgorithm => self.fov_algorithm = self.fov_algorithm.next(),
+            SettingsOption::LevelSummary => self.level_summary = !self.level_summary,
+        }
+    }
+}
+
+/// Reads the settings file. A missing or malformed file gives the defaults.
+pub fn load_settings() -> GameSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|data| ron::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &GameSettings) {
+    let written = ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|data| std::fs::write(SETTINGS_FILE, data).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        rltk::console::log(format!("Unable to write {SETTINGS_FILE}: {e}"));
+    }
+}
@@ -0,0 +1,48 @@
+use super::{Equipped, ExtendedSight, SightBonus, Viewshed};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// The most equipment can add to anyone's sight, however much of it they stack
+const MAX_SIGHT_BONUS: i32 = 4;
+
+/// Adds up the sight bonuses of everything each viewer has equipped, up to MAX_SIGHT_BONUS, and
+/// moves their viewshed range by however much that total changed. The viewshed is marked dirty on
+/// any change, so vision grows or shrinks with the next recompute.
+pub struct SightSystem;
+
+impl<'a> System<'a> for SightSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, ExtendedSight>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, SightBonus>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut viewsheds, mut extended, equipped, bonuses) = data;
+
+        let mut totals: HashMap<Entity, i32> = HashMap::new();
+        for (equipped, bonus) in (&equipped, &bonuses).join() {
+            *totals.entry(equipped.owner).or_insert(0) += bonus.range;
+        }
+
+        for (entity, viewshed) in (&entities, &mut viewsheds).join() {
+            let bonus = i32::min(MAX_SIGHT_BONUS, totals.get(&entity).copied().unwrap_or(0));
+            let applied = extended.get(entity).map_or(0, |sight| sight.bonus);
+            if bonus == applied {
+                continue;
+            }
+
+            viewshed.range += bonus - applied;
+            viewshed.dirty = true;
+            if bonus == 0 {
+                extended.remove(entity);
+            } else {
+                extended
+                    .insert(entity, ExtendedSight { bonus })
+                    .expect("Unable to insert extended sight");
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::{
+    gamelog::Gamelog,
+    grammar::{capitalize, with_article, with_the},
+    spawner, CombatStats, Map, Monster, Name, Portal, Position,
+};
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+
+/// Counts each portal down and brings a monster through any that are due, onto a random free
+/// floor tile beside it. A portal with nowhere to put one, or on a level already holding as many
+/// monsters as its depth allows, waits and tries again next round. Portals themselves don't count
+/// towards the cap. A portal knocked down to nothing is broken at once, even before it's cleared
+/// away. Runs once a round.
+pub fn run_portals(ecs: &mut World) {
+    let due: Vec<(Entity, Position, String)> = {
+        let entities = ecs.entities();
+        let mut portals = ecs.write_storage::<Portal>();
+        let positions = ecs.read_storage::<Position>();
+        let combat_stats = ecs.read_storage::<CombatStats>();
+        (&entities, &mut portals, &positions, &combat_stats)
+            .join()
+            .filter(|(_entity, _portal, _pos, stats)| stats.hp > 0)
+            .filter_map(|(entity, portal, pos, _stats)| {
+                portal.timer = i32::max(0, portal.timer - 1);
+                (portal.timer == 0).then(|| (entity, pos.clone(), portal.spawns.clone()))
+            })
+            .collect()
+    };
+
+    for (portal, pos, spawns) in due {
+        if at_monster_cap(ecs) {
+            return;
+        }
+        let Some(tile) = free_tile_beside(ecs, &pos) else {
+            continue;
+        };
+        let Some(monster) = spawner::spawn_named(ecs, &spawns, tile.clone()) else {
+            continue;
+        };
+
+        {
+            let mut map = ecs.write_resource::<Map>();
+            let idx = map.xy_idx(tile.x, tile.y);
+            // Keeps a second portal from using the same tile this round
+            map.blocked[idx] = true;
+        }
+        if let Some(portal) = ecs.write_storage::<Portal>().get_mut(portal) {
+            portal.timer = portal.cooldown;
+        }
+
+        let seen = {
+            let map = ecs.fetch::<Map>();
+            map.visible_tiles[map.xy_idx(pos.x, pos.y)]
+        };
+        if seen {
+            let names = ecs.read_storage::<Name>();
+            if let (Some(portal_name), Some(monster_name)) = (names.get(portal), names.get(monster))
+            {
+                ecs.write_resource::<Gamelog>().entries.push(format!(
+                    "{} steps out of {}.",
+                    capitalize(&with_article(monster_name)),
+                    with_the(portal_name)
+                ));
+            }
+        }
+    }
+}
+
+fn at_monster_cap(ecs: &World) -> bool {
+    let depth = ecs.fetch::<Map>().depth;
+    let monsters = ecs.read_storage::<Monster>();
+    let portals = ecs.read_storage::<Portal>();
+    (&monsters, !&portals).join().count() >= spawner::max_monsters(depth)
+}
+
+fn free_tile_beside(ecs: &World, pos: &Position) -> Option<Position> {
+    let map = ecs.fetch::<Map>();
+    let free: Vec<Position> = (-1..=1)
+        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+        .filter(|&(dx, dy)| dx != 0 || dy != 0)
+        .map(|(dx, dy)| Position {
+            x: pos.x + dx,
+            y: pos.y + dy,
+        })
+        .filter(|tile| tile.x >= 0 && tile.x < map.width && tile.y >= 0 && tile.y < map.height)
+        .filter(|tile| {
+            let idx = map.xy_idx(tile.x, tile.y);
+            map.tiles[idx].is_floor() && !map.blocked[idx]
+        })
+        .collect();
+    if free.is_empty() {
+        return None;
+    }
+    let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+    Some(free[(rng.roll_dice(1, free.len() as i32) - 1) as usize].clone())
+}
@@ -1,26 +1,86 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
 
 use super::{
-    AreaOfEffect, BlocksTile, CombatStats, Confusion, Consumable, DefenseBonus, EntryTrigger,
-    EquipmentSlot, Equippable, Hidden, HungerClock, HungerState, InflictsDamage, IsSerialized,
-    Item, MagicMapper, Map, MeleePowerBonus, Monster, Name, Player, Position, ProvidesFood,
-    ProvidesHealing, Ranged, Rect, Renderable, SingleActivation, TileType, Viewshed, MAPWIDTH,
+    classes::{self, PlayerClass},
+    dice::DiceExpr,
+    glyphs::{self, Glyph, GlyphSet},
+    inventory_system,
+    inventory_system::equip_item,
+    AoeShape, Aquatic, AreaOfEffect, Asleep, Attributes, Berserker, BlocksTile, CanSwim,
+    CharacterClass, Coins, CombatStats, Confusion, Consumable, Corpse, DamageType, DefenseBonus,
+    Digger, Durability, EntryTrigger, EquipmentSlot, Equippable, Equipped, Experience, Facing,
+    Fearless, Fragile, Gold, Hidden, Home, HungerClock, HungerState, Identifier, InBackpack,
+    InflictsDamage, IsSerialized, Item, Knockback, LightSource, MagicMapper, Map, MassEffect,
+    MeleePowerBonus, Monster, Morale, Name, Pet, PetOrders, Phasing, Player, Portal, Position,
+    ProvidesFood, ProvidesHealing, Rage, Ranged, Reach, Rect, Renderable, Resistances, Scavenger,
+    Senses, SightBonus, SingleActivation, StartsFire, TileType, Tracker, TwoHanded, Value, Vendor,
+    Viewshed, Weakness, Weight, MAPWIDTH,
 };
+use crate::map_themes::MapThemeKind;
+use crate::morale_system::STARTING_MORALE;
 use crate::random_table::RandomTable;
-use rltk::{RandomNumberGenerator, RGB};
+use rltk::{Point, RandomNumberGenerator, RGB};
 use specs::{
     prelude::*,
     saveload::{MarkedBuilder, SimpleMarker},
 };
 
-const MAX_MONSTERS: i32 = 4;
+const MAX_ITEMS: i32 = 4;
+/// One in this many monsters starts the level asleep
+const SLEEP_CHANCE: i32 = 4;
+const MAX_SPAWN_ATTEMPTS: i32 = 50;
+/// Rolled monsters allowed on a level before any trimming: base plus a little more per depth
+const MONSTER_CAP_BASE: i32 = 4;
+const MONSTER_CAP_PER_DEPTH: i32 = 2;
+const STARTING_GOLD: i32 = 50;
+/// Rounds between each monster a portal brings through
+const PORTAL_COOLDOWN: i32 = 12;
+/// Shop prices in gold. Anything missing can't be bought or sold.
+const ITEM_VALUES: [(&str, i32); 27] = [
+    ("Health Potion", 15),
+    ("Poison Potion", 10),
+    ("Confusion Potion", 10),
+    ("Weakness Potion", 10),
+    ("Rage Potion", 20),
+    ("Magic Missile Scroll", 20),
+    ("Fireball Scroll", 30),
+    ("Lightning Scroll", 25),
+    ("Dragon Breath Scroll", 30),
+    ("Thunderclap Scroll", 25),
+    ("Frost Scroll", 25),
+    ("Confusion Scroll", 20),
+    ("Mass Confusion Scroll", 35),
+    ("Magic Mapping Scroll", 25),
+    ("Identify Scroll", 15),
+    ("Flask of Oil", 10),
+    ("Torch", 5),
+    ("Lantern", 25),
+    ("Ring of Clairvoyance", 60),
+    ("Rations", 5),
+    ("Dagger", 10),
+    ("Venom Dagger", 20),
+    ("Shield", 15),
+    ("Longsword", 30),
+    ("Spear", 20),
+    ("Tower Shield", 35),
+    ("Pickaxe", 40),
+];
+/// Monster stats vary by up to this percentage either way
+const STAT_JITTER_PERCENT: i32 = 12;
+/// One in this many rolled monsters is Fierce, and as many again are Weak
+const MODIFIER_CHANCE: i32 = 10;
+/// Fierce monsters are meant to stand out, so a level gets only a couple
+const MAX_FIERCE_PER_LEVEL: i32 = 2;
 
-/// Spawns the player and returns its entity object
-pub fn player(ecs: &mut World, player_pos: Position) -> Entity {
-    ecs.create_entity()
-        .with(player_pos)
+/// Spawns the player with their class's stats and starting kit, and returns its entity object
+pub fn player(ecs: &mut World, player_pos: Position, class: PlayerClass) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::PLAYER);
+    let definition = classes::class_definition(class);
+    let player = ecs
+        .create_entity()
+        .with(player_pos.clone())
         .with(Renderable {
-            glyph: rltk::to_cp437('@'),
+            glyph,
             fg: RGB::named(rltk::YELLOW),
             bg: RGB::named(rltk::BLACK),
             render_order: 0,
@@ -33,30 +93,274 @@ pub fn player(ecs: &mut World, player_pos: Position) -> Entity {
         })
         .with(Name {
             name: "Player".to_string(),
+            proper: false,
         })
         .with(CombatStats {
-            max_hp: 30,
-            hp: 30,
-            defense: 2,
-            power: 5,
+            max_hp: definition.stats.max_hp,
+            hp: definition.stats.max_hp,
+            defense: definition.stats.defense,
+            power: definition.stats.power,
         })
         .with(HungerClock {
             state: HungerState::WellFed,
             duration: 20,
         })
+        .with(CharacterClass { class })
+        .with(Attributes::default())
+        .with(Experience {
+            level: 1,
+            xp: 0,
+            unspent_points: 0,
+        })
+        .with(Gold {
+            amount: STARTING_GOLD,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build();
+
+    for name in definition.backpack.iter() {
+        give_to(ecs, player, name, player_pos.clone());
+    }
+    for name in definition.equipped.iter() {
+        let item = give_to(ecs, player, name, player_pos.clone());
+        let slot = ecs
+            .read_storage::<Equippable>()
+            .get(item)
+            .map(|equippable| equippable.slot);
+        if let Some(slot) = slot {
+            equip_item(
+                &ecs.entities(),
+                &mut ecs.write_storage::<Equipped>(),
+                &mut ecs.write_storage::<InBackpack>(),
+                &ecs.read_storage::<TwoHanded>(),
+                item,
+                player,
+                slot,
+            );
+        }
+    }
+
+    player
+}
+
+/// Parses a dice expression written into a spawn function
+fn dice(expr: &str) -> DiceExpr {
+    expr.parse().expect("Invalid dice expression")
+}
+
+/// Spawns a named item straight into `owner`'s backpack
+fn give_to(ecs: &mut World, owner: Entity, name: &str, pos: Position) -> Entity {
+    let item = spawn_named(ecs, name, pos).expect("Unknown item in classes.ron");
+    inventory_system::stow(ecs, item, owner)
+}
+
+/// Sells supplies on the surface, and buys back anything with a price
+pub fn shopkeeper(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SHOPKEEPER);
+    let sells = [
+        "Health Potion",
+        "Rations",
+        "Torch",
+        "Flask of Oil",
+        "Identify Scroll",
+        "Dagger",
+        "Shield",
+    ];
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Name {
+            name: "Shopkeeper".to_string(),
+            proper: false,
+        })
+        .with(BlocksTile {})
+        .with(Vendor {
+            buys: ITEM_VALUES
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            sells: sells.iter().map(|name| name.to_string()).collect(),
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// Spawns the player's starting companion. Pets fight on the player's side and swap places with them.
+/// Dogs can swim, so the dog follows the player across deep water.
+pub fn dog(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::DOG);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::WHEAT),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Pet {
+            orders: PetOrders::Follow,
+        })
+        .with(CanSwim {})
+        .with(Viewshed {
+            visible_tiles: Vec::new(),
+            range: 8,
+            dirty: true,
+        })
+        .with(Name {
+            name: "Dog".to_string(),
+            proper: false,
+        })
+        .with(BlocksTile {})
+        .with(CombatStats {
+            max_hp: 12,
+            hp: 12,
+            defense: 1,
+            power: 3,
+        })
         .marked::<SimpleMarker<IsSerialized>>()
         .build()
 }
 
-fn orc(ecs: &mut World, pos: Position) {
-    monster(ecs, pos, rltk::to_cp437('o'), "Orc");
+fn orc(ecs: &mut World, pos: Position) -> Entity {
+    let orc = monster(ecs, pos, glyphs::ORC, "Orc");
+    ecs.write_storage::<Tracker>()
+        .insert(orc, Tracker {})
+        .expect("Unable to insert tracker");
+    ecs.write_storage::<Fearless>()
+        .insert(orc, Fearless {})
+        .expect("Unable to insert fearless");
+    ecs.write_storage::<Berserker>()
+        .insert(orc, Berserker { turns: 8 })
+        .expect("Unable to insert berserker");
+    orc
+}
+fn goblin(ecs: &mut World, pos: Position) -> Entity {
+    let goblin = monster(ecs, pos, glyphs::GOBLIN, "Goblin");
+    ecs.write_storage::<Scavenger>()
+        .insert(goblin, Scavenger {})
+        .expect("Unable to insert scavenger");
+    ecs.write_storage::<Berserker>()
+        .insert(goblin, Berserker { turns: 5 })
+        .expect("Unable to insert berserker");
+    goblin
+}
+
+fn eel(ecs: &mut World, pos: Position) -> Entity {
+    let eel = monster(ecs, pos, glyphs::EEL, "Eel");
+    if let Some(render) = ecs.write_storage::<Renderable>().get_mut(eel) {
+        render.fg = RGB::named(rltk::SEA_GREEN);
+    }
+    ecs.write_storage::<CanSwim>()
+        .insert(eel, CanSwim {})
+        .expect("Unable to insert swimmer");
+    ecs.write_storage::<Aquatic>()
+        .insert(eel, Aquatic {})
+        .expect("Unable to insert aquatic");
+    // Blind in the murk, but quick to feel anything moving nearby
+    ecs.write_storage::<Senses>()
+        .insert(
+            eel,
+            Senses {
+                sight: 0,
+                hearing: 4,
+            },
+        )
+        .expect("Unable to insert senses");
+    eel
 }
-fn goblin(ecs: &mut World, pos: Position) {
-    monster(ecs, pos, rltk::to_cp437('g'), "Goblin");
+
+fn ghost(ecs: &mut World, pos: Position) -> Entity {
+    let ghost = monster(ecs, pos, glyphs::GHOST, "Ghost");
+    if let Some(render) = ecs.write_storage::<Renderable>().get_mut(ghost) {
+        render.fg = RGB::named(rltk::LIGHT_BLUE);
+    }
+    ecs.write_storage::<Phasing>()
+        .insert(ghost, Phasing {})
+        .expect("Unable to insert phasing");
+    // Ghosts sense all around them, so can't be caught from behind
+    ecs.write_storage::<Facing>().remove(ghost);
+    ecs.write_storage::<Resistances>()
+        .insert(
+            ghost,
+            Resistances {
+                resist: vec![DamageType::Cold, DamageType::Poison],
+                vulnerable: vec![DamageType::Fire],
+            },
+        )
+        .expect("Unable to insert resistances");
+    ghost
 }
 
-fn monster<S: ToString>(ecs: &mut World, pos: Position, glyph: rltk::FontCharType, name: S) {
+/// A rift that brings a monster of the kind named in `spawns` through every few rounds. It never
+/// moves or fights back, but counts as hostile so it can be attacked and broken.
+fn portal(ecs: &mut World, pos: Position, name: &str, spawns: &str) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::PORTAL);
     ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::MAGENTA),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Monster {})
+        .with(Name {
+            name: name.to_string(),
+            proper: false,
+        })
+        .with(BlocksTile)
+        .with(CombatStats {
+            max_hp: 20,
+            hp: 20,
+            defense: 2,
+            power: 0,
+        })
+        .with(Portal {
+            spawns: spawns.to_string(),
+            cooldown: PORTAL_COOLDOWN,
+            timer: PORTAL_COOLDOWN,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// The eight ways a monster can face
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, -1),
+    (0, 1),
+    (-1, 0),
+    (1, 0),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+fn monster<S: ToString>(ecs: &mut World, pos: Position, glyph: Glyph, name: S) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyph);
+    let spawn_pos = Point::new(pos.x, pos.y);
+    let (stats, asleep, facing) = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let max_hp = i32::max(1, jitter(&mut rng, 16));
+        let stats = CombatStats {
+            max_hp,
+            hp: max_hp,
+            defense: i32::max(0, jitter(&mut rng, 1)),
+            power: i32::max(0, jitter(&mut rng, 4)),
+        };
+        let (dx, dy) = DIRECTIONS[(rng.roll_dice(1, DIRECTIONS.len() as i32) - 1) as usize];
+        let facing = Facing {
+            direction: Point::new(dx, dy),
+        };
+        (stats, rng.roll_dice(1, SLEEP_CHANCE) == 1, facing)
+    };
+    let monster = ecs
+        .create_entity()
         .with(pos)
         .with(Renderable {
             glyph,
@@ -65,6 +369,13 @@ fn monster<S: ToString>(ecs: &mut World, pos: Position, glyph: rltk::FontCharTyp
             render_order: 1,
         })
         .with(Monster {})
+        .with(Morale {
+            value: STARTING_MORALE,
+        })
+        .with(Home {
+            spawn_pos,
+            turns_lost: 0,
+        })
         .with(Viewshed {
             visible_tiles: Vec::new(),
             range: 8,
@@ -72,20 +383,99 @@ fn monster<S: ToString>(ecs: &mut World, pos: Position, glyph: rltk::FontCharTyp
         })
         .with(Name {
             name: name.to_string(),
+            proper: false,
         })
         .with(BlocksTile)
-        .with(CombatStats {
-            max_hp: 16,
-            hp: 16,
-            defense: 1,
-            power: 4,
+        .with(stats)
+        .with(facing)
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build();
+    if asleep {
+        ecs.write_storage::<Asleep>()
+            .insert(monster, Asleep {})
+            .expect("Unable to insert asleep");
+    }
+    monster
+}
+
+/// `value` nudged up or down by as much as STAT_JITTER_PERCENT, rounded to the nearest whole
+fn jitter(rng: &mut RandomNumberGenerator, value: i32) -> i32 {
+    let percent = rng.range(100 - STAT_JITTER_PERCENT, 101 + STAT_JITTER_PERCENT);
+    (value * percent + 50) / 100
+}
+
+#[derive(PartialEq, Copy, Clone)]
+enum MonsterModifier {
+    Fierce,
+    Weak,
+}
+
+const MODIFIER_PREFIXES: [&str; 2] = ["Fierce", "Weak"];
+
+/// Renames a monster after its modifier and adjusts its stats to match
+fn apply_modifier(ecs: &mut World, monster: Entity, modifier: MonsterModifier) {
+    let (prefix, hp_percent, power_change) = match modifier {
+        MonsterModifier::Fierce => (MODIFIER_PREFIXES[0], 125, 2),
+        MonsterModifier::Weak => (MODIFIER_PREFIXES[1], 75, -1),
+    };
+    if let Some(stats) = ecs.write_storage::<CombatStats>().get_mut(monster) {
+        stats.max_hp = i32::max(1, stats.max_hp * hp_percent / 100);
+        stats.hp = stats.max_hp;
+        stats.power = i32::max(0, stats.power + power_change);
+    }
+    if let Some(name) = ecs.write_storage::<Name>().get_mut(monster) {
+        name.name = format!("{prefix} {}", name.name);
+    }
+}
+
+/// A monster's name without any modifier prefix, as in "Goblin" for a Fierce Goblin
+pub fn species_name(name: &str) -> &str {
+    MODIFIER_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix)?.strip_prefix(' '))
+        .unwrap_or(name)
+}
+
+/// Every kind of monster that can spawn, weakest first
+pub fn monster_species() -> Vec<&'static str> {
+    let mut templates = mob_templates(1);
+    templates.sort_by_key(|mob| mob.challenge);
+    templates.iter().map(|mob| mob.name).collect()
+}
+
+/// Leaves the remains of a fallen creature on the floor
+pub fn corpse(ecs: &mut World, pos: Position, name: &str) {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::CORPSE);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::DARK_RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
         })
+        .with(Name {
+            name: format!("{name} corpse"),
+            proper: false,
+        })
+        .with(Corpse {})
         .marked::<SimpleMarker<IsSerialized>>()
         .build();
 }
 
-/// Calls spawn_region() with all possible_targets (floor tiles) from given room
-pub fn spawn_room(ecs: &mut World, room: &Rect, map_depth: i32) {
+/// Rolls on the loot table for a slain monster and drops whatever comes up where it fell
+pub fn drop_loot(ecs: &mut World, pos: Position) {
+    let loot = {
+        let map_depth = ecs.fetch::<Map>().depth;
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        loot_table(map_depth).roll(&mut rng)
+    };
+    spawn_named(ecs, &loot, pos);
+}
+
+/// Calls spawn_region() with all possible_targets (floor tiles) from given room. Nothing is
+/// created until `spawn_all`.
+pub fn spawn_room(ecs: &World, room: &Rect, map_depth: i32, spawn_list: &mut Vec<(usize, String)>) {
     let mut possible_targets: Vec<usize> = Vec::new();
     {
         // scope - keep access to map separated
@@ -93,309 +483,1269 @@ pub fn spawn_room(ecs: &mut World, room: &Rect, map_depth: i32) {
         for y in room.y1 + 1..room.y2 {
             for x in room.x1 + 1..room.x2 {
                 let idx = map.xy_idx(x, y);
-                if map.tiles[idx] == TileType::Floor {
+                if map.tiles[idx].is_floor() || map.tiles[idx].is_water() {
                     possible_targets.push(idx);
                 }
             }
         }
     }
 
-    spawn_region(ecs, &possible_targets, map_depth);
+    spawn_region(ecs, &possible_targets, map_depth, spawn_list);
 }
 
-pub fn spawn_region(ecs: &mut World, area: &[usize], map_depth: i32) {
+/// Rolls what a region holds and adds it to `spawn_list`. Nothing is created until `spawn_all`.
+pub fn spawn_region(
+    ecs: &World,
+    area: &[usize],
+    map_depth: i32,
+    spawn_list: &mut Vec<(usize, String)>,
+) {
     let spawn_table = room_table(map_depth);
-    let mut spawn_points: HashMap<usize, String> = HashMap::new();
-    let mut areas: Vec<usize> = Vec::from(area);
+    let mut spawn_points: BTreeMap<usize, String> = BTreeMap::new();
+    let mut attempted: HashSet<usize> = HashSet::new();
 
     {
+        let map = ecs.fetch::<Map>();
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
         let num_spawns = i32::min(
-            areas.len() as i32,
-            rng.roll_dice(1, MAX_MONSTERS + 3) + (map_depth - 1) - 3,
+            area.len() as i32,
+            rng.roll_dice(1, MAX_ITEMS + 3) + (map_depth - 1) - 3,
         );
-        if num_spawns == 0 {
-            return;
-        }
 
         for _ in 0..num_spawns {
-            let array_index: usize = if areas.len() == 1 {
-                0
-            } else {
-                (rng.roll_dice(1, areas.len() as i32) - 1) as usize
-            };
-            let map_idx = areas[array_index];
-            spawn_points.insert(map_idx, spawn_table.roll(&mut rng));
-            areas.remove(array_index);
+            match pick_spawn_tile(area, &mut attempted, &map, &mut rng, Terrain::Land) {
+                Some(map_idx) => {
+                    spawn_points.insert(map_idx, spawn_table.roll(&mut rng));
+                }
+                None => break,
+            }
+        }
+
+        // Fill the region with monsters until its challenge budget is spent. What lives there
+        // depends on the look of the place.
+        let theme = area.first().map_or(map.theme, |idx| map.theme_at(*idx));
+        let mobs: Vec<MobTemplate> = mob_templates(map_depth)
+            .into_iter()
+            .map(|mob| MobTemplate {
+                weight: biome_weight(theme, &mob),
+                ..mob
+            })
+            .collect();
+        let mut budget = challenge_budget(area.len(), map_depth, &mut rng);
+        loop {
+            let affordable: Vec<&MobTemplate> =
+                mobs.iter().filter(|mob| mob.challenge <= budget).collect();
+            if affordable.is_empty() {
+                break;
+            }
+
+            let mut roll = rng.roll_dice(1, affordable.iter().map(|mob| mob.weight).sum());
+            let mob = affordable
+                .iter()
+                .find(|mob| {
+                    roll -= mob.weight;
+                    roll <= 0
+                })
+                .expect("Roll exceeded total weight");
+            budget -= mob.challenge;
+
+            match pick_spawn_tile(area, &mut attempted, &map, &mut rng, mob.preferred_terrain) {
+                Some(map_idx) => {
+                    spawn_points.insert(map_idx, mob.name.to_string());
+                }
+                // Only this kind of creature has nowhere to go, the rest of the budget still stands
+                None if mob.preferred_terrain != Terrain::Land => {}
+                None => break,
+            }
+        }
+    }
+
+    spawn_list.extend(spawn_points);
+}
+
+/// How many rolled monsters a level at this depth may hold
+pub fn max_monsters(map_depth: i32) -> usize {
+    (MONSTER_CAP_BASE + MONSTER_CAP_PER_DEPTH * map_depth) as usize
+}
+
+/// Creates everything on a level's spawn list. If the rooms rolled more monsters than the depth
+/// allows, the weakest extras are dropped first. Only monsters rolled from the mob templates are
+/// ever trimmed, so guaranteed spawns such as bosses or uniques always make it in.
+pub fn spawn_all(ecs: &mut World, spawn_list: &[(usize, String)], map_depth: i32) {
+    let mobs = mob_templates(map_depth);
+    let mut rolled_monsters: Vec<(usize, i32)> = spawn_list
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (_idx, name))| {
+            mobs.iter()
+                .find(|mob| mob.name == name)
+                .map(|mob| (i, mob.challenge))
+        })
+        .collect();
+
+    let mut trimmed: HashSet<usize> = HashSet::new();
+    let cap = max_monsters(map_depth);
+    if rolled_monsters.len() > cap {
+        rolled_monsters.sort_by_key(|(_i, challenge)| *challenge);
+        let excess = rolled_monsters.len() - cap;
+        trimmed.extend(rolled_monsters[..excess].iter().map(|(i, _challenge)| *i));
+    }
+
+    let mut fierce = 0;
+    for (i, (idx, name)) in spawn_list.iter().enumerate() {
+        if trimmed.contains(&i) {
+            continue;
+        }
+        let entity = spawn_entity(ecs, &(idx, name));
+
+        // Now and then a rolled monster is a tougher or feebler specimen
+        let Some(entity) = entity.filter(|_| mobs.iter().any(|mob| mob.name == name)) else {
+            continue;
+        };
+        let roll = ecs
+            .write_resource::<RandomNumberGenerator>()
+            .roll_dice(1, MODIFIER_CHANCE);
+        match roll {
+            1 if fierce < MAX_FIERCE_PER_LEVEL => {
+                fierce += 1;
+                apply_modifier(ecs, entity, MonsterModifier::Fierce);
+            }
+            2 => apply_modifier(ecs, entity, MonsterModifier::Weak),
+            _ => {}
         }
     }
+}
+
+/// Brings one more monster onto the level, somewhere the player can't see it arrive. Does nothing
+/// once the level holds as many monsters as its depth allows, or if there's nowhere out of sight
+/// on terrain the monster rolled can live on.
+pub fn reinforcement(ecs: &mut World, map_depth: i32) -> Option<Entity> {
+    if ecs.read_storage::<Monster>().join().count() >= max_monsters(map_depth) {
+        return None;
+    }
+
+    let (name, pos) = {
+        let map = ecs.fetch::<Map>();
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let templates = mob_templates(map_depth);
+        let table = templates.iter().fold(RandomTable::new(), |table, mob| {
+            table.add(mob.name, mob.weight)
+        });
+        let name = table.roll(&mut rng);
+        let terrain = templates
+            .iter()
+            .find(|mob| mob.name == name)
+            .map_or(Terrain::Land, |mob| mob.preferred_terrain);
+        let hidden: Vec<usize> = (0..map.tiles.len())
+            .filter(|idx| terrain.allows(map.tiles[*idx]) && !map.blocked[*idx])
+            .filter(|idx| !map.visible_tiles[*idx])
+            .collect();
+        if hidden.is_empty() {
+            return None;
+        }
+        let idx = hidden[(rng.roll_dice(1, hidden.len() as i32) - 1) as usize];
+        let pos = Position {
+            x: (idx % MAPWIDTH) as i32,
+            y: (idx / MAPWIDTH) as i32,
+        };
+        (name, pos)
+    };
+    spawn_named(ecs, &name, pos)
+}
 
-    // Spawning things
-    for spawn in spawn_points.iter() {
-        spawn_entity(ecs, &spawn);
+/// Picks a random open tile of the region that hasn't been tried yet. Spawning happens before
+/// the map's blocked tiles are worked out, so this goes by the tiles themselves.
+/// Gives up after MAX_SPAWN_ATTEMPTS so a fully blocked region just spawns fewer entities.
+fn pick_spawn_tile(
+    area: &[usize],
+    attempted: &mut HashSet<usize>,
+    map: &Map,
+    rng: &mut RandomNumberGenerator,
+    terrain: Terrain,
+) -> Option<usize> {
+    let suitable: Vec<usize> = area
+        .iter()
+        .filter(|idx| terrain.allows(map.tiles[**idx]) && !map.tiles[**idx].blocks_movement())
+        .copied()
+        .collect();
+    for _ in 0..MAX_SPAWN_ATTEMPTS {
+        if suitable.iter().all(|idx| attempted.contains(idx)) {
+            break;
+        }
+        let map_idx = suitable[(rng.roll_dice(1, suitable.len() as i32) - 1) as usize];
+        if attempted.insert(map_idx) {
+            return Some(map_idx);
+        }
     }
+
+    #[cfg(debug_assertions)]
+    rltk::console::log("No free spawn tile left in region, spawning fewer entities");
+    None
+}
+
+/// Total monster challenge a region may hold. Scales with region area and depth.
+fn challenge_budget(area: usize, map_depth: i32, rng: &mut RandomNumberGenerator) -> i32 {
+    let max_budget = area as i32 / 20 + map_depth;
+    rng.range(0, max_budget + 1)
+}
+
+type SpawnFn = fn(&mut World, Position) -> Entity;
+
+/// Everything `spawn_named` knows how to build, by name
+const SPAWNABLE: &[(&str, SpawnFn)] = &[
+    ("Goblin", goblin),
+    ("Orc", orc),
+    ("Ghost", ghost),
+    ("Eel", eel),
+    ("Goblin Portal", |ecs, pos| {
+        portal(ecs, pos, "Goblin Portal", "Goblin")
+    }),
+    ("Orc Portal", |ecs, pos| {
+        portal(ecs, pos, "Orc Portal", "Orc")
+    }),
+    ("Health Potion", health_potion),
+    ("Poison Potion", poison_potion),
+    ("Confusion Potion", confusion_potion),
+    ("Weakness Potion", weakness_potion),
+    ("Rage Potion", rage_potion),
+    ("Fireball Scroll", fireball_scroll),
+    ("Lightning Scroll", lightning_scroll),
+    ("Dragon Breath Scroll", dragon_breath_scroll),
+    ("Thunderclap Scroll", thunderclap_scroll),
+    ("Frost Scroll", frost_scroll),
+    ("Flask of Oil", flask_of_oil),
+    ("Confusion Scroll", confusion_scroll),
+    ("Mass Confusion Scroll", mass_confusion_scroll),
+    ("Magic Missile Scroll", magic_missile_scroll),
+    ("Dagger", dagger),
+    ("Venom Dagger", venom_dagger),
+    ("Shield", shield),
+    ("Longsword", longsword),
+    ("Spear", spear),
+    ("Tower Shield", tower_shield),
+    ("Pickaxe", pickaxe),
+    ("Rations", rations),
+    ("Magic Mapping Scroll", magic_mapping_scroll),
+    ("Identify Scroll", identify_scroll),
+    ("Torch", torch),
+    ("Lantern", lantern),
+    ("Ring of Clairvoyance", ring_of_clairvoyance),
+    ("Bear Trap", bear_trap),
+    ("Gold Coins", gold_coins),
+];
+
+/// Every name `spawn_named` knows how to build
+pub fn spawnable_names() -> impl Iterator<Item = &'static str> {
+    SPAWNABLE.iter().map(|(name, _spawn)| *name)
 }
 
 /// Spawns a named entity at the location map[idx]
-fn spawn_entity(ecs: &mut World, (idx, name): &(&usize, &String)) {
+fn spawn_entity(ecs: &mut World, (idx, name): &(&usize, &String)) -> Option<Entity> {
     let pos = Position {
         x: (*idx % MAPWIDTH) as i32,
         y: (*idx / MAPWIDTH) as i32,
     };
+    spawn_named(ecs, name, pos)
+}
 
-    match name.as_ref() {
-        "Goblin" => goblin(ecs, pos),
-        "Orc" => orc(ecs, pos),
-        "Health Potion" => health_potion(ecs, pos),
-        "Fireball Scroll" => fireball_scroll(ecs, pos),
-        "Confusion Scroll" => confusion_scroll(ecs, pos),
-        "Magic Missile Scroll" => magic_missile_scroll(ecs, pos),
-        "Dagger" => dagger(ecs, pos),
-        "Shield" => shield(ecs, pos),
-        "Longsword" => longsword(ecs, pos),
-        "Tower Shield" => tower_shield(ecs, pos),
-        "Rations" => rations(ecs, pos),
-        "Magic Mapping Scroll" => magic_mapping_scroll(ecs, pos),
-        "Bear Trap" => bear_trap(ecs, pos),
-        _ => {}
+/// Spawns an entity by its name. Returns `None` if the name isn't one of `spawnable_names`.
+pub fn spawn_named(ecs: &mut World, name: &str, pos: Position) -> Option<Entity> {
+    let (_name, spawn) = SPAWNABLE.iter().find(|(known, _spawn)| *known == name)?;
+    let entity = spawn(ecs, pos);
+    if let Some(gold) = item_value(name) {
+        ecs.write_storage::<Value>()
+            .insert(entity, Value { gold })
+            .expect("Unable to insert value");
     }
+    Some(entity)
+}
+
+/// What an item costs at the shop, if it can be traded at all
+pub fn item_value(name: &str) -> Option<i32> {
+    ITEM_VALUES
+        .iter()
+        .find(|(item, _gold)| *item == name)
+        .map(|(_item, gold)| *gold)
 }
 
-fn health_potion(ecs: &mut World, pos: Position) {
+fn health_potion(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::POTION);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('¡'),
+            glyph,
             fg: RGB::named(rltk::MAGENTA),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
             name: "Health Potion".to_string(),
+            proper: false,
         })
         .with(Item {})
+        .with(Weight { pounds: 0.5 })
         .with(ProvidesHealing { heal_amount: 8 })
         .with(Consumable {})
+        .with(Fragile {})
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn magic_missile_scroll(ecs: &mut World, pos: Position) {
+/// Hurts whoever drinks it, though it does the same to an enemy it's thrown at
+fn poison_potion(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::POTION);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::CYAN),
+            glyph,
+            fg: RGB::named(rltk::GREEN),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Magic Missile Scroll".to_string(),
+            name: "Poison Potion".to_string(),
+            proper: false,
         })
         .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(InflictsDamage {
+            damage: dice("2d4"),
+            damage_type: DamageType::Poison,
+        })
         .with(Consumable {})
-        .with(Ranged { range: 6 })
-        .with(InflictsDamage { damage: 8 })
+        .with(Fragile {})
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn fireball_scroll(ecs: &mut World, pos: Position) {
+fn confusion_potion(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::POTION);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::ORANGE),
+            glyph,
+            fg: RGB::named(rltk::PINK),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Fireball Scroll".to_string(),
+            name: "Confusion Potion".to_string(),
+            proper: false,
         })
         .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Confusion { turns: 4 })
         .with(Consumable {})
-        .with(Ranged { range: 6 })
-        .with(InflictsDamage { damage: 20 })
-        .with(AreaOfEffect { radius: 3 })
+        .with(Fragile {})
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn confusion_scroll(ecs: &mut World, pos: Position) {
+fn weakness_potion(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::POTION);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::PINK),
+            glyph,
+            fg: RGB::named(rltk::GREY),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Confusion Scroll".to_string(),
+            name: "Weakness Potion".to_string(),
+            proper: false,
         })
         .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Weakness {
+            amount: 2,
+            turns: 20,
+        })
         .with(Consumable {})
-        .with(Ranged { range: 6 })
-        .with(Confusion { turns: 4 })
+        .with(Fragile {})
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn magic_mapping_scroll(ecs: &mut World, pos: Position) {
+fn rage_potion(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::POTION);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437(')'),
-            fg: RGB::named(rltk::CYAN3),
+            glyph,
+            fg: RGB::named(rltk::CRIMSON),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Scroll of Magic Mapping".to_string(),
+            name: "Rage Potion".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(MagicMapper {})
+        .with(Weight { pounds: 0.5 })
+        .with(Rage { turns: 10 })
         .with(Consumable {})
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn dagger(ecs: &mut World, pos: Position) {
+fn magic_missile_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('/'),
+            glyph,
             fg: RGB::named(rltk::CYAN),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Dagger".to_string(),
+            name: "Magic Missile Scroll".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(Equippable {
-            slot: EquipmentSlot::Melee,
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: dice("2d4+3"),
+            damage_type: DamageType::Physical,
         })
-        .with(MeleePowerBonus { amount: 2 })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn shield(ecs: &mut World, pos: Position) {
+fn fireball_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('('),
-            fg: RGB::named(rltk::CYAN),
+            glyph,
+            fg: RGB::named(rltk::ORANGE),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Shield".to_string(),
+            name: "Fireball Scroll".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(Equippable {
-            slot: EquipmentSlot::Shield,
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: dice("4d6+6"),
+            damage_type: DamageType::Fire,
+        })
+        .with(AreaOfEffect {
+            radius: 3,
+            shape: AoeShape::Circle,
         })
-        .with(DefenseBonus { amount: 1 })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn longsword(ecs: &mut World, pos: Position) {
+/// Strikes everything along a line, stopping at the first wall
+fn lightning_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('/'),
-            fg: RGB::named(rltk::YELLOW),
+            glyph,
+            fg: RGB::named(rltk::LIGHT_BLUE),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Longsword".to_string(),
+            name: "Lightning Scroll".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(Equippable {
-            slot: EquipmentSlot::Melee,
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: dice("3d6+2"),
+            damage_type: DamageType::Lightning,
+        })
+        .with(AreaOfEffect {
+            radius: 8,
+            shape: AoeShape::Line,
         })
-        .with(MeleePowerBonus { amount: 4 })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn tower_shield(ecs: &mut World, pos: Position) {
+fn dragon_breath_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('('),
-            fg: RGB::named(rltk::YELLOW),
+            glyph,
+            fg: RGB::named(rltk::RED),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Tower Shield".to_string(),
+            name: "Dragon Breath Scroll".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(Equippable {
-            slot: EquipmentSlot::Shield,
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 5 })
+        .with(InflictsDamage {
+            damage: dice("2d6+4"),
+            damage_type: DamageType::Fire,
         })
-        .with(DefenseBonus { amount: 3 })
+        .with(AreaOfEffect {
+            radius: 5,
+            shape: AoeShape::Cone,
+        })
+        .with(StartsFire { turns: 4 })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn rations(ecs: &mut World, pos: Position) {
+fn thunderclap_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('%'),
-            fg: RGB::named(rltk::GREEN),
+            glyph,
+            fg: RGB::named(rltk::YELLOW),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Rations".to_string(),
+            name: "Thunderclap Scroll".to_string(),
+            proper: false,
         })
         .with(Item {})
-        .with(ProvidesFood {})
+        .with(Weight { pounds: 0.5 })
         .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: dice("3d6"),
+            damage_type: DamageType::Physical,
+        })
+        .with(AreaOfEffect {
+            radius: 3,
+            shape: AoeShape::Cross,
+        })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
 }
 
-fn bear_trap(ecs: &mut World, pos: Position) {
+/// Freezes everything in a small burst
+fn frost_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
     ecs.create_entity()
         .with(pos)
         .with(Renderable {
-            glyph: rltk::to_cp437('^'),
-            fg: RGB::named(rltk::RED),
+            glyph,
+            fg: RGB::named(rltk::WHITE),
             bg: RGB::named(rltk::BLACK),
             render_order: 2,
         })
         .with(Name {
-            name: "Bear Trap".to_string(),
+            name: "Frost Scroll".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage {
+            damage: dice("3d6+2"),
+            damage_type: DamageType::Cold,
+        })
+        .with(AreaOfEffect {
+            radius: 2,
+            shape: AoeShape::Circle,
         })
-        .with(Hidden {})
-        .with(EntryTrigger {})
-        .with(SingleActivation {})
-        .with(InflictsDamage { damage: 6 })
         .marked::<SimpleMarker<IsSerialized>>()
-        .build();
+        .build()
+}
+
+fn flask_of_oil(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::FLASK);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Flask of Oil".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 1.0 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(AreaOfEffect {
+            radius: 1,
+            shape: AoeShape::Circle,
+        })
+        .with(StartsFire { turns: 6 })
+        .with(Fragile {})
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn confusion_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::PINK),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Confusion Scroll".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Ranged { range: 6 })
+        .with(Confusion { turns: 4 })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// Confuses everything hostile in sight. Kept if there's nothing to read it at.
+fn mass_confusion_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::HOT_PINK),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Mass Confusion Scroll".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Consumable {})
+        .with(Confusion { turns: 3 })
+        .with(MassEffect { fizzles: false })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn magic_mapping_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::CYAN3),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Magic Mapping Scroll".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(MagicMapper {})
+        .with(Consumable {})
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn identify_scroll(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SCROLL);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::WHITE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Identify Scroll".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(Identifier {})
+        .with(Consumable {})
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// Lights the way through dark floor while it's carried
+fn torch(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::TORCH);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::YELLOW),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Torch".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 1.0 })
+        .with(LightSource { range: 5 })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// Held in the off hand, it lights further than a torch and lets you see a little further too
+fn lantern(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::TORCH);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Lantern".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 2.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::OffHand,
+        })
+        .with(LightSource { range: 6 })
+        .with(SightBonus { range: 1 })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn ring_of_clairvoyance(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::RING);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Ring of Clairvoyance".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.1 })
+        .with(Equippable {
+            slot: EquipmentSlot::Trinket,
+        })
+        .with(SightBonus { range: 3 })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn dagger(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::WEAPON);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::CYAN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Dagger".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 1.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus {
+            amount: 2,
+            damage_type: DamageType::Physical,
+        })
+        .with(Durability {
+            current: 30,
+            max: 30,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn venom_dagger(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::WEAPON);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::GREEN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Venom Dagger".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 1.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus {
+            amount: 2,
+            damage_type: DamageType::Poison,
+        })
+        .with(Durability {
+            current: 30,
+            max: 30,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn shield(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SHIELD);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::CYAN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Shield".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 6.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::OffHand,
+        })
+        .with(DefenseBonus { amount: 1 })
+        .with(Durability {
+            current: 40,
+            max: 40,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn pickaxe(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::WEAPON);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::BROWN1),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Pickaxe".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 6.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus {
+            amount: 1,
+            damage_type: DamageType::Physical,
+        })
+        .with(Digger {})
+        .with(Durability {
+            current: 40,
+            max: 40,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn longsword(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::WEAPON);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::YELLOW),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Longsword".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 3.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus {
+            amount: 4,
+            damage_type: DamageType::Physical,
+        })
+        .with(Knockback { distance: 1 })
+        .with(Durability {
+            current: 50,
+            max: 50,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn spear(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::WEAPON);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Spear".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 4.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .with(MeleePowerBonus {
+            amount: 2,
+            damage_type: DamageType::Physical,
+        })
+        .with(Durability {
+            current: 40,
+            max: 40,
+        })
+        .with(Reach { tiles: 2 })
+        .with(TwoHanded {})
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn tower_shield(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::SHIELD);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::YELLOW),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Tower Shield".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 15.0 })
+        .with(Equippable {
+            slot: EquipmentSlot::OffHand,
+        })
+        .with(DefenseBonus { amount: 3 })
+        .with(Durability {
+            current: 60,
+            max: 60,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn rations(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::FOOD);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::GREEN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Rations".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Weight { pounds: 0.5 })
+        .with(ProvidesFood {})
+        .with(Consumable {})
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// A pile of gold that grows richer with depth
+fn gold_coins(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::GOLD);
+    let amount = {
+        let map_depth = ecs.fetch::<Map>().depth;
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        dice("2d6").roll(&mut rng) + map_depth * 2
+    };
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Gold Coins".to_string(),
+            proper: false,
+        })
+        .with(Item {})
+        .with(Coins { amount })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+fn bear_trap(ecs: &mut World, pos: Position) -> Entity {
+    let glyph = ecs.fetch::<GlyphSet>().get(glyphs::TRAP);
+    ecs.create_entity()
+        .with(pos)
+        .with(Renderable {
+            glyph,
+            fg: RGB::named(rltk::RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Bear Trap".to_string(),
+            proper: false,
+        })
+        .with(Hidden {})
+        .with(EntryTrigger {})
+        .with(SingleActivation {})
+        .with(InflictsDamage {
+            damage: dice("2d4+1"),
+            damage_type: DamageType::Physical,
+        })
+        .marked::<SimpleMarker<IsSerialized>>()
+        .build()
+}
+
+/// A spawnable monster and how much of a region's challenge budget it consumes
+struct MobTemplate {
+    name: &'static str,
+    challenge: i32,
+    weight: i32,
+    /// Where it can be placed. A region without any is passed over for it.
+    preferred_terrain: Terrain,
+}
+
+/// The kind of tile something can spawn on
+#[derive(PartialEq, Copy, Clone)]
+enum Terrain {
+    /// Any dry floor
+    Land,
+    DeepWater,
+}
+
+impl Terrain {
+    fn allows(self, tile: TileType) -> bool {
+        match self {
+            Terrain::Land => tile.is_floor(),
+            Terrain::DeepWater => tile == TileType::DeepWater,
+        }
+    }
+}
+
+/// Ghosts haunt built halls rather than the open wilds, where goblins gather instead
+fn biome_weight(theme: MapThemeKind, mob: &MobTemplate) -> i32 {
+    match (theme, mob.name) {
+        (MapThemeKind::Forest, "Ghost") => 0,
+        (MapThemeKind::Forest, "Goblin") => mob.weight * 3 / 2,
+        _ => mob.weight,
+    }
+}
+
+fn mob_templates(map_depth: i32) -> Vec<MobTemplate> {
+    vec![
+        MobTemplate {
+            name: "Goblin",
+            challenge: 1,
+            weight: 10,
+            preferred_terrain: Terrain::Land,
+        },
+        MobTemplate {
+            name: "Orc",
+            challenge: 2,
+            weight: 1 + map_depth,
+            preferred_terrain: Terrain::Land,
+        },
+        MobTemplate {
+            name: "Ghost",
+            challenge: 2,
+            weight: map_depth - 1,
+            preferred_terrain: Terrain::Land,
+        },
+        MobTemplate {
+            name: "Goblin Portal",
+            challenge: 4,
+            weight: map_depth - 2,
+            preferred_terrain: Terrain::Land,
+        },
+        MobTemplate {
+            name: "Orc Portal",
+            challenge: 5,
+            weight: map_depth - 3,
+            preferred_terrain: Terrain::Land,
+        },
+        MobTemplate {
+            name: "Eel",
+            challenge: 1,
+            weight: 4,
+            preferred_terrain: Terrain::DeepWater,
+        },
+    ]
 }
 
 fn room_table(map_depth: i32) -> RandomTable {
     RandomTable::new()
-        .add("Goblin", 10)
-        .add("Orc", 1 + map_depth)
         .add("Health Potion", 7)
+        .add("Poison Potion", 2)
+        .add("Confusion Potion", 2)
+        .add("Weakness Potion", 1 + map_depth / 2)
+        .add("Rage Potion", 1 + map_depth / 3)
         .add("Fireball Scroll", 2 + map_depth)
+        .add("Lightning Scroll", 1 + map_depth)
+        .add("Dragon Breath Scroll", map_depth)
+        .add("Thunderclap Scroll", 1 + map_depth)
+        .add("Frost Scroll", 1 + map_depth)
+        .add("Flask of Oil", 2)
         .add("Confusion Scroll", 2 + map_depth)
+        .add("Mass Confusion Scroll", map_depth - 1)
         .add("Magic Missile Scroll", 4)
         .add("Dagger", 3)
+        .add("Venom Dagger", map_depth)
         .add("Shield", 3)
         .add("Longsword", map_depth - 1)
+        .add("Spear", 2)
         .add("Tower Shield", map_depth - 1)
+        .add("Pickaxe", 1)
         .add("Rations", 10)
         .add("Magic Mapping Scroll", 2)
+        .add("Identify Scroll", 2)
+        .add("Torch", 3)
+        .add("Lantern", 1)
+        .add("Ring of Clairvoyance", map_depth - 1)
         .add("Bear Trap", 2)
+        .add("Gold Coins", 6)
+}
+
+/// What monsters drop when they die. Most of the time it's nothing.
+fn loot_table(map_depth: i32) -> RandomTable {
+    RandomTable::new()
+        .add("None", 12)
+        .add("Gold Coins", 6 + map_depth)
+        .add("Health Potion", 1)
+        .add("Rations", 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with(map: Map) -> World {
+        let mut ecs = World::new();
+        ecs.insert(map);
+        ecs.insert(RandomNumberGenerator::seeded(1));
+        ecs
+    }
+
+    #[test]
+    fn walled_in_room_spawns_nothing() {
+        let ecs = world_with(Map::new(5));
+        let mut spawn_list = Vec::new();
+        spawn_room(&ecs, &Rect::new(10, 10, 8, 6), 5, &mut spawn_list);
+        assert!(spawn_list.is_empty());
+    }
+
+    #[test]
+    fn spawns_only_on_open_floor() {
+        let mut map = Map::new(5);
+        for x in 11..18 {
+            let idx = map.xy_idx(x, 12);
+            map.tiles[idx] = TileType::Floor;
+        }
+        let ecs = world_with(map);
+        let mut spawn_list = Vec::new();
+        for _ in 0..20 {
+            spawn_room(&ecs, &Rect::new(10, 10, 8, 6), 5, &mut spawn_list);
+        }
+        let map = ecs.fetch::<Map>();
+        assert!(!spawn_list.is_empty());
+        assert!(spawn_list
+            .iter()
+            .all(|(idx, _name)| map.tiles[*idx] == TileType::Floor));
+    }
+
+    #[test]
+    fn reinforcements_keep_to_their_terrain() {
+        let (mut ecs, _) = crate::new_world(RandomNumberGenerator::seeded(1));
+        let mut map = Map::new(5);
+        for x in 11..18 {
+            let floor = map.xy_idx(x, 12);
+            map.tiles[floor] = TileType::Floor;
+            let water = map.xy_idx(x, 14);
+            map.tiles[water] = TileType::DeepWater;
+        }
+        map.populate_blocked();
+        ecs.insert(map);
+
+        let mut spawned = Vec::new();
+        for _ in 0..40 {
+            let Some(monster) = reinforcement(&mut ecs, 5) else {
+                continue;
+            };
+            let name = ecs
+                .read_storage::<Name>()
+                .get(monster)
+                .unwrap()
+                .name
+                .clone();
+            let pos = ecs
+                .read_storage::<Position>()
+                .get(monster)
+                .cloned()
+                .unwrap();
+            ecs.delete_entity(monster).unwrap();
+            ecs.maintain();
+            spawned.push((name, pos));
+        }
+
+        let map = ecs.fetch::<Map>();
+        assert!(spawned.iter().any(|(name, _pos)| name == "Eel"));
+        for (name, pos) in spawned {
+            let in_water = map.tiles[map.xy_idx(pos.x, pos.y)] == TileType::DeepWater;
+            assert_eq!(
+                in_water,
+                name == "Eel",
+                "{name} spawned on the wrong terrain"
+            );
+        }
+    }
 }
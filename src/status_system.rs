@@ -0,0 +1,60 @@
+use super::{gamelog::Gamelog, Map, Name, Position, RunState, StatusEffects};
+use crate::grammar::{capitalize, is_are, with_the};
+use specs::prelude::*;
+
+/// Counts down every status effect once a round, after monsters and pets have taken their turns,
+/// and drops the ones that have run out. Only effects on creatures the player can see are
+/// reported as they wear off.
+pub struct StatusSystem;
+
+impl<'a> System<'a> for StatusSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, StatusEffects>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, Gamelog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut statuses, names, positions, player_entity, map, runstate, mut log) =
+            data;
+
+        if *runstate != RunState::MonsterTurn {
+            return;
+        }
+
+        let mut recovered: Vec<Entity> = Vec::new();
+        for (entity, status) in (&entities, &mut statuses).join() {
+            for effect in status.effects.iter_mut() {
+                effect.turns -= 1;
+            }
+
+            let seen = entity == *player_entity
+                || positions
+                    .get(entity)
+                    .is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+            if seen {
+                let subject = match names.get(entity) {
+                    _ if entity == *player_entity => "You are".to_string(),
+                    Some(name) => format!("{} {}", capitalize(&with_the(name)), is_are(name)),
+                    None => "Something is".to_string(),
+                };
+                for effect in status.effects.iter().filter(|effect| effect.turns < 1) {
+                    log.entries.push(effect.kind.expiry_message(&subject));
+                }
+            }
+            status.effects.retain(|effect| effect.turns > 0);
+            if status.effects.is_empty() {
+                recovered.push(entity);
+            }
+        }
+
+        for entity in recovered {
+            statuses.remove(entity);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::{gamelog::Gamelog, spawner, DamageType, Map, SufferDamage, TurnCounter};
+use specs::prelude::*;
+
+/// Rounds the player can spend on a level before the dungeon comes looking for them
+const LEVEL_TURN_LIMIT: i32 = 400;
+/// Rounds between each escalation once the limit is up
+const ESCALATION_INTERVAL: i32 = 25;
+
+/// Under time pressure, lingering on a level past `LEVEL_TURN_LIMIT` brings trouble every
+/// `ESCALATION_INTERVAL` rounds, and more of it each time: first extra monsters, up to the
+/// level's monster cap, then choking air that hurts once the level is full. The surface is safe.
+/// Runs once a round, after the turn counter has moved on.
+pub fn apply_time_pressure(ecs: &mut World) {
+    let (time_pressure, overtime) = {
+        let turn_counter = ecs.fetch::<TurnCounter>();
+        (
+            turn_counter.time_pressure,
+            turn_counter.turns - turn_counter.level_start - LEVEL_TURN_LIMIT,
+        )
+    };
+    let depth = ecs.fetch::<Map>().depth;
+    if !time_pressure || depth < 1 || overtime < 0 || overtime % ESCALATION_INTERVAL != 0 {
+        return;
+    }
+
+    let stage = overtime / ESCALATION_INTERVAL + 1;
+    if stage == 1 {
+        ecs.write_resource::<Gamelog>()
+            .entries
+            .push("You have lingered too long. The dungeon stirs around you.".to_string());
+    }
+
+    let arrived = (0..stage)
+        .take_while(|_| spawner::reinforcement(ecs, depth).is_some())
+        .count() as i32;
+    if arrived > 0 {
+        ecs.write_resource::<Gamelog>()
+            .entries
+            .push("You hear something approaching.".to_string());
+    }
+
+    // Whatever couldn't arrive is made up for by the air itself
+    let damage = stage - arrived;
+    if damage > 0 {
+        let player = *ecs.fetch::<Entity>();
+        SufferDamage::new_damage(
+            &mut ecs.write_storage::<SufferDamage>(),
+            player,
+            damage,
+            DamageType::Physical,
+            Some("stale air"),
+            None,
+            false,
+        );
+        ecs.write_resource::<Gamelog>().entries.push(format!(
+            "The air grows thick and stale. You suffer {damage} hp damage."
+        ));
+    }
+}
@@ -1,7 +1,10 @@
 use super::{
-    gamelog::Gamelog, particle_system::ParticleBuilder, EntityMoved, EntryTrigger, Hidden,
-    InflictsDamage, Map, Name, Position, SingleActivation, SufferDamage,
+    effects::{EffectQueue, EffectType, Targets},
+    gamelog::Gamelog,
+    glyphs::{self, GlyphSet},
+    EntityMoved, EntryTrigger, Hidden, InflictsDamage, Map, Name, Position, SingleActivation,
 };
+use rltk::RandomNumberGenerator;
 use specs::prelude::*;
 
 pub struct TriggerSystem;
@@ -17,9 +20,10 @@ impl<'a> System<'a> for TriggerSystem {
         Entities<'a>,
         WriteExpect<'a, Gamelog>,
         ReadStorage<'a, InflictsDamage>,
-        WriteExpect<'a, ParticleBuilder>,
-        WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, EffectQueue>,
         ReadStorage<'a, SingleActivation>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        ReadExpect<'a, GlyphSet>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -33,16 +37,17 @@ impl<'a> System<'a> for TriggerSystem {
             entities,
             mut log,
             inflicts_damage,
-            mut particle_builder,
-            mut suffer_damage,
+            mut effects,
             single_activation,
+            mut rng,
+            glyphs,
         ) = data;
 
         // Iterate the entities that moved and their final position
         let mut remove_entities: Vec<Entity> = Vec::new();
         for (entity, mut _entity_moved, pos) in (&entities, &mut entity_moved, &position).join() {
             let idx = map.xy_idx(pos.x, pos.y);
-            for tile_entity in map.tile_content[idx].iter() {
+            for tile_entity in map.entities_at(pos.x, pos.y).iter() {
                 // Is Triggerable
                 if entity != *tile_entity && entry_trigger.get(*tile_entity).is_some() {
                     if let Some(name) = names.get(*tile_entity) {
@@ -54,14 +59,24 @@ impl<'a> System<'a> for TriggerSystem {
 
                     // Inflicts Damage
                     if let Some(damage) = inflicts_damage.get(*tile_entity) {
-                        particle_builder.request(
-                            pos.clone(),
-                            rltk::RGB::named(rltk::ORANGE),
-                            rltk::RGB::named(rltk::BLACK),
-                            rltk::to_cp437('‼'),
-                            200.0,
+                        effects.add_effect(
+                            Some(*tile_entity),
+                            EffectType::Particle {
+                                glyph: glyphs.get(glyphs::HIT),
+                                fg: rltk::RGB::named(rltk::ORANGE),
+                                bg: rltk::RGB::named(rltk::BLACK),
+                                lifetime: 200.0,
+                            },
+                            Targets::Tile { tile_idx: idx },
+                        );
+                        effects.add_effect(
+                            Some(*tile_entity),
+                            EffectType::Damage {
+                                amount: damage.damage.roll(&mut rng),
+                                damage_type: damage.damage_type,
+                            },
+                            Targets::Single { target: entity },
                         );
-                        SufferDamage::new_damage(&mut suffer_damage, entity, damage.damage);
                     }
 
                     // If it is single activation, it needs to be removed
@@ -1,6 +1,16 @@
-use super::{gamelog::Gamelog, Hidden, Map, Name, Player, Position, Viewshed};
-use rltk::{field_of_view, Point};
+use super::{
+    bestiary::Bestiary,
+    gamelog::Gamelog,
+    settings::{FovAlgorithm, GameSettings},
+    CombatStats, Equipped, Hidden, InBackpack, LightSource, Map, Monster, Name, Player, Position,
+    TileType, Viewshed,
+};
+use rltk::{FieldOfViewAlg, Point};
 use specs::prelude::*;
+use std::collections::HashMap;
+
+/// How far anyone standing on dark floor sees without a light
+const DARK_SIGHT_RANGE: i32 = 1;
 
 pub struct VisibilitySystem;
 
@@ -15,23 +25,85 @@ impl<'a> System<'a> for VisibilitySystem {
         WriteExpect<'a, rltk::RandomNumberGenerator>,
         WriteExpect<'a, Gamelog>,
         ReadStorage<'a, Name>,
+        ReadStorage<'a, LightSource>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Monster>,
+        ReadStorage<'a, CombatStats>,
+        WriteExpect<'a, Bestiary>,
+        ReadExpect<'a, GameSettings>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player, mut hidden, mut rng, mut log, names) =
-            data;
+        let (
+            mut map,
+            entities,
+            mut viewshed,
+            pos,
+            player,
+            mut hidden,
+            mut rng,
+            mut log,
+            names,
+            lights,
+            backpack,
+            equipped,
+            monsters,
+            combat_stats,
+            mut bestiary,
+            settings,
+        ) = data;
+
+        // The brightest light each entity has on them, whether held, packed or their own glow
+        let mut light_ranges: HashMap<Entity, i32> = HashMap::new();
+        let carried = (&backpack, &lights)
+            .join()
+            .map(|(pack, light)| (pack.owner, light.range))
+            .chain(
+                (&equipped, &lights)
+                    .join()
+                    .map(|(equipped, light)| (equipped.owner, light.range)),
+            )
+            .chain(
+                (&entities, &lights)
+                    .join()
+                    .map(|(ent, light)| (ent, light.range)),
+            );
+        for (owner, range) in carried {
+            let brightest = light_ranges.entry(owner).or_insert(range);
+            *brightest = i32::max(*brightest, range);
+        }
 
+        let fov = match settings.fov_algorithm {
+            FovAlgorithm::Standard => FieldOfViewAlg::RecursiveShadowcasting,
+            FovAlgorithm::Symmetric => FieldOfViewAlg::SymmetricShadowcasting,
+        };
         for (ent, viewshed, pos) in (&entities, &mut viewshed, &pos).join() {
             if viewshed.dirty {
+                let range = if map.tiles[map.xy_idx(pos.x, pos.y)] == TileType::DarkFloor {
+                    light_ranges
+                        .get(&ent)
+                        .map_or(DARK_SIGHT_RANGE, |light| i32::min(*light, viewshed.range))
+                } else {
+                    viewshed.range
+                };
                 viewshed.visible_tiles.clear();
-                viewshed.visible_tiles =
-                    field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+                // Either way, only `Map::is_opaque` tiles block the view
+                viewshed.visible_tiles = fov.field_of_view(Point::new(pos.x, pos.y), range, &*map);
                 viewshed
                     .visible_tiles
                     .retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height);
 
                 // If player, reveal visible tiles
                 if player.get(ent).is_some() {
+                    if settings.room_lighting {
+                        // The whole room lights up, wherever the player stands in it
+                        for tile in map.room_tiles_at(pos.x, pos.y) {
+                            if !viewshed.visible_tiles.contains(&tile) {
+                                viewshed.visible_tiles.push(tile);
+                            }
+                        }
+                    }
                     for t in map.visible_tiles.iter_mut() {
                         *t = false
                     }
@@ -41,7 +113,7 @@ impl<'a> System<'a> for VisibilitySystem {
                         map.visible_tiles[idx] = true;
 
                         // Chance to reveal hidden things
-                        for e in map.tile_content[idx].iter() {
+                        for e in map.entities_at(vis.x, vis.y).iter() {
                             if hidden.get(*e).is_some() && rng.roll_dice(1, 24) == 1 {
                                 if let Some(name) = names.get(*e) {
                                     log.entries
@@ -55,5 +127,19 @@ impl<'a> System<'a> for VisibilitySystem {
                 viewshed.dirty = false;
             }
         }
+
+        // Checked every turn, since monsters can walk into view while the player stands still
+        for (viewshed, _player) in (&viewshed, &player).join() {
+            for tile in viewshed.visible_tiles.iter() {
+                for e in map.entities_at(tile.x, tile.y).iter() {
+                    if monsters.get(*e).is_none() || hidden.get(*e).is_some() {
+                        continue;
+                    }
+                    if let (Some(name), Some(stats)) = (names.get(*e), combat_stats.get(*e)) {
+                        bestiary.record_sighting(&name.name, stats, map.depth);
+                    }
+                }
+            }
+        }
     }
 }
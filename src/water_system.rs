@@ -0,0 +1,62 @@
+use super::{
+    gamelog::Gamelog, CanSwim, CombatStats, DamageType, Map, Position, RunState, SufferDamage,
+    TileType,
+};
+use specs::prelude::*;
+
+const DROWNING_DAMAGE: i32 = 2;
+
+pub struct WaterSystem;
+
+impl<'a> System<'a> for WaterSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, Gamelog>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, CanSwim>,
+        WriteStorage<'a, SufferDamage>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            map,
+            runstate,
+            player_entity,
+            mut gamelog,
+            entities,
+            positions,
+            combat_stats,
+            swimmers,
+            mut suffer_damage,
+        ) = data;
+
+        // Drowning ticks once per round, like fire
+        if *runstate != RunState::MonsterTurn {
+            return;
+        }
+
+        for (entity, pos, _stats, _swimmer) in
+            (&entities, &positions, &combat_stats, !&swimmers).join()
+        {
+            if map.tiles[map.xy_idx(pos.x, pos.y)] != TileType::DeepWater {
+                continue;
+            }
+            SufferDamage::new_damage(
+                &mut suffer_damage,
+                entity,
+                DROWNING_DAMAGE,
+                DamageType::Physical,
+                Some("drowning"),
+                None,
+                false,
+            );
+            if entity == *player_entity {
+                gamelog.entries.push("You are drowning!".to_string());
+            }
+        }
+    }
+}